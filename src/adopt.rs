@@ -0,0 +1,180 @@
+//! Adopting existing files into the config repo (`mimic add`).
+//!
+//! The file is moved under the repo's `dotfiles/` directory and a matching
+//! `[[dotfiles]]` entry is appended to `mimic.toml`. The config is edited with
+//! `toml_edit` so the user's comments and formatting survive.
+
+use crate::linker::copy_dir_all;
+use anyhow::Context;
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table, value};
+
+/// Directory, relative to the config file, that adopted files are moved into
+pub const DOTFILES_DIR: &str = "dotfiles";
+
+/// Name a file gets inside the repo: its own name without the leading dot.
+///
+/// `~/.zshrc` becomes `zshrc` and `~/.config/starship.toml` stays
+/// `starship.toml`, matching the recommended repo layout.
+pub fn repo_file_name(path: &Path) -> anyhow::Result<String> {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow::anyhow!("Path has no usable file name: {}", path.display()))?;
+
+    let stripped = name.strip_prefix('.').unwrap_or(name);
+    if stripped.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Cannot derive a repo file name from: {}",
+            path.display()
+        ));
+    }
+
+    Ok(stripped.to_string())
+}
+
+/// Target as written in `mimic.toml`: `~/...` under the home directory,
+/// absolute otherwise.
+pub fn config_target(path: &Path) -> String {
+    if let Some(home) = home::home_dir()
+        && let Ok(rest) = path.strip_prefix(&home)
+    {
+        return format!("~/{}", rest.display());
+    }
+    path.display().to_string()
+}
+
+/// Append a `[[dotfiles]]` entry to the config text, or a
+/// `[[hosts.<host>.dotfiles]]` entry when a host is given.
+pub fn append_dotfile_entry(
+    config_text: &str,
+    source: &str,
+    target: &str,
+    template: bool,
+    host: Option<&str>,
+) -> anyhow::Result<String> {
+    let mut doc = config_text
+        .parse::<DocumentMut>()
+        .context("Failed to parse config for editing")?;
+
+    let mut entry = Table::new();
+    entry["source"] = value(source);
+    entry["target"] = value(target);
+    if template {
+        entry["template"] = value(true);
+    }
+
+    let parent = config_section(&mut doc, host)?;
+
+    match parent.get_mut("dotfiles") {
+        None => {
+            let mut dotfiles = ArrayOfTables::new();
+            dotfiles.push(entry);
+            parent.insert("dotfiles", Item::ArrayOfTables(dotfiles));
+        }
+        Some(Item::ArrayOfTables(dotfiles)) => dotfiles.push(entry),
+        Some(_) => {
+            return Err(anyhow::anyhow!(
+                "Cannot append to 'dotfiles': it is not written as [[dotfiles]] tables\n\nTo fix:\n  - Rewrite the existing entries as [[dotfiles]] tables\n  - Or add the entry to mimic.toml by hand"
+            ));
+        }
+    }
+
+    Ok(doc.to_string())
+}
+
+/// The top level of the config, or its `[hosts.<host>]` table when a host
+/// is given.
+pub fn config_section<'a>(
+    doc: &'a mut DocumentMut,
+    host: Option<&str>,
+) -> anyhow::Result<&'a mut Table> {
+    match host {
+        None => Ok(doc.as_table_mut()),
+        Some(host) => doc
+            .get_mut("hosts")
+            .and_then(Item::as_table_mut)
+            .and_then(|hosts| hosts.get_mut(host))
+            .and_then(Item::as_table_mut)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Host '{}' not found in config\n\nTo fix:\n  - Add a [hosts.{}] section to mimic.toml\n  - Or run 'mimic hosts list' to see available hosts",
+                    host,
+                    host
+                )
+            }),
+    }
+}
+
+/// Move a file or directory, falling back to copy-and-delete across filesystems.
+pub fn move_path(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    if from.is_dir() {
+        copy_dir_all(from, to)?;
+        fs::remove_dir_all(from)
+    } else {
+        fs::copy(from, to)
+            .with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))?;
+        fs::remove_file(from)
+    }
+    .with_context(|| format!("Failed to remove original: {}", from.display()))
+}
+
+/// Where an adopted file lands inside the repo.
+pub fn repo_destination(config_dir: &Path, path: &Path) -> anyhow::Result<PathBuf> {
+    Ok(config_dir.join(DOTFILES_DIR).join(repo_file_name(path)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_file_name_strips_leading_dot() {
+        assert_eq!(
+            repo_file_name(Path::new("/home/me/.zshrc")).unwrap(),
+            "zshrc"
+        );
+        assert_eq!(
+            repo_file_name(Path::new("/home/me/.config/starship.toml")).unwrap(),
+            "starship.toml"
+        );
+    }
+
+    #[test]
+    fn test_append_preserves_comments() {
+        let config = "# My dotfiles\n\n[variables]\nname = \"me\" # inline\n\n[[dotfiles]]\nsource = \"dotfiles/vimrc\"\ntarget = \"~/.vimrc\"\n\n[hosts.work]\nroles = [\"work\"]\n";
+
+        let top = append_dotfile_entry(config, "dotfiles/zshrc", "~/.zshrc", false, None).unwrap();
+        assert!(top.starts_with("# My dotfiles\n"));
+        assert!(top.contains("name = \"me\" # inline"));
+        let parsed: toml::Value = toml::from_str(&top).unwrap();
+        assert_eq!(parsed["dotfiles"].as_array().unwrap().len(), 2);
+        assert_eq!(parsed["dotfiles"][1]["target"].as_str(), Some("~/.zshrc"));
+
+        let hosted = append_dotfile_entry(
+            config,
+            "dotfiles/gitconfig",
+            "~/.gitconfig",
+            true,
+            Some("work"),
+        )
+        .unwrap();
+        let parsed: toml::Value = toml::from_str(&hosted).unwrap();
+        assert_eq!(
+            parsed["hosts"]["work"]["dotfiles"][0]["template"].as_bool(),
+            Some(true)
+        );
+
+        assert!(append_dotfile_entry(config, "a", "b", false, Some("missing")).is_err());
+    }
+}
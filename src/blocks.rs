@@ -0,0 +1,316 @@
+//! Managed blocks inside files mimic doesn't fully own.
+//!
+//! A block is rendered like a template and kept between two marker lines:
+//!
+//! ```text
+//! # BEGIN mimic: path
+//! export PATH="$HOME/.local/bin:$PATH"
+//! # END mimic: path
+//! ```
+//!
+//! Everything outside the markers belongs to the user or to other tools and is
+//! never touched, so removing a block leaves the rest of the file as it was.
+
+use crate::config::{ManagedBlock, Variables};
+use crate::expand::expand_path_str;
+use crate::state::{BlockState, State};
+use crate::template::{HostContext, render_template_with_host};
+use anyhow::Context;
+use std::fs;
+use std::ops::Range;
+use std::path::Path;
+
+pub fn begin_marker(comment: &str, name: &str) -> String {
+    format!("{} BEGIN mimic: {}", comment, name)
+}
+
+pub fn end_marker(comment: &str, name: &str) -> String {
+    format!("{} END mimic: {}", comment, name)
+}
+
+/// Byte range of a block, from the start of its BEGIN line through the end of
+/// its END line (including the newline, if any).
+pub fn find(text: &str, comment: &str, name: &str) -> anyhow::Result<Option<Range<usize>>> {
+    let begin = begin_marker(comment, name);
+    let end = end_marker(comment, name);
+
+    let mut start = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        match start {
+            None if trimmed == begin => start = Some(offset),
+            Some(start) if trimmed == end => return Ok(Some(start..offset + line.len())),
+            _ => {}
+        }
+        offset += line.len();
+    }
+
+    match start {
+        Some(_) => Err(anyhow::anyhow!(
+            "Block '{}' has no end marker\n\nTo fix:\n  - Add a '{}' line after the block, or remove the '{}' line",
+            name,
+            end,
+            begin
+        )),
+        None => Ok(None),
+    }
+}
+
+/// The content between a block's markers.
+pub fn body<'a>(text: &'a str, range: &Range<usize>) -> &'a str {
+    let block = &text[range.clone()];
+    let after_begin = block.find('\n').map_or(block.len(), |i| i + 1);
+    let before_end = block
+        .trim_end_matches(['\n', '\r'])
+        .rfind('\n')
+        .map_or(after_begin, |i| i + 1);
+    &block[after_begin..before_end.max(after_begin)]
+}
+
+/// Render a block's content, always ending in a newline.
+pub fn render(
+    block: &ManagedBlock,
+    variables: &Variables,
+    host_context: &HostContext,
+) -> anyhow::Result<String> {
+    let template = match (&block.content, &block.source) {
+        (Some(content), None) => content.clone(),
+        (None, Some(source)) => {
+            let path = expand_path_str(source)?;
+            fs::read_to_string(&path).with_context(|| {
+                format!(
+                    "Failed to read block source for '{}': {}",
+                    block.name,
+                    path.display()
+                )
+            })?
+        }
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Block '{}' must set exactly one of `content` or `source`",
+                block.name
+            ));
+        }
+    };
+
+    let mut rendered = render_template_with_host(&template, variables, host_context)
+        .with_context(|| format!("Failed to render block '{}'", block.name))?;
+    if !rendered.is_empty() && !rendered.ends_with('\n') {
+        rendered.push('\n');
+    }
+    Ok(rendered)
+}
+
+/// Insert or replace a block. Returns the new text and whether a newline had
+/// to be added to the end of the file before appending.
+pub fn upsert(
+    text: &str,
+    comment: &str,
+    name: &str,
+    content: &str,
+) -> anyhow::Result<(String, bool)> {
+    let block = format!(
+        "{}\n{}{}\n",
+        begin_marker(comment, name),
+        content,
+        end_marker(comment, name)
+    );
+
+    if let Some(range) = find(text, comment, name)? {
+        let mut updated = text.to_string();
+        updated.replace_range(range, &block);
+        return Ok((updated, false));
+    }
+
+    let mut updated = text.to_string();
+    let added_newline = !updated.is_empty() && !updated.ends_with('\n');
+    if added_newline {
+        updated.push('\n');
+    }
+    updated.push_str(&block);
+    Ok((updated, added_newline))
+}
+
+/// Remove a block, or return `None` if it isn't in the text.
+pub fn remove(text: &str, block: &BlockState) -> anyhow::Result<Option<String>> {
+    let Some(range) = find(text, &block.comment, &block.name)? else {
+        return Ok(None);
+    };
+
+    let at_end = range.end == text.len();
+    let mut updated = text.to_string();
+    updated.replace_range(range, "");
+    if block.added_newline && at_end && updated.ends_with('\n') {
+        updated.pop();
+    }
+    Ok(Some(updated))
+}
+
+/// Write a block into its target file and record it in state.
+pub fn apply_block(
+    block: &ManagedBlock,
+    variables: &Variables,
+    host_context: &HostContext,
+    state: &mut State,
+) -> anyhow::Result<()> {
+    let target = expand_path_str(&block.target)?;
+    let content = render(block, variables, host_context)?;
+
+    let existed = target.exists();
+    let current = if existed {
+        fs::read_to_string(&target)
+            .with_context(|| format!("Failed to read: {}", target.display()))?
+    } else {
+        String::new()
+    };
+
+    let (updated, added_newline) = upsert(&current, &block.comment, &block.name, &content)?;
+    if updated != current {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        fs::write(&target, updated)
+            .with_context(|| format!("Failed to write: {}", target.display()))?;
+    }
+
+    let target = target.to_string_lossy().to_string();
+    let previous = state
+        .blocks
+        .iter()
+        .find(|b| b.target == target && b.name == block.name);
+
+    state.add_block(BlockState {
+        created_file: previous.map_or(!existed, |p| p.created_file),
+        added_newline: previous.is_some_and(|p| p.added_newline) || added_newline,
+        name: block.name.clone(),
+        target,
+        comment: block.comment.clone(),
+        content,
+    });
+
+    Ok(())
+}
+
+/// Write a block's recorded content back into its target file.
+pub fn write_block(block: &BlockState) -> anyhow::Result<()> {
+    let target = Path::new(&block.target);
+    let current = if target.exists() {
+        fs::read_to_string(target)
+            .with_context(|| format!("Failed to read: {}", target.display()))?
+    } else {
+        String::new()
+    };
+
+    let (updated, _) = upsert(&current, &block.comment, &block.name, &block.content)?;
+    if updated != current {
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        fs::write(target, updated)
+            .with_context(|| format!("Failed to write: {}", target.display()))?;
+    }
+    Ok(())
+}
+
+/// Remove a previously applied block from its target file. Returns whether a
+/// block was found and removed.
+pub fn remove_block(block: &BlockState) -> anyhow::Result<bool> {
+    let target = Path::new(&block.target);
+    if !target.exists() {
+        return Ok(false);
+    }
+
+    let current = fs::read_to_string(target)
+        .with_context(|| format!("Failed to read: {}", target.display()))?;
+    let Some(updated) = remove(&current, block)? else {
+        return Ok(false);
+    };
+
+    if block.created_file && updated.trim().is_empty() {
+        fs::remove_file(target)
+            .with_context(|| format!("Failed to remove: {}", target.display()))?;
+    } else {
+        fs::write(target, updated)
+            .with_context(|| format!("Failed to write: {}", target.display()))?;
+    }
+
+    Ok(true)
+}
+
+/// Why an applied block no longer matches what was written, or `None`.
+pub fn block_drift(block: &BlockState) -> Option<String> {
+    let Ok(current) = fs::read_to_string(&block.target) else {
+        return Some("file missing".to_string());
+    };
+
+    match find(&current, &block.comment, &block.name) {
+        Ok(Some(range)) if body(&current, &range) == block.content => None,
+        Ok(Some(_)) => Some("modified in place".to_string()),
+        Ok(None) => Some("block missing".to_string()),
+        Err(_) => Some("end marker missing".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_for(name: &str, added_newline: bool) -> BlockState {
+        BlockState {
+            name: name.to_string(),
+            target: String::new(),
+            comment: "#".to_string(),
+            content: String::new(),
+            created_file: false,
+            added_newline,
+        }
+    }
+
+    #[test]
+    fn test_upsert_appends_then_replaces() {
+        let original = "# distro defaults\nalias ll='ls -l'\n";
+
+        let (added, newline) = upsert(original, "#", "path", "export A=1\n").unwrap();
+        assert!(!newline);
+        assert_eq!(
+            added,
+            "# distro defaults\nalias ll='ls -l'\n# BEGIN mimic: path\nexport A=1\n# END mimic: path\n"
+        );
+
+        let edited = added.replace("alias ll='ls -l'", "alias ll='ls -la'") + "# appended later\n";
+        let (replaced, _) = upsert(&edited, "#", "path", "export A=2\n").unwrap();
+        assert!(replaced.contains("export A=2\n# END mimic: path\n# appended later\n"));
+        assert!(replaced.contains("alias ll='ls -la'"));
+        assert!(!replaced.contains("export A=1"));
+
+        let range = find(&replaced, "#", "path").unwrap().unwrap();
+        assert_eq!(body(&replaced, &range), "export A=2\n");
+    }
+
+    #[test]
+    fn test_remove_restores_original_exactly() {
+        let original = "first\nsecond";
+        let (added, newline) = upsert(original, "#", "env", "X=1\n").unwrap();
+        assert!(newline);
+
+        let removed = remove(&added, &state_for("env", newline)).unwrap().unwrap();
+        assert_eq!(removed, original);
+
+        let middle = "a\n# BEGIN mimic: env\nX=1\n# END mimic: env\nb\n";
+        assert_eq!(
+            remove(middle, &state_for("env", false)).unwrap().unwrap(),
+            "a\nb\n"
+        );
+        assert!(remove("a\n", &state_for("env", false)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_unterminated_block_is_an_error() {
+        let text = "# BEGIN mimic: env\nX=1\n";
+        assert!(find(text, "#", "env").is_err());
+        assert!(upsert(text, "#", "env", "X=2\n").is_err());
+    }
+}
@@ -0,0 +1,360 @@
+//! Converting between Homebrew Bundle `Brewfile`s and `[packages]`
+//! (`mimic brewfile import` and `mimic brewfile export`).
+//!
+//! A Brewfile is Ruby, so only the parts with a `[packages]` equivalent are
+//! read: `tap`, `brew` and `cask` lines, optionally under `if OS.mac?` /
+//! `if OS.linux?` (as a block or a trailing modifier), which become `when`
+//! conditions. Every other line is reported with its line number rather than
+//! dropped silently.
+
+use crate::adopt::config_section;
+use crate::config::{Package, Packages};
+use anyhow::Context;
+use serde::Serialize;
+use toml_edit::{Array, ArrayOfTables, DocumentMut, Item, Table, Value, value};
+
+/// A Brewfile line that was skipped or only partly imported
+#[derive(Debug, Clone, Serialize)]
+pub struct Note {
+    pub line: usize,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Brewfile {
+    /// In file order
+    pub packages: Vec<Package>,
+    pub notes: Vec<Note>,
+}
+
+/// An OS test from `if`/`unless`: the mimic `os` value and whether it must
+/// match. `None` is a condition mimic can't translate.
+type OsCondition = Option<(&'static str, bool)>;
+
+fn os_condition(condition: &str, negated: bool) -> OsCondition {
+    let os = match condition.trim() {
+        "OS.mac?" => "macos",
+        "OS.linux?" => "linux",
+        _ => return None,
+    };
+    Some((os, !negated))
+}
+
+/// `if OS.mac?` or `unless OS.linux?` at the start of a block or after an entry.
+fn parse_condition(text: &str) -> Option<OsCondition> {
+    if let Some(condition) = text.strip_prefix("if ") {
+        Some(os_condition(condition, false))
+    } else {
+        text.strip_prefix("unless ")
+            .map(|condition| os_condition(condition, true))
+    }
+}
+
+/// The line without a trailing `# comment`, ignoring `#` inside strings.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if c == q => quote = None,
+            (None, '#') => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// The leading quoted string and whatever follows it.
+fn quoted(text: &str) -> Option<(&str, &str)> {
+    let quote = text.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let end = text[1..].find(quote)? + 1;
+    Some((&text[1..end], text[end + 1..].trim()))
+}
+
+/// Read the `tap`, `brew` and `cask` entries of a Brewfile.
+pub fn parse(content: &str) -> Brewfile {
+    let mut brewfile = Brewfile::default();
+    let mut blocks: Vec<OsCondition> = Vec::new();
+
+    for (index, raw) in content.lines().enumerate() {
+        let line = index + 1;
+        let text = strip_comment(raw).trim();
+        let mut note = |message: String| brewfile.notes.push(Note { line, message });
+        if text.is_empty() {
+            continue;
+        }
+
+        if let Some(condition) = parse_condition(text) {
+            if condition.is_none() {
+                note(format!(
+                    "Condition '{}' has no mimic equivalent; the entries under it were skipped",
+                    text
+                ));
+            }
+            blocks.push(condition);
+            continue;
+        }
+        match text {
+            "else" => {
+                if let Some(Some((_, matches))) = blocks.last_mut() {
+                    *matches = !*matches;
+                }
+                continue;
+            }
+            "end" => {
+                blocks.pop();
+                continue;
+            }
+            _ => {}
+        }
+
+        let (keyword, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        if !matches!(keyword, "tap" | "brew" | "cask") {
+            note(match keyword {
+                "mas" | "vscode" | "whalebrew" | "go" | "cargo" | "uv" | "flatpak" | "npm" => {
+                    format!("'{}' entries have no mimic equivalent; skipped", keyword)
+                }
+                "cask_args" => "cask_args has no mimic equivalent; skipped".to_string(),
+                _ => format!("Not a Brewfile entry mimic understands: {}", text),
+            });
+            continue;
+        }
+        let Some((name, mut rest)) = quoted(rest.trim()) else {
+            note(format!("Could not read the name in: {}", text));
+            continue;
+        };
+
+        let mut conditions = blocks.clone();
+        if let Some(at) = rest.rfind(" if ").or_else(|| rest.rfind(" unless ")) {
+            conditions.push(parse_condition(rest[at..].trim()).flatten());
+            rest = rest[..at].trim();
+        } else if let Some(condition) = parse_condition(rest) {
+            conditions.push(condition);
+            rest = "";
+        }
+        let Some(conditions) = conditions.into_iter().collect::<Option<Vec<_>>>() else {
+            note(format!(
+                "{} '{}' depends on a condition mimic can't translate; skipped",
+                keyword, name
+            ));
+            continue;
+        };
+
+        if !rest.is_empty() {
+            note(if keyword == "tap" {
+                format!(
+                    "The URL for tap '{}' was dropped; mimic taps it from GitHub",
+                    name
+                )
+            } else {
+                format!(
+                    "Options for {} '{}' were dropped: {}",
+                    keyword,
+                    name,
+                    rest.trim_start_matches(',').trim()
+                )
+            });
+        }
+
+        let when: Vec<String> = conditions
+            .iter()
+            .map(|(os, matches)| format!("os {} '{}'", if *matches { "==" } else { "!=" }, os))
+            .collect();
+        brewfile.packages.push(Package {
+            name: name.to_string(),
+            pkg_type: match keyword {
+                "brew" => "formula",
+                other => other,
+            }
+            .to_string(),
+            only_roles: None,
+            skip_roles: None,
+            when: (!when.is_empty()).then(|| when.join(" && ")),
+            enabled: true,
+        });
+    }
+    brewfile
+}
+
+/// Add packages to the `[packages]` table of the config text, or to
+/// `[hosts.<host>.packages]` when a host is given. Unconditional packages
+/// go into the `tap`, `brew` and `cask` lists and conditional ones into
+/// `[[packages.homebrew]]`; packages the section already lists are left
+/// out. Returns the new text and the packages that were added.
+pub fn add_packages(
+    config_text: &str,
+    packages: &[Package],
+    host: Option<&str>,
+) -> anyhow::Result<(String, Vec<Package>)> {
+    let mut doc = config_text
+        .parse::<DocumentMut>()
+        .context("Failed to parse config for editing")?;
+    let section = config_section(&mut doc, host)?;
+    let table = section
+        .entry("packages")
+        .or_insert_with(|| Item::Table(Table::new()))
+        .as_table_mut()
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Cannot add to 'packages': it is not written as a [packages] table\n\nTo fix:\n  - Rewrite it as a [packages] table\n  - Or add the packages to mimic.toml by hand"
+            )
+        })?;
+
+    let mut listed: Vec<String> = ["tap", "brew", "cask"]
+        .iter()
+        .filter_map(|list| table.get(list).and_then(Item::as_array))
+        .flatten()
+        .filter_map(|name| name.as_str().map(String::from))
+        .collect();
+    if let Some(homebrew) = table.get("homebrew").and_then(Item::as_array_of_tables) {
+        listed.extend(
+            homebrew
+                .iter()
+                .filter_map(|entry| entry.get("name").and_then(Item::as_str).map(String::from)),
+        );
+    }
+
+    let mut added = Vec::new();
+    for package in packages {
+        if listed.contains(&package.name) {
+            continue;
+        }
+        listed.push(package.name.clone());
+
+        match &package.when {
+            None => {
+                let list = match package.pkg_type.as_str() {
+                    "formula" => "brew",
+                    other => other,
+                };
+                push_name(table, list, &package.name)?;
+            }
+            Some(when) => {
+                let mut entry = Table::new();
+                entry["name"] = value(&package.name);
+                entry["type"] = value(&package.pkg_type);
+                entry["when"] = value(when);
+                match table
+                    .entry("homebrew")
+                    .or_insert_with(|| Item::ArrayOfTables(ArrayOfTables::new()))
+                {
+                    Item::ArrayOfTables(homebrew) => homebrew.push(entry),
+                    _ => {
+                        return Err(anyhow::anyhow!(
+                            "Cannot append to 'packages.homebrew': it is not written as [[packages.homebrew]] tables\n\nTo fix:\n  - Rewrite the existing entries as [[packages.homebrew]] tables\n  - Or add the packages to mimic.toml by hand"
+                        ));
+                    }
+                }
+            }
+        }
+        added.push(package.clone());
+    }
+
+    Ok((doc.to_string(), added))
+}
+
+/// Append a name to one of the simple lists, one name per line for lists
+/// mimic creates and in the existing style otherwise.
+fn push_name(table: &mut Table, list: &str, name: &str) -> anyhow::Result<()> {
+    let item = table.entry(list).or_insert_with(|| {
+        let mut array = Array::new();
+        array.set_trailing("\n");
+        array.set_trailing_comma(true);
+        value(array)
+    });
+    let array = item.as_array_mut().ok_or_else(|| {
+        anyhow::anyhow!(
+            "Cannot append to 'packages.{}': it is not a list\n\nTo fix:\n  - Write it as {} = [\"name\", ...]",
+            list,
+            list
+        )
+    })?;
+
+    let prefix = match array.iter().last() {
+        Some(last) => last.decor().prefix().cloned(),
+        None => Some("\n    ".into()),
+    };
+    let mut entry = Value::from(name);
+    if let Some(prefix) = prefix {
+        entry.decor_mut().set_prefix(prefix);
+    }
+    array.push_formatted(entry);
+    Ok(())
+}
+
+/// The Homebrew packages as a Brewfile: taps, then formulae, then casks.
+/// zerobrew packages are Homebrew formulae too, so they are written as
+/// `brew` lines.
+pub fn render(packages: &Packages, header: &str) -> String {
+    let normalized = packages.normalized();
+    let of_type = |pkg_type: &str| -> Vec<&str> {
+        normalized
+            .homebrew
+            .iter()
+            .filter(|package| package.pkg_type == pkg_type)
+            .map(|package| package.name.as_str())
+            .collect()
+    };
+
+    let mut formulae = of_type("formula");
+    for package in &normalized.zerobrew {
+        if !formulae.contains(&package.name.as_str()) {
+            formulae.push(&package.name);
+        }
+    }
+
+    let mut out = format!("# {}\n", header);
+    for (keyword, names) in [
+        ("tap", of_type("tap")),
+        ("brew", formulae),
+        ("cask", of_type("cask")),
+    ] {
+        if names.is_empty() {
+            continue;
+        }
+        out.push('\n');
+        for name in names {
+            out.push_str(&format!("{} \"{}\"\n", keyword, name));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_conditions() {
+        let brewfile = parse(
+            r#"tap "homebrew/cask-fonts" # fonts
+brew "git"
+brew "gnu-sed" unless OS.mac?
+if OS.mac?
+  cask "iterm2"
+else
+  brew "xclip", args: ["with-x"]
+end
+mas "Xcode", id: 497799835
+brew "nvim" if ENV["EDITOR"] == "nvim"
+"#,
+        );
+        let packages: Vec<_> = brewfile
+            .packages
+            .iter()
+            .map(|p| (p.name.as_str(), p.pkg_type.as_str(), p.when.as_deref()))
+            .collect();
+        assert_eq!(
+            packages,
+            vec![
+                ("homebrew/cask-fonts", "tap", None),
+                ("git", "formula", None),
+                ("gnu-sed", "formula", Some("os != 'macos'")),
+                ("iterm2", "cask", Some("os == 'macos'")),
+                ("xclip", "formula", Some("os != 'macos'")),
+            ]
+        );
+        let lines: Vec<usize> = brewfile.notes.iter().map(|note| note.line).collect();
+        assert_eq!(lines, vec![7, 9, 10]);
+    }
+}
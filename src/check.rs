@@ -0,0 +1,1169 @@
+//! `mimic check`: find mistakes in a config without applying it.
+//!
+//! Serde ignores keys it doesn't know, so a misspelled `only_role` is silently
+//! dropped when a config loads. The checker reads each local config file a
+//! second time as a TOML document, where every key still knows its line, and
+//! compares it to the keys in the generated schema (`mimic schema`). It then
+//! loads the merged config and looks for problems that only show up once
+//! everything is put together: missing sources, targets deployed twice, roles
+//! no host has, template variables no host defines and `inherits` pointing
+//! nowhere.
+
+use crate::config::{Config, Variables, should_apply_for_roles};
+use crate::expand::expand_path_str;
+use crate::vars;
+use anyhow::Context;
+use handlebars::template::{HelperTemplate, Parameter, Template, TemplateElement};
+use handlebars::{Path as TemplatePath, PathSeg};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+use toml_edit::{Document, Item, TableLike};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Wrong: the config doesn't do what it says
+    Error,
+    /// Probably unintended, but harmless
+    Warning,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub severity: Severity,
+    pub file: PathBuf,
+    /// 1-based; `None` when the problem has no single place, such as an
+    /// extends repo that failed to load
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+/// The generated `mimic.toml` schema, which names every key mimic reads.
+static SCHEMA: LazyLock<Value> = LazyLock::new(crate::schema::config_schema);
+
+/// Follow a `$ref`, or an `allOf` wrapping one, to the definition it names.
+fn resolve(schema: &'static Value) -> &'static Value {
+    let schema = schema.pointer("/allOf/0").unwrap_or(schema);
+    schema
+        .get("$ref")
+        .and_then(Value::as_str)
+        .and_then(|reference| reference.strip_prefix('#'))
+        .and_then(|pointer| SCHEMA.pointer(pointer))
+        .unwrap_or(schema)
+}
+
+/// Check a config and everything it includes. Fails only if the root config
+/// can't be read; every other problem is a finding, sorted by file and line.
+pub fn check(config_path: &Path) -> anyhow::Result<Vec<Finding>> {
+    let mut checker = Checker::default();
+    let files = checker.read_files(config_path)?;
+    let entries: Vec<Entry> = files.iter().flat_map(collect_entries).collect();
+    // Sources only need the files, so they're checked even if the merged
+    // config can't be built
+    checker.check_sources(&entries);
+
+    // Problems that stop the config from loading are already reported, and
+    // loading it would only repeat the first of them
+    if !checker.unloadable {
+        match Config::from_file(config_path) {
+            Ok(config) => checker.check_merged(&config, &files, &entries),
+            Err(e) => checker.error(config_path, None, first_line(&e)),
+        }
+    }
+
+    let mut findings = checker.findings;
+    findings.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+    Ok(findings)
+}
+
+/// A local config file: the root config or one of its includes.
+struct ConfigFile {
+    path: PathBuf,
+    content: String,
+    doc: Document<String>,
+    /// `None` if it didn't deserialize
+    config: Option<Config>,
+}
+
+/// A role named in `only_roles`/`skip_roles` or by a `[roles.<name>]` section.
+struct RoleRef {
+    role: String,
+    /// `only_roles`, `skip_roles` or `roles` for a section
+    key: &'static str,
+    file: PathBuf,
+    line: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Dotfile,
+    Block,
+    Merge,
+}
+
+impl Kind {
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Dotfile => "dotfile",
+            Kind::Block => "block",
+            Kind::Merge => "merge",
+        }
+    }
+}
+
+/// Which hosts an entry is declared for.
+enum Scope {
+    Base,
+    Host(String),
+    Role(String),
+}
+
+/// A template to check, and where to report problems in it.
+struct TemplateText {
+    file: PathBuf,
+    /// For inline block content: the line of the `[[blocks]]` entry, used
+    /// for every problem instead of lines inside the content
+    inline_at: Option<usize>,
+    text: String,
+}
+
+/// A dotfile, block or merge with the place it was declared.
+struct Entry {
+    kind: Kind,
+    scope: Scope,
+    file: PathBuf,
+    line: usize,
+    target: String,
+    /// The source as written, and where it resolves to
+    source: Option<(String, PathBuf)>,
+    template: Option<TemplateText>,
+    only_roles: Option<Vec<String>>,
+    skip_roles: Option<Vec<String>>,
+    /// Has a `when` condition, so it may never apply together with another
+    conditional: bool,
+}
+
+/// A host the merged config can be applied as; a config without hosts has
+/// a single unnamed one.
+struct HostView {
+    name: Option<String>,
+    chain: Vec<String>,
+    roles: Vec<String>,
+    variables: Variables,
+    /// Target and source of every dotfile and merge left after `exclude_*`
+    /// lists and `enabled = false` entries are applied
+    deployed: Vec<(PathBuf, PathBuf)>,
+}
+
+impl HostView {
+    fn includes(&self, entry: &Entry) -> bool {
+        let in_scope = match &entry.scope {
+            Scope::Base => true,
+            Scope::Host(host) => self.chain.contains(host),
+            Scope::Role(role) => self.roles.contains(role),
+        };
+        let removed = entry.kind != Kind::Block
+            && entry.source.as_ref().is_some_and(|(_, source)| {
+                !self
+                    .deployed
+                    .contains(&(expand(&entry.target), source.clone()))
+            });
+        in_scope
+            && !removed
+            && should_apply_for_roles(&entry.only_roles, &entry.skip_roles, &self.roles)
+    }
+}
+
+#[derive(Default)]
+struct Checker {
+    findings: Vec<Finding>,
+    role_refs: Vec<RoleRef>,
+    /// A problem was found that makes `Config::from_file` fail
+    unloadable: bool,
+}
+
+impl Checker {
+    fn error(&mut self, file: &Path, line: Option<usize>, message: String) {
+        self.push(Severity::Error, file, line, message);
+    }
+
+    fn warning(&mut self, file: &Path, line: Option<usize>, message: String) {
+        self.push(Severity::Warning, file, line, message);
+    }
+
+    fn push(&mut self, severity: Severity, file: &Path, line: Option<usize>, message: String) {
+        self.findings.push(Finding {
+            severity,
+            file: file.to_path_buf(),
+            line,
+            message,
+        });
+    }
+
+    /// An error that stops the config from loading. Serde's report of a line
+    /// that already has an error would only repeat it.
+    fn fatal(&mut self, file: &Path, line: Option<usize>, message: String) {
+        self.unloadable = true;
+        let repeated = line.is_some()
+            && self
+                .findings
+                .iter()
+                .any(|f| f.severity == Severity::Error && f.file == file && f.line == line);
+        if !repeated {
+            self.error(file, line, message);
+        }
+    }
+
+    /// Read, parse and lint the root config and every file it includes.
+    fn read_files(&mut self, root: &Path) -> anyhow::Result<Vec<ConfigFile>> {
+        let mut files = Vec::new();
+        let mut seen = HashSet::new();
+        let mut pending = vec![root.to_path_buf()];
+
+        while let Some(path) = pending.pop() {
+            if !seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())) {
+                continue;
+            }
+            let content = match fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) if path == root => {
+                    return Err(e).with_context(|| {
+                        format!(
+                            "Failed to read config file: {}\n\nTo fix:\n  - Check that the file exists\n  - Verify you have read permissions\n  - Ensure the path is correct",
+                            path.display()
+                        )
+                    });
+                }
+                Err(e) => {
+                    self.fatal(&path, None, format!("Failed to read config file: {}", e));
+                    continue;
+                }
+            };
+            let doc = match Document::parse(content.clone()) {
+                Ok(doc) => doc,
+                Err(e) => {
+                    let line = line_of(&content, e.span());
+                    self.fatal(&path, line, format!("Invalid TOML: {}", e.message().trim()));
+                    continue;
+                }
+            };
+
+            self.walk_table(&path, &content, doc.as_table(), &SCHEMA, "", 1);
+            let config = match toml::from_str::<Config>(&content) {
+                Ok(config) => Some(config),
+                Err(e) => {
+                    let line = line_of(&content, e.span());
+                    self.fatal(&path, line, e.message().trim().to_string());
+                    None
+                }
+            };
+
+            if let Some(patterns) = doc.as_table().get("include").and_then(Item::as_array) {
+                for pattern in patterns.iter() {
+                    let Some(text) = pattern.as_str() else {
+                        continue;
+                    };
+                    match Config::resolve_includes(&path, &content, &[text.to_string()]) {
+                        Ok(included) => pending.extend(included.into_iter().map(|(p, _)| p)),
+                        Err(e) => {
+                            let line = line_of(&content, pattern.span());
+                            self.fatal(&path, line, first_line(&e));
+                        }
+                    }
+                }
+            }
+
+            files.push(ConfigFile {
+                path,
+                content,
+                doc,
+                config,
+            });
+        }
+        Ok(files)
+    }
+
+    /// Report keys the schema doesn't list and recurse into known ones.
+    fn walk_table(
+        &mut self,
+        file: &Path,
+        content: &str,
+        table: &dyn TableLike,
+        schema: &'static Value,
+        section: &str,
+        line: usize,
+    ) {
+        let schema = if schema.get("oneOf").is_some() {
+            match self.hook_variant(file, content, table, schema, line) {
+                Some(variant) => variant,
+                None => return,
+            }
+        } else {
+            schema
+        };
+        let Some(properties) = schema.get("properties").and_then(Value::as_object) else {
+            return;
+        };
+
+        for (key, item) in table.iter() {
+            let key_line = key_line(content, table, key).unwrap_or(line);
+            let Some(field) = properties.get(key) else {
+                let names = properties.keys().map(String::as_str);
+                let location = if section.is_empty() {
+                    String::new()
+                } else {
+                    format!(" in {}", section)
+                };
+                self.error(
+                    file,
+                    Some(key_line),
+                    format!(
+                        "Unknown key '{}'{}{}",
+                        key,
+                        location,
+                        did_you_mean(key, names)
+                    ),
+                );
+                continue;
+            };
+
+            match key {
+                "only_roles" => self.collect_roles(file, content, item, "only_roles", key_line),
+                "skip_roles" => self.collect_roles(file, content, item, "skip_roles", key_line),
+                "when" => {
+                    if let Some(when) = item.as_str()
+                        && let Err(e) = crate::condition::validate(when)
+                    {
+                        self.fatal(
+                            file,
+                            Some(key_line),
+                            format!("Invalid `when` condition: {}", first_line(&e)),
+                        );
+                    }
+                }
+                _ => {}
+            }
+
+            let path = match section.trim_matches(['[', ']']) {
+                "" => key.to_string(),
+                parent => format!("{}.{}", parent, key),
+            };
+            self.walk_item(file, content, &path, item, field, key_line);
+        }
+    }
+
+    /// Remember the roles an `only_roles` or `skip_roles` list names, to
+    /// check once every file is read.
+    fn collect_roles(
+        &mut self,
+        file: &Path,
+        content: &str,
+        item: &Item,
+        key: &'static str,
+        key_line: usize,
+    ) {
+        for value in item.as_array().into_iter().flatten() {
+            if let Some(role) = value.as_str() {
+                self.role_refs.push(RoleRef {
+                    role: role.to_string(),
+                    key,
+                    file: file.to_path_buf(),
+                    line: line_of(content, value.span()).unwrap_or(key_line),
+                });
+            }
+        }
+    }
+
+    fn walk_item(
+        &mut self,
+        file: &Path,
+        content: &str,
+        path: &str,
+        item: &Item,
+        schema: &'static Value,
+        line: usize,
+    ) {
+        let schema = resolve(schema);
+        if let Some(items) = schema.get("items") {
+            // Arrays of plain values have no table entries
+            let section = format!("[[{}]]", path);
+            for (entry, entry_line) in table_entries(content, item) {
+                let entry_line = entry_line.unwrap_or(line);
+                self.walk_table(file, content, entry, resolve(items), &section, entry_line);
+            }
+        } else if schema.get("properties").is_some() || schema.get("oneOf").is_some() {
+            if let Some(table) = item.as_table_like() {
+                let section = format!("[{}]", path);
+                self.walk_table(file, content, table, schema, &section, line);
+            }
+        } else if let Some(inner) = schema.get("additionalProperties").filter(|s| s.is_object()) {
+            // A table of user-chosen names, such as `[hosts.<name>]`
+            let Some(table) = item.as_table_like() else {
+                return;
+            };
+            for (name, child) in table.iter() {
+                let child_line = key_line(content, table, name).unwrap_or(line);
+                if path == "roles" {
+                    self.role_refs.push(RoleRef {
+                        role: name.to_string(),
+                        key: "roles",
+                        file: file.to_path_buf(),
+                        line: child_line,
+                    });
+                }
+                let child_path = format!("{}.{}", path, name);
+                self.walk_item(file, content, &child_path, child, inner, child_line);
+            }
+        }
+    }
+
+    /// The schema for a hook's `type`, whose keys depend on it.
+    fn hook_variant(
+        &mut self,
+        file: &Path,
+        content: &str,
+        hook: &dyn TableLike,
+        schema: &'static Value,
+        line: usize,
+    ) -> Option<&'static Value> {
+        // A missing `type` is reported when the file is deserialized
+        let kind = hook.get("type").and_then(Item::as_str)?;
+        let variants = schema["oneOf"].as_array()?;
+        let type_of = |variant: &'static Value| variant.pointer("/properties/type/const");
+        let Some(variant) = variants.iter().find(|v| type_of(v) == Some(&json!(kind))) else {
+            let names = variants.iter().filter_map(|v| type_of(v)?.as_str());
+            self.fatal(
+                file,
+                key_line(content, hook, "type").or(Some(line)),
+                format!("Unknown hook type '{}'{}", kind, did_you_mean(kind, names)),
+            );
+            return None;
+        };
+
+        if kind == "command"
+            && let Some(command) = hook.get("command").and_then(Item::as_str)
+            && command.trim().is_empty()
+        {
+            let name = hook.get("name").and_then(Item::as_str).unwrap_or("command");
+            self.error(
+                file,
+                key_line(content, hook, "command").or(Some(line)),
+                format!("Hook '{}' has an empty command", name),
+            );
+        }
+        Some(variant)
+    }
+
+    /// Checks that need the whole config: hosts, roles, targets and
+    /// templates. Each runs regardless of what the others found.
+    fn check_merged(&mut self, config: &Config, files: &[ConfigFile], entries: &[Entry]) {
+        let views = host_views(config);
+
+        self.check_inherits(config, files);
+        self.check_roles(config);
+        self.check_targets(entries, &views);
+        self.check_templates(entries, &views);
+    }
+
+    fn check_inherits(&mut self, config: &Config, files: &[ConfigFile]) {
+        let host_names = config.host_names();
+        for file in files {
+            let Some(local) = &file.config else {
+                continue;
+            };
+            let hosts_table = file
+                .doc
+                .as_table()
+                .get("hosts")
+                .and_then(Item::as_table_like);
+            let mut names: Vec<&String> = local.hosts.keys().collect();
+            names.sort();
+            for name in names {
+                let Some(parent) = &local.hosts[name].inherits else {
+                    continue;
+                };
+                let host_table = hosts_table.and_then(|t| t.get(name)?.as_table_like());
+                let line = host_table
+                    .and_then(|t| key_line(&file.content, t, "inherits"))
+                    .or_else(|| hosts_table.and_then(|t| key_line(&file.content, t, name)));
+
+                if !config.hosts.contains_key(parent) {
+                    let candidates = host_names.iter().map(String::as_str);
+                    self.error(
+                        &file.path,
+                        line,
+                        format!(
+                            "Host '{}' inherits from unknown host '{}'{}",
+                            name,
+                            parent,
+                            did_you_mean(parent, candidates)
+                        ),
+                    );
+                } else if let Some(cycle) = inherits_cycle(config, name) {
+                    self.error(
+                        &file.path,
+                        line,
+                        format!("Host '{}' inherits in a cycle: {}", name, cycle.join(" → ")),
+                    );
+                }
+            }
+        }
+    }
+
+    fn check_roles(&mut self, config: &Config) {
+        let used: BTreeSet<String> = config
+            .host_names()
+            .iter()
+            .filter_map(|host| config.resolved_host(host).ok())
+            .flat_map(|host| host.roles)
+            .collect();
+
+        for role_ref in std::mem::take(&mut self.role_refs) {
+            if used.contains(&role_ref.role) {
+                continue;
+            }
+            let subject = match role_ref.key {
+                "roles" => format!("Role '{}'", role_ref.role),
+                key => format!("Role '{}' in {}", role_ref.role, key),
+            };
+            let candidates = used.iter().map(String::as_str);
+            self.warning(
+                &role_ref.file,
+                Some(role_ref.line),
+                format!(
+                    "{} is not given to any host{}",
+                    subject,
+                    did_you_mean(&role_ref.role, candidates)
+                ),
+            );
+        }
+    }
+
+    fn check_sources(&mut self, entries: &[Entry]) {
+        for entry in entries {
+            if let Some((written, resolved)) = &entry.source
+                && !resolved.exists()
+            {
+                let kind = entry.kind.label();
+                self.error(
+                    &entry.file,
+                    Some(entry.line),
+                    format!(
+                        "{}{} source not found: {}",
+                        kind[..1].to_uppercase(),
+                        &kind[1..],
+                        written
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Report targets two entries deploy on the same host, and targets
+    /// inside a directory another dotfile deploys. Blocks share files by
+    /// design, several merges can feed one file, and entries with `when`
+    /// may be meant to exclude each other, so those are left out.
+    fn check_targets(&mut self, entries: &[Entry], views: &[HostView]) {
+        let targets: Vec<Option<PathBuf>> = entries
+            .iter()
+            .map(|entry| {
+                (entry.kind != Kind::Block && !entry.conditional)
+                    .then(|| expand_path_str(&entry.target).ok())
+                    .flatten()
+            })
+            .collect();
+
+        let mut reported = HashSet::new();
+        for view in views {
+            let active: Vec<usize> = (0..entries.len())
+                .filter(|&i| targets[i].is_some() && view.includes(&entries[i]))
+                .collect();
+            for (position, &a) in active.iter().enumerate() {
+                for &b in &active[position + 1..] {
+                    if !reported.insert((a, b)) {
+                        continue;
+                    }
+                    let (first, second) = (&entries[a], &entries[b]);
+                    let (first_target, second_target) =
+                        (targets[a].as_ref().unwrap(), targets[b].as_ref().unwrap());
+
+                    if first_target == second_target {
+                        if first.kind == Kind::Merge && second.kind == Kind::Merge {
+                            continue;
+                        }
+                        self.error(
+                            &second.file,
+                            Some(second.line),
+                            format!(
+                                "Target {} is also deployed by the {} at {}:{}",
+                                second.target,
+                                first.kind.label(),
+                                first.file.display(),
+                                first.line
+                            ),
+                        );
+                    } else if first.kind == Kind::Dotfile && second_target.starts_with(first_target)
+                    {
+                        self.overlap(second, first);
+                    } else if second.kind == Kind::Dotfile
+                        && first_target.starts_with(second_target)
+                    {
+                        self.overlap(first, second);
+                    }
+                }
+            }
+        }
+    }
+
+    fn overlap(&mut self, inner: &Entry, outer: &Entry) {
+        self.warning(
+            &inner.file,
+            Some(inner.line),
+            format!(
+                "Target {} is inside {}, which the dotfile at {}:{} deploys",
+                inner.target,
+                outer.target,
+                outer.file.display(),
+                outer.line
+            ),
+        );
+    }
+
+    /// Parse every template and report `variables.*` paths that some host
+    /// the template applies to doesn't define.
+    fn check_templates(&mut self, entries: &[Entry], views: &[HostView]) {
+        for entry in entries {
+            let Some(template) = &entry.template else {
+                continue;
+            };
+            let compiled = match Template::compile(&template.text) {
+                Ok(compiled) => compiled,
+                Err(e) => {
+                    let line = template.inline_at.or(e.pos().map(|(line, _)| line));
+                    self.error(
+                        &template.file,
+                        line,
+                        format!("Invalid template: {}", e.reason()),
+                    );
+                    continue;
+                }
+            };
+
+            let mut references = Vec::new();
+            collect_variables(&compiled, &[], &mut references);
+            let mut seen = HashSet::new();
+            for (path, line) in references {
+                if !seen.insert(path.clone()) {
+                    continue;
+                }
+                let missing: Vec<&HostView> = views
+                    .iter()
+                    .filter(|view| view.includes(entry) && !is_defined(&view.variables, &path))
+                    .collect();
+                if missing.is_empty() {
+                    continue;
+                }
+                let hosts: Vec<String> = missing
+                    .iter()
+                    .filter_map(|view| view.name.as_ref())
+                    .map(|name| format!("'{}'", name))
+                    .collect();
+                let hosts = match hosts.len() {
+                    0 => String::new(),
+                    1 => format!(" for host {}", hosts[0]),
+                    _ => format!(" for hosts {}", hosts.join(", ")),
+                };
+                self.error(
+                    &template.file,
+                    Some(template.inline_at.unwrap_or(line)),
+                    format!(
+                        "Template variable 'variables.{}' is not defined{}",
+                        path.join("."),
+                        hosts
+                    ),
+                );
+            }
+        }
+    }
+}
+
+/// The dotfiles, blocks and merges a file declares, at every level.
+fn collect_entries(file: &ConfigFile) -> Vec<Entry> {
+    let Some(config) = &file.config else {
+        return Vec::new();
+    };
+    let dir = file
+        .path
+        .parent()
+        .and_then(|dir| dir.canonicalize().ok())
+        .or_else(|| std::env::current_dir().ok())
+        .unwrap_or_default();
+    let root = file.doc.as_table();
+
+    let named = |section: &str, name: &str| {
+        root.get(section)
+            .and_then(Item::as_table_like)
+            .and_then(|t| t.get(name))
+            .and_then(Item::as_table_like)
+    };
+    let mut layers = vec![(
+        Scope::Base,
+        Some(root as &dyn TableLike),
+        &config.dotfiles,
+        &config.blocks,
+        &config.merges,
+    )];
+    for (name, host) in &config.hosts {
+        layers.push((
+            Scope::Host(name.clone()),
+            named("hosts", name),
+            &host.dotfiles,
+            &host.blocks,
+            &host.merges,
+        ));
+    }
+    for (name, role) in &config.roles {
+        layers.push((
+            Scope::Role(name.clone()),
+            named("roles", name),
+            &role.dotfiles,
+            &role.blocks,
+            &role.merges,
+        ));
+    }
+
+    let mut entries = Vec::new();
+    for (scope, table, dotfiles, blocks, merges) in layers {
+        let lines = |key: &str| -> Vec<Option<usize>> {
+            table
+                .and_then(|t| t.get(key))
+                .map(|item| {
+                    table_entries(&file.content, item)
+                        .into_iter()
+                        .map(|(_, line)| line)
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let line_at = |lines: &[Option<usize>], index: usize| {
+            lines.get(index).copied().flatten().unwrap_or(1)
+        };
+        let scoped = |scope: &Scope| match scope {
+            Scope::Base => Scope::Base,
+            Scope::Host(name) => Scope::Host(name.clone()),
+            Scope::Role(name) => Scope::Role(name.clone()),
+        };
+
+        let dotfile_lines = lines("dotfiles");
+        for (index, dotfile) in dotfiles.iter().enumerate() {
+            if !dotfile.enabled {
+                continue;
+            }
+            let source = resolve_source(&dotfile.source, &dir);
+            let template = (dotfile.is_template() && !dotfile.is_encrypted())
+                .then(|| fs::read_to_string(&source).ok())
+                .flatten()
+                .map(|text| TemplateText {
+                    file: source.clone(),
+                    inline_at: None,
+                    text,
+                });
+            entries.push(Entry {
+                kind: Kind::Dotfile,
+                scope: scoped(&scope),
+                file: file.path.clone(),
+                line: line_at(&dotfile_lines, index),
+                target: dotfile.target.clone(),
+                source: Some((dotfile.source.clone(), source)),
+                template,
+                only_roles: dotfile.only_roles.clone(),
+                skip_roles: dotfile.skip_roles.clone(),
+                conditional: dotfile.when.is_some(),
+            });
+        }
+
+        let block_lines = lines("blocks");
+        for (index, block) in blocks.iter().enumerate() {
+            if !block.enabled {
+                continue;
+            }
+            let line = line_at(&block_lines, index);
+            let source = block
+                .source
+                .as_ref()
+                .map(|written| (written.clone(), resolve_source(written, &dir)));
+            let template = match (&block.content, &source) {
+                (Some(content), _) => Some(TemplateText {
+                    file: file.path.clone(),
+                    inline_at: Some(line),
+                    text: content.clone(),
+                }),
+                (None, Some((_, path))) => fs::read_to_string(path).ok().map(|text| TemplateText {
+                    file: path.clone(),
+                    inline_at: None,
+                    text,
+                }),
+                (None, None) => None,
+            };
+            entries.push(Entry {
+                kind: Kind::Block,
+                scope: scoped(&scope),
+                file: file.path.clone(),
+                line,
+                target: block.target.clone(),
+                source,
+                template,
+                only_roles: block.only_roles.clone(),
+                skip_roles: block.skip_roles.clone(),
+                conditional: block.when.is_some(),
+            });
+        }
+
+        let merge_lines = lines("merges");
+        for (index, merge) in merges.iter().enumerate() {
+            if !merge.enabled {
+                continue;
+            }
+            entries.push(Entry {
+                kind: Kind::Merge,
+                scope: scoped(&scope),
+                file: file.path.clone(),
+                line: line_at(&merge_lines, index),
+                target: merge.target.clone(),
+                source: Some((merge.source.clone(), resolve_source(&merge.source, &dir))),
+                template: None,
+                only_roles: merge.only_roles.clone(),
+                skip_roles: merge.skip_roles.clone(),
+                conditional: merge.when.is_some(),
+            });
+        }
+    }
+    entries
+}
+
+/// Resolve a source the way config loading does: relative to the file's
+/// directory unless it starts with `/`, `~` or `$`.
+fn resolve_source(source: &str, dir: &Path) -> PathBuf {
+    let expanded = expand(source);
+    if expanded.is_absolute() {
+        expanded
+    } else {
+        dir.join(expanded)
+    }
+}
+
+fn host_views(config: &Config) -> Vec<HostView> {
+    if config.hosts.is_empty() {
+        return vec![HostView {
+            name: None,
+            chain: Vec::new(),
+            roles: Vec::new(),
+            variables: config.variables.clone(),
+            deployed: deployed(config),
+        }];
+    }
+    config
+        .host_names()
+        .into_iter()
+        .filter_map(|name| {
+            let (chain, merged) = match (config.host_chain(&name), config.with_host(&name)) {
+                (Ok(chain), Ok(merged)) => (chain, merged),
+                // A broken `inherits` is reported by `check_inherits`; check
+                // the host's own entries as if it inherited nothing
+                _ => {
+                    let mut detached = config.clone();
+                    detached.hosts.get_mut(&name)?.inherits = None;
+                    (
+                        detached.host_chain(&name).ok()?,
+                        detached.with_host(&name).ok()?,
+                    )
+                }
+            };
+            Some(HostView {
+                roles: merged.hosts[&name].roles.clone(),
+                deployed: deployed(&merged),
+                name: Some(name),
+                chain,
+                variables: merged.variables,
+            })
+        })
+        .collect()
+}
+
+fn deployed(config: &Config) -> Vec<(PathBuf, PathBuf)> {
+    config
+        .dotfiles
+        .iter()
+        .map(|d| (&d.target, &d.source))
+        .chain(config.merges.iter().map(|m| (&m.target, &m.source)))
+        .map(|(target, source)| (expand(target), expand(source)))
+        .collect()
+}
+
+fn expand(path: &str) -> PathBuf {
+    expand_path_str(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// The cycle `host` is part of, starting and ending with it.
+fn inherits_cycle(config: &Config, host: &str) -> Option<Vec<String>> {
+    let mut chain = vec![host.to_string()];
+    let mut current = host;
+    while let Some(parent) = config.hosts.get(current)?.inherits.as_deref() {
+        let repeated = chain.iter().any(|name| name == parent);
+        chain.push(parent.to_string());
+        if parent == host {
+            return Some(chain);
+        }
+        if repeated {
+            // A cycle further up the chain, reported for the hosts in it
+            return None;
+        }
+        current = parent;
+    }
+    None
+}
+
+/// Collect each `variables.*` path a template reads, as segments after
+/// `variables`, with the line it is on. Paths in `guards` were tested by an
+/// enclosing `#if`/`#unless` and are left out.
+fn collect_variables(
+    template: &Template,
+    guards: &[Vec<String>],
+    references: &mut Vec<(Vec<String>, usize)>,
+) {
+    for (index, element) in template.elements.iter().enumerate() {
+        let line = template.mapping.get(index).map_or(1, |mapping| mapping.0);
+        collect_element(element, line, guards, references);
+    }
+}
+
+fn collect_element(
+    element: &TemplateElement,
+    line: usize,
+    guards: &[Vec<String>],
+    references: &mut Vec<(Vec<String>, usize)>,
+) {
+    match element {
+        TemplateElement::Expression(helper)
+        | TemplateElement::HtmlExpression(helper)
+        | TemplateElement::HelperBlock(helper) => collect_helper(helper, line, guards, references),
+        _ => {}
+    }
+}
+
+fn collect_helper(
+    helper: &HelperTemplate,
+    line: usize,
+    guards: &[Vec<String>],
+    references: &mut Vec<(Vec<String>, usize)>,
+) {
+    // `{{#if variables.x}}` is how templates handle an optional variable, so
+    // the test and the uses in the branch it guards may be undefined
+    let tested = match helper.name.as_name() {
+        Some("if" | "unless") if helper.block => match helper.params.first() {
+            Some(Parameter::Path(path)) => variable_path(path),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    let parameters = std::iter::once(&helper.name)
+        .chain(&helper.params)
+        .chain(helper.hash.values());
+    for parameter in parameters {
+        match parameter {
+            Parameter::Path(path) => {
+                if let Some(segments) = variable_path(path)
+                    && tested.as_ref() != Some(&segments)
+                    && !is_guarded(&segments, guards)
+                {
+                    references.push((segments, line));
+                }
+            }
+            Parameter::Subexpression(subexpression) => {
+                collect_element(&subexpression.element, line, guards, references)
+            }
+            _ => {}
+        }
+    }
+
+    let mut inner = guards.to_vec();
+    inner.extend(tested);
+    let (then_guards, else_guards) = match helper.name.as_name() {
+        Some("if") => (inner.as_slice(), guards),
+        Some("unless") => (guards, inner.as_slice()),
+        _ => (guards, guards),
+    };
+    if let Some(nested) = &helper.template {
+        collect_variables(nested, then_guards, references);
+    }
+    if let Some(nested) = &helper.inverse {
+        collect_variables(nested, else_guards, references);
+    }
+}
+
+/// Whether `path` is, or is inside, a variable an enclosing block tested.
+fn is_guarded(path: &[String], guards: &[Vec<String>]) -> bool {
+    guards.iter().any(|guard| path.starts_with(guard))
+}
+
+/// `variables.a.b` or `@root.variables.a.b` as `["a", "b"]`.
+fn variable_path(path: &TemplatePath) -> Option<Vec<String>> {
+    let TemplatePath::Relative((segments, raw)) = path else {
+        return None;
+    };
+    let skip = usize::from(raw.starts_with("@root"));
+    let names = segments
+        .iter()
+        .skip(skip)
+        .map(|segment| match segment {
+            PathSeg::Named(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<String>>>()?;
+    match names.split_first() {
+        Some((first, rest)) if first == "variables" && !rest.is_empty() => Some(rest.to_vec()),
+        _ => None,
+    }
+}
+
+/// Whether `path` leads to a value. Lists, scalars and variable sources are
+/// leaves, so anything below them counts as defined.
+fn is_defined(variables: &Variables, path: &[String]) -> bool {
+    let Some((first, rest)) = path.split_first() else {
+        return true;
+    };
+    let Some(mut value) = variables.get(first) else {
+        return false;
+    };
+    for key in rest {
+        match value {
+            Value::Object(table) if !vars::is_source(value) => match table.get(key) {
+                Some(child) => value = child,
+                None => return false,
+            },
+            _ => return true,
+        }
+    }
+    true
+}
+
+/// The entries of an array of tables or an array of inline tables, with the
+/// line each starts on.
+fn table_entries<'a>(content: &str, item: &'a Item) -> Vec<(&'a dyn TableLike, Option<usize>)> {
+    if let Some(tables) = item.as_array_of_tables() {
+        tables
+            .iter()
+            .map(|table| (table as &dyn TableLike, line_of(content, table.span())))
+            .collect()
+    } else if let Some(array) = item.as_array() {
+        array
+            .iter()
+            .filter_map(|value| value.as_inline_table())
+            .map(|table| (table as &dyn TableLike, line_of(content, table.span())))
+            .collect()
+    } else {
+        Vec::new()
+    }
+}
+
+fn key_line(content: &str, table: &dyn TableLike, key: &str) -> Option<usize> {
+    let (key, item) = table.get_key_value(key)?;
+    line_of(content, key.span().or_else(|| item.span()))
+}
+
+fn line_of(content: &str, span: Option<Range<usize>>) -> Option<usize> {
+    let start = span?.start.min(content.len());
+    Some(
+        content.as_bytes()[..start]
+            .iter()
+            .filter(|&&b| b == b'\n')
+            .count()
+            + 1,
+    )
+}
+
+fn first_line(error: &anyhow::Error) -> String {
+    error
+        .to_string()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// `; did you mean 'x'?` for the candidate closest to `name`, if any is close.
+fn did_you_mean<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> String {
+    candidates
+        .into_iter()
+        .map(|candidate| (strsim::jaro_winkler(name, candidate), candidate))
+        .filter(|(score, _)| *score >= 0.8)
+        .max_by(|a, b| a.0.total_cmp(&b.0))
+        .map(|(_, candidate)| format!("; did you mean '{}'?", candidate))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_collects_variable_paths_with_lines() {
+        let template = Template::compile(
+            "a {{variables.name}}\n{{#if variables.git.email}}\n{{upper @root.variables.shell}}{{/if}}\n{{host.name}} {{#each variables.list}}{{this}}{{/each}}",
+        )
+        .unwrap();
+        assert_eq!(
+            paths(&template),
+            vec![
+                ("name".to_string(), 1),
+                ("shell".to_string(), 3),
+                ("list".to_string(), 4),
+            ]
+        );
+    }
+
+    fn paths(template: &Template) -> Vec<(String, usize)> {
+        let mut references = Vec::new();
+        collect_variables(template, &[], &mut references);
+        references
+            .into_iter()
+            .map(|(path, line)| (path.join("."), line))
+            .collect()
+    }
+
+    #[test]
+    fn test_variables_tested_by_if_are_optional() {
+        let template = Template::compile(
+            "{{#if variables.key}}signingkey = {{variables.key.id}}{{else}}{{variables.key}}{{/if}}\n{{#unless variables.name}}{{variables.name}}{{else}}{{variables.name}}{{/unless}}",
+        )
+        .unwrap();
+        assert_eq!(
+            paths(&template),
+            vec![("key".to_string(), 1), ("name".to_string(), 2)]
+        );
+    }
+
+    #[test]
+    fn test_sources_and_lists_are_leaves() {
+        let variables = Variables::from([
+            ("git".to_string(), serde_json::json!({ "email": "a@b.c" })),
+            (
+                "key".to_string(),
+                serde_json::json!({ "from": { "env": "KEY" } }),
+            ),
+            ("list".to_string(), serde_json::json!([1, 2])),
+        ]);
+        let path = |p: &str| p.split('.').map(String::from).collect::<Vec<_>>();
+        assert!(is_defined(&variables, &path("git.email")));
+        assert!(!is_defined(&variables, &path("git.name")));
+        assert!(is_defined(&variables, &path("key.anything")));
+        assert!(is_defined(&variables, &path("list.0")));
+        assert!(!is_defined(&variables, &path("missing")));
+    }
+}
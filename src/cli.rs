@@ -1,16 +1,33 @@
 use clap::{Parser, Subcommand};
 use colored::Colorize;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use crate::blocks;
+use crate::check::{self, Severity};
+use crate::condition::{self, Facts};
 use crate::config;
-use crate::config::{Config, should_apply_for_roles};
-use crate::diff::{Change, DiffEngine};
+use crate::config::{Config, MachineFacts, skip_reason};
+use crate::diff::{Change, DiffEngine, ResourceType, copy_drift_reason};
+use crate::error::{CheckFailed, DriftDetected};
+use crate::generations::{self, GenerationStore};
+use crate::git;
 use crate::git_auth;
 use crate::hooks;
 use crate::installer::HomebrewManager;
-use crate::linker::{ApplyToAllChoice, apply_dotfile};
+use crate::linker::{
+    ApplyToAllChoice, PermissionSpec, PruneOutcome, apply_dotfile, content_hash, permission_drift,
+    prune_dotfile,
+};
+use crate::merge;
+use crate::output::{
+    ApplyReport, ApplyResult, CheckReport, DiffReport, ErrorReport, HostDetectReport, HostReport,
+    HostsReport, OutputFormat, RepoStatus, ResourceStatus, RoleReport, RolesReport, StatusReport,
+    VarsReport, exit_code, print_json,
+};
+use crate::progress;
 use crate::state::State;
 use crate::template::HostContext;
+use crate::vars::{self, ResolvedVariable};
 use crate::zerobrew::ZerobrewManager;
 use anyhow::Context;
 
@@ -51,12 +68,42 @@ pub struct Cli {
         help = "Use a specific initialized dotfiles branch"
     )]
     pub branch: Option<String>,
+
+    #[arg(
+        long,
+        global = true,
+        conflicts_with = "refresh",
+        help = "Use cached extends repos without fetching"
+    )]
+    pub offline: bool,
+
+    #[arg(
+        long,
+        global = true,
+        help = "Fetch extends repos even if the cache is still fresh"
+    )]
+    pub refresh: bool,
+
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        default_value_t = OutputFormat::Text,
+        help = "Output format (json is supported by apply, diff, status, history, hosts, roles, vars, check, schema and import)"
+    )]
+    pub output: OutputFormat,
 }
 
 #[derive(Subcommand)]
 pub enum Commands {
     #[command(about = "Apply configuration changes")]
-    Apply,
+    Apply {
+        #[arg(
+            long,
+            help = "Remove dotfiles that are no longer in the config without asking"
+        )]
+        prune: bool,
+    },
 
     #[command(about = "Show preview of changes without applying")]
     Diff,
@@ -67,15 +114,36 @@ pub enum Commands {
     #[command(about = "Undo last apply operation")]
     Undo,
 
+    #[command(about = "List recorded apply generations")]
+    History,
+
+    #[command(about = "Restore the symlinks and backups of an earlier generation")]
+    Rollback {
+        #[arg(help = "Generation number (see 'mimic history')")]
+        generation: u32,
+    },
+
     #[command(about = "Manage host configurations", subcommand)]
     Hosts(HostCommands),
 
+    #[command(about = "Show what each role contributes", subcommand)]
+    Roles(RoleCommands),
+
     #[command(about = "Render a template file to preview output")]
     Render {
-        #[arg(help = "Path to template file")]
-        template: String,
+        #[arg(help = "Path to template file (omit to print the resolved variables)")]
+        template: Option<String>,
     },
 
+    #[command(about = "Show template variables and where their values come from")]
+    Vars,
+
+    #[command(about = "Check the config for unknown keys, missing files and conflicts")]
+    Check,
+
+    #[command(about = "Print a JSON Schema for mimic.toml, for editor completion and validation")]
+    Schema,
+
     #[command(about = "Manage secrets in macOS Keychain", subcommand)]
     Secrets(SecretsCommands),
 
@@ -94,6 +162,56 @@ pub enum Commands {
         target: String,
     },
 
+    #[command(about = "Move an existing file into the repo and manage it")]
+    Add {
+        #[arg(help = "File or directory to adopt (e.g., ~/.config/starship.toml)")]
+        path: String,
+
+        #[arg(long, help = "Render the file as a template")]
+        template: bool,
+    },
+
+    #[command(
+        about = "Generate a mimic.toml from a stow, chezmoi or yadm repo or a plain directory"
+    )]
+    Import {
+        #[arg(long, value_enum, help = "Layout of the directory")]
+        from: crate::import::ImportFrom,
+
+        #[arg(help = "Directory to import (e.g., ~/.local/share/chezmoi)")]
+        path: String,
+
+        #[arg(long, help = "Overwrite an existing mimic.toml")]
+        force: bool,
+    },
+
+    #[command(
+        about = "Convert between a Homebrew Bundle Brewfile and [packages]",
+        subcommand
+    )]
+    Brewfile(BrewfileCommands),
+
+    #[command(about = "Encrypt a file for use as an encrypted dotfile source")]
+    Encrypt {
+        #[arg(help = "File to encrypt; written next to it with a .age extension")]
+        path: String,
+
+        #[arg(long, help = "Keep the plaintext file")]
+        keep: bool,
+    },
+
+    #[command(about = "Print the decrypted contents of an encrypted file")]
+    Decrypt {
+        #[arg(help = "Encrypted file (e.g., dotfiles/npmrc.age)")]
+        path: String,
+    },
+
+    #[command(about = "Move extends repos to their latest commit and rewrite mimic.lock")]
+    Update {
+        #[arg(help = "Repos to update, as written in [[extends]] (default: all)")]
+        repos: Vec<String>,
+    },
+
     #[command(about = "Remove brew packages not listed in config")]
     Clean,
 }
@@ -128,6 +246,21 @@ pub enum SecretsCommands {
     Export,
 }
 
+#[derive(Subcommand)]
+pub enum BrewfileCommands {
+    #[command(about = "Add the tap, brew and cask entries of a Brewfile to [packages]")]
+    Import {
+        #[arg(help = "Brewfile to read (e.g., ~/Brewfile)")]
+        path: String,
+    },
+
+    #[command(about = "Write the packages this host installs as a Brewfile")]
+    Export {
+        #[arg(help = "File to write (default: print to stdout)")]
+        path: Option<String>,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum HostCommands {
     #[command(about = "List all configured hosts")]
@@ -138,24 +271,75 @@ pub enum HostCommands {
         #[arg(help = "Name of the host to show")]
         name: String,
     },
+
+    #[command(about = "Explain which host this machine resolves to and why")]
+    Detect,
+}
+
+#[derive(Subcommand)]
+pub enum RoleCommands {
+    #[command(about = "List all roles and the hosts that have them")]
+    List,
+
+    #[command(about = "Show the dotfiles, packages, hooks and variables a role adds")]
+    Show {
+        #[arg(help = "Name of the role to show")]
+        name: String,
+    },
 }
 
 impl Cli {
     pub fn run(&self) -> anyhow::Result<()> {
+        if self.json() {
+            match &self.command {
+                Commands::Apply { .. }
+                | Commands::Diff
+                | Commands::Status
+                | Commands::History
+                | Commands::Hosts(_)
+                | Commands::Roles(_)
+                | Commands::Vars
+                | Commands::Check
+                | Commands::Schema
+                | Commands::Import { .. } => {}
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "--output json is not supported by this command\n\nTo fix:\n  - Use it with apply, diff, status, history, hosts, roles, vars, check, schema or import\n  - Or drop --output json"
+                    ));
+                }
+            }
+        }
+
         match &self.command {
-            Commands::Apply => self.run_apply(),
+            Commands::Apply { prune } => self.run_apply(*prune),
             Commands::Diff => self.run_diff(),
             Commands::Status => self.run_status(),
             Commands::Undo => self.run_undo(),
+            Commands::History => self.run_history(),
+            Commands::Rollback { generation } => self.run_rollback(*generation),
             Commands::Hosts(hosts_cmd) => self.run_hosts(hosts_cmd),
-            Commands::Render { template } => self.run_render(template),
+            Commands::Roles(roles_cmd) => self.run_roles(roles_cmd),
+            Commands::Render { template } => self.run_render(template.as_deref()),
+            Commands::Vars => self.run_vars(),
+            Commands::Check => self.run_check(),
+            Commands::Schema => print_json(&crate::schema::config_schema()),
             Commands::Secrets(secrets_cmd) => self.run_secrets(secrets_cmd),
             Commands::Init { repo, apply } => self.run_init(repo, *apply),
             Commands::Edit { target } => self.run_edit(target),
+            Commands::Add { path, template } => self.run_add(path, *template),
+            Commands::Import { from, path, force } => self.run_import(*from, path, *force),
+            Commands::Brewfile(brewfile_cmd) => self.run_brewfile(brewfile_cmd),
+            Commands::Encrypt { path, keep } => self.run_encrypt(path, *keep),
+            Commands::Decrypt { path } => self.run_decrypt(path),
+            Commands::Update { repos } => self.run_update(repos),
             Commands::Clean => self.run_clean(),
         }
     }
 
+    fn json(&self) -> bool {
+        self.output == OutputFormat::Json
+    }
+
     fn find_config(&self) -> anyhow::Result<PathBuf> {
         if let Some(config_path) = &self.config {
             return Ok(config_path.clone());
@@ -233,15 +417,69 @@ impl Cli {
         PathBuf::from(".mimic/state.toml")
     }
 
+    /// Generations live next to an explicit `--state` file so that isolated
+    /// state (tests, CI) gets isolated history too.
+    fn generation_store(&self) -> anyhow::Result<GenerationStore> {
+        let root = match &self.state {
+            Some(state_path) => state_path
+                .parent()
+                .unwrap_or_else(|| Path::new("."))
+                .join("generations"),
+            None => GenerationStore::default_root()?,
+        };
+        Ok(GenerationStore::new(root))
+    }
+
+    /// HEAD of the git repository holding the config file, if it is in one.
+    fn config_repo_head(&self) -> Option<git::RepoHead> {
+        let config_path = self.find_config().ok()?;
+        let config_dir = match config_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        git::head(&config_dir)
+    }
+
+    /// Record which commit of the config repo the state corresponds to.
+    fn record_applied_commit(&self, state: &mut State) {
+        let head = self.config_repo_head();
+        state.applied_dirty = head.as_ref().is_some_and(|h| h.dirty);
+        state.applied_repo = head.as_ref().map(|h| h.root.to_string_lossy().to_string());
+        state.applied_commit = head.map(|h| h.commit);
+    }
+
     fn detect_hostname() -> String {
         whoami::hostname().unwrap_or_else(|_| "unknown".to_string())
     }
 
+    /// Load the config merged for the selected host, with variable sources
+    /// resolved.
     fn resolve_config_and_host(&self) -> anyhow::Result<(Config, Option<String>)> {
+        let (mut config, host_name) = self.merged_config_and_host()?;
+        self.resolve_variables(&mut config)?;
+        Ok((config, host_name))
+    }
+
+    /// Replace `name.from = { env = ... }` (or `command`, `file`, `prompt`)
+    /// variables with their values on this machine.
+    fn resolve_variables(&self, config: &mut Config) -> anyhow::Result<Vec<ResolvedVariable>> {
+        let mut data = vars::DataFile::load(&self.get_data_path())?;
+        let (variables, report) = vars::resolve(&config.variables, &mut data)?;
+        data.save()?;
+        config.variables = variables;
+        Ok(report)
+    }
+
+    /// Prompt answers are machine-local, so they live next to the state file.
+    fn get_data_path(&self) -> PathBuf {
+        self.get_state_path().with_file_name(vars::DATA_FILE)
+    }
+
+    fn merged_config_and_host(&self) -> anyhow::Result<(Config, Option<String>)> {
         let config_path = self.find_config()?;
 
         if self.verbose {
-            println!(
+            progress!(
                 "{} {}",
                 "Loading config:".bright_black(),
                 config_path.display()
@@ -254,24 +492,77 @@ impl Cli {
             return Ok((base_config, None));
         }
 
-        let host_name = if let Some(host) = &self.host {
-            host.clone()
-        } else {
-            Self::detect_hostname()
-        };
+        let host_name = self.select_host(&base_config)?;
 
         if self.verbose {
-            println!("{} {}", "Using host:".bright_black(), host_name);
+            progress!("{} {}", "Using host:".bright_black(), host_name);
         }
 
         let merged_config = base_config.with_host(&host_name)?;
         Ok((merged_config, Some(host_name)))
     }
 
+    /// The host named with `--host`, or the one matching this machine.
+    fn select_host(&self, config: &Config) -> anyhow::Result<String> {
+        Ok(self.host_selection(config, &MachineFacts::detect())?.name)
+    }
+
+    fn host_selection(
+        &self,
+        config: &Config,
+        machine: &MachineFacts,
+    ) -> anyhow::Result<config::HostSelection> {
+        match &self.host {
+            Some(host) => {
+                config.host_chain(host)?;
+                Ok(config::HostSelection {
+                    name: host.clone(),
+                    reason: "chosen with --host".to_string(),
+                })
+            }
+            None => config.select_host(machine),
+        }
+    }
+
+    fn format_matcher(matcher: &config::HostMatch) -> String {
+        [
+            ("hostname", &matcher.hostname),
+            ("os", &matcher.os),
+            ("user", &matcher.user),
+        ]
+        .iter()
+        .filter_map(|(fact, rule)| rule.as_ref().map(|r| format!("{}={}", fact, r)))
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+
     fn run_hosts(&self, cmd: &HostCommands) -> anyhow::Result<()> {
         let config_path = self.find_config()?;
         let config = Config::from_file(&config_path)?;
 
+        if self.json() {
+            return match cmd {
+                HostCommands::List => print_json(&HostsReport {
+                    hosts: config
+                        .host_names()
+                        .iter()
+                        .map(|name| HostReport::new(&config, name))
+                        .collect::<anyhow::Result<_>>()?,
+                }),
+                HostCommands::Show { name } => print_json(&HostReport::new(&config, name)?),
+                HostCommands::Detect => {
+                    let machine = MachineFacts::detect();
+                    let selection = self.host_selection(&config, &machine);
+                    print_json(&HostDetectReport {
+                        candidates: config.host_candidates(&machine),
+                        selected: selection.as_ref().ok().cloned(),
+                        error: selection.err().map(|e| e.to_string()),
+                        machine,
+                    })
+                }
+            };
+        }
+
         match cmd {
             HostCommands::List => {
                 if config.hosts.is_empty() {
@@ -288,23 +579,108 @@ impl Cli {
                         } else {
                             host_config.roles.join(", ")
                         };
-                        println!("  {} ({})", name.green(), roles);
+                        let mut notes = Vec::new();
+                        if let Some(parent) = &host_config.inherits {
+                            notes.push(format!("inherits {}", parent));
+                        }
+                        if let Some(matcher) = &host_config.matcher {
+                            notes.push(format!("match {}", Self::format_matcher(matcher)));
+                        }
+                        if notes.is_empty() {
+                            println!("  {} ({})", name.green(), roles);
+                        } else {
+                            println!(
+                                "  {} ({}) {}",
+                                name.green(),
+                                roles,
+                                notes.join(", ").bright_black()
+                            );
+                        }
                     }
                 }
                 Ok(())
             }
+            HostCommands::Detect => {
+                let machine = MachineFacts::detect();
+                println!("{}", "This machine:".bold());
+                println!("  hostname {}", machine.hostname);
+                println!("  os       {}", machine.os);
+                println!("  user     {}", machine.user);
+                println!();
+
+                if config.hosts.is_empty() {
+                    println!("{}", "No hosts configured.".yellow());
+                    return Ok(());
+                }
+
+                println!("{}", "Hosts:".bold());
+                for candidate in config.host_candidates(&machine) {
+                    let mark = if candidate.matched {
+                        "✓".green()
+                    } else {
+                        "✗".bright_black()
+                    };
+                    println!(
+                        "  {} {} {}",
+                        mark,
+                        candidate.name,
+                        candidate.details.join(", ").bright_black()
+                    );
+                }
+                println!();
+
+                let selection = self.host_selection(&config, &machine)?;
+                println!(
+                    "{} {} ({})",
+                    "Selected:".bold(),
+                    selection.name.green(),
+                    selection.reason
+                );
+                Ok(())
+            }
             HostCommands::Show { name } => {
+                let chain = config.host_chain(name)?;
                 let merged = config.with_host(name)?;
+                let resolved = &merged.hosts[name];
+                let layers = config.host_layers(name)?;
 
                 println!("{} {}", "Host:".bold(), name.green());
+                if chain.len() > 1 {
+                    println!("{} {}", "Inherits:".bold(), chain.join(" → "));
+                }
+                if let Some(matcher) = &config.hosts[name].matcher {
+                    println!("{} {}", "Match:".bold(), Self::format_matcher(matcher));
+                }
+                println!();
+
+                println!("{}", "Roles:".bold());
+                if resolved.roles.is_empty() {
+                    println!("  {}", "(none)".bright_black());
+                } else {
+                    for role in &resolved.roles {
+                        let origin = Self::first_origin(&layers, false, |h| h.roles.contains(role));
+                        println!("  {} {}", role, Self::format_origin(&origin));
+                    }
+                }
                 println!();
 
                 println!("{}", "Variables:".bold());
                 if merged.variables.is_empty() {
                     println!("  {}", "(none)".bright_black());
                 } else {
-                    for (key, value) in &merged.variables {
-                        println!("  {} = {}", key, value);
+                    let mut keys: Vec<&String> = merged.variables.keys().collect();
+                    keys.sort();
+                    for key in keys {
+                        let origin =
+                            Self::last_origin(&layers, config.variables.contains_key(key), |h| {
+                                h.variables.contains_key(key)
+                            });
+                        println!(
+                            "  {} = {} {}",
+                            key,
+                            Self::format_variable(&merged.variables[key]),
+                            Self::format_origin(&origin)
+                        );
                     }
                 }
                 println!();
@@ -314,17 +690,231 @@ impl Cli {
                     println!("  {}", "(none)".bright_black());
                 } else {
                     for dotfile in &merged.dotfiles {
-                        println!("  {} → {}", dotfile.source, dotfile.target);
+                        let defines = |d: &config::Dotfile| {
+                            d.source == dotfile.source && d.target == dotfile.target
+                        };
+                        let origin =
+                            Self::last_origin(&layers, config.dotfiles.iter().any(defines), |h| {
+                                h.dotfiles.iter().any(defines)
+                            });
+                        println!(
+                            "  {} → {} {}",
+                            dotfile.source,
+                            dotfile.target,
+                            Self::format_origin(&origin)
+                        );
                     }
                 }
                 println!();
 
                 println!("{}", "Packages:".bold());
-                if merged.packages.homebrew.is_empty() {
+                if merged.packages.homebrew.is_empty() && merged.packages.zerobrew.is_empty() {
+                    println!("  {}", "(none)".bright_black());
+                } else {
+                    let base_packages = config.packages.normalized();
+                    for (package, manager) in merged
+                        .packages
+                        .homebrew
+                        .iter()
+                        .map(|p| (p, "brew"))
+                        .chain(merged.packages.zerobrew.iter().map(|p| (p, "zb")))
+                    {
+                        let in_list = |packages: &config::Packages| {
+                            let normalized = packages.normalized();
+                            let list = if manager == "zb" {
+                                normalized.zerobrew
+                            } else {
+                                normalized.homebrew
+                            };
+                            list.iter().any(|p| p.name == package.name)
+                        };
+                        let origin = Self::first_origin(&layers, in_list(&base_packages), |h| {
+                            in_list(&h.packages)
+                        });
+                        let label = if manager == "zb" {
+                            "zb".to_string()
+                        } else {
+                            package.pkg_type.clone()
+                        };
+                        println!(
+                            "  {} ({}) {}",
+                            package.name,
+                            label,
+                            Self::format_origin(&origin)
+                        );
+                    }
+                }
+                println!();
+
+                println!("{}", "Hooks:".bold());
+                if merged.hooks.is_empty() {
+                    println!("  {}", "(none)".bright_black());
+                } else {
+                    for hook in &merged.hooks {
+                        let origin = Self::last_origin(&layers, config.hooks.contains(hook), |h| {
+                            h.hooks.contains(hook)
+                        });
+                        println!("  {} {}", hook.name(), Self::format_origin(&origin));
+                    }
+                }
+                println!();
+
+                println!("{}", "Mise tools:".bold());
+                if merged.mise.tools.is_empty() {
                     println!("  {}", "(none)".bright_black());
                 } else {
-                    for package in &merged.packages.homebrew {
-                        println!("  {} ({})", package.name, package.pkg_type);
+                    let mut tools: Vec<&String> = merged.mise.tools.keys().collect();
+                    tools.sort();
+                    for tool in tools {
+                        let origin =
+                            Self::last_origin(&layers, config.mise.tools.contains_key(tool), |h| {
+                                h.mise.tools.contains_key(tool)
+                            });
+                        println!(
+                            "  {} = {} {}",
+                            tool,
+                            merged.mise.tools[tool],
+                            Self::format_origin(&origin)
+                        );
+                    }
+                }
+
+                let removals = config.host_removals(name)?;
+                if !removals.is_empty() {
+                    println!();
+                    println!("{}", "Removed:".bold());
+                    for (origin, removal) in &removals {
+                        println!(
+                            "  {} ({}) {}",
+                            removal,
+                            removal.by,
+                            Self::format_origin(origin)
+                        );
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    fn run_roles(&self, cmd: &RoleCommands) -> anyhow::Result<()> {
+        let config_path = self.find_config()?;
+        let config = Config::from_file(&config_path)?;
+
+        if self.json() {
+            return match cmd {
+                RoleCommands::List => print_json(&RolesReport {
+                    roles: config
+                        .role_names()
+                        .iter()
+                        .map(|name| RoleReport::new(&config, name))
+                        .collect::<anyhow::Result<_>>()?,
+                }),
+                RoleCommands::Show { name } => print_json(&RoleReport::new(&config, name)?),
+            };
+        }
+
+        match cmd {
+            RoleCommands::List => {
+                let names = config.role_names();
+                if names.is_empty() {
+                    println!("{}", "No roles configured.".yellow());
+                    println!("Add a [roles.name] section or list roles on a host.");
+                    return Ok(());
+                }
+
+                println!("{}", "Configured roles:".bold());
+                for name in names {
+                    let report = RoleReport::new(&config, &name)?;
+                    let contents = if report.bundle {
+                        Self::summarize_role(&report)
+                    } else {
+                        "tag only".to_string()
+                    };
+                    let hosts = if report.hosts.is_empty() {
+                        "no hosts".to_string()
+                    } else {
+                        format!("hosts: {}", report.hosts.join(", "))
+                    };
+                    println!("  {} ({}) {}", name.green(), contents, hosts.bright_black());
+                }
+                Ok(())
+            }
+            RoleCommands::Show { name } => {
+                let report = RoleReport::new(&config, name)?;
+
+                println!("{} {}", "Role:".bold(), name.green());
+                if report.hosts.is_empty() {
+                    println!("{} {}", "Hosts:".bold(), "(none)".bright_black());
+                } else {
+                    println!("{} {}", "Hosts:".bold(), report.hosts.join(", "));
+                }
+                if !report.bundle {
+                    println!(
+                        "{}",
+                        format!(
+                            "No [roles.{}] section; the role only selects entries through only_roles/skip_roles.",
+                            name
+                        )
+                        .bright_black()
+                    );
+                    return Ok(());
+                }
+                println!();
+
+                println!("{}", "Variables:".bold());
+                if report.variables.is_empty() {
+                    println!("  {}", "(none)".bright_black());
+                }
+                for (key, value) in &report.variables {
+                    println!("  {} = {}", key, Self::format_variable(value));
+                }
+                println!();
+
+                println!("{}", "Dotfiles:".bold());
+                if report.dotfiles.is_empty() {
+                    println!("  {}", "(none)".bright_black());
+                }
+                for dotfile in &report.dotfiles {
+                    println!("  {} → {}", dotfile.source, dotfile.target);
+                }
+                println!();
+
+                println!("{}", "Packages:".bold());
+                if report.packages.homebrew.is_empty() && report.packages.zerobrew.is_empty() {
+                    println!("  {}", "(none)".bright_black());
+                }
+                for package in &report.packages.homebrew {
+                    println!("  {} ({})", package.name, package.pkg_type);
+                }
+                for package in &report.packages.zerobrew {
+                    println!("  {} (zb)", package.name);
+                }
+                println!();
+
+                println!("{}", "Hooks:".bold());
+                if report.hooks.is_empty() {
+                    println!("  {}", "(none)".bright_black());
+                }
+                for hook in &report.hooks {
+                    println!("  {}", hook.name());
+                }
+                println!();
+
+                println!("{}", "Mise tools:".bold());
+                if report.mise_tools.is_empty() {
+                    println!("  {}", "(none)".bright_black());
+                }
+                for (tool, version) in &report.mise_tools {
+                    println!("  {} = {}", tool, version);
+                }
+
+                if !report.removes.is_empty() {
+                    println!();
+                    println!("{}", "Removes:".bold());
+                    for removal in &report.removes {
+                        println!("  {} ({})", removal, removal.by);
                     }
                 }
 
@@ -333,6 +923,71 @@ impl Cli {
         }
     }
 
+    /// e.g. "2 dotfiles, 1 package"; "empty" if the bundle adds nothing.
+    fn summarize_role(report: &RoleReport) -> String {
+        let packages = report.packages.homebrew.len() + report.packages.zerobrew.len();
+        let parts: Vec<String> = [
+            (report.dotfiles.len(), "dotfile"),
+            (packages, "package"),
+            (report.hooks.len(), "hook"),
+            (report.variables.len(), "variable"),
+            (report.mise_tools.len(), "mise tool"),
+        ]
+        .iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, what)| format!("{} {}{}", count, what, if *count == 1 { "" } else { "s" }))
+        .collect();
+        if parts.is_empty() {
+            "empty".to_string()
+        } else {
+            parts.join(", ")
+        }
+    }
+
+    /// Name the layer that supplied an entry whose later definitions override
+    /// earlier ones (the nearest host in the chain wins, then role bundles,
+    /// then the base config).
+    fn last_origin(
+        layers: &[(String, config::HostConfig)],
+        in_base: bool,
+        defines: impl Fn(&config::HostConfig) -> bool,
+    ) -> String {
+        layers
+            .iter()
+            .rev()
+            .find(|(_, layer)| defines(layer))
+            .map(|(name, _)| name.clone())
+            .unwrap_or_else(|| {
+                if in_base {
+                    "base".to_string()
+                } else {
+                    "unknown".to_string()
+                }
+            })
+    }
+
+    /// Name the layer that supplied an entry where the first definition wins
+    /// (the base config, then role bundles, then the root of the chain down
+    /// to the host).
+    fn first_origin(
+        layers: &[(String, config::HostConfig)],
+        in_base: bool,
+        defines: impl Fn(&config::HostConfig) -> bool,
+    ) -> String {
+        if in_base {
+            return "base".to_string();
+        }
+        layers
+            .iter()
+            .find(|(_, layer)| defines(layer))
+            .map(|(name, _)| name.clone())
+            .unwrap_or_else(|| "unknown".to_string())
+    }
+
+    fn format_origin(origin: &str) -> colored::ColoredString {
+        format!("(from {})", origin).bright_black()
+    }
+
     /// Get the roles for the resolved host.
     fn get_host_roles(config: &Config, host_name: &Option<String>) -> Vec<String> {
         if let Some(name) = host_name {
@@ -348,13 +1003,13 @@ impl Cli {
 
     /// Build a HostContext from the resolved host, with safe fallback.
     fn build_host_context(config: &Config, host_name: &Option<String>) -> HostContext {
-        if let Some(name) = host_name {
-            if let Some(host_config) = config.hosts.get(name) {
-                return HostContext {
-                    name: name.clone(),
-                    roles: host_config.roles.clone(),
-                };
-            }
+        if let Some(name) = host_name
+            && let Some(host_config) = config.hosts.get(name)
+        {
+            return HostContext {
+                name: name.clone(),
+                roles: host_config.roles.clone(),
+            };
         }
         HostContext {
             name: host_name.as_deref().unwrap_or("default").to_string(),
@@ -362,12 +1017,15 @@ impl Cli {
         }
     }
 
-    /// Filter a config by roles, returning only dotfiles and packages that apply.
-    fn filter_config_by_roles(config: Config, host_roles: &[String]) -> Config {
+    /// Filter a config by roles and `when` conditions, returning only the
+    /// resources that apply on this machine.
+    fn filter_config(config: Config, host_roles: &[String], facts: &Facts) -> Config {
         let filtered_dotfiles: Vec<_> = config
             .dotfiles
             .iter()
-            .filter(|df| should_apply_for_roles(&df.only_roles, &df.skip_roles, host_roles))
+            .filter(|df| {
+                skip_reason(&df.only_roles, &df.skip_roles, &df.when, host_roles, facts).is_none()
+            })
             .cloned()
             .collect();
 
@@ -376,42 +1034,109 @@ impl Cli {
         let filtered_packages: Vec<_> = normalized
             .homebrew
             .iter()
-            .filter(|pkg| should_apply_for_roles(&pkg.only_roles, &pkg.skip_roles, host_roles))
+            .filter(|pkg| {
+                skip_reason(
+                    &pkg.only_roles,
+                    &pkg.skip_roles,
+                    &pkg.when,
+                    host_roles,
+                    facts,
+                )
+                .is_none()
+            })
             .cloned()
             .collect();
         let filtered_zb: Vec<_> = normalized
             .zerobrew
             .iter()
-            .filter(|pkg| should_apply_for_roles(&pkg.only_roles, &pkg.skip_roles, host_roles))
+            .filter(|pkg| {
+                skip_reason(
+                    &pkg.only_roles,
+                    &pkg.skip_roles,
+                    &pkg.when,
+                    host_roles,
+                    facts,
+                )
+                .is_none()
+            })
+            .cloned()
+            .collect();
+        let filtered_blocks: Vec<_> = config
+            .blocks
+            .iter()
+            .filter(|block| {
+                skip_reason(
+                    &block.only_roles,
+                    &block.skip_roles,
+                    &block.when,
+                    host_roles,
+                    facts,
+                )
+                .is_none()
+            })
+            .cloned()
+            .collect();
+        let filtered_merges: Vec<_> = config
+            .merges
+            .iter()
+            .filter(|merge| {
+                skip_reason(
+                    &merge.only_roles,
+                    &merge.skip_roles,
+                    &merge.when,
+                    host_roles,
+                    facts,
+                )
+                .is_none()
+            })
             .cloned()
             .collect();
 
         Config {
             extends: Vec::new(),
+            include: Vec::new(),
             variables: config.variables,
             dotfiles: filtered_dotfiles,
+            blocks: filtered_blocks,
+            merges: filtered_merges,
             packages: crate::config::Packages {
                 homebrew: filtered_packages,
                 brew: Vec::new(),
                 cask: Vec::new(),
+                tap: Vec::new(),
                 zerobrew: filtered_zb,
                 zb: Vec::new(),
             },
             hosts: config.hosts,
+            roles: config.roles,
             hooks: config.hooks,
             secrets: config.secrets,
             mise: config.mise,
+            encryption: config.encryption,
+            exclude_dotfiles: config.exclude_dotfiles,
+            exclude_packages: config.exclude_packages,
+            exclude_hooks: config.exclude_hooks,
+            removed: config.removed,
         }
     }
 
     fn run_diff(&self) -> anyhow::Result<()> {
         let (config, host_name) = self.resolve_config_and_host()?;
+        let host_ctx = Self::build_host_context(&config, &host_name);
         let host_roles = Self::get_host_roles(&config, &host_name);
-        let filtered_config = Self::filter_config_by_roles(config, &host_roles);
+        let facts = Facts::new(&Self::detect_hostname(), &config.variables);
+        let filtered_config = Self::filter_config(config, &host_roles, &facts);
 
-        let diff_engine = DiffEngine::new();
+        let state = State::load(self.get_state_path()).unwrap_or_default();
+        let diff_engine = DiffEngine::new()
+            .with_state(&state)
+            .with_host_context(host_ctx);
         let changes = diff_engine.diff(&filtered_config)?;
 
+        if self.json() {
+            return print_json(&DiffReport::new(host_name, changes));
+        }
+
         if changes.is_empty() {
             println!("{}", "No changes detected.".bright_black());
             return Ok(());
@@ -430,49 +1155,90 @@ impl Cli {
             .iter()
             .filter(|c| matches!(c, Change::Modify { .. }))
             .count();
+        let remove_count = changes
+            .iter()
+            .filter(|c| matches!(c, Change::Remove { .. }))
+            .count();
 
-        if add_count > 0 || modify_count > 0 {
+        if add_count > 0 || modify_count > 0 || remove_count > 0 {
             println!();
             println!(
-                "{} {} to add, {} to modify",
+                "{} {} to add, {} to modify, {} to remove",
                 "Summary:".bold(),
                 add_count.to_string().green(),
-                modify_count.to_string().yellow()
+                modify_count.to_string().yellow(),
+                remove_count.to_string().red()
             );
         }
 
         Ok(())
     }
 
-    fn run_apply(&self) -> anyhow::Result<()> {
+    fn run_apply(&self, prune: bool) -> anyhow::Result<()> {
         let (config, host_name) = self.resolve_config_and_host()?;
         let host_ctx = Self::build_host_context(&config, &host_name);
 
         // Filter config by roles for diff preview (must match what we actually apply)
         let host_roles = Self::get_host_roles(&config, &host_name);
-        let filtered_for_diff = Self::filter_config_by_roles(config.clone(), &host_roles);
+        let facts = Facts::new(&Self::detect_hostname(), &config.variables);
+        let filtered_for_diff = Self::filter_config(config.clone(), &host_roles, &facts);
 
-        let diff_engine = DiffEngine::new();
+        let state_path = self.get_state_path();
+        let mut state = State::load(&state_path).unwrap_or_else(|_| State::new());
+
+        let diff_engine = DiffEngine::new()
+            .with_state(&state)
+            .with_host_context(host_ctx.clone());
         let changes = diff_engine.diff(&filtered_for_diff)?;
+        let orphans = diff_engine.orphaned_dotfiles(&filtered_for_diff)?;
 
         if changes.is_empty() {
-            println!("{}", "No changes to apply.".bright_black());
+            progress!("{}", "No changes to apply.".bright_black());
+
+            // Nothing to deploy, but the machine now matches the repo's HEAD
+            if !self.dry_run && state_path.exists() {
+                let previous = (state.applied_commit.clone(), state.applied_dirty);
+                self.record_applied_commit(&mut state);
+                if previous != (state.applied_commit.clone(), state.applied_dirty) {
+                    state
+                        .save(&state_path)
+                        .map_err(|e| anyhow::anyhow!("Failed to save state: {}", e))?;
+                }
+            }
+            if self.json() {
+                print_json(&ApplyReport {
+                    host: host_name,
+                    dry_run: self.dry_run,
+                    changes,
+                    results: Vec::new(),
+                    generation: None,
+                })?;
+            }
             return Ok(());
         }
 
-        println!("{}", "Changes to apply:".bold());
+        progress!("{}", "Changes to apply:".bold());
         for change in &changes {
-            println!("{}", change.format());
+            progress!("{}", change.format());
         }
 
         if self.dry_run {
-            println!();
-            println!("{}", "Dry-run mode: No changes were made.".yellow());
+            progress!();
+            progress!("{}", "Dry-run mode: No changes were made.".yellow());
+            if self.json() {
+                print_json(&ApplyReport {
+                    host: host_name,
+                    dry_run: true,
+                    changes,
+                    results: Vec::new(),
+                    generation: None,
+                })?;
+            }
             return Ok(());
         }
 
         if !self.yes {
-            println!();
+            progress!();
             use dialoguer::Confirm;
             let proceed = Confirm::new()
                 .with_prompt("Apply these changes?")
@@ -480,19 +1246,94 @@ impl Cli {
                 .interact()?;
 
             if !proceed {
-                println!("{}", "Aborted.".yellow());
+                progress!("{}", "Aborted.".yellow());
+                if self.json() {
+                    print_json(&ApplyReport {
+                        host: host_name,
+                        dry_run: false,
+                        changes,
+                        results: Vec::new(),
+                        generation: None,
+                    })?;
+                }
                 return Ok(());
             }
         }
 
-        let state_path = self.get_state_path();
-        let mut state = State::load(&state_path).unwrap_or_else(|_| State::new());
+        // Removing files is opt-in when nobody is there to confirm it
+        let prune = if prune || orphans.is_empty() {
+            prune
+        } else if self.yes {
+            false
+        } else {
+            use dialoguer::Confirm;
+            Confirm::new()
+                .with_prompt(format!(
+                    "Remove {} dotfile{} no longer in the config?",
+                    orphans.len(),
+                    if orphans.len() == 1 { "" } else { "s" }
+                ))
+                .default(true)
+                .interact()?
+        };
 
         state.active_host = host_name.clone();
 
-        println!();
-        println!("{}", "Applying changes...".bold());
+        progress!();
+        progress!("{}", "Applying changes...".bold());
 
+        let mut results: Vec<ApplyResult> = Vec::new();
+
+        if prune {
+            for orphan in &orphans {
+                match prune_dotfile(orphan, &mut state) {
+                    Ok(PruneOutcome::Removed { restored_backup }) => {
+                        let note = if restored_backup {
+                            " (backup restored)"
+                        } else {
+                            ""
+                        };
+                        progress!("  {} removed {}{}", "-".red(), orphan.target, note);
+                        results.push(ApplyResult::removed(ResourceType::Dotfile, &orphan.target));
+                    }
+                    Ok(PruneOutcome::LeftInPlace(reason)) => {
+                        progress!(
+                            "  {} {} left in place ({}); no longer managed",
+                            "↷".yellow(),
+                            orphan.target,
+                            reason
+                        );
+                        results.push(ApplyResult::skipped(
+                            ResourceType::Dotfile,
+                            &orphan.target,
+                            &reason,
+                        ));
+                    }
+                    Err(e) => {
+                        eprintln!("  {} {} - {}", "✗".red(), orphan.target, e);
+                        results.push(ApplyResult::failed(
+                            ResourceType::Dotfile,
+                            &orphan.target,
+                            e.to_string(),
+                        ));
+                    }
+                }
+            }
+        } else if !orphans.is_empty() {
+            progress!(
+                "  {} Keeping {} dotfile{} no longer in the config (use --prune to remove)",
+                "↷".bright_black(),
+                orphans.len(),
+                if orphans.len() == 1 { "" } else { "s" }
+            );
+            for orphan in &orphans {
+                results.push(ApplyResult::skipped(
+                    ResourceType::Dotfile,
+                    &orphan.target,
+                    "prune not requested",
+                ));
+            }
+        }
         let mut apply_to_all: Option<ApplyToAllChoice> = if self.yes {
             Some(ApplyToAllChoice::Backup)
         } else {
@@ -500,19 +1341,26 @@ impl Cli {
         };
 
         for dotfile in &config.dotfiles {
-            if !should_apply_for_roles(&dotfile.only_roles, &dotfile.skip_roles, &host_ctx.roles) {
+            if let Some(reason) = skip_reason(
+                &dotfile.only_roles,
+                &dotfile.skip_roles,
+                &dotfile.when,
+                &host_ctx.roles,
+                &facts,
+            ) {
                 if self.verbose {
-                    println!(
-                        "  {} {} (role mismatch)",
-                        "↷".bright_black(),
-                        dotfile.target
-                    );
+                    progress!("  {} {} ({})", "↷".bright_black(), dotfile.target, reason);
                 }
+                results.push(ApplyResult::skipped(
+                    ResourceType::Dotfile,
+                    &dotfile.target,
+                    reason,
+                ));
                 continue;
             }
 
             if self.verbose {
-                println!(
+                progress!(
                     "  {} {} → {}",
                     "Linking:".bright_black(),
                     dotfile.source,
@@ -522,10 +1370,16 @@ impl Cli {
 
             match apply_dotfile(dotfile, &config, &host_ctx, &mut state, &mut apply_to_all) {
                 Ok(()) => {
-                    println!("  {} {}", "✓".green(), dotfile.target);
+                    progress!("  {} {}", "✓".green(), dotfile.target);
+                    results.push(ApplyResult::applied(ResourceType::Dotfile, &dotfile.target));
                 }
                 Err(e) => {
                     eprintln!("  {} {} - {}", "✗".red(), dotfile.target, e);
+                    results.push(ApplyResult::failed(
+                        ResourceType::Dotfile,
+                        &dotfile.target,
+                        e.to_string(),
+                    ));
                     if !self.yes {
                         use dialoguer::Confirm;
                         let continue_on_error = Confirm::new()
@@ -541,32 +1395,140 @@ impl Cli {
             }
         }
 
+        for block in &config.blocks {
+            let label = format!("{} in {}", block.name, block.target);
+            if let Some(reason) = skip_reason(
+                &block.only_roles,
+                &block.skip_roles,
+                &block.when,
+                &host_ctx.roles,
+                &facts,
+            ) {
+                if self.verbose {
+                    progress!("  {} {} ({})", "↷".bright_black(), label, reason);
+                }
+                results.push(ApplyResult::skipped(ResourceType::Block, &label, reason));
+                continue;
+            }
+
+            match blocks::apply_block(block, &config.variables, &host_ctx, &mut state) {
+                Ok(()) => {
+                    progress!("  {} block {}", "✓".green(), label);
+                    results.push(ApplyResult::applied(ResourceType::Block, &label));
+                }
+                Err(e) => {
+                    eprintln!("  {} block {} - {}", "✗".red(), label, e);
+                    results.push(ApplyResult::failed(
+                        ResourceType::Block,
+                        &label,
+                        e.to_string(),
+                    ));
+                }
+            }
+        }
+
+        for entry in &config.merges {
+            if let Some(reason) = skip_reason(
+                &entry.only_roles,
+                &entry.skip_roles,
+                &entry.when,
+                &host_ctx.roles,
+                &facts,
+            ) {
+                if self.verbose {
+                    progress!("  {} {} ({})", "↷".bright_black(), entry.target, reason);
+                }
+                results.push(ApplyResult::skipped(
+                    ResourceType::Merge,
+                    &entry.target,
+                    reason,
+                ));
+                continue;
+            }
+
+            match merge::apply_merge(entry, &mut state) {
+                Ok(()) => {
+                    progress!("  {} merge {}", "✓".green(), entry.target);
+                    results.push(ApplyResult::applied(ResourceType::Merge, &entry.target));
+                }
+                Err(e) => {
+                    eprintln!("  {} merge {} - {}", "✗".red(), entry.target, e);
+                    results.push(ApplyResult::failed(
+                        ResourceType::Merge,
+                        &entry.target,
+                        e.to_string(),
+                    ));
+                }
+            }
+        }
+
         let homebrew = HomebrewManager::new();
         let normalized_packages = config.packages.normalized();
 
-        // Partition packages into formulae (batch install) and casks (install one at a time)
+        // Partition packages into taps (added first, since formulae and casks
+        // may come from them), formulae (batch install) and casks (install one at a time)
+        let mut taps: Vec<&str> = Vec::new();
         let mut formulae: Vec<&str> = Vec::new();
         let mut casks: Vec<&config::Package> = Vec::new();
 
         for package in &normalized_packages.homebrew {
-            if !should_apply_for_roles(&package.only_roles, &package.skip_roles, &host_ctx.roles) {
+            if let Some(reason) = skip_reason(
+                &package.only_roles,
+                &package.skip_roles,
+                &package.when,
+                &host_ctx.roles,
+                &facts,
+            ) {
                 if self.verbose {
-                    println!("  {} {} (role mismatch)", "↷".bright_black(), package.name);
+                    progress!("  {} {} ({})", "↷".bright_black(), package.name, reason);
                 }
+                results.push(ApplyResult::skipped(
+                    ResourceType::Package,
+                    &package.name,
+                    reason,
+                ));
                 continue;
             }
 
-            if package.pkg_type == "cask" {
-                casks.push(package);
-            } else {
-                formulae.push(&package.name);
+            match package.pkg_type.as_str() {
+                "cask" => casks.push(package),
+                "tap" => taps.push(&package.name),
+                _ => formulae.push(&package.name),
+            }
+        }
+
+        for name in &taps {
+            match homebrew.tap(name) {
+                Ok(()) => {
+                    progress!("  {} brew tap: {}", "✓".green(), name);
+                    results.push(ApplyResult::applied(ResourceType::Package, *name));
+                }
+                Err(e) => {
+                    eprintln!("  {} {} - {}", "✗".red(), name, e);
+                    results.push(ApplyResult::failed(
+                        ResourceType::Package,
+                        *name,
+                        e.to_string(),
+                    ));
+                    if !self.yes {
+                        use dialoguer::Confirm;
+                        let continue_on_error = Confirm::new()
+                            .with_prompt("Continue with remaining packages?")
+                            .default(true)
+                            .interact()?;
+
+                        if !continue_on_error {
+                            return Err(e);
+                        }
+                    }
+                }
             }
         }
 
         // Batch install all formulae with a single `brew install x y z` call
         if !formulae.is_empty() {
             if self.verbose {
-                println!(
+                progress!(
                     "  {} {} formulae: {}",
                     "Installing:".bright_black(),
                     formulae.len(),
@@ -577,19 +1539,21 @@ impl Cli {
             match homebrew.install_many_formulae(&formulae, &mut state) {
                 Ok(installed) => {
                     for name in &installed {
-                        println!("  {} brew formula: {}", "✓".green(), name);
+                        progress!("  {} brew formula: {}", "✓".green(), name);
                     }
                     // Also print already-installed formulae that were skipped
                     for name in &formulae {
                         if !installed.iter().any(|i| i == name) {
-                            println!("  {} brew formula: {}", "✓".green(), name);
+                            progress!("  {} brew formula: {}", "✓".green(), name);
                         }
+                        results.push(ApplyResult::applied(ResourceType::Package, *name));
                     }
                 }
                 Err(errors) => {
                     for (cmd, e) in &errors {
                         eprintln!("  {} {} - {}", "✗".red(), cmd, e);
                     }
+                    Self::push_batch_results(&mut results, &formulae, &errors, &state, "brew");
                     if !self.yes {
                         use dialoguer::Confirm;
                         let continue_on_error = Confirm::new()
@@ -608,15 +1572,21 @@ impl Cli {
         // Install casks one at a time (they may need interactive prompts)
         for package in &casks {
             if self.verbose {
-                println!("  {} {} (cask)", "Installing:".bright_black(), package.name);
+                progress!("  {} {} (cask)", "Installing:".bright_black(), package.name);
             }
 
             match homebrew.install_cask(&package.name, &mut state) {
                 Ok(()) => {
-                    println!("  {} brew cask: {}", "✓".green(), package.name);
+                    progress!("  {} brew cask: {}", "✓".green(), package.name);
+                    results.push(ApplyResult::applied(ResourceType::Package, &package.name));
                 }
                 Err(e) => {
                     eprintln!("  {} {} - {}", "✗".red(), package.name, e);
+                    results.push(ApplyResult::failed(
+                        ResourceType::Package,
+                        &package.name,
+                        e.to_string(),
+                    ));
                     if !self.yes {
                         use dialoguer::Confirm;
                         let continue_on_error = Confirm::new()
@@ -637,14 +1607,21 @@ impl Cli {
             .zerobrew
             .iter()
             .filter(|p| {
-                should_apply_for_roles(&p.only_roles, &p.skip_roles, &host_ctx.roles)
+                skip_reason(
+                    &p.only_roles,
+                    &p.skip_roles,
+                    &p.when,
+                    &host_ctx.roles,
+                    &facts,
+                )
+                .is_none()
             })
             .map(|p| p.name.as_str())
             .collect();
 
         if !zb_packages.is_empty() {
             if self.verbose {
-                println!(
+                progress!(
                     "  {} {} zerobrew packages: {}",
                     "Installing:".bright_black(),
                     zb_packages.len(),
@@ -656,18 +1633,20 @@ impl Cli {
             match zerobrew.install_many(&zb_packages, &mut state) {
                 Ok(installed) => {
                     for name in &installed {
-                        println!("  {} zb: {}", "✓".green(), name);
+                        progress!("  {} zb: {}", "✓".green(), name);
                     }
                     for name in &zb_packages {
                         if !installed.iter().any(|i| i == name) {
-                            println!("  {} zb: {}", "✓".green(), name);
+                            progress!("  {} zb: {}", "✓".green(), name);
                         }
+                        results.push(ApplyResult::applied(ResourceType::Package, *name));
                     }
                 }
                 Err(errors) => {
                     for (cmd, e) in &errors {
                         eprintln!("  {} {} - {}", "✗".red(), cmd, e);
                     }
+                    Self::push_batch_results(&mut results, &zb_packages, &errors, &state, "zb");
                     if !self.yes {
                         use dialoguer::Confirm;
                         let continue_on_error = Confirm::new()
@@ -684,16 +1663,26 @@ impl Cli {
         }
 
         if !config.hooks.is_empty() {
-            println!();
-            println!("{}", "Running activation hooks...".bright_cyan().bold());
+            progress!();
+            progress!("{}", "Running activation hooks...".bright_cyan().bold());
+
+            let outcomes =
+                hooks::execute_hooks(&config.hooks, &host_ctx.roles, &facts, self.verbose);
+            for outcome in &outcomes {
+                results.push(match &outcome.error {
+                    None => ApplyResult::applied(ResourceType::Hook, &outcome.name),
+                    Some(e) => ApplyResult::failed(ResourceType::Hook, &outcome.name, e.clone()),
+                });
+            }
 
-            match hooks::execute_hooks(&config.hooks, &host_ctx.roles, self.verbose) {
-                Ok(()) => {
-                    println!();
-                    println!("{}", "✓ All hooks completed successfully".green());
+            match outcomes.iter().find(|o| o.fatal) {
+                None => {
+                    progress!();
+                    progress!("{}", "✓ All hooks completed successfully".green());
                     state.hooks = config.hooks.clone();
                 }
-                Err(e) => {
+                Some(failed) => {
+                    let e = anyhow::anyhow!("{}", failed.error.as_deref().unwrap_or("hook failed"));
                     eprintln!();
                     eprintln!("{} Hook execution failed: {}", "✗".red(), e);
                     if !self.yes {
@@ -711,32 +1700,92 @@ impl Cli {
             }
         }
 
+        self.record_applied_commit(&mut state);
         state
             .save(&state_path)
             .map_err(|e| anyhow::anyhow!("Failed to save state: {}", e))?;
 
-        println!();
-        println!("{}", "✓ Successfully applied configuration".green().bold());
-        println!(
+        let generation = self
+            .generation_store()
+            .and_then(|store| store.record(&state, None));
+
+        progress!();
+        progress!("{}", "✓ Successfully applied configuration".green().bold());
+        progress!(
             "  {}: {}",
             "State saved to".bright_black(),
             state_path.display()
         );
+        let generation = match generation {
+            Ok(info) => {
+                progress!("  {}: {}", "Generation".bright_black(), info.number);
+                Some(info.number)
+            }
+            Err(e) => {
+                eprintln!("{} Failed to record generation: {}", "Warning:".yellow(), e);
+                None
+            }
+        };
+
+        if self.json() {
+            print_json(&ApplyReport {
+                host: host_name,
+                dry_run: false,
+                changes,
+                results,
+                generation,
+            })?;
+        }
 
         Ok(())
     }
 
+    /// Record per-package results for a batch install that failed as a whole.
+    /// Packages the installer still recorded in state count as applied.
+    fn push_batch_results(
+        results: &mut Vec<ApplyResult>,
+        names: &[&str],
+        errors: &[(String, anyhow::Error)],
+        state: &State,
+        manager: &str,
+    ) {
+        let error = errors
+            .iter()
+            .map(|(_, e)| e.to_string())
+            .collect::<Vec<_>>()
+            .join("; ");
+        for name in names {
+            let installed = state
+                .packages
+                .iter()
+                .any(|p| p.name == *name && p.manager == manager);
+            results.push(if installed {
+                ApplyResult::applied(ResourceType::Package, *name)
+            } else {
+                ApplyResult::failed(ResourceType::Package, *name, error.clone())
+            });
+        }
+    }
+
     fn run_status(&self) -> anyhow::Result<()> {
         let state_path = self.get_state_path();
 
         if !state_path.exists() {
+            if self.json() {
+                return print_json(&StatusReport {
+                    in_sync: true,
+                    active_host: None,
+                    resources: Vec::new(),
+                    config_repo: None,
+                });
+            }
             println!("{}", "No state file found.".yellow());
             println!("  Run 'mimic apply' to initialize.");
             return Ok(());
         }
 
         if self.verbose {
-            println!(
+            progress!(
                 "{} {}",
                 "Loading state:".bright_black(),
                 state_path.display()
@@ -744,199 +1793,318 @@ impl Cli {
         }
 
         let state = State::load(&state_path)?;
+        let mut report = Self::status_report(&state);
+        if let Ok((mut config, _)) = self.merged_config_and_host() {
+            // status only reports, so it reads variables without running
+            // commands, prompting or saving answers
+            let mut data = vars::DataFile::load(&self.get_data_path())?;
+            let (variables, unresolved) = vars::resolve_cached(&config.variables, &mut data);
+            config.variables = variables;
+            for warning in Self::mark_unmet_conditions(&mut report, &config, &unresolved) {
+                eprintln!("{} {}", "⚠".yellow(), warning);
+            }
+        }
 
-        if state.dotfiles.is_empty() && state.packages.is_empty() {
+        if self.json() {
+            print_json(&report)?;
+        } else if report.resources.is_empty() {
             println!("{}", "No resources managed.".bright_black());
-            return Ok(());
+        } else {
+            self.print_status(&report);
         }
 
-        println!("{}", "Status Report".bold());
-        println!();
+        if !report.in_sync {
+            return Err(DriftDetected.into());
+        }
 
-        let mut dotfiles_ok = 0;
-        let mut dotfiles_drift = 0;
-        let mut drift_details = Vec::new();
+        Ok(())
+    }
+
+    /// Check every resource recorded in the state for drift.
+    fn status_report(state: &State) -> StatusReport {
+        let mut resources = Vec::new();
 
         for dotfile in &state.dotfiles {
             let target_path = PathBuf::from(&dotfile.target);
-            let source_path = PathBuf::from(&dotfile.source);
 
             // For template dotfiles, the symlink points to the rendered file,
             // not the original source template.
-            let expected_path = if let Some(ref rendered) = dotfile.rendered_path {
-                PathBuf::from(rendered)
-            } else {
-                source_path.clone()
+            let expected_path = match &dotfile.rendered_path {
+                Some(rendered) => PathBuf::from(rendered),
+                None => PathBuf::from(&dotfile.source),
             };
 
-            if !target_path.exists() {
-                drift_details.push(format!(
-                    "  {} {} (missing)",
-                    "✗".red(),
-                    target_path.display()
-                ));
-                dotfiles_drift += 1;
-            } else if !target_path.is_symlink() {
-                drift_details.push(format!(
-                    "  {} {} (not a symlink)",
-                    "✗".red(),
-                    target_path.display()
-                ));
-                dotfiles_drift += 1;
+            let drift = if dotfile.is_copy() {
+                Self::copied_dotfile_drift(dotfile, &target_path, &expected_path)
+                    .map(|reason| (reason, false))
             } else {
-                match std::fs::read_link(&target_path) {
-                    Ok(actual_target) => {
-                        let canonical_actual = match actual_target.canonicalize() {
-                            Ok(p) => p,
-                            Err(_) => {
-                                drift_details.push(format!(
-                                    "  {} {} (broken link)",
-                                    "✗".red(),
-                                    target_path.display()
-                                ));
-                                dotfiles_drift += 1;
-                                continue;
-                            }
-                        };
-
-                        let canonical_expected = match expected_path.canonicalize() {
-                            Ok(p) => p,
-                            Err(_) => {
-                                drift_details.push(format!(
-                                    "  {} {} (source missing: {})",
-                                    "✗".red(),
-                                    target_path.display(),
-                                    expected_path.display()
-                                ));
-                                dotfiles_drift += 1;
-                                continue;
-                            }
-                        };
-
-                        if canonical_actual != canonical_expected {
-                            drift_details.push(format!(
-                                "  {} {} (points to {} instead of {})",
-                                "✗".yellow(),
-                                target_path.display(),
-                                actual_target.display(),
-                                expected_path.display()
-                            ));
-                            dotfiles_drift += 1;
-                        } else {
-                            dotfiles_ok += 1;
-                            if self.verbose {
-                                println!("  {} {}", "✓".green(), target_path.display());
-                            }
-                        }
-                    }
-                    Err(e) => {
-                        drift_details.push(format!(
-                            "  {} {} (error reading link: {})",
-                            "✗".red(),
-                            target_path.display(),
-                            e
-                        ));
-                        dotfiles_drift += 1;
-                    }
-                }
+                Self::symlink_drift(&target_path, &expected_path)
             }
+            .or_else(|| {
+                permission_drift(&target_path, PermissionSpec::of_state(dotfile))
+                    .map(|reason| (reason, false))
+            });
+
+            resources.push(ResourceStatus {
+                resource_type: ResourceType::Dotfile,
+                name: dotfile.target.clone(),
+                source: Some(dotfile.source.clone()),
+                manager: None,
+                in_sync: drift.is_none(),
+                severe: drift.as_ref().is_some_and(|(_, severe)| *severe),
+                reason: drift.map(|(reason, _)| reason),
+            });
+        }
+
+        for block in &state.blocks {
+            let drift = blocks::block_drift(block);
+            resources.push(ResourceStatus {
+                resource_type: ResourceType::Block,
+                name: format!("{} in {}", block.name, block.target),
+                source: None,
+                manager: None,
+                in_sync: drift.is_none(),
+                severe: false,
+                reason: drift,
+            });
+        }
+
+        for entry in &state.merges {
+            let drift = merge::merge_drift(entry);
+            resources.push(ResourceStatus {
+                resource_type: ResourceType::Merge,
+                name: entry.target.clone(),
+                source: Some(entry.source.clone()),
+                manager: None,
+                in_sync: drift.is_none(),
+                severe: false,
+                reason: drift,
+            });
         }
 
         let homebrew = HomebrewManager::new();
         let zerobrew = ZerobrewManager::new();
-        let mut packages_ok = 0;
-        let mut packages_drift = 0;
 
         for package in &state.packages {
-            if package.manager == "brew" {
-                match homebrew.is_installed(&package.name) {
-                    Ok(true) => {
-                        packages_ok += 1;
-                        if self.verbose {
-                            println!("  {} brew: {}", "✓".green(), package.name);
-                        }
-                    }
-                    Ok(false) => {
-                        drift_details.push(format!(
-                            "  {} brew package not installed: {}",
-                            "✗".yellow(),
-                            package.name
-                        ));
-                        packages_drift += 1;
-                    }
-                    Err(e) => {
-                        drift_details.push(format!(
-                            "  {} error checking {}: {}",
-                            "✗".red(),
-                            package.name,
-                            e
-                        ));
-                        packages_drift += 1;
-                    }
+            let installed = match package.manager.as_str() {
+                "brew" => homebrew.is_installed(&package.name),
+                "zb" => zerobrew.is_installed(&package.name),
+                _ => continue,
+            };
+
+            let (in_sync, reason, severe) = match installed {
+                Ok(true) => (true, None, false),
+                Ok(false) => (false, Some("not installed".to_string()), false),
+                Err(e) => (false, Some(e.to_string()), true),
+            };
+
+            resources.push(ResourceStatus {
+                resource_type: ResourceType::Package,
+                name: package.name.clone(),
+                source: None,
+                manager: Some(package.manager.clone()),
+                in_sync,
+                reason,
+                severe,
+            });
+        }
+
+        StatusReport {
+            in_sync: resources.iter().all(|r| r.in_sync),
+            active_host: state.active_host.clone(),
+            resources,
+            config_repo: Self::repo_status(state),
+        }
+    }
+
+    /// Flag applied resources whose `when` condition no longer holds, so
+    /// status agrees with what diff and apply would do now.
+    /// Mark applied resources whose `when` no longer holds. A condition that
+    /// reads an `unresolved` variable is not evaluated; the returned warnings
+    /// name those resources.
+    fn mark_unmet_conditions(
+        report: &mut StatusReport,
+        config: &Config,
+        unresolved: &[(String, anyhow::Error)],
+    ) -> Vec<String> {
+        use crate::expand::expand_path_str;
+
+        let facts = Facts::new(&Self::detect_hostname(), &config.variables);
+        let target = |path: &str| {
+            expand_path_str(path)
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|_| path.to_string())
+        };
+
+        // The condition of each resource that fails, or why it wasn't checked
+        let mut unmet: Vec<(ResourceType, String, Result<String, String>)> = Vec::new();
+        let mut check = |resource_type, name: String, when: &Option<String>| {
+            let Some(expr) = when else {
+                return;
+            };
+            let unreadable = condition::variables(expr).into_iter().find_map(|variable| {
+                unresolved
+                    .iter()
+                    .find(|(name, _)| *name == variable)
+                    .map(|(_, e)| e)
+            });
+            if let Some(e) = unreadable {
+                let reason = e.to_string();
+                let reason = reason.lines().next().unwrap_or_default().to_string();
+                unmet.push((resource_type, name, Err(reason)));
+            } else if !facts.eval(expr) {
+                unmet.push((resource_type, name, Ok(expr.clone())));
+            }
+        };
+        for dotfile in &config.dotfiles {
+            check(
+                ResourceType::Dotfile,
+                target(&dotfile.target),
+                &dotfile.when,
+            );
+        }
+        for block in &config.blocks {
+            let name = format!("{} in {}", block.name, target(&block.target));
+            check(ResourceType::Block, name, &block.when);
+        }
+        for entry in &config.merges {
+            check(ResourceType::Merge, target(&entry.target), &entry.when);
+        }
+        let packages = config.packages.normalized();
+        for package in packages.homebrew.iter().chain(&packages.zerobrew) {
+            check(ResourceType::Package, package.name.clone(), &package.when);
+        }
+
+        let mut warnings = Vec::new();
+        for resource in &mut report.resources {
+            match unmet
+                .iter()
+                .find(|(t, name, _)| *t == resource.resource_type && *name == resource.name)
+            {
+                Some((_, _, Ok(expr))) => {
+                    resource.in_sync = false;
+                    resource.reason = Some(format!("condition no longer met: {}", expr));
                 }
-            } else if package.manager == "zb" {
-                match zerobrew.is_installed(&package.name) {
-                    Ok(true) => {
-                        packages_ok += 1;
-                        if self.verbose {
-                            println!("  {} zb: {}", "✓".green(), package.name);
-                        }
-                    }
-                    Ok(false) => {
-                        drift_details.push(format!(
-                            "  {} zb package not installed: {}",
-                            "✗".yellow(),
-                            package.name
-                        ));
-                        packages_drift += 1;
-                    }
-                    Err(e) => {
-                        drift_details.push(format!(
-                            "  {} error checking {} (zb): {}",
-                            "✗".red(),
-                            package.name,
-                            e
-                        ));
-                        packages_drift += 1;
+                Some((_, _, Err(reason))) => warnings.push(format!(
+                    "Condition on {} not checked: {}",
+                    resource.name, reason
+                )),
+                None => {}
+            }
+        }
+        report.in_sync = report.resources.iter().all(|r| r.in_sync);
+        warnings
+    }
+
+    /// Check a symlinked dotfile. Returns the drift reason and whether it is
+    /// severe, or `None` if the link is correct.
+    fn symlink_drift(target_path: &Path, expected_path: &Path) -> Option<(String, bool)> {
+        if !target_path.exists() {
+            return Some(("missing".to_string(), true));
+        }
+        if !target_path.is_symlink() {
+            return Some(("not a symlink".to_string(), true));
+        }
+
+        let actual_target = match std::fs::read_link(target_path) {
+            Ok(actual_target) => actual_target,
+            Err(e) => return Some((format!("error reading link: {}", e), true)),
+        };
+        let Ok(canonical_actual) = actual_target.canonicalize() else {
+            return Some(("broken link".to_string(), true));
+        };
+        let Ok(canonical_expected) = expected_path.canonicalize() else {
+            return Some((format!("source missing: {}", expected_path.display()), true));
+        };
+
+        if canonical_actual != canonical_expected {
+            return Some((
+                format!(
+                    "points to {} instead of {}",
+                    actual_target.display(),
+                    expected_path.display()
+                ),
+                false,
+            ));
+        }
+
+        None
+    }
+
+    fn print_status(&self, report: &StatusReport) {
+        println!("{}", "Status Report".bold());
+        println!();
+
+        let mut drift_details = Vec::new();
+        let (mut dotfiles_ok, mut dotfiles_total) = (0, 0);
+        let (mut blocks_ok, mut blocks_total) = (0, 0);
+        let (mut merges_ok, mut merges_total) = (0, 0);
+        let (mut packages_ok, mut packages_total) = (0, 0);
+
+        for resource in &report.resources {
+            let is_package = resource.resource_type == ResourceType::Package;
+            let manager = resource.manager.as_deref().unwrap_or_default();
+            let (ok, total) = match resource.resource_type {
+                ResourceType::Package => (&mut packages_ok, &mut packages_total),
+                ResourceType::Block => (&mut blocks_ok, &mut blocks_total),
+                ResourceType::Merge => (&mut merges_ok, &mut merges_total),
+                _ => (&mut dotfiles_ok, &mut dotfiles_total),
+            };
+            *total += 1;
+
+            if resource.in_sync {
+                *ok += 1;
+                if self.verbose {
+                    if is_package {
+                        println!("  {} {}: {}", "✓".green(), manager, resource.name);
+                    } else {
+                        println!("  {} {}", "✓".green(), resource.name);
                     }
                 }
+                continue;
             }
+
+            let symbol = if resource.severe {
+                "✗".red()
+            } else {
+                "✗".yellow()
+            };
+            let reason = resource.reason.as_deref().unwrap_or_default();
+            drift_details.push(if !is_package {
+                format!("  {} {} ({})", symbol, resource.name, reason)
+            } else if resource.severe {
+                format!(
+                    "  {} error checking {} ({}): {}",
+                    symbol, resource.name, manager, reason
+                )
+            } else {
+                format!(
+                    "  {} {} package {}: {}",
+                    symbol, manager, reason, resource.name
+                )
+            });
         }
 
-        let total_dotfiles = dotfiles_ok + dotfiles_drift;
-        let total_packages = packages_ok + packages_drift;
+        if let Some(repo) = &report.config_repo {
+            println!("{}", Self::format_repo_status(repo));
+        }
 
         if !self.verbose {
-            if dotfiles_ok == total_dotfiles && total_dotfiles > 0 {
-                println!(
-                    "  {} {}/{} dotfiles in sync",
-                    "✓".green(),
-                    dotfiles_ok,
-                    total_dotfiles
-                );
-            } else if total_dotfiles > 0 {
-                println!(
-                    "  {} {}/{} dotfiles in sync",
-                    "✗".yellow(),
-                    dotfiles_ok,
-                    total_dotfiles
-                );
-            }
-
-            if packages_ok == total_packages && total_packages > 0 {
-                println!(
-                    "  {} {}/{} packages installed",
-                    "✓".green(),
-                    packages_ok,
-                    total_packages
-                );
-            } else if total_packages > 0 {
-                println!(
-                    "  {} {}/{} packages installed",
-                    "✗".yellow(),
-                    packages_ok,
-                    total_packages
-                );
+            for (ok, total, label) in [
+                (dotfiles_ok, dotfiles_total, "dotfiles in sync"),
+                (blocks_ok, blocks_total, "blocks in sync"),
+                (merges_ok, merges_total, "merged files in sync"),
+                (packages_ok, packages_total, "packages installed"),
+            ] {
+                if total == 0 {
+                    continue;
+                }
+                let symbol = if ok == total {
+                    "✓".green()
+                } else {
+                    "✗".yellow()
+                };
+                println!("  {} {}/{} {}", symbol, ok, total, label);
             }
         }
 
@@ -949,24 +2117,163 @@ impl Cli {
         }
 
         println!();
-        if dotfiles_drift > 0 || packages_drift > 0 {
+        if report.in_sync {
+            println!("{}", "✓ All resources in sync".green().bold());
+        } else {
             println!(
                 "{}",
                 "Run 'mimic apply' to reconcile drift.".yellow().bold()
             );
-            return Err(anyhow::anyhow!("__drift_detected__"));
+        }
+    }
+
+    /// Compare the config repo against the commit recorded at apply time.
+    /// Returns `None` if no commit was recorded.
+    fn repo_status(state: &State) -> Option<RepoStatus> {
+        let applied = state.applied_commit.clone()?;
+        let path = state.applied_repo.clone()?;
+        let head = git::head(Path::new(&path));
+
+        let relation = head
+            .as_ref()
+            .map(|head| git::relation(Path::new(&path), &applied, &head.commit));
+        let (relation, ahead, behind) = match relation {
+            None => ("missing", 0, 0),
+            Some(git::CommitRelation::Same) => ("same", 0, 0),
+            Some(git::CommitRelation::Ahead(n)) => ("ahead", n, 0),
+            Some(git::CommitRelation::Behind(n)) => ("behind", 0, n),
+            Some(git::CommitRelation::Diverged { ahead, behind }) => ("diverged", ahead, behind),
+            Some(git::CommitRelation::Unknown) => ("unknown", 0, 0),
+        };
+
+        Some(RepoStatus {
+            path,
+            applied_commit: applied,
+            applied_dirty: state.applied_dirty,
+            dirty: head.as_ref().is_some_and(|h| h.dirty),
+            head: head.map(|h| h.commit),
+            relation,
+            ahead,
+            behind,
+        })
+    }
+
+    fn format_repo_status(repo: &RepoStatus) -> String {
+        let applied_label = if repo.applied_dirty {
+            format!("{} + uncommitted changes", git::short(&repo.applied_commit))
         } else {
-            println!("{}", "✓ All resources in sync".green().bold());
+            git::short(&repo.applied_commit).to_string()
+        };
+        let Some(head) = repo.head.as_deref() else {
+            return format!(
+                "  {} config repo not found at {} (applied {})",
+                "?".yellow(),
+                repo.path,
+                applied_label
+            );
+        };
+        let dirty_note = if repo.dirty {
+            format!(", {}", "uncommitted changes".yellow())
+        } else {
+            String::new()
+        };
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+
+        match repo.relation {
+            "same" if !repo.applied_dirty && !repo.dirty => format!(
+                "  {} config repo at applied commit {}",
+                "✓".green(),
+                applied_label
+            ),
+            "same" => format!(
+                "  {} config repo at applied commit {}{}",
+                "!".yellow(),
+                applied_label,
+                dirty_note
+            ),
+            "ahead" => format!(
+                "  {} config repo is {} commit{} ahead of last apply ({} → {}){}",
+                "!".yellow(),
+                repo.ahead,
+                plural(repo.ahead),
+                applied_label,
+                git::short(head),
+                dirty_note
+            ),
+            "behind" => format!(
+                "  {} config repo is {} commit{} behind last apply ({} → {}){}",
+                "!".yellow(),
+                repo.behind,
+                plural(repo.behind),
+                applied_label,
+                git::short(head),
+                dirty_note
+            ),
+            "diverged" => format!(
+                "  {} config repo has diverged from last apply ({} ahead, {} behind; {} → {}){}",
+                "!".yellow(),
+                repo.ahead,
+                repo.behind,
+                applied_label,
+                git::short(head),
+                dirty_note
+            ),
+            _ => format!(
+                "  {} config repo moved from {} to {} (applied commit not in local history){}",
+                "!".yellow(),
+                applied_label,
+                git::short(head),
+                dirty_note
+            ),
         }
+    }
 
-        Ok(())
+    /// Check a copy-mode dotfile against the hash recorded at apply time.
+    /// Returns the drift reason, or `None` if the copy is in sync.
+    fn copied_dotfile_drift(
+        dotfile: &crate::state::DotfileState,
+        target_path: &std::path::Path,
+        expected_path: &std::path::Path,
+    ) -> Option<String> {
+        if !target_path.exists() && !target_path.is_symlink() {
+            return Some("missing".to_string());
+        }
+        if target_path.is_symlink() {
+            return Some("is a symlink, expected a copy".to_string());
+        }
+
+        let target_hash = match content_hash(target_path) {
+            Ok(hash) => hash,
+            Err(e) => return Some(format!("error reading copy: {}", e)),
+        };
+        let expected_hash = match content_hash(expected_path) {
+            Ok(hash) => hash,
+            Err(_) => return Some(format!("source missing: {}", expected_path.display())),
+        };
+        if target_hash == expected_hash {
+            return None;
+        }
+
+        Some(
+            copy_drift_reason(
+                dotfile.content_hash.as_deref(),
+                &target_hash,
+                &expected_hash,
+            )
+            .to_string(),
+        )
     }
 
     fn run_undo(&self) -> anyhow::Result<()> {
         let state_path = self.get_state_path();
 
         let state = match State::load(&state_path) {
-            Ok(state) if state.dotfiles.is_empty() && state.packages.is_empty() => {
+            Ok(state)
+                if state.dotfiles.is_empty()
+                    && state.blocks.is_empty()
+                    && state.merges.is_empty()
+                    && state.packages.is_empty() =>
+            {
                 println!("{}", "Nothing to undo.".yellow());
                 return Ok(());
             }
@@ -999,6 +2306,23 @@ impl Cli {
                 println!("  {} {}", "Processing:".bright_black(), target.display());
             }
 
+            // A copy edited since apply is the user's now: keep it, and the
+            // backup with it, rather than overwrite their changes
+            if dotfile.is_copy()
+                && target.exists()
+                && content_hash(&target).ok().as_deref() != dotfile.content_hash.as_deref()
+            {
+                println!(
+                    "  {} Left {} in place: modified since it was applied",
+                    "⚠".yellow(),
+                    target.display()
+                );
+                if let Some(backup_path) = &dotfile.backup_path {
+                    println!("    Original kept at: {}", backup_path);
+                }
+                continue;
+            }
+
             if target.exists() || target.is_symlink() {
                 let remove_result = if target.is_dir() && !target.is_symlink() {
                     std::fs::remove_dir_all(&target)
@@ -1040,21 +2364,20 @@ impl Cli {
                             backups_restored += 1;
                             // Clean up the backup file after successful restore
                             // (rename already moved it; copy leaves it behind)
-                            if backup_path.exists() {
-                                if let Err(e) = if backup_path.is_dir() {
+                            if backup_path.exists()
+                                && let Err(e) = if backup_path.is_dir() {
                                     std::fs::remove_dir_all(&backup_path)
                                 } else {
                                     std::fs::remove_file(&backup_path)
-                                } {
-                                    if self.verbose {
-                                        eprintln!(
-                                            "  {} Could not remove backup file {}: {}",
-                                            "⚠".yellow(),
-                                            backup_path.display(),
-                                            e
-                                        );
-                                    }
                                 }
+                                && self.verbose
+                            {
+                                eprintln!(
+                                    "  {} Could not remove backup file {}: {}",
+                                    "⚠".yellow(),
+                                    backup_path.display(),
+                                    e
+                                );
                             }
                             println!(
                                 "  {} Restored backup: {} → {}",
@@ -1111,6 +2434,64 @@ impl Cli {
             }
         }
 
+        let mut blocks_removed = 0;
+        for block in &state.blocks {
+            match blocks::remove_block(block) {
+                Ok(true) => {
+                    blocks_removed += 1;
+                    println!(
+                        "  {} Removed block {} from {}",
+                        "✓".green(),
+                        block.name,
+                        block.target
+                    );
+                }
+                Ok(false) => {
+                    if self.verbose {
+                        println!(
+                            "  {} Block already removed: {} in {}",
+                            "○".bright_black(),
+                            block.name,
+                            block.target
+                        );
+                    }
+                }
+                Err(e) => {
+                    let error_msg = format!(
+                        "Failed to remove block {} from {}: {}",
+                        block.name, block.target, e
+                    );
+                    eprintln!("  {} {}", "✗".red(), error_msg);
+                    errors.push(error_msg);
+                }
+            }
+        }
+
+        let mut merges_restored = 0;
+        for entry in &state.merges {
+            match merge::undo_merge(entry) {
+                Ok(true) => {
+                    merges_restored += 1;
+                    println!("  {} Restored merged keys in {}", "✓".green(), entry.target);
+                }
+                Ok(false) => {
+                    if self.verbose {
+                        println!(
+                            "  {} Merge target already gone: {}",
+                            "○".bright_black(),
+                            entry.target
+                        );
+                    }
+                }
+                Err(e) => {
+                    let error_msg =
+                        format!("Failed to restore merged keys in {}: {}", entry.target, e);
+                    eprintln!("  {} {}", "✗".red(), error_msg);
+                    errors.push(error_msg);
+                }
+            }
+        }
+
         let package_count = state.packages.len();
 
         let mut new_state = State::new();
@@ -1125,10 +2506,22 @@ impl Cli {
             println!("{}", "✓ Successfully undone last apply".green().bold());
             println!("  {} symlinks removed", symlinks_removed);
             println!("  {} backups restored", backups_restored);
+            if blocks_removed > 0 {
+                println!("  {} blocks removed", blocks_removed);
+            }
+            if merges_restored > 0 {
+                println!("  {} merged files restored", merges_restored);
+            }
         } else {
             println!("{}", "⚠ Undo completed with errors".yellow().bold());
             println!("  {} symlinks removed", symlinks_removed);
             println!("  {} backups restored", backups_restored);
+            if blocks_removed > 0 {
+                println!("  {} blocks removed", blocks_removed);
+            }
+            if merges_restored > 0 {
+                println!("  {} merged files restored", merges_restored);
+            }
             println!("  {} errors occurred", errors.len());
         }
 
@@ -1140,6 +2533,11 @@ impl Cli {
             );
         }
 
+        println!(
+            "  {} Earlier applies remain available via 'mimic history' and 'mimic rollback <n>'",
+            "ℹ".bright_black()
+        );
+
         if self.verbose {
             println!(
                 "  {}: {}",
@@ -1151,7 +2549,165 @@ impl Cli {
         Ok(())
     }
 
-    fn run_render(&self, template: &str) -> anyhow::Result<()> {
+    fn run_history(&self) -> anyhow::Result<()> {
+        let generations = self.generation_store()?.list()?;
+
+        if self.json() {
+            return print_json(&generations);
+        }
+
+        if generations.is_empty() {
+            println!("{}", "No generations recorded.".yellow());
+            println!("  Run 'mimic apply' to record one.");
+            return Ok(());
+        }
+
+        println!("{}", "Generations:".bold());
+        let latest = generations.last().map(|g| g.number);
+        for generation in generations.iter().rev() {
+            let mut line = format!(
+                "  {:>4}  {}",
+                format!("#{}", generation.number).cyan(),
+                generation
+                    .created_at
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M:%S")
+            );
+            line.push_str(&format!(
+                "  {} {}",
+                "host:".bright_black(),
+                generation.host.as_deref().unwrap_or("-")
+            ));
+            line.push_str(&format!(
+                "  {} {}",
+                "commit:".bright_black(),
+                generation
+                    .commit
+                    .as_deref()
+                    .map(|c| &c[..c.len().min(7)])
+                    .unwrap_or("-")
+            ));
+            line.push_str(&format!(
+                "  {}",
+                format!(
+                    "({} dotfiles, {} packages)",
+                    generation.dotfiles, generation.packages
+                )
+                .bright_black()
+            ));
+            if let Some(note) = &generation.note {
+                line.push_str(&format!("  {}", note.bright_black()));
+            }
+            if Some(generation.number) == latest {
+                line.push_str(&format!("  {}", "latest".green()));
+            }
+            println!("{}", line);
+        }
+
+        Ok(())
+    }
+
+    fn run_rollback(&self, number: u32) -> anyhow::Result<()> {
+        let store = self.generation_store()?;
+        let generation = store.load(number)?;
+        let state_path = self.get_state_path();
+        let current = State::load(&state_path).unwrap_or_default();
+
+        println!(
+            "{} {} ({}, host: {})",
+            "Rolling back to generation".bold(),
+            format!("#{}", number).cyan(),
+            generation
+                .info
+                .created_at
+                .with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M:%S"),
+            generation.info.host.as_deref().unwrap_or("-")
+        );
+        println!();
+
+        if self.dry_run {
+            for dotfile in &current.dotfiles {
+                if !generation
+                    .state
+                    .dotfiles
+                    .iter()
+                    .any(|d| d.target == dotfile.target)
+                {
+                    println!("  {} {}", "-".red(), dotfile.target);
+                }
+            }
+            for dotfile in &generation.state.dotfiles {
+                let from = dotfile.rendered_path.as_ref().unwrap_or(&dotfile.source);
+                println!(
+                    "  {} {} {} {}",
+                    "+".green(),
+                    dotfile.target,
+                    "→".bright_black(),
+                    from
+                );
+            }
+            println!();
+            println!("{}", "Dry run - no changes made".yellow());
+            return Ok(());
+        }
+
+        if !self.yes {
+            use dialoguer::Confirm;
+            let proceed = Confirm::new()
+                .with_prompt(format!("Roll back to generation {}?", number))
+                .default(false)
+                .interact()?;
+            if !proceed {
+                println!("{}", "Rollback cancelled".yellow());
+                return Ok(());
+            }
+        }
+
+        let report = generations::rollback(&generation, &current);
+        for error in &report.errors {
+            eprintln!("  {} {}", "✗".red(), error);
+        }
+
+        let mut state = generation.state;
+        state.applied_at = chrono::Utc::now();
+        state
+            .save(&state_path)
+            .map_err(|e| anyhow::anyhow!("Failed to save state: {}", e))?;
+
+        let note = format!("rollback to #{}", number);
+        if let Err(e) = store.record(&state, Some(&note)) {
+            eprintln!("{} Failed to record generation: {}", "Warning:".yellow(), e);
+        }
+
+        println!();
+        if report.errors.is_empty() {
+            println!(
+                "{}",
+                format!("✓ Rolled back to generation {}", number)
+                    .green()
+                    .bold()
+            );
+        } else {
+            println!("{}", "⚠ Rollback completed with errors".yellow().bold());
+        }
+        println!("  {} dotfiles removed", report.removed);
+        println!("  {} backups restored", report.restored);
+        println!("  {} dotfiles deployed", report.deployed);
+        if !report.errors.is_empty() {
+            println!("  {} errors occurred", report.errors.len());
+        }
+        if !state.packages.is_empty() || !current.packages.is_empty() {
+            println!(
+                "  {} Packages are not installed or removed by rollback (use 'mimic apply' or 'mimic clean')",
+                "ℹ".bright_black()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn run_render(&self, template: Option<&str>) -> anyhow::Result<()> {
         use crate::template::render_file;
 
         let config_path = self.find_config()?;
@@ -1166,28 +2722,174 @@ impl Cli {
 
         let base_config = Config::from_file(&config_path)?;
 
-        let host_name = if let Some(host) = &self.host {
-            host.clone()
-        } else {
-            Self::detect_hostname()
-        };
-
-        let merged_config = if !base_config.hosts.is_empty() {
+        let (mut merged_config, host_name) = if !base_config.hosts.is_empty() {
+            let host_name = self.select_host(&base_config)?;
             if self.verbose {
                 println!("{} {}", "Using host:".bright_black(), host_name);
             }
-            base_config.with_host(&host_name)?
+            (base_config.with_host(&host_name)?, Some(host_name))
         } else {
-            base_config
+            let host_name = self.host.clone().unwrap_or_else(Self::detect_hostname);
+            (base_config, Some(host_name))
         };
+        self.resolve_variables(&mut merged_config)?;
+
+        let host_ctx = Self::build_host_context(&merged_config, &host_name);
 
-        let host_ctx = Self::build_host_context(&merged_config, &Some(host_name));
+        let Some(template) = template else {
+            Self::print_variable_tree(&merged_config.variables);
+            return Ok(());
+        };
+        if self.verbose {
+            Self::print_variable_tree(&merged_config.variables);
+            println!();
+        }
 
         let template_path = PathBuf::from(template);
         let rendered = render_file(&template_path, &merged_config.variables, &host_ctx)?;
 
-        println!("{}", rendered);
-        Ok(())
+        println!("{}", rendered);
+        Ok(())
+    }
+
+    fn run_vars(&self) -> anyhow::Result<()> {
+        let (mut config, host_name) = self.merged_config_and_host()?;
+        let variables = self.resolve_variables(&mut config)?;
+
+        if self.json() {
+            return print_json(&VarsReport {
+                host: host_name,
+                data_file: self.get_data_path(),
+                variables,
+            });
+        }
+
+        match &host_name {
+            Some(host) => println!("{} {}", "Variables for host".bold(), host.green()),
+            None => println!("{}", "Variables:".bold()),
+        }
+        if variables.is_empty() {
+            println!("  {}", "(none)".bright_black());
+        }
+        for variable in &variables {
+            println!(
+                "  {} = {} {}",
+                variable.name,
+                Self::format_variable(&variable.value),
+                Self::format_origin(&variable.source)
+            );
+        }
+        if variables.iter().any(|v| v.source.starts_with("prompt")) {
+            println!();
+            println!(
+                "{}",
+                format!(
+                    "Prompt answers are saved in {}; delete an entry to be asked again.",
+                    self.get_data_path().display()
+                )
+                .bright_black()
+            );
+        }
+        Ok(())
+    }
+
+    fn run_check(&self) -> anyhow::Result<()> {
+        let config_path = self.find_config()?;
+        let findings = check::check(&config_path)?;
+        let count = |severity| findings.iter().filter(|f| f.severity == severity).count();
+        let (errors, warnings) = (count(Severity::Error), count(Severity::Warning));
+
+        if self.json() {
+            print_json(&CheckReport {
+                findings,
+                errors,
+                warnings,
+            })?;
+        } else {
+            for finding in &findings {
+                let location = match finding.line {
+                    Some(line) => format!("{}:{}:", finding.file.display(), line),
+                    None => format!("{}:", finding.file.display()),
+                };
+                let severity = match finding.severity {
+                    Severity::Error => "error:".red().bold(),
+                    Severity::Warning => "warning:".yellow().bold(),
+                };
+                println!("{} {} {}", location.bold(), severity, finding.message);
+            }
+
+            if findings.is_empty() {
+                println!(
+                    "{} No problems found in {}",
+                    "✓".green(),
+                    config_path.display()
+                );
+            } else {
+                let plural = |n: usize, word: &str| {
+                    format!("{} {}{}", n, word, if n == 1 { "" } else { "s" })
+                };
+                let summary = format!(
+                    "{}, {}",
+                    plural(errors, "error"),
+                    plural(warnings, "warning")
+                );
+                println!();
+                if errors > 0 {
+                    println!("{} {}", "✗".red(), summary);
+                } else {
+                    println!("{} {}", "⚠".yellow(), summary);
+                }
+            }
+        }
+
+        if errors > 0 {
+            return Err(CheckFailed.into());
+        }
+        Ok(())
+    }
+
+    /// Print the variables templates see, one value per line and nested
+    /// tables indented under their key.
+    fn print_variable_tree(variables: &config::Variables) {
+        println!("{}", "Variables:".bold());
+        if variables.is_empty() {
+            println!("  {}", "(none)".bright_black());
+            return;
+        }
+        let mut keys: Vec<&String> = variables.keys().collect();
+        keys.sort();
+        for key in keys {
+            Self::print_variable_node(key, &variables[key], 1);
+        }
+    }
+
+    fn print_variable_node(key: &str, value: &serde_json::Value, depth: usize) {
+        let indent = "  ".repeat(depth);
+        match value {
+            serde_json::Value::Object(table) => {
+                println!("{}{}", indent, key.cyan());
+                for (child, value) in table {
+                    Self::print_variable_node(child, value, depth + 1);
+                }
+            }
+            serde_json::Value::Array(items)
+                if items.iter().any(|v| v.is_object() || v.is_array()) =>
+            {
+                println!("{}{}", indent, key.cyan());
+                for (index, item) in items.iter().enumerate() {
+                    Self::print_variable_node(&format!("[{}]", index), item, depth + 1);
+                }
+            }
+            _ => println!("{}{} = {}", indent, key.cyan(), value),
+        }
+    }
+
+    /// A variable on one line: strings as written, anything else as JSON.
+    fn format_variable(value: &serde_json::Value) -> String {
+        match value {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string(),
+        }
     }
 
     fn run_secrets(&self, cmd: &SecretsCommands) -> anyhow::Result<()> {
@@ -1401,17 +3103,20 @@ impl Cli {
             }
 
             let apply_cli = Cli {
-                command: Commands::Apply,
+                command: Commands::Apply { prune: false },
                 config: Some(config_path),
                 host: self.host.clone(),
                 yes: true,
                 dry_run: self.dry_run,
                 verbose: self.verbose,
                 state: self.state.clone(),
+                offline: self.offline,
+                refresh: self.refresh,
+                output: self.output,
                 branch: self.branch.clone(),
             };
 
-            apply_cli.run_apply()?;
+            apply_cli.run_apply(false)?;
         }
 
         println!();
@@ -1485,12 +3190,9 @@ impl Cli {
         use std::env;
         use std::process::Command;
 
-        let expanded_target = if target.starts_with("~/") {
+        let expanded_target = if let Some(rest) = target.strip_prefix("~/") {
             if let Some(home) = directories::BaseDirs::new() {
-                home.home_dir()
-                    .join(&target[2..])
-                    .to_string_lossy()
-                    .to_string()
+                home.home_dir().join(rest).to_string_lossy().to_string()
             } else {
                 target.to_string()
             }
@@ -1601,6 +3303,570 @@ impl Cli {
         Ok(())
     }
 
+    /// Encryption settings from the config, or the defaults if there is none.
+    fn encryption_settings(&self) -> anyhow::Result<config::EncryptionConfig> {
+        match self.find_config() {
+            Ok(config_path) => Ok(Config::from_file(&config_path)?.encryption),
+            Err(_) => Ok(config::EncryptionConfig::default()),
+        }
+    }
+
+    fn run_encrypt(&self, path: &str, keep: bool) -> anyhow::Result<()> {
+        use crate::encryption;
+        use crate::expand::expand_path_str;
+        use std::fs;
+
+        let plaintext_path = expand_path_str(path)?;
+        if plaintext_path.extension().and_then(|e| e.to_str())
+            == Some(encryption::ENCRYPTED_EXTENSION)
+        {
+            return Err(anyhow::anyhow!(
+                "{} is already encrypted\n\nTo fix:\n  - Run 'mimic decrypt {}' to see its contents",
+                plaintext_path.display(),
+                path
+            ));
+        }
+        let plaintext = fs::read(&plaintext_path).with_context(|| {
+            format!(
+                "File not found: {}\n\nTo fix:\n  - Check that the path is correct",
+                plaintext_path.display()
+            )
+        })?;
+        let encrypted_path = encryption::encrypted_path_for(&plaintext_path);
+
+        if self.dry_run {
+            println!(
+                "{} Would encrypt {} → {}",
+                "→".bright_black(),
+                plaintext_path.display(),
+                encrypted_path.display()
+            );
+            return Ok(());
+        }
+
+        let settings = self.encryption_settings()?;
+        let (identity, generated) = encryption::load_or_generate_identity(&settings)?;
+        if let Some(key_path) = generated {
+            println!(
+                "{} Generated a new age key at {}",
+                "ℹ".bright_black(),
+                key_path.display()
+            );
+            println!(
+                "  {} Back it up: encrypted files can't be decrypted without it",
+                "⚠".yellow()
+            );
+        }
+
+        let recipients = encryption::recipients(&settings, &identity)?;
+        let ciphertext = encryption::encrypt(&plaintext, &recipients)?;
+        fs::write(&encrypted_path, ciphertext)
+            .with_context(|| format!("Failed to write: {}", encrypted_path.display()))?;
+        if !keep {
+            fs::remove_file(&plaintext_path).with_context(|| {
+                format!("Failed to remove plaintext: {}", plaintext_path.display())
+            })?;
+        }
+
+        println!(
+            "{} Encrypted {} → {}",
+            "✓".green(),
+            plaintext_path.display(),
+            encrypted_path.display()
+        );
+        println!(
+            "  {} Deploy it with a [[dotfiles]] entry using encrypted = true",
+            "ℹ".bright_black()
+        );
+        Ok(())
+    }
+
+    fn run_update(&self, repos: &[String]) -> anyhow::Result<()> {
+        use crate::lock::{Lockfile, lock_path_for};
+
+        if self.offline {
+            return Err(anyhow::anyhow!(
+                "mimic update needs to fetch extends repos\n\nTo fix:\n  - Run it without --offline"
+            ));
+        }
+
+        let config_path = self.find_config()?;
+
+        // Check the names before anything is fetched or the lock rewritten.
+        // Repos extended by extends repos are only known from the lock file.
+        let mut known = Config::local_extends(&config_path)?;
+        for locked in Lockfile::load(&lock_path_for(&config_path))?.extends {
+            if !known.contains(&locked.repo) {
+                known.push(locked.repo);
+            }
+        }
+        if let Some(unknown) = repos.iter().find(|repo| !known.contains(repo)) {
+            return Err(anyhow::anyhow!(
+                "'{}' is not an extends repo of {}\n\nTo fix:\n  - Use one of: {}",
+                unknown,
+                config_path.display(),
+                if known.is_empty() {
+                    "(none configured)".to_string()
+                } else {
+                    known.join(", ")
+                }
+            ));
+        }
+
+        let changes = Config::update_extends(&config_path, repos)?;
+
+        if changes.is_empty() {
+            println!("{}", "All extends repos are up to date.".bright_black());
+            return Ok(());
+        }
+
+        for change in &changes {
+            let short = |commit: &str| commit.chars().take(12).collect::<String>();
+            let spec = match &change.spec {
+                Some((from, to)) => format!(" ({} → {})", from, to).bright_black().to_string(),
+                None => String::new(),
+            };
+            match &change.from {
+                Some(from) => println!(
+                    "  {} {} {} → {}{}",
+                    "↑".green(),
+                    change.repo,
+                    short(from).bright_black(),
+                    short(&change.to),
+                    spec
+                ),
+                None => println!(
+                    "  {} {} locked at {}",
+                    "+".green(),
+                    change.repo,
+                    short(&change.to)
+                ),
+            }
+        }
+        println!();
+        println!(
+            "{} {}",
+            "Updated".green(),
+            lock_path_for(&config_path).display()
+        );
+        Ok(())
+    }
+
+    fn run_decrypt(&self, path: &str) -> anyhow::Result<()> {
+        use crate::encryption;
+        use crate::expand::expand_path_str;
+        use std::io::Write;
+
+        let encrypted_path = expand_path_str(path)?;
+        let plaintext = encryption::decrypt_file(&encrypted_path, &self.encryption_settings()?)?;
+        std::io::stdout().write_all(&plaintext)?;
+        Ok(())
+    }
+
+    fn run_import(
+        &self,
+        from: crate::import::ImportFrom,
+        path: &str,
+        force: bool,
+    ) -> anyhow::Result<()> {
+        use crate::expand::expand_path_str;
+        use crate::import;
+        use crate::output::ImportReport;
+        use std::fs;
+
+        let root = std::path::absolute(expand_path_str(path)?)?;
+        let mut imported = import::import(from, &root)?;
+        if imported.dotfile_count() == 0 {
+            return Err(anyhow::anyhow!(
+                "No dotfiles found in {}\n\nTo fix:\n  - Check that --from {} matches how the directory is laid out",
+                root.display(),
+                from.name()
+            ));
+        }
+
+        let config_path = self
+            .config
+            .clone()
+            .unwrap_or_else(|| root.join("mimic.toml"));
+        let config_path = std::path::absolute(&config_path)?;
+        // Sources are relative to the config's directory
+        if config_path.parent() != Some(root.as_path()) {
+            imported.relocate(&root);
+        }
+        if config_path.exists() && !force && !self.dry_run {
+            return Err(anyhow::anyhow!(
+                "{} already exists\n\nTo fix:\n  - Pass --force to overwrite it\n  - Or write somewhere else with --config <path>",
+                config_path.display()
+            ));
+        }
+
+        let rendered = import::render(&imported, from, &root);
+        if !self.dry_run {
+            fs::write(&config_path, &rendered)
+                .with_context(|| format!("Failed to write config: {}", config_path.display()))?;
+        }
+
+        if self.json() {
+            return print_json(&ImportReport {
+                config: config_path,
+                written: !self.dry_run,
+                dotfiles: imported.dotfile_count(),
+                notes: imported.notes,
+            });
+        }
+
+        if self.dry_run {
+            print!("{}", rendered);
+            println!();
+        } else {
+            println!(
+                "{} Wrote {} dotfiles to {}",
+                "✓".green(),
+                imported.dotfile_count(),
+                config_path.display().to_string().cyan()
+            );
+        }
+        if !imported.notes.is_empty() {
+            println!();
+            println!("{}", "Could not translate:".yellow().bold());
+            for note in &imported.notes {
+                println!("  {} {}: {}", "⚠".yellow(), note.path, note.message);
+            }
+        }
+        println!();
+        if self.dry_run {
+            println!("{}", "Dry-run mode: No changes were made.".yellow());
+        } else {
+            println!(
+                "{}",
+                "Run 'mimic check' and 'mimic diff' before the first 'mimic apply'.".bright_black()
+            );
+        }
+        Ok(())
+    }
+
+    fn run_brewfile(&self, cmd: &BrewfileCommands) -> anyhow::Result<()> {
+        use crate::brewfile;
+        use crate::expand::expand_path_str;
+        use std::fs;
+
+        match cmd {
+            BrewfileCommands::Import { path } => {
+                let brewfile_path = expand_path_str(path)?;
+                let content = fs::read_to_string(&brewfile_path).map_err(|_| {
+                    anyhow::anyhow!(
+                        "Brewfile not found: {}\n\nTo fix:\n  - Check that the path is correct\n  - Or run 'brew bundle dump' to create one",
+                        brewfile_path.display()
+                    )
+                })?;
+                let parsed = brewfile::parse(&content);
+                if parsed.packages.is_empty() {
+                    return Err(anyhow::anyhow!(
+                        "No tap, brew or cask entries found in {}\n\nTo fix:\n  - Check that it is a Homebrew Bundle Brewfile",
+                        brewfile_path.display()
+                    ));
+                }
+
+                let config_path = self.find_config()?;
+                let config_text = fs::read_to_string(&config_path)
+                    .with_context(|| format!("Failed to read config: {}", config_path.display()))?;
+                let (updated_config, added) =
+                    brewfile::add_packages(&config_text, &parsed.packages, self.host.as_deref())?;
+
+                let section = match &self.host {
+                    Some(host) => format!("[hosts.{}.packages]", host),
+                    None => "[packages]".to_string(),
+                };
+                println!(
+                    "{} {} → {}",
+                    "Importing".bold(),
+                    brewfile_path.display().to_string().cyan(),
+                    section.cyan()
+                );
+                for package in &added {
+                    let keyword = match package.pkg_type.as_str() {
+                        "formula" => "brew",
+                        other => other,
+                    };
+                    match &package.when {
+                        Some(when) => println!(
+                            "  {} {} {} {}",
+                            "+".green(),
+                            keyword,
+                            package.name,
+                            format!("(when {})", when).bright_black()
+                        ),
+                        None => println!("  {} {} {}", "+".green(), keyword, package.name),
+                    }
+                }
+                let already_listed = parsed.packages.len() - added.len();
+                if already_listed > 0 {
+                    println!("  {} {} already listed", "=".bright_black(), already_listed);
+                }
+                if !parsed.notes.is_empty() {
+                    println!();
+                    println!("{}", "Not imported:".yellow().bold());
+                    for note in &parsed.notes {
+                        println!(
+                            "  {} {}:{}: {}",
+                            "⚠".yellow(),
+                            path,
+                            note.line,
+                            note.message
+                        );
+                    }
+                }
+
+                if self.dry_run {
+                    println!();
+                    println!("{}", "Dry-run mode: No changes were made.".yellow());
+                    return Ok(());
+                }
+                if !added.is_empty() {
+                    fs::write(&config_path, updated_config).with_context(|| {
+                        format!("Failed to write config: {}", config_path.display())
+                    })?;
+                }
+                println!();
+                println!(
+                    "{} Added {} packages to {}",
+                    "✓".green(),
+                    added.len(),
+                    config_path.display()
+                );
+                Ok(())
+            }
+            BrewfileCommands::Export { path } => {
+                let (config, host_name) = self.resolve_config_and_host()?;
+                let host_roles = Self::get_host_roles(&config, &host_name);
+                let facts = Facts::new(&Self::detect_hostname(), &config.variables);
+                let filtered = Self::filter_config(config, &host_roles, &facts);
+
+                let header = match &host_name {
+                    Some(host) => format!("Generated by `mimic brewfile export` for host {}", host),
+                    None => "Generated by `mimic brewfile export`".to_string(),
+                };
+                let rendered = brewfile::render(&filtered.packages, &header);
+
+                let Some(path) = path else {
+                    print!("{}", rendered);
+                    return Ok(());
+                };
+                let output_path = expand_path_str(path)?;
+                if self.dry_run {
+                    print!("{}", rendered);
+                    println!();
+                    println!("{}", "Dry-run mode: No changes were made.".yellow());
+                    return Ok(());
+                }
+                fs::write(&output_path, &rendered)
+                    .with_context(|| format!("Failed to write {}", output_path.display()))?;
+                let count = rendered
+                    .lines()
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .count();
+                println!(
+                    "{} Wrote {} packages to {}",
+                    "✓".green(),
+                    count,
+                    output_path.display().to_string().cyan()
+                );
+                Ok(())
+            }
+        }
+    }
+
+    fn run_add(&self, path: &str, template: bool) -> anyhow::Result<()> {
+        use crate::adopt;
+        use crate::config::Dotfile;
+        use crate::expand::expand_path_str;
+        use crate::secrets_scan::scan_for_secrets;
+        use dialoguer::Confirm;
+        use std::fs;
+
+        let config_path = self.find_config()?;
+        let config_dir = fs::canonicalize(&config_path)
+            .with_context(|| format!("Failed to resolve config path: {}", config_path.display()))?
+            .parent()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| anyhow::anyhow!("Invalid config path: {}", config_path.display()))?;
+
+        let original = std::path::absolute(expand_path_str(path)?)?;
+        let metadata = fs::symlink_metadata(&original).map_err(|_| {
+            anyhow::anyhow!(
+                "File not found: {}\n\nTo fix:\n  - Check that the path is correct\n  - Use an absolute path or ~/...",
+                original.display()
+            )
+        })?;
+
+        if metadata.file_type().is_symlink() {
+            return Err(anyhow::anyhow!(
+                "{} is a symlink\n\nTo fix:\n  - It may already be managed: run 'mimic status'\n  - Or add the file it points to instead",
+                original.display()
+            ));
+        }
+
+        if original.starts_with(&config_dir) {
+            return Err(anyhow::anyhow!(
+                "{} is already inside the config repo\n\nTo fix:\n  - Add a [[dotfiles]] entry for it to mimic.toml by hand",
+                original.display()
+            ));
+        }
+
+        let config = Config::from_file(&config_path)?;
+        let already_managed = config
+            .dotfiles
+            .iter()
+            .chain(config.hosts.values().flat_map(|host| host.dotfiles.iter()))
+            .chain(config.roles.values().flat_map(|role| role.dotfiles.iter()))
+            .any(|dotfile| expand_path_str(&dotfile.target).is_ok_and(|target| target == original));
+        if already_managed {
+            return Err(anyhow::anyhow!(
+                "{} is already a target in the config\n\nTo fix:\n  - Run 'mimic edit {}' to change it\n  - Or run 'mimic apply' to deploy it",
+                original.display(),
+                path
+            ));
+        }
+
+        let destination = adopt::repo_destination(&config_dir, &original)?;
+        if fs::symlink_metadata(&destination).is_ok() {
+            return Err(anyhow::anyhow!(
+                "{} already exists in the repo\n\nTo fix:\n  - Rename or remove the existing file\n  - Or add an entry for it to mimic.toml by hand",
+                destination.display()
+            ));
+        }
+
+        let source_entry = format!(
+            "{}/{}",
+            adopt::DOTFILES_DIR,
+            adopt::repo_file_name(&original)?
+        );
+        let target_entry = adopt::config_target(&original);
+        let config_text = fs::read_to_string(&config_path)
+            .with_context(|| format!("Failed to read config: {}", config_path.display()))?;
+        let updated_config = adopt::append_dotfile_entry(
+            &config_text,
+            &source_entry,
+            &target_entry,
+            template,
+            self.host.as_deref(),
+        )?;
+
+        // Templates are rendered with the host's variables; resolve them up
+        // front so a bad host fails before anything is moved
+        let (merged_config, host_name) = if template {
+            self.resolve_config_and_host()?
+        } else {
+            (config, self.host.clone())
+        };
+        let host_ctx = Self::build_host_context(&merged_config, &host_name);
+
+        println!(
+            "{} {} → {}",
+            "Adding".bold(),
+            target_entry.cyan(),
+            source_entry.cyan()
+        );
+        if let Some(host) = &self.host {
+            println!("  {} host {}", "→".bright_black(), host);
+        }
+
+        if self.dry_run {
+            println!();
+            println!("{}", "Dry-run mode: No changes were made.".yellow());
+            return Ok(());
+        }
+
+        let paths_to_scan: Vec<PathBuf> = walkdir::WalkDir::new(&original)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .map(|e| e.path().to_path_buf())
+            .collect();
+
+        if scan_for_secrets(&paths_to_scan)? > 0 {
+            println!();
+            println!("{}", "⚠ Secrets detected in file".yellow().bold());
+            println!(
+                "{}",
+                "Consider keeping them in the secrets store instead: mimic secrets set <key>"
+                    .bright_black()
+            );
+            println!();
+
+            if !self.yes {
+                let proceed = Confirm::new()
+                    .with_prompt("Secrets detected. Move the file into the repo anyway?")
+                    .default(false)
+                    .interact()?;
+
+                if !proceed {
+                    println!("{}", "Aborted.".yellow());
+                    return Ok(());
+                }
+            } else {
+                println!(
+                    "{}",
+                    "Warning: --yes flag enabled, continuing despite secrets"
+                        .yellow()
+                        .bold()
+                );
+            }
+        }
+
+        adopt::move_path(&original, &destination)?;
+        println!(
+            "  {} Moved to {}",
+            "✓".green(),
+            destination.display().to_string().green()
+        );
+
+        fs::write(&config_path, updated_config)
+            .with_context(|| format!("Failed to write config: {}", config_path.display()))?;
+        println!(
+            "  {} Added entry to {}",
+            "✓".green(),
+            config_path.display().to_string().green()
+        );
+
+        let dotfile = Dotfile {
+            source: destination.to_string_lossy().to_string(),
+            target: original.to_string_lossy().to_string(),
+            template,
+            encrypted: false,
+            mode: Default::default(),
+            link: Default::default(),
+            permissions: None,
+            dir_permissions: None,
+            only_roles: None,
+            skip_roles: None,
+            when: None,
+            enabled: true,
+        };
+
+        let state_path = self.get_state_path();
+        let mut state = State::load(&state_path).unwrap_or_else(|_| State::new());
+        apply_dotfile(&dotfile, &merged_config, &host_ctx, &mut state, &mut None).with_context(
+            || {
+                format!(
+                    "Failed to link {}\n\nTo fix:\n  - The file is now at {}\n  - Fix the problem and run 'mimic apply'",
+                    original.display(),
+                    destination.display()
+                )
+            },
+        )?;
+        state.save(&state_path)?;
+        println!("  {} Linked {}", "✓".green(), target_entry.green());
+
+        println!();
+        println!(
+            "{}",
+            "Commit the new file and mimic.toml to your dotfiles repo.".bright_black()
+        );
+
+        Ok(())
+    }
+
     fn run_clean(&self) -> anyhow::Result<()> {
         let (config, _host_name) = self.resolve_config_and_host()?;
 
@@ -1617,11 +3883,8 @@ impl Cli {
             .filter(|p| p.pkg_type == "cask")
             .map(|p| p.name.clone())
             .collect();
-        let config_zb: std::collections::HashSet<String> = normalized
-            .zerobrew
-            .iter()
-            .map(|p| p.name.clone())
-            .collect();
+        let config_zb: std::collections::HashSet<String> =
+            normalized.zerobrew.iter().map(|p| p.name.clone()).collect();
 
         let homebrew = HomebrewManager::new();
         let installed_formulas = homebrew.list_installed()?;
@@ -1768,14 +4031,29 @@ impl Cli {
 
 pub fn run() -> Result<(), i32> {
     let cli = Cli::parse();
+    crate::output::set_progress_to_stderr(cli.json());
+    config::set_extends_fetch(if cli.offline {
+        config::ExtendsFetch::Offline
+    } else if cli.refresh {
+        config::ExtendsFetch::Refresh
+    } else {
+        config::ExtendsFetch::Default
+    });
 
     match cli.run() {
         Ok(()) => Ok(()),
         Err(e) => {
-            // Drift detection returns a silent exit code 1
-            // (the user-facing message was already printed)
-            if e.to_string() == "__drift_detected__" {
-                return Err(1);
+            // Drift was already reported; only the exit code is left to set
+            if e.downcast_ref::<DriftDetected>().is_some()
+                || e.downcast_ref::<CheckFailed>().is_some()
+            {
+                return Err(exit_code::DRIFT);
+            }
+
+            if cli.json() {
+                let _ = print_json(&ErrorReport {
+                    error: e.to_string(),
+                });
             }
 
             eprintln!("{} {}", "Error:".red().bold(), e);
@@ -1788,7 +4066,7 @@ pub fn run() -> Result<(), i32> {
                 eprintln!("  {}", source);
             }
 
-            Err(1)
+            Err(exit_code::ERROR)
         }
     }
 }
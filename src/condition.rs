@@ -0,0 +1,469 @@
+//! `when` expressions on dotfiles, packages and hooks.
+//!
+//! A condition is a small boolean expression over facts about the machine:
+//!
+//! ```toml
+//! when = "os == 'linux' && has('nvim')"
+//! ```
+//!
+//! - `os`, `arch`, `hostname` — e.g. `'macos'`, `'aarch64'`
+//! - `env.NAME` — an environment variable, empty if unset
+//! - `variables.NAME` — a config variable, empty if unset; nested tables are
+//!   reached with more dots, e.g. `variables.git.email`
+//! - `has('cmd')` — the command is on `PATH`
+//! - `exists('~/path')` — the file or directory exists
+//!
+//! Values compare with `==` and `!=` and combine with `&&`, `||`, `!` and
+//! parentheses. A value on its own is true unless it is empty, `'false'` or
+//! `'0'`. Expressions are checked when the config is loaded, so evaluating one
+//! never fails.
+
+use crate::config::Variables;
+use crate::expand::expand_path_str;
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+/// Facts a condition can test, collected once per run.
+pub struct Facts {
+    pub os: String,
+    pub arch: String,
+    pub hostname: String,
+    pub variables: Variables,
+    commands: RefCell<HashMap<String, bool>>,
+}
+
+impl Facts {
+    pub fn new(hostname: &str, variables: &Variables) -> Self {
+        Self {
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            hostname: hostname.to_string(),
+            variables: variables.clone(),
+            commands: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Look up `variables.a.b`: booleans stay booleans, numbers and strings
+    /// compare as text, lists and tables are true unless empty.
+    fn variable(&self, path: &str) -> Value {
+        let mut parts = path.split('.');
+        let mut value = parts.next().and_then(|name| self.variables.get(name));
+        for part in parts {
+            value = value.and_then(|v| v.get(part));
+        }
+        match value {
+            None | Some(serde_json::Value::Null) => Value::Str(String::new()),
+            Some(serde_json::Value::Bool(b)) => Value::Bool(*b),
+            Some(serde_json::Value::String(s)) => Value::Str(s.clone()),
+            Some(serde_json::Value::Number(n)) => Value::Str(n.to_string()),
+            Some(serde_json::Value::Array(items)) => Value::Bool(!items.is_empty()),
+            Some(serde_json::Value::Object(table)) => Value::Bool(!table.is_empty()),
+        }
+    }
+
+    fn has(&self, command: &str) -> bool {
+        *self
+            .commands
+            .borrow_mut()
+            .entry(command.to_string())
+            .or_insert_with(|| crate::hooks::command_exists(command))
+    }
+
+    /// Evaluate a `when` expression. An expression that doesn't parse is
+    /// false; `validate` reports those when the config is loaded.
+    pub fn eval(&self, when: &str) -> bool {
+        parse(when).is_ok_and(|expr| expr.eval(self).truthy())
+    }
+
+    /// Whether an optional condition holds (no condition always holds).
+    pub fn allows(&self, when: &Option<String>) -> bool {
+        when.as_deref().is_none_or(|when| self.eval(when))
+    }
+}
+
+/// Check that a `when` expression parses.
+pub fn validate(when: &str) -> anyhow::Result<()> {
+    parse(when).map(|_| ())
+}
+
+/// The top-level variable names a `when` expression reads, e.g. `git` for
+/// `variables.git.email`. Empty if it doesn't parse.
+pub fn variables(when: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    if let Ok(expr) = parse(when) {
+        expr.collect_variables(&mut names);
+    }
+    names
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Bool(bool),
+    Str(String),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            Value::Str(s) => !s.is_empty() && s != "false" && s != "0",
+        }
+    }
+
+    fn as_str(&self) -> String {
+        match self {
+            Value::Bool(b) => b.to_string(),
+            Value::Str(s) => s.clone(),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value),
+    Os,
+    Arch,
+    Hostname,
+    Env(String),
+    Variable(String),
+    Has(String),
+    Exists(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Eq(Box<Expr>, Box<Expr>),
+    Ne(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn collect_variables(&self, names: &mut Vec<String>) {
+        match self {
+            Expr::Variable(path) => {
+                let name = path.split('.').next().unwrap_or(path);
+                if !names.iter().any(|n| n == name) {
+                    names.push(name.to_string());
+                }
+            }
+            Expr::Not(expr) => expr.collect_variables(names),
+            Expr::And(a, b) | Expr::Or(a, b) | Expr::Eq(a, b) | Expr::Ne(a, b) => {
+                a.collect_variables(names);
+                b.collect_variables(names);
+            }
+            _ => {}
+        }
+    }
+
+    fn eval(&self, facts: &Facts) -> Value {
+        match self {
+            Expr::Literal(value) => value.clone(),
+            Expr::Os => Value::Str(facts.os.clone()),
+            Expr::Arch => Value::Str(facts.arch.clone()),
+            Expr::Hostname => Value::Str(facts.hostname.clone()),
+            Expr::Env(name) => Value::Str(std::env::var(name).unwrap_or_default()),
+            Expr::Variable(name) => facts.variable(name),
+            Expr::Has(command) => Value::Bool(facts.has(command)),
+            Expr::Exists(path) => {
+                Value::Bool(expand_path_str(path).is_ok_and(|p| p.exists() || p.is_symlink()))
+            }
+            Expr::Not(expr) => Value::Bool(!expr.eval(facts).truthy()),
+            Expr::And(a, b) => Value::Bool(a.eval(facts).truthy() && b.eval(facts).truthy()),
+            Expr::Or(a, b) => Value::Bool(a.eval(facts).truthy() || b.eval(facts).truthy()),
+            Expr::Eq(a, b) => Value::Bool(a.eval(facts).as_str() == b.eval(facts).as_str()),
+            Expr::Ne(a, b) => Value::Bool(a.eval(facts).as_str() != b.eval(facts).as_str()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    LParen,
+    RParen,
+    Eq,
+    Ne,
+    And,
+    Or,
+    Not,
+}
+
+fn tokenize(input: &str) -> anyhow::Result<Vec<(usize, Token)>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+        let token = match c {
+            _ if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == c)
+                    .ok_or_else(|| syntax_error(input, start, "unterminated string"))?;
+                let value: String = chars[i + 1..i + 1 + end].iter().collect();
+                i += end + 2;
+                tokens.push((start, Token::Str(value)));
+                continue;
+            }
+            _ if two == "==" => Token::Eq,
+            _ if two == "!=" => Token::Ne,
+            _ if two == "&&" => Token::And,
+            _ if two == "||" => Token::Or,
+            '!' => Token::Not,
+            _ if c.is_alphanumeric() || c == '_' => {
+                let len = chars[i..]
+                    .iter()
+                    .position(|&ch| !(ch.is_alphanumeric() || ch == '_' || ch == '.'))
+                    .unwrap_or(chars.len() - i);
+                let ident: String = chars[i..i + len].iter().collect();
+                i += len;
+                tokens.push((start, Token::Ident(ident)));
+                continue;
+            }
+            _ => return Err(syntax_error(input, start, &format!("unexpected '{}'", c))),
+        };
+        i += match token {
+            Token::Eq | Token::Ne | Token::And | Token::Or => 2,
+            _ => 1,
+        };
+        tokens.push((start, token));
+    }
+
+    Ok(tokens)
+}
+
+fn syntax_error(input: &str, position: usize, message: &str) -> anyhow::Error {
+    anyhow::anyhow!(
+        "Invalid when expression \"{}\": {} at position {}\n\nTo fix:\n  - Use facts os, arch, hostname, env.NAME, variables.NAME\n  - Use functions has('cmd') and exists('path')\n  - Combine with ==, !=, &&, || and !",
+        input,
+        message,
+        position + 1
+    )
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+}
+
+fn parse(input: &str) -> anyhow::Result<Expr> {
+    let mut parser = Parser {
+        input,
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let expr = parser.or()?;
+    if let Some((position, _)) = parser.tokens.get(parser.pos) {
+        return Err(syntax_error(input, *position, "unexpected input"));
+    }
+    Ok(expr)
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, token)| token)
+    }
+
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.input.chars().count(), |(position, _)| *position)
+    }
+
+    fn expect(&mut self, expected: Token, what: &str) -> anyhow::Result<()> {
+        if self.peek() == Some(&expected) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(syntax_error(
+                self.input,
+                self.position(),
+                &format!("expected {}", what),
+            ))
+        }
+    }
+
+    fn or(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> anyhow::Result<Expr> {
+        let mut expr = self.comparison()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            expr = Expr::And(Box::new(expr), Box::new(self.comparison()?));
+        }
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> anyhow::Result<Expr> {
+        let left = self.unary()?;
+        match self.peek() {
+            Some(Token::Eq) => {
+                self.pos += 1;
+                Ok(Expr::Eq(Box::new(left), Box::new(self.unary()?)))
+            }
+            Some(Token::Ne) => {
+                self.pos += 1;
+                Ok(Expr::Ne(Box::new(left), Box::new(self.unary()?)))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn unary(&mut self) -> anyhow::Result<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> anyhow::Result<Expr> {
+        let position = self.position();
+        let Some((_, token)) = self.tokens.get(self.pos).cloned() else {
+            return Err(syntax_error(self.input, position, "unexpected end"));
+        };
+        self.pos += 1;
+
+        match token {
+            Token::LParen => {
+                let expr = self.or()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(expr)
+            }
+            Token::Str(value) => Ok(Expr::Literal(Value::Str(value))),
+            Token::Ident(ident) => self.ident(&ident, position),
+            _ => Err(syntax_error(self.input, position, "expected a value")),
+        }
+    }
+
+    fn ident(&mut self, ident: &str, position: usize) -> anyhow::Result<Expr> {
+        if self.peek() == Some(&Token::LParen) {
+            self.pos += 1;
+            let argument = match self.tokens.get(self.pos).cloned() {
+                Some((_, Token::Str(value))) => value,
+                _ => {
+                    return Err(syntax_error(
+                        self.input,
+                        self.position(),
+                        "expected a quoted argument",
+                    ));
+                }
+            };
+            self.pos += 1;
+            self.expect(Token::RParen, "')'")?;
+            return match ident {
+                "has" => Ok(Expr::Has(argument)),
+                "exists" => Ok(Expr::Exists(argument)),
+                _ => Err(syntax_error(
+                    self.input,
+                    position,
+                    &format!("unknown function '{}'", ident),
+                )),
+            };
+        }
+
+        match ident {
+            "os" => Ok(Expr::Os),
+            "arch" => Ok(Expr::Arch),
+            "hostname" => Ok(Expr::Hostname),
+            "true" => Ok(Expr::Literal(Value::Bool(true))),
+            "false" => Ok(Expr::Literal(Value::Bool(false))),
+            _ => {
+                if let Some(name) = ident.strip_prefix("env.").filter(|n| !n.is_empty()) {
+                    Ok(Expr::Env(name.to_string()))
+                } else if let Some(name) =
+                    ident.strip_prefix("variables.").filter(|n| !n.is_empty())
+                {
+                    Ok(Expr::Variable(name.to_string()))
+                } else {
+                    Err(syntax_error(
+                        self.input,
+                        position,
+                        &format!("unknown fact '{}'", ident),
+                    ))
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn facts() -> Facts {
+        let mut facts = Facts::new(
+            "laptop",
+            &HashMap::from([
+                ("editor".to_string(), "nvim".into()),
+                (
+                    "git".to_string(),
+                    serde_json::json!({ "signing": true, "email": "me@example.com" }),
+                ),
+                ("ports".to_string(), serde_json::json!([])),
+            ]),
+        );
+        facts.os = "linux".to_string();
+        facts.arch = "x86_64".to_string();
+        facts
+    }
+
+    #[test]
+    fn test_facts_and_operators() {
+        let facts = facts();
+        assert!(facts.eval("os == 'linux'"));
+        assert!(!facts.eval("os == \"macos\""));
+        assert!(facts.eval("os != 'macos' && arch == 'x86_64'"));
+        assert!(facts.eval("os == 'macos' || hostname == 'laptop'"));
+        assert!(facts.eval("!(os == 'macos')"));
+        assert!(facts.eval("variables.editor == 'nvim'"));
+        assert!(!facts.eval("variables.missing"));
+        assert!(facts.eval("variables.git.signing && variables.git.email == 'me@example.com'"));
+        assert!(!facts.eval("variables.ports || variables.git.missing"));
+        assert!(facts.eval("has('sh') && !has('definitely-not-a-command-xyz')"));
+        assert!(facts.eval("exists('/') && !exists('/definitely/not/here')"));
+        assert!(facts.allows(&None));
+    }
+
+    #[test]
+    fn test_variables_read_by_an_expression() {
+        assert_eq!(
+            variables("variables.git.email == 'a' || !variables.work && variables.git.signing"),
+            vec!["git", "work"]
+        );
+        assert!(variables("os == 'linux'").is_empty());
+    }
+
+    #[test]
+    fn test_invalid_expressions_are_reported() {
+        for bad in [
+            "os ==",
+            "os = 'linux'",
+            "platform == 'linux'",
+            "has(nvim)",
+            "os == 'linux",
+            "(os == 'linux'",
+            "unknown('x')",
+        ] {
+            assert!(validate(bad).is_err(), "{} should not parse", bad);
+        }
+        let err = validate("platform == 'linux'").unwrap_err().to_string();
+        assert!(err.contains("unknown fact 'platform'"));
+    }
+}
@@ -1,68 +1,245 @@
+use crate::condition::Facts;
 use crate::hooks::Hook;
-use serde::Deserialize;
+use crate::lock::{ExtendsLock, LockChange};
+use colored::Colorize;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::collections::hash_map::DefaultHasher;
 use std::fs;
 use std::hash::{Hash, Hasher};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{Duration, SystemTime};
 
-#[derive(Debug, Deserialize, Clone, Default)]
+/// Template variables: any TOML value, passed to templates as JSON.
+pub type Variables = HashMap<String, serde_json::Value>;
+
+/// A `mimic.toml` file
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
 pub struct Config {
+    /// Git repos whose config is merged in before this one
     #[serde(default)]
     pub extends: Vec<ExtendsRepo>,
 
+    /// Local config files (glob patterns) merged in before this one
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Template variables: any TOML value, or a source such as `{ from = { env = "NAME" } }`
     #[serde(default)]
-    pub variables: HashMap<String, String>,
+    pub variables: Variables,
 
+    /// Files and directories linked or copied into place
     #[serde(default)]
     pub dotfiles: Vec<Dotfile>,
 
+    /// Marker-delimited blocks kept inside files mimic doesn't own
+    #[serde(default)]
+    pub blocks: Vec<ManagedBlock>,
+
+    /// Fragments deep-merged into an application's own config file
+    #[serde(default)]
+    pub merges: Vec<MergeEntry>,
+
     #[serde(default)]
     pub packages: Packages,
 
+    /// Per-machine configuration, selected with `--host` or `match` rules
     #[serde(default)]
     pub hosts: HashMap<String, HostConfig>,
 
+    /// Bundles merged into every host that has the role
+    #[serde(default)]
+    pub roles: HashMap<String, RoleConfig>,
+
+    /// Steps run after dotfiles and packages are applied
     #[serde(default)]
     pub hooks: Vec<Hook>,
 
+    /// Secrets templates can read, by name
     #[serde(default)]
     pub secrets: HashMap<String, SecretMetadata>,
 
     #[serde(default)]
     pub mise: MiseSection,
+
+    #[serde(default)]
+    pub encryption: EncryptionConfig,
+
+    /// Dotfile targets to remove from the layers beneath this one
+    #[serde(default)]
+    pub exclude_dotfiles: Vec<String>,
+
+    /// Package names to remove from the layers beneath this one
+    #[serde(default)]
+    pub exclude_packages: Vec<String>,
+
+    /// Hook names (or types, for hooks without a name) to remove from the
+    /// layers beneath this one
+    #[serde(default)]
+    pub exclude_hooks: Vec<String>,
+
+    /// Everything the merged layers remove, kept because it also applies to
+    /// layers merged later; `mimic hosts show` lists the
+    /// [`dropped`](Removal::dropped) ones
+    #[serde(skip)]
+    pub removed: Vec<Removal>,
+}
+
+/// Where the age key for `encrypted = true` dotfiles comes from
+#[derive(Debug, Deserialize, Serialize, Clone, Default, JsonSchema)]
+pub struct EncryptionConfig {
+    /// Identity file; defaults to `~/.mimic/age.key`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity: Option<String>,
+    /// Name of a secret in the secrets backend holding the identity instead
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub identity_secret: Option<String>,
+    /// Extra public keys files are encrypted to, e.g. for other machines
+    #[serde(default)]
+    pub recipients: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// A `[[extends]]` entry: a shared config repo merged in under this one
+#[derive(Debug, Deserialize, Clone, JsonSchema)]
 pub struct ExtendsRepo {
+    /// Git URL or local path of the repo
     pub repo: String,
 
+    /// Branch to follow instead of the default branch
     #[serde(default)]
     pub branch: Option<String>,
 
+    /// Pin to a tag instead of following a branch
+    #[serde(default)]
+    pub tag: Option<String>,
+
+    /// Pin to a commit instead of following a branch
+    #[serde(default)]
+    pub rev: Option<String>,
+
+    /// Config file inside the repo
     #[serde(default = "default_extends_config")]
     pub config: String,
+
+    /// How long a fetched checkout counts as fresh, e.g. `"30m"` or `"1d"`
+    #[serde(default)]
+    pub ttl: Option<String>,
+}
+
+/// Default freshness of an extends checkout
+const DEFAULT_EXTENDS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Marker touched after each successful fetch of an extends checkout
+const FETCH_STAMP: &str = ".git/mimic-fetched";
+
+/// When config loads may reach the network for `[[extends]]` repos
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExtendsFetch {
+    /// Fetch when a checkout is older than its TTL
+    Default,
+    /// Never fetch; fail if a repo isn't cached
+    Offline,
+    /// Fetch regardless of the TTL
+    Refresh,
+}
+
+static EXTENDS_FETCH: AtomicU8 = AtomicU8::new(0);
+
+/// Set the fetch policy for extends repos (`--offline` / `--refresh`).
+pub fn set_extends_fetch(mode: ExtendsFetch) {
+    EXTENDS_FETCH.store(mode as u8, Ordering::Relaxed);
+}
+
+pub fn extends_fetch() -> ExtendsFetch {
+    match EXTENDS_FETCH.load(Ordering::Relaxed) {
+        1 => ExtendsFetch::Offline,
+        2 => ExtendsFetch::Refresh,
+        _ => ExtendsFetch::Default,
+    }
+}
+
+/// Parse a duration like `"90s"`, `"30m"`, `"6h"` or `"1d"`; a bare number
+/// is seconds.
+pub fn parse_duration(text: &str) -> anyhow::Result<Duration> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (digits, unit) = text.split_at(split);
+    let value: u64 = digits.parse().map_err(|_| {
+        anyhow::anyhow!(
+            "Invalid duration '{}'\n\nTo fix:\n  - Use a number with a unit, e.g. \"30m\", \"6h\" or \"1d\"",
+            text
+        )
+    })?;
+    let seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(anyhow::anyhow!(
+                "Invalid duration unit '{}' in '{}'\n\nTo fix:\n  - Use s, m, h or d, e.g. \"6h\"",
+                unit,
+                text
+            ));
+        }
+    };
+    Ok(Duration::from_secs(value * seconds))
+}
+
+impl ExtendsRepo {
+    fn ttl(&self) -> anyhow::Result<Duration> {
+        match &self.ttl {
+            Some(ttl) => parse_duration(ttl)
+                .map_err(|e| anyhow::anyhow!("In [[extends]] for '{}': {}", self.repo, e)),
+            None => Ok(DEFAULT_EXTENDS_TTL),
+        }
+    }
+
+    /// The branch, tag or rev the repo follows, if any.
+    fn reference(&self) -> Option<&str> {
+        self.rev
+            .as_deref()
+            .or(self.tag.as_deref())
+            .or(self.branch.as_deref())
+    }
 }
 
 fn default_extends_config() -> String {
     "mimic.toml".to_string()
 }
 
-#[derive(Debug, Deserialize, Clone, Default)]
+/// A `[hosts.<name>]` section, merged over the base config on that machine
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
 pub struct HostConfig {
+    /// Host whose configuration this one builds on
     #[serde(default)]
     pub inherits: Option<String>,
 
+    /// Select this host on machines matching these facts
+    #[serde(default, rename = "match")]
+    pub matcher: Option<HostMatch>,
+
+    /// Roles for `only_roles`/`skip_roles` filters and `[roles.<name>]` bundles
     #[serde(default)]
     pub roles: Vec<String>,
 
     #[serde(default)]
-    pub variables: HashMap<String, String>,
+    pub variables: Variables,
 
     #[serde(default)]
     pub dotfiles: Vec<Dotfile>,
 
+    #[serde(default)]
+    pub blocks: Vec<ManagedBlock>,
+
+    #[serde(default)]
+    pub merges: Vec<MergeEntry>,
+
     #[serde(default)]
     pub packages: Packages,
 
@@ -74,30 +251,357 @@ pub struct HostConfig {
 
     #[serde(default)]
     pub mise: MiseSection,
+
+    /// Dotfile targets to remove from the layers beneath this one
+    #[serde(default)]
+    pub exclude_dotfiles: Vec<String>,
+
+    /// Package names to remove from the layers beneath this one
+    #[serde(default)]
+    pub exclude_packages: Vec<String>,
+
+    /// Hook names (or types, for hooks without a name) to remove from the
+    /// layers beneath this one
+    #[serde(default)]
+    pub exclude_hooks: Vec<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// A `[roles.<name>]` section: dotfiles, packages, hooks, variables and mise
+/// tools a host gets by listing the role in its `roles`.
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
+pub struct RoleConfig {
+    #[serde(default)]
+    pub variables: Variables,
+
+    #[serde(default)]
+    pub dotfiles: Vec<Dotfile>,
+
+    #[serde(default)]
+    pub blocks: Vec<ManagedBlock>,
+
+    #[serde(default)]
+    pub merges: Vec<MergeEntry>,
+
+    #[serde(default)]
+    pub packages: Packages,
+
+    #[serde(default)]
+    pub hooks: Vec<Hook>,
+
+    #[serde(default)]
+    pub mise: MiseSection,
+
+    /// Dotfile targets to remove from the layers beneath this one
+    #[serde(default)]
+    pub exclude_dotfiles: Vec<String>,
+
+    /// Package names to remove from the layers beneath this one
+    #[serde(default)]
+    pub exclude_packages: Vec<String>,
+
+    /// Hook names (or types, for hooks without a name) to remove from the
+    /// layers beneath this one
+    #[serde(default)]
+    pub exclude_hooks: Vec<String>,
+}
+
+impl HostConfig {
+    /// What this layer removes from the layers beneath it.
+    pub fn removals(&self) -> Vec<Removal> {
+        layer_removals(
+            &self.exclude_dotfiles,
+            &self.exclude_packages,
+            &self.exclude_hooks,
+            &self.dotfiles,
+            &self.blocks,
+            &self.merges,
+            &self.packages.normalized(),
+        )
+    }
+}
+
+impl RoleConfig {
+    /// The bundle as a host layer, so it merges like an inherited host.
+    pub fn as_host(&self) -> HostConfig {
+        HostConfig {
+            variables: self.variables.clone(),
+            dotfiles: self.dotfiles.clone(),
+            blocks: self.blocks.clone(),
+            merges: self.merges.clone(),
+            packages: self.packages.clone(),
+            hooks: self.hooks.clone(),
+            mise: self.mise.clone(),
+            exclude_dotfiles: self.exclude_dotfiles.clone(),
+            exclude_packages: self.exclude_packages.clone(),
+            exclude_hooks: self.exclude_hooks.clone(),
+            ..HostConfig::default()
+        }
+    }
+}
+
+/// Facts a host is selected by, e.g. `match = { hostname = "MBP-*", os = "macos" }`.
+/// Every field that is set has to match.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct HostMatch {
+    /// Glob pattern, compared case-insensitively
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hostname: Option<String>,
+    /// `macos`, `linux`, ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    /// Glob pattern for the login name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+}
+
+impl HostMatch {
+    /// Check each rule against `machine`. Returns whether all rules hold and
+    /// a description of each check.
+    pub fn check(&self, machine: &MachineFacts) -> (bool, Vec<String>) {
+        let glob = |pattern: &str, value: &str, case_sensitive: bool| {
+            glob::Pattern::new(pattern).is_ok_and(|p| {
+                p.matches_with(
+                    value,
+                    glob::MatchOptions {
+                        case_sensitive,
+                        ..Default::default()
+                    },
+                )
+            })
+        };
+
+        let mut all = true;
+        let mut details = Vec::new();
+        let mut record = |fact: &str, rule: &str, value: &str, matched: bool| {
+            all &= matched;
+            details.push(format!(
+                "{} '{}' {} '{}'",
+                fact,
+                rule,
+                if matched { "matches" } else { "does not match" },
+                value
+            ));
+        };
+        if let Some(pattern) = &self.hostname {
+            let matched = glob(pattern, &machine.hostname, false);
+            record("hostname", pattern, &machine.hostname, matched);
+        }
+        if let Some(os) = &self.os {
+            record("os", os, &machine.os, os.eq_ignore_ascii_case(&machine.os));
+        }
+        if let Some(pattern) = &self.user {
+            let matched = glob(pattern, &machine.user, true);
+            record("user", pattern, &machine.user, matched);
+        }
+        (all, details)
+    }
+}
+
+/// What host selection knows about the current machine
+#[derive(Debug, Clone, Serialize)]
+pub struct MachineFacts {
+    pub hostname: String,
+    pub os: String,
+    pub user: String,
+}
+
+impl MachineFacts {
+    pub fn detect() -> Self {
+        Self {
+            hostname: whoami::hostname().unwrap_or_else(|_| "unknown".to_string()),
+            os: std::env::consts::OS.to_string(),
+            user: whoami::username().unwrap_or_else(|_| "unknown".to_string()),
+        }
+    }
+}
+
+/// How one configured host compares to the current machine
+#[derive(Debug, Clone, Serialize)]
+pub struct HostCandidate {
+    pub name: String,
+    pub matched: bool,
+    /// Why it matched or not, e.g. "hostname 'MBP-*' matches 'MBP-AB12CD'"
+    pub details: Vec<String>,
+}
+
+/// The host chosen for this machine and why
+#[derive(Debug, Clone, Serialize)]
+pub struct HostSelection {
+    pub name: String,
+    pub reason: String,
+}
+
+/// Name of the host used when no other host matches
+pub const DEFAULT_HOST: &str = "default";
+
+/// A secret stored in the secrets backend (`mimic secrets set`)
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub struct SecretMetadata {
     pub description: Option<String>,
+    /// Environment variable `mimic secrets export` sets
     pub env_var: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone, Default)]
+/// Tools mimic installs with mise
+#[derive(Debug, Deserialize, Clone, Default, JsonSchema)]
 pub struct MiseSection {
+    /// Tool name to version, e.g. `node = "22"`
     #[serde(default)]
     pub tools: HashMap<String, String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// How a dotfile is placed at its target
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployMode {
+    /// Symlink the target to the source (or rendered template)
+    #[default]
+    Symlink,
+    /// Write a real copy of the source to the target
+    Copy,
+}
+
+/// Unix permission bits, written in the config as an octal string like `"0600"`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Permissions(u32);
+
+impl Permissions {
+    pub fn new(bits: u32) -> anyhow::Result<Self> {
+        if bits > 0o7777 {
+            return Err(anyhow::anyhow!(
+                "Invalid permissions '{:o}': expected at most 4 octal digits, e.g. \"0600\"",
+                bits
+            ));
+        }
+        Ok(Self(bits))
+    }
+
+    pub fn bits(self) -> u32 {
+        self.0
+    }
+}
+
+impl std::str::FromStr for Permissions {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let digits = s.strip_prefix("0o").unwrap_or(s);
+        let bits = u32::from_str_radix(digits, 8).map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid permissions '{}': expected an octal mode like \"0600\"",
+                s
+            )
+        })?;
+        Self::new(bits)
+    }
+}
+
+impl std::fmt::Display for Permissions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:04o}", self.0)
+    }
+}
+
+impl Serialize for Permissions {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Permissions {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PermissionsVisitor;
+
+        impl serde::de::Visitor<'_> for PermissionsVisitor {
+            type Value = Permissions;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("an octal mode like \"0600\"")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Permissions, E> {
+                v.parse().map_err(E::custom)
+            }
+
+            // TOML octal integers (`0o600`) arrive as plain numbers
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Permissions, E> {
+                u32::try_from(v)
+                    .map_err(E::custom)
+                    .and_then(|bits| Permissions::new(bits).map_err(E::custom))
+            }
+        }
+
+        deserializer.deserialize_any(PermissionsVisitor)
+    }
+}
+
+impl JsonSchema for Permissions {
+    fn schema_name() -> std::borrow::Cow<'static, str> {
+        "Permissions".into()
+    }
+
+    fn json_schema(_: &mut schemars::SchemaGenerator) -> schemars::Schema {
+        schemars::json_schema!({
+            "description": "Unix permission bits as an octal string like \"0600\", or a TOML octal integer like 0o600",
+            "type": ["string", "integer"],
+            "pattern": "^(0o)?[0-7]{1,4}$",
+            "minimum": 0,
+            "maximum": 0o7777
+        })
+    }
+}
+
+/// How a directory source is linked into place
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LinkStrategy {
+    /// Link the source as a single unit (the whole directory)
+    #[default]
+    Whole,
+    /// Create real directories and link each file individually (stow-style)
+    Tree,
+}
+
+/// A file or directory from the repo placed at a target
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub struct Dotfile {
+    /// File or directory in the repo, relative to the config file
+    #[serde(default)]
     pub source: String,
+    /// Where it is placed, e.g. `~/.zshrc`
     pub target: String,
+    /// Render the source as a Handlebars template (implied by `.tmpl` and `.hbs`)
     #[serde(default)]
     pub template: bool,
+    /// Source is age-encrypted and decrypted into the rendered directory
+    #[serde(default)]
+    pub encrypted: bool,
+    /// Link the target to the source (`symlink`) or write a real file (`copy`)
+    #[serde(default)]
+    pub mode: DeployMode,
+    /// How a directory source is linked
+    #[serde(default)]
+    pub link: LinkStrategy,
+    /// Permissions for the deployed file, or for every file in a deployed directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+    /// Permissions for parent directories the linker creates, and for
+    /// directories inside a deployed directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_permissions: Option<Permissions>,
+    /// Apply only on hosts with at least one of these roles
     #[serde(default)]
     pub only_roles: Option<Vec<String>>,
+    /// Skip on hosts with any of these roles
     #[serde(default)]
     pub skip_roles: Option<Vec<String>>,
+    /// Condition on system facts, e.g. `os == 'linux' && has('nvim')`
+    #[serde(default)]
+    pub when: Option<String>,
+    /// `false` removes the inherited dotfile with the same target instead
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
 }
 
 impl Dotfile {
@@ -105,32 +609,151 @@ impl Dotfile {
     pub fn is_template(&self) -> bool {
         self.template || self.source.ends_with(".tmpl") || self.source.ends_with(".hbs")
     }
+
+    /// Check if this dotfile's source is encrypted
+    pub fn is_encrypted(&self) -> bool {
+        self.encrypted
+    }
+
+    /// Check if this dotfile is deployed from a file in the rendered directory
+    /// (templates and encrypted sources) rather than from its source
+    pub fn is_rendered(&self) -> bool {
+        self.is_template() || self.is_encrypted()
+    }
+
+    /// Check if this dotfile is deployed as a real copy instead of a symlink
+    pub fn is_copy(&self) -> bool {
+        self.mode == DeployMode::Copy
+    }
+
+    /// Check if this dotfile's directory source is linked file by file
+    pub fn is_tree(&self) -> bool {
+        self.link == LinkStrategy::Tree
+    }
 }
 
-#[derive(Debug, Deserialize, Default, Clone)]
+/// A marker-delimited block kept inside a file mimic doesn't otherwise own,
+/// such as a distro-provided `~/.bashrc`.
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct ManagedBlock {
+    /// Identifies the block in its markers (`# BEGIN mimic: <name>`)
+    pub name: String,
+    /// File the block is kept in
+    pub target: String,
+    /// Inline block content, rendered as a template
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// File holding the block content, rendered as a template
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Comment prefix for the marker lines
+    #[serde(default = "default_block_comment")]
+    pub comment: String,
+    /// Apply only on hosts with at least one of these roles
+    #[serde(default)]
+    pub only_roles: Option<Vec<String>>,
+    /// Skip on hosts with any of these roles
+    #[serde(default)]
+    pub skip_roles: Option<Vec<String>>,
+    /// Condition on system facts, e.g. `os == 'linux'`
+    #[serde(default)]
+    pub when: Option<String>,
+    /// `false` removes the inherited block with the same name and target instead
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_block_comment() -> String {
+    "#".to_string()
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// A fragment deep-merged into an application's own JSON, TOML or YAML config
+/// file, leaving the keys the application writes untouched.
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
+pub struct MergeEntry {
+    /// Fragment in the repo holding the managed keys
+    #[serde(default)]
+    pub source: String,
+    /// The application's config file
+    pub target: String,
+    /// File format; inferred from the target's extension when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format: Option<MergeFormat>,
+    /// Apply only on hosts with at least one of these roles
+    #[serde(default)]
+    pub only_roles: Option<Vec<String>>,
+    /// Skip on hosts with any of these roles
+    #[serde(default)]
+    pub skip_roles: Option<Vec<String>>,
+    /// Condition on system facts, e.g. `os == 'linux'`
+    #[serde(default)]
+    pub when: Option<String>,
+    /// `false` removes the inherited merge with the same target instead
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+/// Format of a merge target
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeFormat {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl MergeEntry {
+    /// The configured format, or the one implied by the target's extension.
+    pub fn resolved_format(&self) -> anyhow::Result<MergeFormat> {
+        if let Some(format) = self.format {
+            return Ok(format);
+        }
+        match Path::new(&self.target).extension().and_then(|e| e.to_str()) {
+            Some("json") | Some("jsonc") => Ok(MergeFormat::Json),
+            Some("toml") => Ok(MergeFormat::Toml),
+            Some("yaml") | Some("yml") => Ok(MergeFormat::Yaml),
+            _ => Err(anyhow::anyhow!(
+                "Cannot tell the format of merge target: {}\n\nTo fix:\n  - Add format = \"json\", \"toml\" or \"yaml\" to the [[merges]] entry",
+                self.target
+            )),
+        }
+    }
+}
+
+/// Packages installed with Homebrew or zerobrew
+#[derive(Debug, Deserialize, Serialize, Default, Clone, JsonSchema)]
 pub struct Packages {
+    /// Verbose format: Homebrew formulas and casks
     #[serde(default)]
     pub homebrew: Vec<Package>,
 
     /// Simple format: list of formula names
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub brew: Vec<String>,
 
     /// Simple format: list of cask names
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub cask: Vec<String>,
 
+    /// Simple format: list of third-party taps, e.g. `homebrew/cask-fonts`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tap: Vec<String>,
+
     /// Verbose format: zerobrew packages
     #[serde(default)]
     pub zerobrew: Vec<Package>,
 
     /// Simple format: list of zerobrew package names
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub zb: Vec<String>,
 }
 
 impl Packages {
-    /// Merge simple format (brew/cask/zb) into homebrew/zerobrew Vec<Package>.
+    /// Merge simple format (brew/cask/tap/zb) into homebrew/zerobrew Vec<Package>.
     /// This allows both formats to coexist, deduplicating by name.
     pub fn normalized(&self) -> Self {
         let mut homebrew = self.homebrew.clone();
@@ -143,6 +766,8 @@ impl Packages {
                     pkg_type: "formula".to_string(),
                     only_roles: None,
                     skip_roles: None,
+                    when: None,
+                    enabled: true,
                 });
             }
         }
@@ -155,6 +780,22 @@ impl Packages {
                     pkg_type: "cask".to_string(),
                     only_roles: None,
                     skip_roles: None,
+                    when: None,
+                    enabled: true,
+                });
+            }
+        }
+
+        // Add taps (skip if already present in homebrew)
+        for name in &self.tap {
+            if !homebrew.iter().any(|p| p.name == *name) {
+                homebrew.push(Package {
+                    name: name.clone(),
+                    pkg_type: "tap".to_string(),
+                    only_roles: None,
+                    skip_roles: None,
+                    when: None,
+                    enabled: true,
                 });
             }
         }
@@ -169,6 +810,8 @@ impl Packages {
                     pkg_type: "formula".to_string(),
                     only_roles: None,
                     skip_roles: None,
+                    when: None,
+                    enabled: true,
                 });
             }
         }
@@ -177,24 +820,37 @@ impl Packages {
             homebrew,
             brew: Vec::new(),
             cask: Vec::new(),
+            tap: Vec::new(),
             zerobrew,
             zb: Vec::new(),
         }
     }
 }
 
-#[derive(Debug, Deserialize, Clone)]
+/// A package in the verbose format
+#[derive(Debug, Deserialize, Serialize, Clone, JsonSchema)]
 pub struct Package {
     pub name: String,
 
+    /// `formula`, `cask` or `tap`
     #[serde(rename = "type")]
     pub pkg_type: String,
 
+    /// Install only on hosts with at least one of these roles
     #[serde(default)]
     pub only_roles: Option<Vec<String>>,
 
+    /// Skip on hosts with any of these roles
     #[serde(default)]
     pub skip_roles: Option<Vec<String>>,
+
+    /// Condition on system facts, e.g. `os == 'macos'`
+    #[serde(default)]
+    pub when: Option<String>,
+
+    /// `false` removes the inherited package with the same name instead
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
 }
 
 /// Check if a resource should be applied based on role filtering
@@ -233,21 +889,410 @@ pub fn should_apply_for_roles(
     true
 }
 
+/// Why a resource is skipped on this machine, or `None` if it applies.
+pub fn skip_reason(
+    only_roles: &Option<Vec<String>>,
+    skip_roles: &Option<Vec<String>>,
+    when: &Option<String>,
+    host_roles: &[String],
+    facts: &Facts,
+) -> Option<&'static str> {
+    if !should_apply_for_roles(only_roles, skip_roles, host_roles) {
+        Some("role mismatch")
+    } else if !facts.allows(when) {
+        Some("condition not met")
+    } else {
+        None
+    }
+}
+
+/// What a [`Removal`] takes out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RemovalKind {
+    Dotfile,
+    Block,
+    Merge,
+    Package,
+    Hook,
+}
+
+/// An entry a layer removes from the layers merged beneath it, through an
+/// `exclude_*` list or an entry with `enabled = false`. Layers are merged in
+/// the order includes, extends, the file itself, then a host's role bundles
+/// and its `inherits` chain, so an entry added above the removal stays.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Removal {
+    pub kind: RemovalKind,
+    /// Target of a dotfile or merge; name of a block, package or hook
+    pub key: String,
+    /// The file a removed block is kept in
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// The setting that removed it: an `exclude_*` key or `enabled = false`
+    pub by: &'static str,
+    /// Whether it dropped an entry from a layer beneath; one that matched
+    /// nothing has no effect and is not reported
+    #[serde(skip)]
+    pub dropped: bool,
+}
+
+impl Removal {
+    fn new(kind: RemovalKind, key: &str, by: &'static str) -> Self {
+        Self {
+            kind,
+            key: key.to_string(),
+            target: None,
+            by,
+            dropped: false,
+        }
+    }
+}
+
+impl std::fmt::Display for Removal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            RemovalKind::Dotfile => "dotfile",
+            RemovalKind::Block => "block",
+            RemovalKind::Merge => "merge",
+            RemovalKind::Package => "package",
+            RemovalKind::Hook => "hook",
+        };
+        match &self.target {
+            Some(target) => write!(f, "{} {} in {}", kind, self.key, target),
+            None => write!(f, "{} {}", kind, self.key),
+        }
+    }
+}
+
+/// Whether two targets name the same file, so `~/.zshrc` matches
+/// `$HOME/.zshrc`.
+fn same_target(a: &str, b: &str) -> bool {
+    a == b
+        || matches!(
+            (crate::expand::expand_path_str(a), crate::expand::expand_path_str(b)),
+            (Ok(a), Ok(b)) if a == b
+        )
+}
+
+/// The removals one layer asks for. A disabled entry only needs the fields
+/// that identify it.
+fn layer_removals(
+    exclude_dotfiles: &[String],
+    exclude_packages: &[String],
+    exclude_hooks: &[String],
+    dotfiles: &[Dotfile],
+    blocks: &[ManagedBlock],
+    merges: &[MergeEntry],
+    packages: &Packages,
+) -> Vec<Removal> {
+    let excluded = exclude_dotfiles
+        .iter()
+        .map(|target| Removal::new(RemovalKind::Dotfile, target, "exclude_dotfiles"))
+        .chain(
+            exclude_packages
+                .iter()
+                .map(|name| Removal::new(RemovalKind::Package, name, "exclude_packages")),
+        )
+        .chain(
+            exclude_hooks
+                .iter()
+                .map(|name| Removal::new(RemovalKind::Hook, name, "exclude_hooks")),
+        );
+
+    const DISABLED: &str = "enabled = false";
+    let disabled = dotfiles
+        .iter()
+        .filter(|d| !d.enabled)
+        .map(|d| Removal::new(RemovalKind::Dotfile, &d.target, DISABLED))
+        .chain(blocks.iter().filter(|b| !b.enabled).map(|b| Removal {
+            target: Some(b.target.clone()),
+            ..Removal::new(RemovalKind::Block, &b.name, DISABLED)
+        }))
+        .chain(
+            merges
+                .iter()
+                .filter(|m| !m.enabled)
+                .map(|m| Removal::new(RemovalKind::Merge, &m.target, DISABLED)),
+        )
+        .chain(
+            packages
+                .homebrew
+                .iter()
+                .chain(&packages.zerobrew)
+                .filter(|p| !p.enabled)
+                .map(|p| Removal::new(RemovalKind::Package, &p.name, DISABLED)),
+        );
+
+    excluded.chain(disabled).collect()
+}
+
+/// Drop the entries matched by `removals` from lists merged so far,
+/// marking each removal that matched something as `dropped`.
+/// `packages` must be normalized.
+fn remove_entries(
+    removals: &mut [Removal],
+    dotfiles: &mut Vec<Dotfile>,
+    blocks: &mut Vec<ManagedBlock>,
+    merges: &mut Vec<MergeEntry>,
+    packages: &mut Packages,
+    hooks: &mut Vec<Hook>,
+) {
+    fn drop_matching<T>(list: &mut Vec<T>, matches: impl Fn(&T) -> bool) -> bool {
+        let before = list.len();
+        list.retain(|entry| !matches(entry));
+        list.len() != before
+    }
+
+    for removal in removals {
+        let key = removal.key.as_str();
+        let dropped = match removal.kind {
+            RemovalKind::Dotfile => drop_matching(dotfiles, |d| same_target(key, &d.target)),
+            RemovalKind::Block => drop_matching(blocks, |b| {
+                b.name == key
+                    && removal
+                        .target
+                        .as_deref()
+                        .is_some_and(|t| same_target(t, &b.target))
+            }),
+            RemovalKind::Merge => drop_matching(merges, |m| same_target(key, &m.target)),
+            RemovalKind::Package => {
+                let homebrew = drop_matching(&mut packages.homebrew, |p| p.name == key);
+                drop_matching(&mut packages.zerobrew, |p| p.name == key) || homebrew
+            }
+            RemovalKind::Hook => drop_matching(hooks, |h| h.name() == key),
+        };
+        removal.dropped |= dropped;
+    }
+}
+
+/// Merge a later layer's variables (host, role, extending config) into
+/// `base`. Tables merge key by key, all the way down; any other value,
+/// including a list, replaces the earlier one. A key written with a trailing
+/// `+` (`"paths+" = [...]`) appends its list to the earlier list instead.
+pub fn merge_variables(base: &mut Variables, overlay: &Variables) {
+    for (key, value) in overlay {
+        let (name, append) = split_append_key(key);
+        let merged = merge_variable(base.remove(name), value, append);
+        base.insert(name.to_string(), merged);
+    }
+}
+
+fn split_append_key(key: &str) -> (&str, bool) {
+    match key.strip_suffix('+') {
+        Some(name) if !name.is_empty() => (name, true),
+        _ => (key, false),
+    }
+}
+
+fn merge_variable(
+    base: Option<serde_json::Value>,
+    overlay: &serde_json::Value,
+    append: bool,
+) -> serde_json::Value {
+    use serde_json::Value;
+
+    match (base, overlay) {
+        (Some(Value::Array(mut items)), Value::Array(more)) if append => {
+            items.extend(more.iter().cloned());
+            Value::Array(items)
+        }
+        (base, Value::Object(overlay)) => {
+            let mut table = match base {
+                Some(Value::Object(table)) => table,
+                _ => serde_json::Map::new(),
+            };
+            for (key, value) in overlay {
+                let (name, append) = split_append_key(key);
+                // Take the old value in place so the table keeps its order
+                let existing = table.get_mut(name).map(Value::take);
+                let merged = merge_variable(existing, value, append);
+                table.insert(name.to_string(), merged);
+            }
+            Value::Object(table)
+        }
+        (_, value) => value.clone(),
+    }
+}
+
 impl Config {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(content: &str) -> anyhow::Result<Self> {
         let config: Config =
             toml::from_str(content).map_err(|e| anyhow::anyhow!("TOML parse error: {}", e))?;
+        config.validate_conditions()?;
+        config.validate_sources()?;
         Ok(config)
     }
 
+    /// Check that every enabled dotfile and merge has a source; a disabled
+    /// one only names the inherited entry it removes.
+    fn validate_sources(&self) -> anyhow::Result<()> {
+        let layers = std::iter::once((&self.dotfiles, &self.merges))
+            .chain(self.hosts.values().map(|h| (&h.dotfiles, &h.merges)))
+            .chain(self.roles.values().map(|r| (&r.dotfiles, &r.merges)));
+        for (dotfiles, merges) in layers {
+            let missing = dotfiles
+                .iter()
+                .filter(|d| d.enabled && d.source.is_empty())
+                .map(|d| ("dotfile", &d.target))
+                .chain(
+                    merges
+                        .iter()
+                        .filter(|m| m.enabled && m.source.is_empty())
+                        .map(|m| ("merge", &m.target)),
+                )
+                .next();
+            if let Some((kind, target)) = missing {
+                return Err(anyhow::anyhow!(
+                    "The {} for {} has no source\n\nTo fix:\n  - Add source = \"...\" with the file in the repo\n  - Or set enabled = false to remove an inherited {} with this target",
+                    kind,
+                    target,
+                    kind
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// What this file's own `exclude_*` lists and disabled entries remove.
+    fn own_removals(&self) -> Vec<Removal> {
+        layer_removals(
+            &self.exclude_dotfiles,
+            &self.exclude_packages,
+            &self.exclude_hooks,
+            &self.dotfiles,
+            &self.blocks,
+            &self.merges,
+            &self.packages.normalized(),
+        )
+    }
+
+    /// Check that every `when` expression parses, naming the resource it is on.
+    fn validate_conditions(&self) -> anyhow::Result<()> {
+        let hosts = self.hosts.iter().map(|(name, host)| {
+            (
+                format!("[hosts.{}] ", name),
+                &host.dotfiles,
+                &host.blocks,
+                &host.merges,
+                &host.packages,
+                &host.hooks,
+            )
+        });
+        let roles = self.roles.iter().map(|(name, role)| {
+            (
+                format!("[roles.{}] ", name),
+                &role.dotfiles,
+                &role.blocks,
+                &role.merges,
+                &role.packages,
+                &role.hooks,
+            )
+        });
+        let sections = std::iter::once((
+            String::new(),
+            &self.dotfiles,
+            &self.blocks,
+            &self.merges,
+            &self.packages,
+            &self.hooks,
+        ))
+        .chain(hosts)
+        .chain(roles);
+
+        for (section, dotfiles, blocks, merges, packages, hooks) in sections {
+            let conditions = dotfiles
+                .iter()
+                .map(|d| (format!("dotfile {}", d.target), &d.when))
+                .chain(
+                    blocks
+                        .iter()
+                        .map(|b| (format!("block {}", b.name), &b.when)),
+                )
+                .chain(
+                    merges
+                        .iter()
+                        .map(|m| (format!("merge {}", m.target), &m.when)),
+                )
+                .chain(
+                    packages
+                        .homebrew
+                        .iter()
+                        .chain(&packages.zerobrew)
+                        .map(|p| (format!("package {}", p.name), &p.when)),
+                )
+                .chain(
+                    hooks
+                        .iter()
+                        .map(|h| (format!("hook {}", h.name()), h.when())),
+                );
+            for (resource, when) in conditions {
+                if let Some(when) = when {
+                    crate::condition::validate(when)
+                        .map_err(|e| anyhow::anyhow!("In {}{}: {}", section, resource, e))?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a config file, checking out `[[extends]]` repos at the commits
+    /// recorded in the `mimic.lock` next to it. Repos not in the lock yet are
+    /// resolved and added to it.
     pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let mut lock = ExtendsLock::load(path.as_ref())?;
         let mut loading_stack = Vec::new();
-        Self::from_file_internal(path.as_ref(), &mut loading_stack)
+        let config = Self::from_file_internal(path.as_ref(), &mut loading_stack, &mut lock)?;
+        lock.finish()?;
+        Ok(config)
+    }
+
+    /// Move `repos` (every extends repo if empty) to the latest commit of
+    /// their branch or tag and rewrite `mimic.lock`.
+    pub fn update_extends<P: AsRef<Path>>(
+        path: P,
+        repos: &[String],
+    ) -> anyhow::Result<Vec<LockChange>> {
+        let mut lock = ExtendsLock::for_update(path.as_ref(), repos)?;
+        let mut loading_stack = Vec::new();
+        Self::from_file_internal(path.as_ref(), &mut loading_stack, &mut lock)?;
+        lock.finish()
+    }
+
+    /// The repos extended by a config file and the local files it includes,
+    /// read without fetching anything.
+    pub fn local_extends(path: &Path) -> anyhow::Result<Vec<String>> {
+        use anyhow::Context;
+        let mut repos: Vec<String> = Vec::new();
+        let mut seen: Vec<PathBuf> = Vec::new();
+        let mut pending = vec![path.to_path_buf()];
+        while let Some(file) = pending.pop() {
+            if seen.contains(&file) {
+                continue;
+            }
+            let content = fs::read_to_string(&file)
+                .with_context(|| format!("Failed to read config file: {}", file.display()))?;
+            let config = Self::from_str(&content)?;
+            for extend in config.extends {
+                if !repos.contains(&extend.repo) {
+                    repos.push(extend.repo);
+                }
+            }
+            pending.extend(
+                Self::resolve_includes(&file, &content, &config.include)?
+                    .into_iter()
+                    .map(|(included, _)| included),
+            );
+            seen.push(file);
+        }
+        Ok(repos)
     }
 
     fn from_file_internal(
         path_ref: &Path,
         loading_stack: &mut Vec<PathBuf>,
+        lock: &mut ExtendsLock,
     ) -> anyhow::Result<Self> {
         use anyhow::Context;
         let resolved_path = path_ref
@@ -261,7 +1306,7 @@ impl Config {
                 .collect();
             chain.push(resolved_path.display().to_string());
             return Err(anyhow::anyhow!(
-                "Cyclic config extends detected:\n  - {}",
+                "Cyclic config extends or include detected:\n  - {}",
                 chain.join("\n  - ")
             ));
         }
@@ -276,6 +1321,8 @@ impl Config {
             )
         })?;
             let mut config = Self::from_str(&content)?;
+            let includes = Self::resolve_includes(path_ref, &content, &config.include)?;
+            config.include.clear();
 
             // Resolve relative source paths against the config file's directory
             if let Some(config_dir) = path_ref.parent() {
@@ -287,17 +1334,96 @@ impl Config {
                     .or_else(|_| std::env::current_dir())
                     .unwrap_or_else(|_| config_dir.to_path_buf());
                 config.resolve_source_paths(&config_dir);
-                config = config.resolve_extends(loading_stack)?;
+                config = config.resolve_extends(loading_stack, lock)?;
+            }
+
+            let mut merged = Config::default();
+            for (include_path, line) in includes {
+                let included = Self::from_file_internal(&include_path, loading_stack, lock)
+                    .map_err(|e| {
+                        anyhow::anyhow!(
+                            "Failed to load included config {} (included from {}:{}): {}",
+                            include_path.display(),
+                            path_ref.display(),
+                            line,
+                            e
+                        )
+                    })?;
+                merged = Self::merge(merged, included);
             }
 
-            Ok(config)
+            Ok(Self::merge(merged, config))
         })();
 
         loading_stack.pop();
         result
     }
 
-    fn resolve_extends(mut self, loading_stack: &mut Vec<PathBuf>) -> anyhow::Result<Self> {
+    /// Expand `include` patterns relative to the including file. Returns each
+    /// matched file with the line of the pattern that pulled it in, in pattern
+    /// order and sorted by path within a pattern.
+    pub fn resolve_includes(
+        path: &Path,
+        content: &str,
+        patterns: &[String],
+    ) -> anyhow::Result<Vec<(PathBuf, usize)>> {
+        let base_dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+
+        let mut includes: Vec<(PathBuf, usize)> = Vec::new();
+        for pattern in patterns {
+            let line = content
+                .lines()
+                .position(|l| {
+                    l.contains(&format!("\"{}\"", pattern)) || l.contains(&format!("'{}'", pattern))
+                })
+                .map_or(1, |i| i + 1);
+
+            let expanded = crate::expand::expand_path_str(pattern)?;
+            let full = base_dir.join(expanded);
+            let full_str = full.to_string_lossy();
+
+            let paths = glob::glob(&full_str).map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid include pattern '{}' at {}:{}: {}\n\nTo fix:\n  - Use glob syntax like \"hosts/*.toml\"",
+                    pattern,
+                    path.display(),
+                    line,
+                    e
+                )
+            })?;
+            let mut matched: Vec<PathBuf> = paths
+                .filter_map(Result::ok)
+                .filter(|p| p.is_file())
+                .collect();
+            matched.sort();
+
+            let is_glob = pattern.contains(['*', '?', '[']);
+            if matched.is_empty() && !is_glob {
+                return Err(anyhow::anyhow!(
+                    "Included config not found: {} (include at {}:{})\n\nTo fix:\n  - Check the path; it is relative to the including file",
+                    full.display(),
+                    path.display(),
+                    line
+                ));
+            }
+
+            for included in matched {
+                if !includes.iter().any(|(p, _)| p == &included) {
+                    includes.push((included, line));
+                }
+            }
+        }
+        Ok(includes)
+    }
+
+    fn resolve_extends(
+        mut self,
+        loading_stack: &mut Vec<PathBuf>,
+        lock: &mut ExtendsLock,
+    ) -> anyhow::Result<Self> {
         if self.extends.is_empty() {
             return Ok(self);
         }
@@ -308,9 +1434,9 @@ impl Config {
         let mut merged = Config::default();
 
         for extend in extends {
-            let extended_config_path = Self::ensure_extended_repo(&extend)?;
-            let extended =
-                Self::from_file_internal(&extended_config_path, loading_stack).map_err(|e| {
+            let extended_config_path = Self::ensure_extended_repo(&extend, lock)?;
+            let extended = Self::from_file_internal(&extended_config_path, loading_stack, lock)
+                .map_err(|e| {
                     anyhow::anyhow!(
                         "Failed to load extended config from {}: {}",
                         extended_config_path.display(),
@@ -323,15 +1449,37 @@ impl Config {
         Ok(Self::merge(merged, self))
     }
 
+    /// Merge `overlay` over `base`. The overlay's removals, its own and
+    /// those of every layer already merged into it, apply to all of `base`;
+    /// the result keeps them in `removed` and drops its disabled entries.
     fn merge(base: Config, overlay: Config) -> Config {
         let mut variables = base.variables;
-        variables.extend(overlay.variables);
+        merge_variables(&mut variables, &overlay.variables);
 
-        let mut dotfiles = base.dotfiles;
-        dotfiles.extend(overlay.dotfiles);
+        let mut removed = overlay.removed.clone();
+        removed.extend(overlay.own_removals());
 
+        let mut dotfiles = base.dotfiles;
+        let mut blocks = base.blocks;
+        let mut merges = base.merges;
         let mut merged_packages = base.packages.normalized();
-        let overlay_packages = overlay.packages.normalized();
+        let mut hooks = base.hooks;
+        remove_entries(
+            &mut removed,
+            &mut dotfiles,
+            &mut blocks,
+            &mut merges,
+            &mut merged_packages,
+            &mut hooks,
+        );
+
+        dotfiles.extend(overlay.dotfiles.into_iter().filter(|d| d.enabled));
+        blocks.extend(overlay.blocks.into_iter().filter(|b| b.enabled));
+        merges.extend(overlay.merges.into_iter().filter(|m| m.enabled));
+
+        let mut overlay_packages = overlay.packages.normalized();
+        overlay_packages.homebrew.retain(|p| p.enabled);
+        overlay_packages.zerobrew.retain(|p| p.enabled);
         for pkg in overlay_packages.homebrew {
             if let Some(existing) = merged_packages
                 .homebrew
@@ -358,7 +1506,9 @@ impl Config {
         let mut hosts = base.hosts;
         hosts.extend(overlay.hosts);
 
-        let mut hooks = base.hooks;
+        let mut roles = base.roles;
+        roles.extend(overlay.roles);
+
         hooks.extend(overlay.hooks);
 
         let mut secrets = base.secrets;
@@ -367,39 +1517,84 @@ impl Config {
         let mut mise = base.mise;
         mise.tools.extend(overlay.mise.tools);
 
+        let mut encryption = base.encryption;
+        if overlay.encryption.identity.is_some() {
+            encryption.identity = overlay.encryption.identity;
+        }
+        if overlay.encryption.identity_secret.is_some() {
+            encryption.identity_secret = overlay.encryption.identity_secret;
+        }
+        encryption.recipients.extend(overlay.encryption.recipients);
+
+        let mut all_removed = base.removed;
+        all_removed.extend(removed);
+
         Config {
             extends: Vec::new(),
+            include: Vec::new(),
             variables,
             dotfiles,
+            blocks,
+            merges,
             packages: merged_packages,
             hosts,
+            roles,
             hooks,
             secrets,
             mise,
+            encryption,
+            exclude_dotfiles: Vec::new(),
+            exclude_packages: Vec::new(),
+            exclude_hooks: Vec::new(),
+            removed: all_removed,
         }
     }
 
-    fn ensure_extended_repo(extend: &ExtendsRepo) -> anyhow::Result<PathBuf> {
+    fn ensure_extended_repo(
+        extend: &ExtendsRepo,
+        lock: &mut ExtendsLock,
+    ) -> anyhow::Result<PathBuf> {
+        if [&extend.branch, &extend.tag, &extend.rev]
+            .iter()
+            .filter(|r| r.is_some())
+            .count()
+            > 1
+        {
+            return Err(anyhow::anyhow!(
+                "Extended repo '{}' sets more than one of branch, tag and rev\n\nTo fix:\n  - Keep only one of them in its [[extends]] entry",
+                extend.repo
+            ));
+        }
+
         let base_dirs = directories::BaseDirs::new()
             .ok_or_else(|| anyhow::anyhow!("Failed to determine home directory"))?;
 
         let repo_dir = base_dirs
             .config_dir()
             .join("mimic/extends")
-            .join(Self::extends_repo_id(
-                &extend.repo,
-                extend.branch.as_deref(),
-            ));
-
-        if repo_dir.exists() {
-            Self::git_update_extended_repo(&repo_dir, extend)?;
-        } else {
+            .join(Self::extends_repo_id(&extend.repo, extend.reference()));
+
+        if !repo_dir.exists() {
+            if extends_fetch() == ExtendsFetch::Offline {
+                return Err(anyhow::anyhow!(
+                    "Extended repo '{}' is not cached and --offline was given\n\nTo fix:\n  - Run once without --offline to clone it",
+                    extend.repo
+                ));
+            }
             if let Some(parent) = repo_dir.parent() {
                 fs::create_dir_all(parent)?;
             }
             Self::git_clone_extended_repo(&repo_dir, extend)?;
+            Self::stamp_fetched(&repo_dir);
         }
 
+        let commit = match lock.locked_commit(extend) {
+            Some(commit) => commit.to_string(),
+            None => Self::resolve_extended_commit(&repo_dir, extend, lock.updates(extend))?,
+        };
+        Self::git_checkout_extended_repo(&repo_dir, extend, &commit)?;
+        lock.record(extend, &commit);
+
         let config_path = repo_dir.join(&extend.config);
         if !config_path.exists() {
             return Err(anyhow::anyhow!(
@@ -415,7 +1610,7 @@ impl Config {
     fn git_clone_extended_repo(repo_dir: &Path, extend: &ExtendsRepo) -> anyhow::Result<()> {
         let mut cmd = Command::new("git");
         cmd.arg("clone").arg("--depth").arg("1");
-        if let Some(branch) = &extend.branch {
+        if let Some(branch) = extend.branch.as_ref().or(extend.tag.as_ref()) {
             cmd.arg("--branch").arg(branch).arg("--single-branch");
         }
         cmd.arg(&extend.repo).arg(repo_dir);
@@ -446,11 +1641,8 @@ impl Config {
 
             let mut retry_cmd = Command::new("git");
             retry_cmd.arg("clone").arg("--depth").arg("1");
-            if let Some(branch) = &extend.branch {
-                retry_cmd
-                    .arg("--branch")
-                    .arg(branch)
-                    .arg("--single-branch");
+            if let Some(branch) = extend.branch.as_ref().or(extend.tag.as_ref()) {
+                retry_cmd.arg("--branch").arg(branch).arg("--single-branch");
             }
             retry_cmd.arg(&extend.repo).arg(repo_dir);
 
@@ -475,34 +1667,215 @@ impl Config {
         )))
     }
 
-    fn git_update_extended_repo(repo_dir: &Path, extend: &ExtendsRepo) -> anyhow::Result<()> {
-        let mut cmd = Command::new("git");
-        cmd.arg("-C").arg(repo_dir).arg("pull").arg("--ff-only");
-        if let Some(branch) = &extend.branch {
-            cmd.arg("origin").arg(branch);
+    fn stamp_fetched(repo_dir: &Path) {
+        // Best effort: a missing stamp only means the next load fetches again
+        let _ = fs::write(repo_dir.join(FETCH_STAMP), "");
+    }
+
+    fn fetched_within(repo_dir: &Path, ttl: Duration) -> bool {
+        fs::metadata(repo_dir.join(FETCH_STAMP))
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|fetched| SystemTime::now().duration_since(fetched).ok())
+            .is_some_and(|age| age < ttl)
+    }
+
+    /// The commit the repo's branch, tag or rev resolves to, fetching first
+    /// unless the checkout is fresh or the load is offline. If the fetch fails
+    /// and the cached checkout can answer, warn and use that instead; `update`
+    /// turns that fallback into an error.
+    fn resolve_extended_commit(
+        repo_dir: &Path,
+        extend: &ExtendsRepo,
+        update: bool,
+    ) -> anyhow::Result<String> {
+        let mode = extends_fetch();
+        let fresh = !update
+            && mode == ExtendsFetch::Default
+            && Self::fetched_within(repo_dir, extend.ttl()?);
+
+        if mode == ExtendsFetch::Offline || fresh {
+            match Self::cached_extended_commit(repo_dir, extend) {
+                Ok(commit) => return Ok(commit),
+                Err(e) if mode == ExtendsFetch::Offline => {
+                    return Err(anyhow::anyhow!(
+                        "{}\n\nTo fix:\n  - Run once without --offline to fetch it",
+                        e
+                    ));
+                }
+                // e.g. a short rev a shallow clone doesn't have yet
+                Err(_) => {}
+            }
         }
 
-        let output = cmd.output().map_err(|e| {
-            if e.kind() == std::io::ErrorKind::NotFound {
-                anyhow::anyhow!(
-                    "Git is not installed or not in PATH\n\nTo fix:\n  - Install git: brew install git (macOS)\n  - Or: apt install git (Linux)"
-                )
-            } else {
-                anyhow::anyhow!(
-                    "Failed to execute git pull for extended repo '{}': {}",
+        match Self::git_fetch_extended_repo(repo_dir, extend) {
+            Ok(commit) => {
+                Self::stamp_fetched(repo_dir);
+                Ok(commit)
+            }
+            Err(e) if update => Err(e),
+            Err(e) => {
+                let Ok(commit) = Self::cached_extended_commit(repo_dir, extend) else {
+                    return Err(e);
+                };
+                eprintln!(
+                    "{} Could not update extended repo '{}'; using the cached copy ({})",
+                    "⚠".yellow(),
                     extend.repo,
-                    e
-                )
+                    &commit[..commit.len().min(12)]
+                );
+                Ok(commit)
             }
-        })?;
-        if output.status.success() {
-            return Ok(());
         }
+    }
 
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        Err(anyhow::anyhow!(Self::format_extended_repo_error(
-            "update", extend, &stderr
-        )))
+    /// What the repo's branch, tag or rev resolves to in the local checkout.
+    fn cached_extended_commit(repo_dir: &Path, extend: &ExtendsRepo) -> anyhow::Result<String> {
+        let reference = match (&extend.rev, &extend.tag) {
+            (Some(rev), _) => format!("{}^{{commit}}", rev),
+            (None, Some(tag)) => format!("refs/tags/{}^{{commit}}", tag),
+            (None, None) => "HEAD^{commit}".to_string(),
+        };
+        let output =
+            Self::git_in_extended_repo(repo_dir, extend, &["rev-parse", "--verify", &reference])?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Extended repo '{}' has no cached commit for '{}'",
+                extend.repo,
+                extend.reference().unwrap_or("HEAD")
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Run git in an extended repo's checkout.
+    fn git_in_extended_repo(
+        repo_dir: &Path,
+        extend: &ExtendsRepo,
+        args: &[&str],
+    ) -> anyhow::Result<std::process::Output> {
+        Command::new("git")
+            .arg("-C")
+            .arg(repo_dir)
+            .args(args)
+            .output()
+            .map_err(|e| {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    anyhow::anyhow!(
+                        "Git is not installed or not in PATH\n\nTo fix:\n  - Install git: brew install git (macOS)\n  - Or: apt install git (Linux)"
+                    )
+                } else {
+                    anyhow::anyhow!(
+                        "Failed to execute git {} for extended repo '{}': {}",
+                        args[0],
+                        extend.repo,
+                        e
+                    )
+                }
+            })
+    }
+
+    /// Fetch the latest commit of the repo's branch, tag or rev from origin
+    /// and return its hash.
+    fn git_fetch_extended_repo(repo_dir: &Path, extend: &ExtendsRepo) -> anyhow::Result<String> {
+        let refspec = match (&extend.rev, &extend.tag, &extend.branch) {
+            (Some(rev), _, _) => rev.clone(),
+            (None, Some(tag), _) => format!("refs/tags/{}", tag),
+            (None, None, Some(branch)) => branch.clone(),
+            (None, None, None) => "HEAD".to_string(),
+        };
+
+        let output = Self::git_in_extended_repo(
+            repo_dir,
+            extend,
+            &["fetch", "--quiet", "origin", &refspec],
+        )?;
+        let resolved = if output.status.success() {
+            "FETCH_HEAD^{commit}".to_string()
+        } else if let Some(rev) = &extend.rev {
+            // Servers only hand out full hashes; fetch everything for short ones
+            let unshallow = repo_dir.join(".git/shallow").exists();
+            let mut args = vec!["fetch", "--quiet", "--tags", "origin"];
+            if unshallow {
+                args.push("--unshallow");
+            }
+            let output = Self::git_in_extended_repo(repo_dir, extend, &args)?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(anyhow::anyhow!(Self::format_extended_repo_error(
+                    "update", extend, &stderr
+                )));
+            }
+            format!("{}^{{commit}}", rev)
+        } else {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!(Self::format_extended_repo_error(
+                "update", extend, &stderr
+            )));
+        };
+
+        let output =
+            Self::git_in_extended_repo(repo_dir, extend, &["rev-parse", "--verify", &resolved])?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!(
+                "Extended repo '{}' has no commit '{}'\n\nTo fix:\n  - Check the rev in its [[extends]] entry",
+                extend.repo,
+                extend.reference().unwrap_or("HEAD")
+            ));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// Check out `commit`, fetching it first if the checkout doesn't have it.
+    fn git_checkout_extended_repo(
+        repo_dir: &Path,
+        extend: &ExtendsRepo,
+        commit: &str,
+    ) -> anyhow::Result<()> {
+        let object = format!("{}^{{commit}}", commit);
+        let present = Self::git_in_extended_repo(repo_dir, extend, &["cat-file", "-e", &object])?;
+        if !present.status.success() {
+            if extends_fetch() == ExtendsFetch::Offline {
+                return Err(anyhow::anyhow!(
+                    "Commit {} of extended repo '{}' (from mimic.lock) is not cached and --offline was given\n\nTo fix:\n  - Run once without --offline to fetch it",
+                    commit,
+                    extend.repo
+                ));
+            }
+            let output = Self::git_in_extended_repo(
+                repo_dir,
+                extend,
+                &["fetch", "--quiet", "origin", commit],
+            )?;
+            if !output.status.success() {
+                // Falling back to whatever is cached would leave mimic.lock
+                // naming a commit this machine isn't on
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                return Err(anyhow::anyhow!(
+                    "{}\n\nThe commit locked in mimic.lock ({}) is not cached and could not be fetched.\n\nTo fix:\n  - Check your network connection and that the repo is reachable\n  - Or run 'mimic update {}' to lock the latest commit instead",
+                    Self::format_extended_repo_error("update", extend, &stderr),
+                    commit,
+                    extend.repo
+                ));
+            }
+            Self::stamp_fetched(repo_dir);
+        }
+
+        let output = Self::git_in_extended_repo(
+            repo_dir,
+            extend,
+            &["checkout", "--quiet", "--detach", commit],
+        )?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!(
+                "Failed to check out {} in extended repo '{}':\n{}",
+                commit,
+                extend.repo,
+                stderr.trim()
+            ));
+        }
+        Ok(())
     }
 
     fn format_extended_repo_error(action: &str, extend: &ExtendsRepo, stderr: &str) -> String {
@@ -525,9 +1898,9 @@ impl Config {
         )
     }
 
-    fn extends_repo_id(repo: &str, branch: Option<&str>) -> String {
-        let raw = if let Some(branch) = branch {
-            format!("{}#{}", repo, branch)
+    fn extends_repo_id(repo: &str, reference: Option<&str>) -> String {
+        let raw = if let Some(reference) = reference {
+            format!("{}#{}", repo, reference)
         } else {
             repo.to_string()
         };
@@ -555,14 +1928,47 @@ impl Config {
     /// Resolve all relative dotfile source paths against the given base directory.
     /// This ensures source paths work regardless of the current working directory.
     pub fn resolve_source_paths(&mut self, base_dir: &Path) {
-        for dotfile in &mut self.dotfiles {
+        Self::resolve_layer_paths(
+            &mut self.dotfiles,
+            &mut self.blocks,
+            &mut self.merges,
+            base_dir,
+        );
+        for host_config in self.hosts.values_mut() {
+            Self::resolve_layer_paths(
+                &mut host_config.dotfiles,
+                &mut host_config.blocks,
+                &mut host_config.merges,
+                base_dir,
+            );
+        }
+        for role in self.roles.values_mut() {
+            Self::resolve_layer_paths(
+                &mut role.dotfiles,
+                &mut role.blocks,
+                &mut role.merges,
+                base_dir,
+            );
+        }
+    }
+
+    fn resolve_layer_paths(
+        dotfiles: &mut [Dotfile],
+        blocks: &mut [ManagedBlock],
+        merges: &mut [MergeEntry],
+        base_dir: &Path,
+    ) {
+        for dotfile in dotfiles {
             dotfile.source = Self::resolve_relative_path(&dotfile.source, base_dir);
         }
-        for host_config in self.hosts.values_mut() {
-            for dotfile in &mut host_config.dotfiles {
-                dotfile.source = Self::resolve_relative_path(&dotfile.source, base_dir);
+        for block in blocks {
+            if let Some(source) = &mut block.source {
+                *source = Self::resolve_relative_path(source, base_dir);
             }
         }
+        for merge in merges {
+            merge.source = Self::resolve_relative_path(&merge.source, base_dir);
+        }
     }
 
     /// If a path is relative (doesn't start with `/` or `~` or `$`), join it with the base directory.
@@ -575,57 +1981,341 @@ impl Config {
         }
     }
 
-    /// Merge the base config with a specific host configuration
-    pub fn with_host(&self, host_name: &str) -> anyhow::Result<Config> {
-        let host = self
-            .hosts
-            .get(host_name)
-            .ok_or_else(|| anyhow::anyhow!("Host '{}' not found in config", host_name))?;
+    /// Resolve the `inherits` chain for a host, ordered from the root ancestor
+    /// down to the host itself.
+    pub fn host_chain(&self, host_name: &str) -> anyhow::Result<Vec<String>> {
+        let mut chain: Vec<String> = Vec::new();
+        let mut current = host_name.to_string();
+
+        loop {
+            if let Some(cycle_start) = chain.iter().position(|name| name == &current) {
+                let mut cycle: Vec<String> = chain[cycle_start..].to_vec();
+                cycle.push(current);
+                return Err(anyhow::anyhow!(
+                    "Cyclic host inherits detected:\n  - {}",
+                    cycle.join("\n  - ")
+                ));
+            }
 
-        let mut merged_vars = self.variables.clone();
-        for (key, value) in &host.variables {
-            merged_vars.insert(key.clone(), value.clone());
+            let host = self.hosts.get(&current).ok_or_else(|| match chain.last() {
+                Some(child) => anyhow::anyhow!(
+                    "Host '{}' inherits from unknown host '{}'\n\nTo fix:\n  - Define [hosts.{}] or correct the `inherits` value",
+                    child,
+                    current,
+                    current
+                ),
+                None => anyhow::anyhow!("Host '{}' not found in config", current),
+            })?;
+
+            chain.push(current);
+            match &host.inherits {
+                Some(parent) => current = parent.clone(),
+                None => break,
+            }
         }
 
-        let mut merged_dotfiles = self.dotfiles.clone();
-        merged_dotfiles.extend(host.dotfiles.clone());
+        chain.reverse();
+        Ok(chain)
+    }
 
-        let mut merged_packages = self.packages.normalized();
-        let host_packages = host.packages.normalized();
-        for pkg in host_packages.homebrew {
-            if !merged_packages.homebrew.iter().any(|p| p.name == pkg.name) {
-                merged_packages.homebrew.push(pkg);
+    /// Flatten a host and every host it inherits from into a single `HostConfig`.
+    ///
+    /// Hosts later in the chain override secrets and mise tools and merge
+    /// their variables over earlier ones (see [`merge_variables`]); dotfiles
+    /// and hooks accumulate; packages and roles are added if not already
+    /// present. Each host's removals apply to the hosts before it.
+    pub fn resolved_host(&self, host_name: &str) -> anyhow::Result<HostConfig> {
+        let mut resolved = HostConfig::default();
+
+        for name in self.host_chain(host_name)? {
+            let host = &self.hosts[&name];
+            remove_entries(
+                &mut host.removals(),
+                &mut resolved.dotfiles,
+                &mut resolved.blocks,
+                &mut resolved.merges,
+                &mut resolved.packages,
+                &mut resolved.hooks,
+            );
+
+            for role in &host.roles {
+                if !resolved.roles.contains(role) {
+                    resolved.roles.push(role.clone());
+                }
             }
-        }
-        for pkg in host_packages.zerobrew {
-            if !merged_packages.zerobrew.iter().any(|p| p.name == pkg.name) {
-                merged_packages.zerobrew.push(pkg);
+
+            merge_variables(&mut resolved.variables, &host.variables);
+            resolved
+                .dotfiles
+                .extend(host.dotfiles.iter().filter(|d| d.enabled).cloned());
+            resolved
+                .blocks
+                .extend(host.blocks.iter().filter(|b| b.enabled).cloned());
+            resolved
+                .merges
+                .extend(host.merges.iter().filter(|m| m.enabled).cloned());
+
+            let host_packages = host.packages.normalized();
+            resolved.packages = resolved.packages.normalized();
+            for pkg in host_packages.homebrew.into_iter().filter(|p| p.enabled) {
+                if !resolved
+                    .packages
+                    .homebrew
+                    .iter()
+                    .any(|p| p.name == pkg.name)
+                {
+                    resolved.packages.homebrew.push(pkg);
+                }
+            }
+            for pkg in host_packages.zerobrew.into_iter().filter(|p| p.enabled) {
+                if !resolved
+                    .packages
+                    .zerobrew
+                    .iter()
+                    .any(|p| p.name == pkg.name)
+                {
+                    resolved.packages.zerobrew.push(pkg);
+                }
             }
+
+            resolved.hooks.extend(host.hooks.clone());
+            resolved.secrets.extend(host.secrets.clone());
+            resolved.mise.tools.extend(host.mise.tools.clone());
         }
 
-        let mut merged_hooks = self.hooks.clone();
-        merged_hooks.extend(host.hooks.clone());
+        Ok(resolved)
+    }
 
-        let mut merged_secrets = self.secrets.clone();
-        for (key, value) in &host.secrets {
-            merged_secrets.insert(key.clone(), value.clone());
-        }
+    /// The `[roles.*]` sections for `roles`, in the same order, as host layers.
+    /// Roles without a section are plain tags and contribute nothing.
+    pub fn role_layers(&self, roles: &[String]) -> Vec<(String, HostConfig)> {
+        roles
+            .iter()
+            .filter_map(|role| {
+                self.roles
+                    .get(role)
+                    .map(|bundle| (role.clone(), bundle.as_host()))
+            })
+            .collect()
+    }
+
+    /// The layers merged on top of the base config for a host, in merge
+    /// order: the bundles of its roles (named `role <name>`), then each host
+    /// from the root of its `inherits` chain down to the host itself.
+    pub fn host_layers(&self, host_name: &str) -> anyhow::Result<Vec<(String, HostConfig)>> {
+        let roles = self.resolved_host(host_name)?.roles;
+        let chain = self.host_chain(host_name)?;
+        Ok(self
+            .role_layers(&roles)
+            .into_iter()
+            .map(|(role, layer)| (format!("role {}", role), layer))
+            .chain(
+                chain
+                    .into_iter()
+                    .map(|name| (name.clone(), self.hosts[&name].clone())),
+            )
+            .collect())
+    }
+
+    /// Merge the base config with a specific host configuration, including
+    /// everything the host inherits. The returned config's entry for the host
+    /// is replaced by its resolved form, so role lookups see inherited roles.
+    ///
+    /// The bundles of the host's roles are merged between the base config and
+    /// the host, in the order the roles are listed, so a host can still
+    /// override a variable or mise tool its role sets. Each layer's removals
+    /// (see [`Removal`]) apply to the base config and the layers before it.
+    pub fn with_host(&self, host_name: &str) -> anyhow::Result<Config> {
+        self.merge_host(host_name).map(|(config, _)| config)
+    }
+
+    /// Everything removed on a host, with the layer that removed it: `base`
+    /// for the config files, `role <name>` or a host in the `inherits` chain.
+    /// Removals that matched nothing beneath their layer are left out.
+    pub fn host_removals(&self, host_name: &str) -> anyhow::Result<Vec<(String, Removal)>> {
+        self.merge_host(host_name).map(|(_, removals)| removals)
+    }
+
+    /// [`Config::with_host`], along with the removals that took effect.
+    fn merge_host(&self, host_name: &str) -> anyhow::Result<(Config, Vec<(String, Removal)>)> {
+        let host = self.resolved_host(host_name)?;
+        let layers = self.host_layers(host_name)?;
 
+        let mut merged_vars = self.variables.clone();
+        let mut merged_dotfiles: Vec<Dotfile> = self
+            .dotfiles
+            .iter()
+            .filter(|d| d.enabled)
+            .cloned()
+            .collect();
+        let mut merged_blocks: Vec<ManagedBlock> =
+            self.blocks.iter().filter(|b| b.enabled).cloned().collect();
+        let mut merged_merges: Vec<MergeEntry> =
+            self.merges.iter().filter(|m| m.enabled).cloned().collect();
+        let mut merged_packages = self.packages.normalized();
+        merged_packages.homebrew.retain(|p| p.enabled);
+        merged_packages.zerobrew.retain(|p| p.enabled);
+        let mut merged_hooks = self.hooks.clone();
+        let mut merged_secrets = self.secrets.clone();
         let mut merged_mise = self.mise.clone();
-        for (key, value) in &host.mise.tools {
-            merged_mise.tools.insert(key.clone(), value.clone());
+        let mut removed: Vec<(String, Removal)> = self
+            .removed
+            .iter()
+            .filter(|removal| removal.dropped)
+            .map(|removal| ("base".to_string(), removal.clone()))
+            .collect();
+
+        for (name, layer) in &layers {
+            merge_variables(&mut merged_vars, &layer.variables);
+
+            let mut layer_removals = layer.removals();
+            remove_entries(
+                &mut layer_removals,
+                &mut merged_dotfiles,
+                &mut merged_blocks,
+                &mut merged_merges,
+                &mut merged_packages,
+                &mut merged_hooks,
+            );
+            removed.extend(
+                layer_removals
+                    .into_iter()
+                    .filter(|removal| removal.dropped)
+                    .map(|removal| (name.clone(), removal)),
+            );
+
+            merged_dotfiles.extend(layer.dotfiles.iter().filter(|d| d.enabled).cloned());
+            merged_blocks.extend(layer.blocks.iter().filter(|b| b.enabled).cloned());
+            merged_merges.extend(layer.merges.iter().filter(|m| m.enabled).cloned());
+
+            let layer_packages = layer.packages.normalized();
+            for pkg in layer_packages.homebrew.into_iter().filter(|p| p.enabled) {
+                if !merged_packages.homebrew.iter().any(|p| p.name == pkg.name) {
+                    merged_packages.homebrew.push(pkg);
+                }
+            }
+            for pkg in layer_packages.zerobrew.into_iter().filter(|p| p.enabled) {
+                if !merged_packages.zerobrew.iter().any(|p| p.name == pkg.name) {
+                    merged_packages.zerobrew.push(pkg);
+                }
+            }
+
+            merged_hooks.extend(layer.hooks.clone());
+
+            for (key, value) in &layer.secrets {
+                merged_secrets.insert(key.clone(), value.clone());
+            }
+            for (key, value) in &layer.mise.tools {
+                merged_mise.tools.insert(key.clone(), value.clone());
+            }
         }
 
-        Ok(Config {
+        let mut hosts = self.hosts.clone();
+        hosts.insert(host_name.to_string(), host);
+
+        let config = Config {
             extends: Vec::new(),
+            include: Vec::new(),
             variables: merged_vars,
             dotfiles: merged_dotfiles,
+            blocks: merged_blocks,
+            merges: merged_merges,
             packages: merged_packages,
-            hosts: self.hosts.clone(),
+            hosts,
+            roles: self.roles.clone(),
             hooks: merged_hooks,
             secrets: merged_secrets,
             mise: merged_mise,
-        })
+            encryption: self.encryption.clone(),
+            exclude_dotfiles: Vec::new(),
+            exclude_packages: Vec::new(),
+            exclude_hooks: Vec::new(),
+            removed: removed.iter().map(|(_, removal)| removal.clone()).collect(),
+        };
+        Ok((config, removed))
+    }
+
+    /// Compare every host to the machine, in name order.
+    pub fn host_candidates(&self, machine: &MachineFacts) -> Vec<HostCandidate> {
+        self.host_names()
+            .into_iter()
+            .map(|name| {
+                let host = &self.hosts[&name];
+                let (matched, details) = if name.eq_ignore_ascii_case(&machine.hostname) {
+                    (
+                        true,
+                        vec![format!("named after hostname '{}'", machine.hostname)],
+                    )
+                } else {
+                    match &host.matcher {
+                        Some(matcher) => matcher.check(machine),
+                        None if name == DEFAULT_HOST => (
+                            false,
+                            vec!["fallback when no other host matches".to_string()],
+                        ),
+                        None => (
+                            false,
+                            vec!["no match rules; selected by exact hostname only".to_string()],
+                        ),
+                    }
+                };
+                HostCandidate {
+                    name,
+                    matched,
+                    details,
+                }
+            })
+            .collect()
+    }
+
+    /// Pick the host for this machine: a host named after its hostname, else
+    /// the one host whose `match` rules all hold, else `[hosts.default]`.
+    pub fn select_host(&self, machine: &MachineFacts) -> anyhow::Result<HostSelection> {
+        if let Some(name) = self
+            .host_names()
+            .into_iter()
+            .find(|name| name.eq_ignore_ascii_case(&machine.hostname))
+        {
+            return Ok(HostSelection {
+                reason: format!("named after hostname '{}'", machine.hostname),
+                name,
+            });
+        }
+
+        let candidates = self.host_candidates(machine);
+        let matched: Vec<&HostCandidate> = candidates.iter().filter(|c| c.matched).collect();
+        let describe = |candidates: &[&HostCandidate]| {
+            candidates
+                .iter()
+                .map(|c| format!("  - {}: {}", c.name, c.details.join(", ")))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        match matched.as_slice() {
+            [only] => Ok(HostSelection {
+                name: only.name.clone(),
+                reason: only.details.join(", "),
+            }),
+            [] if self.hosts.contains_key(DEFAULT_HOST) => Ok(HostSelection {
+                name: DEFAULT_HOST.to_string(),
+                reason: "no other host matches this machine".to_string(),
+            }),
+            [] => Err(anyhow::anyhow!(
+                "No host matches this machine (hostname '{}', os '{}', user '{}')\n\nCandidates:\n{}\n\nTo fix:\n  - Add a `match` rule to one of these hosts, or a [hosts.default] section\n  - Or choose one with --host <name>",
+                machine.hostname,
+                machine.os,
+                machine.user,
+                describe(&candidates.iter().collect::<Vec<_>>())
+            )),
+            several => Err(anyhow::anyhow!(
+                "Several hosts match this machine (hostname '{}', os '{}', user '{}'):\n{}\n\nTo fix:\n  - Narrow their `match` rules so only one applies\n  - Or choose one with --host <name>",
+                machine.hostname,
+                machine.os,
+                machine.user,
+                describe(several)
+            )),
+        }
     }
 
     /// Get list of configured host names
@@ -634,4 +2324,28 @@ impl Config {
         names.sort();
         names
     }
+
+    /// Every role with a `[roles.*]` section or listed by a host, sorted.
+    pub fn role_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .roles
+            .keys()
+            .chain(self.hosts.values().flat_map(|host| &host.roles))
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Hosts that have `role`, directly or through `inherits`, in name order.
+    pub fn hosts_with_role(&self, role: &str) -> Vec<String> {
+        self.host_names()
+            .into_iter()
+            .filter(|name| {
+                self.resolved_host(name)
+                    .is_ok_and(|host| host.roles.iter().any(|r| r == role))
+            })
+            .collect()
+    }
 }
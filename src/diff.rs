@@ -1,13 +1,22 @@
-use crate::config::{Config, Dotfile};
+use crate::blocks;
+use crate::config::{Config, Dotfile, ManagedBlock, MergeEntry, Variables};
 use crate::expand::expand_path_str;
 use crate::installer::HomebrewManager;
-use crate::linker::rendered_path_for;
+use crate::linker::{
+    PermissionSpec, content_hash, permission_drift, rendered_path_for, tree_entries,
+};
+use crate::merge;
+use crate::state::{DotfileState, State};
+use crate::template::HostContext;
 use crate::zerobrew::ZerobrewManager;
 use colored::Colorize;
+use serde::Serialize;
+use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
 pub enum Change {
     Add {
         resource_type: ResourceType,
@@ -18,15 +27,38 @@ pub enum Change {
         description: String,
         reason: String,
     },
+    /// Recorded in state but no longer in the config
+    Remove {
+        resource_type: ResourceType,
+        description: String,
+    },
     AlreadyCorrect {
         description: String,
     },
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ResourceType {
     Dotfile,
+    /// Managed block inside a file
+    Block,
+    /// Keys merged into an application's config file
+    Merge,
     Package,
+    Hook,
+}
+
+impl ResourceType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResourceType::Dotfile => "dotfile",
+            ResourceType::Block => "block",
+            ResourceType::Merge => "merge",
+            ResourceType::Package => "package",
+            ResourceType::Hook => "hook",
+        }
+    }
 }
 
 impl Change {
@@ -37,10 +69,7 @@ impl Change {
                 description,
             } => {
                 let symbol = "+".green().bold();
-                let type_label = match resource_type {
-                    ResourceType::Dotfile => "dotfile",
-                    ResourceType::Package => "package",
-                };
+                let type_label = resource_type.label();
                 format!("{} {} {}", symbol, type_label, description.white())
             }
             Change::Modify {
@@ -49,10 +78,7 @@ impl Change {
                 reason,
             } => {
                 let symbol = "~".yellow().bold();
-                let type_label = match resource_type {
-                    ResourceType::Dotfile => "dotfile",
-                    ResourceType::Package => "package",
-                };
+                let type_label = resource_type.label();
                 format!(
                     "{} {} {} ({})",
                     symbol,
@@ -61,6 +87,19 @@ impl Change {
                     reason.yellow()
                 )
             }
+            Change::Remove {
+                resource_type,
+                description,
+            } => {
+                let symbol = "-".red().bold();
+                format!(
+                    "{} {} {} ({})",
+                    symbol,
+                    resource_type.label(),
+                    description.white(),
+                    "no longer in config".red()
+                )
+            }
             Change::AlreadyCorrect { description } => {
                 let symbol = "✓".bright_black();
                 format!("{} {}", symbol, description.bright_black())
@@ -72,6 +111,8 @@ impl Change {
 pub struct DiffEngine {
     homebrew: HomebrewManager,
     zerobrew: ZerobrewManager,
+    applied_dotfiles: Vec<DotfileState>,
+    host_context: HostContext,
 }
 
 impl DiffEngine {
@@ -79,6 +120,8 @@ impl DiffEngine {
         Self {
             homebrew: HomebrewManager::new(),
             zerobrew: ZerobrewManager::new(),
+            applied_dotfiles: Vec::new(),
+            host_context: HostContext::default(),
         }
     }
 
@@ -86,17 +129,44 @@ impl DiffEngine {
         Self {
             homebrew,
             zerobrew: ZerobrewManager::new(),
+            applied_dotfiles: Vec::new(),
+            host_context: HostContext::default(),
         }
     }
 
+    /// Host used when rendering managed blocks for comparison
+    pub fn with_host_context(mut self, host_context: HostContext) -> Self {
+        self.host_context = host_context;
+        self
+    }
+
+    /// Compare against what was last applied, so copy-mode dotfiles can tell
+    /// edits made in place apart from changes to the source.
+    pub fn with_state(mut self, state: &State) -> Self {
+        self.applied_dotfiles = state.dotfiles.clone();
+        self
+    }
+
     pub fn diff(&self, config: &Config) -> anyhow::Result<Vec<Change>> {
         let mut changes = Vec::new();
 
         for dotfile in &config.dotfiles {
+            if dotfile.is_tree() {
+                changes.extend(self.diff_tree_dotfile(dotfile)?);
+                continue;
+            }
             let change = self.diff_dotfile(dotfile)?;
             changes.push(change);
         }
 
+        for block in &config.blocks {
+            changes.push(self.diff_block(block, &config.variables)?);
+        }
+
+        for merge in &config.merges {
+            changes.push(self.diff_merge(merge)?);
+        }
+
         let normalized_packages = config.packages.normalized();
         for package in &normalized_packages.homebrew {
             let change = self.diff_package(&package.name, &package.pkg_type)?;
@@ -108,10 +178,50 @@ impl DiffEngine {
             changes.push(change);
         }
 
+        for orphan in self.orphaned_dotfiles(config)? {
+            changes.push(Change::Remove {
+                resource_type: ResourceType::Dotfile,
+                description: orphan.target,
+            });
+        }
+
         Ok(changes)
     }
 
-    fn diff_dotfile(&self, dotfile: &Dotfile) -> anyhow::Result<Change> {
+    /// Dotfiles recorded in state that the config no longer deploys.
+    pub fn orphaned_dotfiles(&self, config: &Config) -> anyhow::Result<Vec<DotfileState>> {
+        let mut expected = HashSet::new();
+        // Tree targets whose source can't be walked; keep everything under them
+        let mut unreadable_trees = Vec::new();
+
+        for dotfile in &config.dotfiles {
+            if dotfile.is_tree() {
+                match tree_entries(dotfile) {
+                    Ok(entries) => {
+                        for entry in entries {
+                            expected.insert(expand_path_str(&entry.target)?);
+                        }
+                    }
+                    Err(_) => unreadable_trees.push(expand_path_str(&dotfile.target)?),
+                }
+            } else {
+                expected.insert(expand_path_str(&dotfile.target)?);
+            }
+        }
+
+        Ok(self
+            .applied_dotfiles
+            .iter()
+            .filter(|d| {
+                let target = Path::new(&d.target);
+                !expected.contains(target)
+                    && !unreadable_trees.iter().any(|tree| target.starts_with(tree))
+            })
+            .cloned()
+            .collect())
+    }
+
+    pub fn diff_dotfile(&self, dotfile: &Dotfile) -> anyhow::Result<Change> {
         let expanded_source = expand_path(&dotfile.source)?;
         let expanded_target = expand_path(&dotfile.target)?;
 
@@ -136,6 +246,10 @@ impl DiffEngine {
 
         let target_metadata = fs::symlink_metadata(&expanded_target)?;
 
+        if dotfile.is_copy() {
+            return self.diff_copied_dotfile(dotfile, &expanded_source, &expanded_target);
+        }
+
         if !target_metadata.is_symlink() {
             return Ok(Change::Modify {
                 resource_type: ResourceType::Dotfile,
@@ -146,9 +260,9 @@ impl DiffEngine {
 
         let current_link_target = fs::read_link(&expanded_target)?;
 
-        // For template dotfiles, the symlink should point to the rendered file
+        // For template and encrypted dotfiles, the symlink should point to the rendered file
         // in ~/.mimic/rendered/, not the original source template.
-        let expected_path = if dotfile.is_template() {
+        let expected_path = if dotfile.is_rendered() {
             rendered_path_for(&expanded_source)?
         } else {
             expanded_source.clone()
@@ -173,9 +287,7 @@ impl DiffEngine {
         };
 
         if canonical_expected == canonical_current {
-            Ok(Change::AlreadyCorrect {
-                description: format!("{}", expanded_target.display()),
-            })
+            Ok(Self::permission_change(dotfile, &expanded_target))
         } else {
             Ok(Change::Modify {
                 resource_type: ResourceType::Dotfile,
@@ -185,12 +297,172 @@ impl DiffEngine {
         }
     }
 
+    /// Diff a `link = "tree"` dotfile, producing one change per file.
+    pub fn diff_tree_dotfile(&self, dotfile: &Dotfile) -> anyhow::Result<Vec<Change>> {
+        let expanded_source = expand_path(&dotfile.source)?;
+        let expanded_target = expand_path(&dotfile.target)?;
+
+        if !expanded_source.is_dir() {
+            let reason = if expanded_source.exists() {
+                format!(
+                    "tree link source is not a directory: {}",
+                    expanded_source.display()
+                )
+            } else {
+                format!("source missing: {}", expanded_source.display())
+            };
+            return Ok(vec![Change::Modify {
+                resource_type: ResourceType::Dotfile,
+                description: format!("{}", expanded_target.display()),
+                reason,
+            }]);
+        }
+
+        if expanded_target.is_symlink() {
+            return Ok(vec![Change::Modify {
+                resource_type: ResourceType::Dotfile,
+                description: format!("{}", expanded_target.display()),
+                reason: "is a symlink, expected a directory of per-file links".to_string(),
+            }]);
+        }
+
+        tree_entries(dotfile)?
+            .iter()
+            .map(|entry| self.diff_dotfile(entry))
+            .collect()
+    }
+
+    fn diff_copied_dotfile(
+        &self,
+        dotfile: &Dotfile,
+        expanded_source: &Path,
+        expanded_target: &Path,
+    ) -> anyhow::Result<Change> {
+        if expanded_target.is_symlink() {
+            return Ok(Change::Modify {
+                resource_type: ResourceType::Dotfile,
+                description: format!("{}", expanded_target.display()),
+                reason: "is a symlink, expected a copy".to_string(),
+            });
+        }
+
+        let expected_path = if dotfile.is_rendered() {
+            rendered_path_for(expanded_source)?
+        } else {
+            expanded_source.to_path_buf()
+        };
+
+        if !expected_path.exists() {
+            return Ok(Change::Modify {
+                resource_type: ResourceType::Dotfile,
+                description: format!("{}", expanded_target.display()),
+                reason: format!("expected source missing: {}", expected_path.display()),
+            });
+        }
+
+        let target_hash = content_hash(expanded_target)?;
+        let expected_hash = content_hash(&expected_path)?;
+
+        if target_hash == expected_hash {
+            return Ok(Self::permission_change(dotfile, expanded_target));
+        }
+
+        let recorded_hash = self
+            .applied_dotfiles
+            .iter()
+            .find(|d| Path::new(&d.target) == expanded_target)
+            .and_then(|d| d.content_hash.clone());
+
+        Ok(Change::Modify {
+            resource_type: ResourceType::Dotfile,
+            description: format!("{}", expanded_target.display()),
+            reason: copy_drift_reason(recorded_hash.as_deref(), &target_hash, &expected_hash)
+                .to_string(),
+        })
+    }
+
+    pub fn diff_block(
+        &self,
+        block: &ManagedBlock,
+        variables: &Variables,
+    ) -> anyhow::Result<Change> {
+        let target = expand_path(&block.target)?;
+        let description = format!("{} in {}", block.name, target.display());
+        let expected = blocks::render(block, variables, &self.host_context)?;
+
+        let current = match fs::read_to_string(&target) {
+            Ok(current) => current,
+            Err(_) => {
+                return Ok(Change::Add {
+                    resource_type: ResourceType::Block,
+                    description,
+                });
+            }
+        };
+
+        Ok(match blocks::find(&current, &block.comment, &block.name) {
+            Ok(None) => Change::Add {
+                resource_type: ResourceType::Block,
+                description,
+            },
+            Ok(Some(range)) if blocks::body(&current, &range) == expected => {
+                Change::AlreadyCorrect { description }
+            }
+            Ok(Some(_)) => Change::Modify {
+                resource_type: ResourceType::Block,
+                description,
+                reason: "content differs".to_string(),
+            },
+            Err(_) => Change::Modify {
+                resource_type: ResourceType::Block,
+                description,
+                reason: "end marker missing".to_string(),
+            },
+        })
+    }
+
+    pub fn diff_merge(&self, merge: &MergeEntry) -> anyhow::Result<Change> {
+        let target = expand_path(&merge.target)?;
+        let description = format!("{}", target.display());
+        if !target.exists() {
+            return Ok(Change::Add {
+                resource_type: ResourceType::Merge,
+                description,
+            });
+        }
+
+        let differing = merge::differing_keys(merge)?;
+        Ok(if differing.is_empty() {
+            Change::AlreadyCorrect { description }
+        } else {
+            Change::Modify {
+                resource_type: ResourceType::Merge,
+                description,
+                reason: format!("keys differ: {}", differing.join(", ")),
+            }
+        })
+    }
+
+    /// `AlreadyCorrect` for a correctly deployed dotfile, unless its
+    /// permissions differ from the ones in the config.
+    fn permission_change(dotfile: &Dotfile, expanded_target: &Path) -> Change {
+        match permission_drift(expanded_target, PermissionSpec::of(dotfile)) {
+            Some(reason) => Change::Modify {
+                resource_type: ResourceType::Dotfile,
+                description: format!("{}", expanded_target.display()),
+                reason,
+            },
+            None => Change::AlreadyCorrect {
+                description: format!("{}", expanded_target.display()),
+            },
+        }
+    }
+
     fn diff_package(&self, name: &str, package_type: &str) -> anyhow::Result<Change> {
         let is_installed = self.homebrew.is_installed_any(name, package_type)?;
-        let type_label = if package_type == "cask" {
-            "cask"
-        } else {
-            "formula"
+        let type_label = match package_type {
+            "cask" | "tap" => package_type,
+            _ => "formula",
         };
 
         if is_installed {
@@ -227,6 +499,17 @@ impl Default for DiffEngine {
     }
 }
 
+/// Describe why a copied dotfile differs from its source, given the hash
+/// recorded at the last apply.
+pub fn copy_drift_reason(recorded: Option<&str>, target: &str, expected: &str) -> &'static str {
+    match recorded {
+        Some(recorded) if recorded != target && recorded == expected => "modified in place",
+        Some(recorded) if recorded == target && recorded != expected => "source changed",
+        Some(_) => "modified in place and source changed",
+        None => "content differs from source",
+    }
+}
+
 fn expand_path(path: &str) -> anyhow::Result<std::path::PathBuf> {
     expand_path_str(path)
 }
@@ -270,6 +553,20 @@ mod tests {
         assert!(formatted.contains("✓"));
     }
 
+    #[test]
+    fn test_copy_drift_reason() {
+        assert_eq!(copy_drift_reason(Some("a"), "b", "a"), "modified in place");
+        assert_eq!(copy_drift_reason(Some("a"), "a", "b"), "source changed");
+        assert_eq!(
+            copy_drift_reason(Some("a"), "b", "c"),
+            "modified in place and source changed"
+        );
+        assert_eq!(
+            copy_drift_reason(None, "b", "c"),
+            "content differs from source"
+        );
+    }
+
     #[test]
     fn test_expand_path_with_tilde() {
         let result = expand_path("~/test");
@@ -299,8 +596,15 @@ mod tests {
             source: source.to_string_lossy().to_string(),
             target: target.to_string_lossy().to_string(),
             template: false,
+            encrypted: false,
+            mode: Default::default(),
+            link: Default::default(),
+            permissions: None,
+            dir_permissions: None,
             only_roles: None,
             skip_roles: None,
+            when: None,
+            enabled: true,
         };
 
         let engine = DiffEngine::new();
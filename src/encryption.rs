@@ -0,0 +1,205 @@
+//! age encryption for `encrypted = true` dotfile sources.
+//!
+//! Encrypted sources live in the repo as `.age` files. At apply time they are
+//! decrypted into `~/.mimic/rendered` (mode 0600) and linked from there, the
+//! same way rendered templates are. The identity comes from a local key file
+//! (`~/.mimic/age.key` by default) or from the secrets backend.
+
+use crate::config::EncryptionConfig;
+use crate::expand::expand_path_str;
+use crate::secrets;
+use age::secrecy::ExposeSecret;
+use age::x25519::{Identity, Recipient};
+use anyhow::Context;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Extension given to files encrypted by `mimic encrypt`.
+pub const ENCRYPTED_EXTENSION: &str = "age";
+
+pub fn default_identity_path() -> anyhow::Result<PathBuf> {
+    let home =
+        home::home_dir().ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    Ok(home.join(".mimic/age.key"))
+}
+
+fn identity_path(settings: &EncryptionConfig) -> anyhow::Result<PathBuf> {
+    match &settings.identity {
+        Some(path) => expand_path_str(path),
+        None => default_identity_path(),
+    }
+}
+
+/// Parse an identity from key file contents, skipping `#` comment lines.
+fn parse_identity(text: &str) -> anyhow::Result<Identity> {
+    let key = text
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with("AGE-SECRET-KEY-"))
+        .ok_or_else(|| anyhow::anyhow!("No AGE-SECRET-KEY line found"))?;
+    Identity::from_str(key).map_err(|e| anyhow::anyhow!("Invalid age identity: {}", e))
+}
+
+/// Load the identity used to decrypt sources.
+pub fn load_identity(settings: &EncryptionConfig) -> anyhow::Result<Identity> {
+    if let Some(name) = &settings.identity_secret {
+        let key = secrets::get_secret(name).with_context(|| {
+            format!(
+                "Failed to load age identity from secret '{}'\n\nTo fix:\n  - Store it with 'mimic secrets set {}'",
+                name, name
+            )
+        })?;
+        return parse_identity(&key)
+            .with_context(|| format!("Secret '{}' is not an age identity", name));
+    }
+
+    let path = identity_path(settings)?;
+    let text = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "No age identity at {}\n\nTo fix:\n  - Copy your key file there from another machine\n  - Or set [encryption] identity or identity_secret in mimic.toml",
+            path.display()
+        )
+    })?;
+    parse_identity(&text).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Load the identity, generating a new key file at the default location if
+/// none is configured or present. Returns the identity and, if one was
+/// generated, where it was written.
+pub fn load_or_generate_identity(
+    settings: &EncryptionConfig,
+) -> anyhow::Result<(Identity, Option<PathBuf>)> {
+    let path = identity_path(settings)?;
+    if settings.identity_secret.is_some() || settings.identity.is_some() || path.exists() {
+        return Ok((load_identity(settings)?, None));
+    }
+
+    let identity = Identity::generate();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        fs::set_permissions(parent, fs::Permissions::from_mode(0o700))?;
+    }
+    fs::write(
+        &path,
+        format!(
+            "# public key: {}\n{}\n",
+            identity.to_public(),
+            identity.to_string().expose_secret()
+        ),
+    )
+    .with_context(|| format!("Failed to write: {}", path.display()))?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+
+    Ok((identity, Some(path)))
+}
+
+/// Public keys to encrypt to: the identity's own plus any configured extras.
+pub fn recipients(
+    settings: &EncryptionConfig,
+    identity: &Identity,
+) -> anyhow::Result<Vec<Recipient>> {
+    let mut recipients = vec![identity.to_public()];
+    for recipient in &settings.recipients {
+        let parsed = Recipient::from_str(recipient).map_err(|e| {
+            anyhow::anyhow!(
+                "Invalid age recipient '{}': {}\n\nTo fix:\n  - Recipients are public keys starting with 'age1'",
+                recipient,
+                e
+            )
+        })?;
+        if !recipients
+            .iter()
+            .any(|r| r.to_string() == parsed.to_string())
+        {
+            recipients.push(parsed);
+        }
+    }
+    Ok(recipients)
+}
+
+pub fn encrypt(plaintext: &[u8], recipients: &[Recipient]) -> anyhow::Result<Vec<u8>> {
+    use std::io::Write;
+
+    let encryptor =
+        age::Encryptor::with_recipients(recipients.iter().map(|r| r as &dyn age::Recipient))?;
+    let mut ciphertext = Vec::with_capacity(plaintext.len());
+    let mut writer = encryptor.wrap_output(&mut ciphertext)?;
+    writer.write_all(plaintext)?;
+    writer.finish()?;
+    Ok(ciphertext)
+}
+
+pub fn decrypt(ciphertext: &[u8], identity: &Identity) -> anyhow::Result<Vec<u8>> {
+    age::decrypt(identity, ciphertext).map_err(|e| anyhow::anyhow!("{}", e))
+}
+
+/// Decrypt an encrypted source file.
+pub fn decrypt_file(path: &Path, settings: &EncryptionConfig) -> anyhow::Result<Vec<u8>> {
+    let identity = load_identity(settings)?;
+    let ciphertext =
+        fs::read(path).with_context(|| format!("Failed to read: {}", path.display()))?;
+    decrypt(&ciphertext, &identity).with_context(|| {
+        format!(
+            "Failed to decrypt {}\n\nTo fix:\n  - Check the file was encrypted to this machine's key",
+            path.display()
+        )
+    })
+}
+
+/// Where `mimic encrypt` writes the encrypted copy of `path`.
+pub fn encrypted_path_for(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(ENCRYPTED_EXTENSION);
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_to_multiple_recipients() {
+        let mine = Identity::generate();
+        let theirs = Identity::generate();
+        let settings = EncryptionConfig {
+            recipients: vec![theirs.to_public().to_string()],
+            ..Default::default()
+        };
+
+        let recipients = recipients(&settings, &mine).unwrap();
+        assert_eq!(recipients.len(), 2);
+
+        let ciphertext = encrypt(b"token=secret\n", &recipients).unwrap();
+        assert_eq!(decrypt(&ciphertext, &mine).unwrap(), b"token=secret\n");
+        assert_eq!(decrypt(&ciphertext, &theirs).unwrap(), b"token=secret\n");
+        assert!(decrypt(&ciphertext, &Identity::generate()).is_err());
+    }
+
+    #[test]
+    fn test_identity_file_comments_are_skipped() {
+        let identity = Identity::generate();
+        let text = format!(
+            "# created: today\n# public key: {}\n{}\n",
+            identity.to_public(),
+            identity.to_string().expose_secret()
+        );
+        let parsed = parse_identity(&text).unwrap();
+        assert_eq!(
+            parsed.to_public().to_string(),
+            identity.to_public().to_string()
+        );
+        assert!(parse_identity("# nothing here\n").is_err());
+    }
+
+    #[test]
+    fn test_encrypted_path_appends_extension() {
+        assert_eq!(
+            encrypted_path_for(Path::new("/repo/dotfiles/npmrc")),
+            PathBuf::from("/repo/dotfiles/npmrc.age")
+        );
+    }
+}
@@ -75,3 +75,15 @@ pub enum StateError {
     #[error("I/O error: {0}")]
     Io(#[from] io::Error),
 }
+
+/// Returned by `mimic status` when managed resources have drifted. The report
+/// has already been printed, so it maps to exit code 1 without a message.
+#[derive(Error, Debug)]
+#[error("drift detected")]
+pub struct DriftDetected;
+
+/// Returned by `mimic check` when it found errors. The findings have already
+/// been printed, so like drift it maps to exit code 1 without a message.
+#[derive(Error, Debug)]
+#[error("check found errors")]
+pub struct CheckFailed;
@@ -0,0 +1,440 @@
+//! Numbered snapshots of applied state ("generations").
+//!
+//! Every successful apply records a generation: a copy of the state file plus
+//! the backups and rendered files it refers to. Backups are consumed by
+//! `mimic undo`, so keeping private copies is what lets `mimic rollback` return
+//! to any earlier generation.
+
+use crate::blocks;
+use crate::linker::{PermissionSpec, apply_permissions, content_hash, copy_dir_all};
+use crate::merge;
+use crate::state::{BlockState, DotfileState, State};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GenerationInfo {
+    pub number: u32,
+    pub created_at: DateTime<Utc>,
+    pub host: Option<String>,
+    pub commit: Option<String>,
+    pub dotfiles: usize,
+    pub packages: usize,
+    #[serde(default)]
+    pub note: Option<String>,
+}
+
+/// A generation loaded from disk.
+pub struct Generation {
+    pub info: GenerationInfo,
+    /// State as it was recorded; paths refer to their original locations.
+    pub state: State,
+    dir: PathBuf,
+}
+
+impl Generation {
+    /// Private copy of the backup for the dotfile at `index`, if one was kept.
+    pub fn stored_backup(&self, index: usize) -> Option<PathBuf> {
+        let path = self
+            .dir
+            .join("files")
+            .join(index.to_string())
+            .join("backup");
+        (path.exists() || path.is_symlink()).then_some(path)
+    }
+
+    /// Private copy of the rendered template for the dotfile at `index`, if any.
+    pub fn stored_rendered(&self, index: usize) -> Option<PathBuf> {
+        let path = self
+            .dir
+            .join("files")
+            .join(index.to_string())
+            .join("rendered");
+        path.exists().then_some(path)
+    }
+}
+
+/// Summary of what a rollback changed on disk.
+#[derive(Debug, Default)]
+pub struct RollbackReport {
+    pub removed: usize,
+    pub restored: usize,
+    pub deployed: usize,
+    pub errors: Vec<String>,
+}
+
+pub struct GenerationStore {
+    root: PathBuf,
+}
+
+impl GenerationStore {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Default location: `<data dir>/mimic/generations`.
+    pub fn default_root() -> anyhow::Result<PathBuf> {
+        let base_dirs = directories::BaseDirs::new()
+            .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+        Ok(base_dirs.data_dir().join("mimic/generations"))
+    }
+
+    /// List recorded generations, oldest first.
+    pub fn list(&self) -> anyhow::Result<Vec<GenerationInfo>> {
+        if !self.root.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut generations = Vec::new();
+        for entry in fs::read_dir(&self.root)
+            .with_context(|| format!("Failed to read directory: {}", self.root.display()))?
+        {
+            let path = entry?.path().join("generation.toml");
+            if !path.exists() {
+                continue;
+            }
+            let content = fs::read_to_string(&path)?;
+            let info: GenerationInfo = toml::from_str(&content)
+                .with_context(|| format!("Failed to parse {}", path.display()))?;
+            generations.push(info);
+        }
+
+        generations.sort_by_key(|g| g.number);
+        Ok(generations)
+    }
+
+    /// Snapshot `state` as the next generation.
+    pub fn record(&self, state: &State, note: Option<&str>) -> anyhow::Result<GenerationInfo> {
+        let number = self.list()?.last().map(|g| g.number + 1).unwrap_or(1);
+        let dir = self.generation_dir(number);
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+
+        for (index, dotfile) in state.dotfiles.iter().enumerate() {
+            let files_dir = dir.join("files").join(index.to_string());
+
+            if let Some(backup) = &dotfile.backup_path {
+                let backup = Path::new(backup);
+                if backup.exists() {
+                    fs::create_dir_all(&files_dir)?;
+                    copy_path(backup, &files_dir.join("backup"))?;
+                }
+            }
+
+            if let Some(rendered) = &dotfile.rendered_path {
+                let rendered = Path::new(rendered);
+                if rendered.exists() {
+                    fs::create_dir_all(&files_dir)?;
+                    copy_path(rendered, &files_dir.join("rendered"))?;
+                }
+            }
+        }
+
+        state
+            .save(dir.join("state.toml"))
+            .map_err(|e| anyhow::anyhow!("Failed to save generation state: {}", e))?;
+
+        let info = GenerationInfo {
+            number,
+            created_at: Utc::now(),
+            host: state.active_host.clone(),
+            commit: state.applied_commit.clone(),
+            dotfiles: state.dotfiles.len(),
+            packages: state.packages.len(),
+            note: note.map(str::to_string),
+        };
+        fs::write(
+            dir.join("generation.toml"),
+            toml::to_string_pretty(&info)
+                .map_err(|e| anyhow::anyhow!("Failed to serialize generation: {}", e))?,
+        )?;
+
+        Ok(info)
+    }
+
+    /// Load a generation by number.
+    pub fn load(&self, number: u32) -> anyhow::Result<Generation> {
+        let info = self
+            .list()?
+            .into_iter()
+            .find(|g| g.number == number)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Generation {} not found\n\nTo fix:\n  - Run 'mimic history' to list available generations",
+                    number
+                )
+            })?;
+
+        let dir = self.generation_dir(number);
+        let state = State::load(dir.join("state.toml"))
+            .with_context(|| format!("Failed to load state for generation {}", number))?;
+
+        Ok(Generation { info, state, dir })
+    }
+
+    fn generation_dir(&self, number: u32) -> PathBuf {
+        self.root.join(number.to_string())
+    }
+}
+
+/// A copy-mode target edited since it was applied; like `mimic undo`,
+/// rollback leaves it and its backup alone.
+fn edited_copy(dotfile: &DotfileState) -> bool {
+    let target = Path::new(&dotfile.target);
+    dotfile.is_copy()
+        && target.exists()
+        && content_hash(target).ok().as_deref() != dotfile.content_hash.as_deref()
+}
+
+fn edited_copy_error(dotfile: &DotfileState) -> String {
+    match &dotfile.backup_path {
+        Some(backup) => format!(
+            "Left {} in place: modified since it was applied (original kept at {})",
+            dotfile.target, backup
+        ),
+        None => format!(
+            "Left {} in place: modified since it was applied",
+            dotfile.target
+        ),
+    }
+}
+
+/// Bring the filesystem from `current` back to what `generation` recorded.
+///
+/// Targets only managed by the current state are removed and their backups
+/// restored. Every target in the generation is then redeployed, with its
+/// backup and rendered file put back from the generation's private copies.
+/// Targets that exist but are not managed by mimic, and copies edited since
+/// the last apply, are left alone and reported as errors. Managed blocks and merged keys are rewritten with the
+/// values the generation recorded. Packages are never uninstalled.
+pub fn rollback(generation: &Generation, current: &State) -> RollbackReport {
+    let mut report = RollbackReport::default();
+
+    let generation_targets: HashSet<&str> = generation
+        .state
+        .dotfiles
+        .iter()
+        .map(|d| d.target.as_str())
+        .collect();
+    let current_targets: HashMap<&str, &DotfileState> = current
+        .dotfiles
+        .iter()
+        .map(|d| (d.target.as_str(), d))
+        .collect();
+
+    for dotfile in &current.dotfiles {
+        if generation_targets.contains(dotfile.target.as_str()) {
+            continue;
+        }
+        let target = Path::new(&dotfile.target);
+        if edited_copy(dotfile) {
+            report.errors.push(edited_copy_error(dotfile));
+            continue;
+        }
+        if target.exists() || target.is_symlink() {
+            match remove_path(target) {
+                Ok(()) => report.removed += 1,
+                Err(e) => {
+                    report
+                        .errors
+                        .push(format!("Failed to remove {}: {}", target.display(), e));
+                    continue;
+                }
+            }
+        }
+        if let Some(backup) = &dotfile.backup_path {
+            let backup = Path::new(backup);
+            if backup.exists() {
+                match fs::rename(backup, target) {
+                    Ok(()) => report.restored += 1,
+                    Err(e) => report.errors.push(format!(
+                        "Failed to restore backup {} to {}: {}",
+                        backup.display(),
+                        target.display(),
+                        e
+                    )),
+                }
+            }
+        }
+    }
+
+    for (index, dotfile) in generation.state.dotfiles.iter().enumerate() {
+        let target = Path::new(&dotfile.target);
+        let result = (|| -> anyhow::Result<()> {
+            if target.exists() || target.is_symlink() {
+                let Some(managed) = current_targets.get(dotfile.target.as_str()) else {
+                    return Err(anyhow::anyhow!(
+                        "{} exists and is not managed by mimic; leaving it in place",
+                        target.display()
+                    ));
+                };
+                if edited_copy(managed) {
+                    return Err(anyhow::anyhow!(edited_copy_error(managed)));
+                }
+                remove_path(target)?;
+            }
+
+            if let (Some(backup), Some(stored)) =
+                (&dotfile.backup_path, generation.stored_backup(index))
+            {
+                let backup = Path::new(backup);
+                if !backup.exists() {
+                    copy_path(&stored, backup)?;
+                }
+            }
+
+            let deployed_from = match &dotfile.rendered_path {
+                Some(rendered) => {
+                    let rendered = PathBuf::from(rendered);
+                    if let Some(stored) = generation.stored_rendered(index) {
+                        if let Some(parent) = rendered.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        copy_path(&stored, &rendered)?;
+                    }
+                    rendered
+                }
+                None => PathBuf::from(&dotfile.source),
+            };
+
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if dotfile.is_copy() {
+                copy_path(&deployed_from, target)?;
+            } else {
+                symlink(&deployed_from, target).with_context(|| {
+                    format!(
+                        "Failed to create symlink {} → {}",
+                        target.display(),
+                        deployed_from.display()
+                    )
+                })?;
+            }
+            apply_permissions(target, PermissionSpec::of_state(dotfile))?;
+            Ok(())
+        })();
+
+        match result {
+            Ok(()) => report.deployed += 1,
+            Err(e) => report.errors.push(e.to_string()),
+        }
+    }
+
+    let in_generation = |block: &BlockState| {
+        generation
+            .state
+            .blocks
+            .iter()
+            .any(|b| b.target == block.target && b.name == block.name)
+    };
+    for block in current.blocks.iter().filter(|b| !in_generation(b)) {
+        match blocks::remove_block(block) {
+            Ok(true) => report.removed += 1,
+            Ok(false) => {}
+            Err(e) => report.errors.push(e.to_string()),
+        }
+    }
+    for block in &generation.state.blocks {
+        match blocks::write_block(block) {
+            Ok(()) => report.deployed += 1,
+            Err(e) => report.errors.push(e.to_string()),
+        }
+    }
+
+    for entry in current.merges.iter().filter(|m| {
+        !generation
+            .state
+            .merges
+            .iter()
+            .any(|g| g.target == m.target && g.source == m.source)
+    }) {
+        match merge::undo_merge(entry) {
+            Ok(true) => report.removed += 1,
+            Ok(false) => {}
+            Err(e) => report.errors.push(e.to_string()),
+        }
+    }
+    for entry in &generation.state.merges {
+        match merge::write_merge(entry) {
+            Ok(()) => report.deployed += 1,
+            Err(e) => report.errors.push(e.to_string()),
+        }
+    }
+
+    report
+}
+
+fn copy_path(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if from.is_dir() {
+        copy_dir_all(from, to)
+    } else {
+        fs::copy(from, to)
+            .map(|_| ())
+            .with_context(|| format!("Failed to copy {} to {}", from.display(), to.display()))
+    }
+}
+
+fn remove_path(path: &Path) -> std::io::Result<()> {
+    if path.is_dir() && !path.is_symlink() {
+        fs::remove_dir_all(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::DotfileState;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_generation_numbers_increase() {
+        let temp = TempDir::new().unwrap();
+        let store = GenerationStore::new(temp.path());
+
+        let first = store.record(&State::new(), None).unwrap();
+        let second = store.record(&State::new(), Some("second")).unwrap();
+
+        assert_eq!(first.number, 1);
+        assert_eq!(second.number, 2);
+
+        let listed = store.list().unwrap();
+        assert_eq!(listed.len(), 2);
+        assert_eq!(listed[1].note.as_deref(), Some("second"));
+    }
+
+    #[test]
+    fn test_record_keeps_private_backup_copy() {
+        let temp = TempDir::new().unwrap();
+        let backup = temp.path().join("zshrc.backup");
+        fs::write(&backup, "original").unwrap();
+
+        let mut state = State::new();
+        state.add_dotfile(DotfileState {
+            source: "/src/zshrc".to_string(),
+            target: "/home/me/.zshrc".to_string(),
+            backup_path: Some(backup.to_string_lossy().to_string()),
+            rendered_path: None,
+            content_hash: None,
+            permissions: None,
+            dir_permissions: None,
+        });
+
+        let store = GenerationStore::new(temp.path().join("generations"));
+        let info = store.record(&state, None).unwrap();
+
+        // The original backup can be consumed later without losing history
+        fs::remove_file(&backup).unwrap();
+
+        let generation = store.load(info.number).unwrap();
+        let stored = generation.stored_backup(0).unwrap();
+        assert_eq!(fs::read_to_string(stored).unwrap(), "original");
+    }
+}
@@ -0,0 +1,88 @@
+//! Read-only queries against the git repository that holds the config.
+//!
+//! Used to record which commit was applied and to tell `mimic status` whether
+//! the repo has moved on since. Every function degrades to `None`/`Unknown`
+//! when git is missing or the directory is not a repository.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// HEAD of a repository at a point in time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepoHead {
+    pub root: PathBuf,
+    pub commit: String,
+    /// Whether the work tree had uncommitted changes
+    pub dirty: bool,
+}
+
+/// How the repo's current HEAD relates to the last applied commit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommitRelation {
+    Same,
+    /// HEAD has commits the applied commit doesn't
+    Ahead(usize),
+    /// HEAD is an ancestor of the applied commit
+    Behind(usize),
+    Diverged {
+        ahead: usize,
+        behind: usize,
+    },
+    /// The applied commit is not in the local history (e.g. shallow clone)
+    Unknown,
+}
+
+fn git(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Read HEAD and work tree status of the repository containing `dir`.
+pub fn head(dir: &Path) -> Option<RepoHead> {
+    let root = git(dir, &["rev-parse", "--show-toplevel"])?;
+    let commit = git(dir, &["rev-parse", "HEAD"])?;
+    let dirty = git(dir, &["status", "--porcelain"])
+        .map(|status| !status.is_empty())
+        .unwrap_or(false);
+
+    Some(RepoHead {
+        root: PathBuf::from(root),
+        commit,
+        dirty,
+    })
+}
+
+/// Compare the repo's current HEAD against a previously applied commit.
+pub fn relation(dir: &Path, applied: &str, current: &str) -> CommitRelation {
+    if applied == current {
+        return CommitRelation::Same;
+    }
+
+    let range = format!("{}...{}", applied, current);
+    let Some(counts) = git(dir, &["rev-list", "--left-right", "--count", &range]) else {
+        return CommitRelation::Unknown;
+    };
+
+    let mut parts = counts.split_whitespace().map(|n| n.parse::<usize>());
+    match (parts.next(), parts.next()) {
+        (Some(Ok(0)), Some(Ok(ahead))) => CommitRelation::Ahead(ahead),
+        (Some(Ok(behind)), Some(Ok(0))) => CommitRelation::Behind(behind),
+        (Some(Ok(behind)), Some(Ok(ahead))) => CommitRelation::Diverged { ahead, behind },
+        _ => CommitRelation::Unknown,
+    }
+}
+
+/// Abbreviate a commit hash for display.
+pub fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(7)]
+}
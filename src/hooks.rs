@@ -1,83 +1,131 @@
 use colored::Colorize;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::process::{Command, Stdio};
 
+use crate::condition::Facts;
+use crate::output::child_stdout;
+use crate::progress;
 use crate::spinner::Spinner;
 
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+/// A step run after dotfiles and packages are applied, chosen by `type`
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, JsonSchema)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 pub enum Hook {
+    /// Install Rust toolchains, components and targets with rustup
     #[serde(rename = "rustup")]
     Rustup {
         toolchains: Vec<String>,
         components: Vec<String>,
         targets: Vec<String>,
+        /// Toolchain to make the default
         #[serde(default)]
         default: Option<String>,
+        /// Run only on hosts with at least one of these roles
         #[serde(default)]
         only_roles: Option<Vec<String>>,
+        /// Skip on hosts with any of these roles
         #[serde(default)]
         skip_roles: Option<Vec<String>>,
+        /// Condition on system facts, e.g. `os == 'linux'`
+        #[serde(default)]
+        when: Option<String>,
     },
+    /// Install binaries from git repos with `cargo install --git`
     #[serde(rename = "cargo-install")]
     CargoInstall {
         packages: Vec<CargoPackage>,
+        /// Run only on hosts with at least one of these roles
         #[serde(default)]
         only_roles: Option<Vec<String>>,
+        /// Skip on hosts with any of these roles
         #[serde(default)]
         skip_roles: Option<Vec<String>>,
+        /// Condition on system facts, e.g. `os == 'linux'`
+        #[serde(default)]
+        when: Option<String>,
     },
+    /// Install the `[mise]` tools with `mise install`
     #[serde(rename = "mise")]
     Mise {
+        /// Run only on hosts with at least one of these roles
         #[serde(default)]
         only_roles: Option<Vec<String>>,
+        /// Skip on hosts with any of these roles
         #[serde(default)]
         skip_roles: Option<Vec<String>>,
+        /// Condition on system facts, e.g. `os == 'linux'`
+        #[serde(default)]
+        when: Option<String>,
     },
+    /// Install global packages with `pnpm add -g`
     #[serde(rename = "pnpm-global")]
     PnpmGlobal {
         packages: Vec<String>,
+        /// Run only on hosts with at least one of these roles
         #[serde(default)]
         only_roles: Option<Vec<String>>,
+        /// Skip on hosts with any of these roles
         #[serde(default)]
         skip_roles: Option<Vec<String>>,
+        /// Condition on system facts, e.g. `os == 'linux'`
+        #[serde(default)]
+        when: Option<String>,
     },
+    /// Install a Python version with uv and link its interpreter
     #[serde(rename = "uv-python")]
     UvPython {
         version: String,
+        /// Link name to path, e.g. `python3 = "~/.local/bin/python3"`
         symlinks: HashMap<String, String>,
+        /// Run only on hosts with at least one of these roles
         #[serde(default)]
         only_roles: Option<Vec<String>>,
+        /// Skip on hosts with any of these roles
         #[serde(default)]
         skip_roles: Option<Vec<String>>,
+        /// Condition on system facts, e.g. `os == 'linux'`
+        #[serde(default)]
+        when: Option<String>,
     },
+    /// Run a shell command
     #[serde(rename = "command")]
     Command {
         name: String,
         command: String,
+        /// Whether a failure stops the apply
         on_failure: FailureMode,
+        /// Run only on hosts with at least one of these roles
         #[serde(default)]
         only_roles: Option<Vec<String>>,
+        /// Skip on hosts with any of these roles
         #[serde(default)]
         skip_roles: Option<Vec<String>>,
+        /// Condition on system facts, e.g. `os == 'linux'`
+        #[serde(default)]
+        when: Option<String>,
     },
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, JsonSchema)]
 pub struct CargoPackage {
     pub name: String,
+    /// Repository URL
     pub git: String,
+    /// Binary to install, if the crate has several
     #[serde(default)]
     pub bin: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
-#[derive(Default)]
+#[derive(Default, JsonSchema)]
 pub enum FailureMode {
+    /// Report the failure and keep going
     #[default]
     Continue,
+    /// Stop the apply
     Fail,
 }
 
@@ -104,6 +152,17 @@ impl Hook {
         }
     }
 
+    pub fn when(&self) -> &Option<String> {
+        match self {
+            Hook::Rustup { when, .. } => when,
+            Hook::CargoInstall { when, .. } => when,
+            Hook::Mise { when, .. } => when,
+            Hook::PnpmGlobal { when, .. } => when,
+            Hook::UvPython { when, .. } => when,
+            Hook::Command { when, .. } => when,
+        }
+    }
+
     pub fn skip_roles(&self) -> &Option<Vec<String>> {
         match self {
             Hook::Rustup { skip_roles, .. } => skip_roles,
@@ -116,31 +175,57 @@ impl Hook {
     }
 }
 
-/// Execute all hooks in sequence, filtering by roles
-pub fn execute_hooks(hooks: &[Hook], host_roles: &[String], verbose: bool) -> anyhow::Result<()> {
-    if hooks.is_empty() {
-        return Ok(());
-    }
+/// Result of running one hook.
+#[derive(Debug)]
+pub struct HookOutcome {
+    pub name: String,
+    /// Set if the hook failed
+    pub error: Option<String>,
+    /// The failure stops the remaining hooks (`on_failure = "fail"`)
+    pub fatal: bool,
+}
+
+/// Execute all hooks in sequence, filtering by roles and `when` conditions.
+///
+/// Returns one outcome per hook that ran. Execution stops after the first
+/// fatal failure, which is the last outcome in that case.
+pub fn execute_hooks(
+    hooks: &[Hook],
+    host_roles: &[String],
+    facts: &Facts,
+    verbose: bool,
+) -> Vec<HookOutcome> {
+    let mut outcomes = Vec::new();
 
     for hook in hooks {
-        if !crate::config::should_apply_for_roles(hook.only_roles(), hook.skip_roles(), host_roles)
-        {
+        if let Some(reason) = crate::config::skip_reason(
+            hook.only_roles(),
+            hook.skip_roles(),
+            hook.when(),
+            host_roles,
+            facts,
+        ) {
             if verbose {
-                println!("  {} {} (role mismatch)", "↷".bright_black(), hook.name());
+                progress!("  {} {} ({})", "↷".bright_black(), hook.name(), reason);
             }
             continue;
         }
 
-        execute_hook(hook, verbose)?;
+        let outcome = execute_hook(hook, verbose);
+        let fatal = outcome.fatal;
+        outcomes.push(outcome);
+        if fatal {
+            break;
+        }
     }
 
-    Ok(())
+    outcomes
 }
 
 /// Execute a single hook
-fn execute_hook(hook: &Hook, verbose: bool) -> anyhow::Result<()> {
-    println!();
-    println!("{} {}", "→ Hook:".bright_cyan(), hook.name());
+fn execute_hook(hook: &Hook, verbose: bool) -> HookOutcome {
+    progress!();
+    progress!("{} {}", "→ Hook:".bright_cyan(), hook.name());
 
     let result = match hook {
         Hook::Rustup {
@@ -165,20 +250,26 @@ fn execute_hook(hook: &Hook, verbose: bool) -> anyhow::Result<()> {
 
     match result {
         Ok(()) => {
-            println!("  {} Hook completed", "✓".green());
-            Ok(())
+            progress!("  {} Hook completed", "✓".green());
+            HookOutcome {
+                name: hook.name(),
+                error: None,
+                fatal: false,
+            }
         }
         Err(e) => {
-            let should_fail = match hook {
+            let fatal = match hook {
                 Hook::Command { on_failure, .. } => matches!(on_failure, FailureMode::Fail),
                 _ => false,
             };
 
-            if should_fail {
-                Err(e)
-            } else {
+            if !fatal {
                 eprintln!("  {} Hook failed (continuing): {}", "⚠".yellow(), e);
-                Ok(())
+            }
+            HookOutcome {
+                name: hook.name(),
+                error: Some(e.to_string()),
+                fatal,
             }
         }
     }
@@ -193,7 +284,7 @@ fn execute_rustup_hook(
 ) -> anyhow::Result<()> {
     if !command_exists("rustup") {
         let spinner = if verbose {
-            println!("  {} Installing rustup...", "→".bright_black());
+            progress!("  {} Installing rustup...", "→".bright_black());
             None
         } else {
             Some(Spinner::new("Installing rustup..."))
@@ -202,7 +293,7 @@ fn execute_rustup_hook(
         let status = Command::new("sh")
             .arg("-c")
             .arg("curl --proto '=https' --tlsv1.2 -sSf https://sh.rustup.rs | sh -s -- -y --default-toolchain stable --no-modify-path")
-            .stdout(if verbose { Stdio::inherit() } else { Stdio::null() })
+            .stdout(if verbose { child_stdout() } else { Stdio::null() })
             .stderr(if verbose { Stdio::inherit() } else { Stdio::null() })
             .status()?;
 
@@ -220,7 +311,7 @@ fn execute_rustup_hook(
 
     for toolchain in toolchains {
         if verbose {
-            println!(
+            progress!(
                 "  {} Installing {} toolchain...",
                 "→".bright_black(),
                 toolchain
@@ -239,7 +330,7 @@ fn execute_rustup_hook(
         let status = Command::new("rustup")
             .args(["toolchain", "install", toolchain])
             .stdout(if verbose {
-                Stdio::inherit()
+                child_stdout()
             } else {
                 Stdio::null()
             })
@@ -265,7 +356,7 @@ fn execute_rustup_hook(
     for toolchain in toolchains {
         for component in components {
             if verbose {
-                println!(
+                progress!(
                     "  {} Adding {} to {}...",
                     "→".bright_black(),
                     component,
@@ -275,7 +366,7 @@ fn execute_rustup_hook(
             let status = Command::new("rustup")
                 .args(["component", "add", "--toolchain", toolchain, component])
                 .stdout(if verbose {
-                    Stdio::inherit()
+                    child_stdout()
                 } else {
                     Stdio::null()
                 })
@@ -300,7 +391,7 @@ fn execute_rustup_hook(
     for toolchain in toolchains {
         for target in targets {
             if verbose {
-                println!(
+                progress!(
                     "  {} Adding target {} to {}...",
                     "→".bright_black(),
                     target,
@@ -310,7 +401,7 @@ fn execute_rustup_hook(
             let status = Command::new("rustup")
                 .args(["target", "add", "--toolchain", toolchain, target])
                 .stdout(if verbose {
-                    Stdio::inherit()
+                    child_stdout()
                 } else {
                     Stdio::null()
                 })
@@ -334,7 +425,7 @@ fn execute_rustup_hook(
 
     if let Some(default_toolchain) = default {
         if verbose {
-            println!(
+            progress!(
                 "  {} Setting default toolchain to {}...",
                 "→".bright_black(),
                 default_toolchain
@@ -343,7 +434,7 @@ fn execute_rustup_hook(
         let status = Command::new("rustup")
             .args(["default", default_toolchain])
             .stdout(if verbose {
-                Stdio::inherit()
+                child_stdout()
             } else {
                 Stdio::null()
             })
@@ -372,7 +463,7 @@ fn execute_cargo_install_hook(packages: &[CargoPackage], verbose: bool) -> anyho
 
     for package in packages {
         if verbose {
-            println!("  {} Installing {}...", "→".bright_black(), package.name);
+            progress!("  {} Installing {}...", "→".bright_black(), package.name);
         }
 
         let spinner = if verbose {
@@ -393,7 +484,7 @@ fn execute_cargo_install_hook(packages: &[CargoPackage], verbose: bool) -> anyho
         let status = Command::new("cargo")
             .args(&args)
             .stdout(if verbose {
-                Stdio::inherit()
+                child_stdout()
             } else {
                 Stdio::null()
             })
@@ -427,7 +518,7 @@ fn execute_mise_hook(verbose: bool) -> anyhow::Result<()> {
     }
 
     if verbose {
-        println!("  {} Running mise install...", "→".bright_black());
+        progress!("  {} Running mise install...", "→".bright_black());
     }
 
     let spinner = if verbose {
@@ -439,7 +530,7 @@ fn execute_mise_hook(verbose: bool) -> anyhow::Result<()> {
     let status = Command::new("mise")
         .arg("install")
         .stdout(if verbose {
-            Stdio::inherit()
+            child_stdout()
         } else {
             Stdio::null()
         })
@@ -471,7 +562,7 @@ fn execute_pnpm_global_hook(packages: &[String], verbose: bool) -> anyhow::Resul
 
     for package in packages {
         if verbose {
-            println!("  {} Installing {}...", "→".bright_black(), package);
+            progress!("  {} Installing {}...", "→".bright_black(), package);
         }
 
         let spinner = if verbose {
@@ -486,7 +577,7 @@ fn execute_pnpm_global_hook(packages: &[String], verbose: bool) -> anyhow::Resul
         let status = Command::new("pnpm")
             .args(["add", "-g", package])
             .stdout(if verbose {
-                Stdio::inherit()
+                child_stdout()
             } else {
                 Stdio::null()
             })
@@ -524,7 +615,7 @@ fn execute_uv_python_hook(
     }
 
     if verbose {
-        println!("  {} Installing Python {}...", "→".bright_black(), version);
+        progress!("  {} Installing Python {}...", "→".bright_black(), version);
     }
 
     let install_spinner = if verbose {
@@ -539,7 +630,7 @@ fn execute_uv_python_hook(
     let status = Command::new("uv")
         .args(["python", "install", version])
         .stdout(if verbose {
-            Stdio::inherit()
+            child_stdout()
         } else {
             Stdio::null()
         })
@@ -575,7 +666,7 @@ fn execute_uv_python_hook(
         let expanded_target = crate::expand::expand_tilde(target)?;
 
         if verbose {
-            println!(
+            progress!(
                 "  {} Creating symlink {} → {}",
                 "→".bright_black(),
                 name,
@@ -604,7 +695,7 @@ fn execute_command_hook(
     verbose: bool,
 ) -> anyhow::Result<()> {
     if verbose {
-        println!("  {} Running: {}", "→".bright_black(), command);
+        progress!("  {} Running: {}", "→".bright_black(), command);
     }
 
     let spinner = if verbose {
@@ -617,7 +708,7 @@ fn execute_command_hook(
         .arg("-c")
         .arg(command)
         .stdout(if verbose {
-            Stdio::inherit()
+            child_stdout()
         } else {
             Stdio::null()
         })
@@ -643,7 +734,7 @@ fn execute_command_hook(
 }
 
 /// Check if a command exists in PATH
-fn command_exists(command: &str) -> bool {
+pub fn command_exists(command: &str) -> bool {
     Command::new("which")
         .arg(command)
         .stdout(Stdio::null())
@@ -666,6 +757,7 @@ mod tests {
             default: None,
             only_roles: None,
             skip_roles: None,
+            when: None,
         };
         assert_eq!(hook.name(), "rustup");
 
@@ -675,6 +767,7 @@ mod tests {
             on_failure: FailureMode::Continue,
             only_roles: None,
             skip_roles: None,
+            when: None,
         };
         assert_eq!(hook.name(), "test");
     }
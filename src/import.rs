@@ -0,0 +1,1019 @@
+//! Translating another dotfile manager's repo into a `mimic.toml`
+//! (`mimic import`).
+//!
+//! Each importer reads its tool's layout and describes the same files as
+//! `[[dotfiles]]` entries pointing at the files where they already are, so
+//! the repo itself is left untouched. Anything mimic has no equivalent for
+//! (scripts, chezmoi externals, yadm's encrypted archive, ...) is skipped and
+//! reported as a [`Note`] rather than guessed at.
+
+use crate::adopt::config_target;
+use crate::config::{DEFAULT_HOST, DeployMode, Dotfile, LinkStrategy, Permissions};
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use toml_edit::{Array, ArrayOfTables, DocumentMut, InlineTable, Item, Table, value};
+
+/// Dotfile managers `mimic import` understands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImportFrom {
+    /// GNU Stow directory: one directory per package, mirroring the home directory
+    Stow,
+    /// chezmoi source directory, with `dot_`, `private_` and `.tmpl` names
+    Chezmoi,
+    /// yadm repo: a copy of the home directory with `##` alternates
+    Yadm,
+    /// Plain directory of dotfiles, named with or without their leading dot
+    Dir,
+}
+
+impl ImportFrom {
+    pub fn name(self) -> &'static str {
+        match self {
+            ImportFrom::Stow => "stow",
+            ImportFrom::Chezmoi => "chezmoi",
+            ImportFrom::Yadm => "yadm",
+            ImportFrom::Dir => "dir",
+        }
+    }
+}
+
+/// Something the importer skipped or only partly translated
+#[derive(Debug, Clone, Serialize)]
+pub struct Note {
+    /// Relative to the imported directory
+    pub path: String,
+    pub message: String,
+}
+
+/// Dotfiles that only apply on one machine (yadm `##hostname.` alternates)
+#[derive(Debug, Default)]
+pub struct ImportedHost {
+    pub dotfiles: Vec<Dotfile>,
+    /// Targets the host deploys in place of the base config's
+    pub exclude_dotfiles: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Import {
+    pub dotfiles: Vec<Dotfile>,
+    /// By hostname, so mimic selects them on that machine
+    pub hosts: BTreeMap<String, ImportedHost>,
+    pub notes: Vec<Note>,
+}
+
+impl Import {
+    fn note(&mut self, path: &Path, message: impl Into<String>) {
+        self.notes.push(Note {
+            path: path.display().to_string(),
+            message: message.into(),
+        });
+    }
+
+    /// Make every source absolute, for a config written outside the
+    /// imported directory.
+    pub fn relocate(&mut self, root: &Path) {
+        let hosts = self.hosts.values_mut().flat_map(|h| h.dotfiles.iter_mut());
+        for dotfile in self.dotfiles.iter_mut().chain(hosts) {
+            dotfile.source = root.join(&dotfile.source).display().to_string();
+        }
+    }
+
+    pub fn dotfile_count(&self) -> usize {
+        self.dotfiles.len()
+            + self
+                .hosts
+                .values()
+                .map(|host| host.dotfiles.len())
+                .sum::<usize>()
+    }
+}
+
+/// Read `root` as laid out for `from`. Sources in the result are relative
+/// to `root`.
+pub fn import(from: ImportFrom, root: &Path) -> anyhow::Result<Import> {
+    if !root.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Not a directory: {}\n\nTo fix:\n  - Pass the {} directory to import",
+            root.display(),
+            match from {
+                ImportFrom::Stow => "stow",
+                ImportFrom::Chezmoi => "chezmoi source",
+                ImportFrom::Yadm => "yadm repo",
+                ImportFrom::Dir => "dotfiles",
+            }
+        ));
+    }
+
+    let mut import = Import::default();
+    match from {
+        ImportFrom::Stow => stow(root, &mut import)?,
+        ImportFrom::Chezmoi => chezmoi(root, &mut import)?,
+        ImportFrom::Yadm => yadm(root, &mut import)?,
+        ImportFrom::Dir => dir(root, &mut import)?,
+    }
+    Ok(import)
+}
+
+fn dotfile(source: &Path, target: String) -> Dotfile {
+    Dotfile {
+        source: source.display().to_string(),
+        target,
+        template: false,
+        encrypted: false,
+        mode: DeployMode::default(),
+        link: LinkStrategy::default(),
+        permissions: None,
+        dir_permissions: None,
+        only_roles: None,
+        skip_roles: None,
+        when: None,
+        enabled: true,
+    }
+}
+
+/// A directory linked file by file, which is how stow, chezmoi and yadm all
+/// treat directories.
+fn tree(source: &Path, target: String) -> Dotfile {
+    Dotfile {
+        link: LinkStrategy::Tree,
+        ..dotfile(source, target)
+    }
+}
+
+fn join_target(dir: &str, name: &str) -> String {
+    format!("{}/{}", dir.trim_end_matches('/'), name)
+}
+
+/// Entries of a directory as (name, is_dir), sorted by name.
+fn entries(dir: &Path) -> anyhow::Result<Vec<(String, bool)>> {
+    let mut entries = Vec::new();
+    for entry in
+        fs::read_dir(dir).with_context(|| format!("Failed to read directory: {}", dir.display()))?
+    {
+        let entry = entry?;
+        let is_dir = entry.file_type()?.is_dir();
+        entries.push((entry.file_name().to_string_lossy().into_owned(), is_dir));
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+/// Whether every name under `dir` passes `plain`, so the directory can be
+/// one tree-linked entry instead of one entry per file.
+fn subtree_plain(dir: &Path, plain: &dyn Fn(&str) -> bool) -> anyhow::Result<bool> {
+    for (name, is_dir) in entries(dir)? {
+        if !plain(&name) || (is_dir && !subtree_plain(&dir.join(&name), plain)?) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn is_readme(name: &str) -> bool {
+    ["README", "LICENSE", "COPYING"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
+// --- GNU Stow ---
+
+fn stow(root: &Path, import: &mut Import) -> anyhow::Result<()> {
+    let mut dotfiles = false;
+    // Stow's default target is the parent of the stow directory
+    let mut target = root.parent().map(config_target);
+
+    if let Ok(stowrc) = fs::read_to_string(root.join(".stowrc")) {
+        let mut words = stowrc.split_whitespace();
+        while let Some(word) = words.next() {
+            match word {
+                "--dotfiles" => dotfiles = true,
+                "-t" | "--target" => target = words.next().map(String::from),
+                _ if word.starts_with("--target=") => {
+                    target = Some(word["--target=".len()..].to_string())
+                }
+                _ => import.note(
+                    Path::new(".stowrc"),
+                    format!("Option '{}' has no mimic equivalent and was ignored", word),
+                ),
+            }
+        }
+    }
+    let base = target.unwrap_or_else(|| "~".to_string());
+
+    for (name, is_dir) in entries(root)? {
+        if name.starts_with('.') || is_readme(&name) {
+            continue;
+        }
+        if !is_dir {
+            import.note(Path::new(&name), "Not inside a stow package; skipped");
+            continue;
+        }
+        let package = Path::new(&name);
+        if root.join(package).join(".stow-local-ignore").exists() {
+            import.note(
+                &package.join(".stow-local-ignore"),
+                "Ignore patterns were not applied; remove the entries for ignored files by hand",
+            );
+        }
+        for (child, child_is_dir) in entries(&root.join(package))? {
+            if stow_ignored(&child) || is_readme(&child) {
+                continue;
+            }
+            stow_entry(
+                root,
+                &package.join(&child),
+                &base,
+                child_is_dir,
+                dotfiles,
+                import,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Stow's built-in ignore list
+fn stow_ignored(name: &str) -> bool {
+    matches!(
+        name,
+        ".git"
+            | ".gitignore"
+            | ".gitmodules"
+            | ".stow-local-ignore"
+            | "CVS"
+            | ".cvsignore"
+            | ".svn"
+            | "_darcs"
+            | ".hg"
+            | "RCS"
+    ) || name.ends_with('~')
+        || name.starts_with(".#")
+        || (name.starts_with('#') && name.ends_with('#'))
+}
+
+fn stow_entry(
+    root: &Path,
+    source: &Path,
+    target_dir: &str,
+    is_dir: bool,
+    dotfiles: bool,
+    import: &mut Import,
+) -> anyhow::Result<()> {
+    let name = source
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let translated = match name.strip_prefix("dot-") {
+        Some(rest) if dotfiles => format!(".{}", rest),
+        Some(_) => {
+            import.note(
+                source,
+                "Linked as 'dot-...'; if you run stow with --dotfiles, add it to .stowrc and import again",
+            );
+            name.clone()
+        }
+        None => name.clone(),
+    };
+    let target = join_target(target_dir, &translated);
+
+    if !is_dir {
+        import.dotfiles.push(dotfile(source, target));
+        return Ok(());
+    }
+    let full = root.join(source);
+    if !dotfiles || subtree_plain(&full, &|name| !name.starts_with("dot-"))? {
+        import.dotfiles.push(tree(source, target));
+        return Ok(());
+    }
+    for (child, child_is_dir) in entries(&full)? {
+        if !stow_ignored(&child) {
+            stow_entry(
+                root,
+                &source.join(&child),
+                &target,
+                child_is_dir,
+                dotfiles,
+                import,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// --- chezmoi ---
+
+/// A chezmoi source name split into its target name and attributes.
+#[derive(Debug, Default)]
+struct ChezmoiName {
+    name: String,
+    private: bool,
+    readonly: bool,
+    executable: bool,
+    encrypted: bool,
+    template: bool,
+    exact: bool,
+    empty: bool,
+    /// Why the entry can't be imported
+    unsupported: Option<&'static str>,
+}
+
+impl ChezmoiName {
+    fn parse(raw: &str, is_dir: bool) -> Self {
+        let mut parsed = ChezmoiName::default();
+        let mut rest = raw;
+
+        // chezmoi's entry types come before any attribute prefix
+        let remove = (
+            "remove_",
+            "chezmoi remove entry; mimic only removes files it deployed",
+        );
+        let unsupported: &[(&str, &'static str)] = if is_dir {
+            &[
+                (
+                    "external_",
+                    "chezmoi external directory; clone it with a hook or an [[extends]] repo",
+                ),
+                remove,
+            ]
+        } else {
+            &[
+                ("run_", "chezmoi script; run it from a command hook instead"),
+                (
+                    "symlink_",
+                    "chezmoi symlink; its content is the link target, which mimic doesn't create",
+                ),
+                (
+                    "modify_",
+                    "chezmoi modify script; a [[merges]] or [[blocks]] entry may do the same job",
+                ),
+                (
+                    "create_",
+                    "chezmoi create-only file; mimic keeps files in sync, so it was left out",
+                ),
+                remove,
+            ]
+        };
+        if let Some((_, reason)) = unsupported
+            .iter()
+            .find(|(prefix, _)| raw.starts_with(prefix))
+        {
+            parsed.unsupported = Some(reason);
+            return parsed;
+        }
+
+        loop {
+            let flag = match rest {
+                _ if rest.starts_with("literal_") => {
+                    rest = &rest["literal_".len()..];
+                    break;
+                }
+                _ if rest.starts_with("dot_") => {
+                    parsed.name.push('.');
+                    rest = &rest["dot_".len()..];
+                    break;
+                }
+                _ if rest.starts_with("private_") => &mut parsed.private,
+                _ if rest.starts_with("readonly_") => &mut parsed.readonly,
+                _ if rest.starts_with("exact_") && is_dir => &mut parsed.exact,
+                _ if rest.starts_with("encrypted_") && !is_dir => &mut parsed.encrypted,
+                _ if rest.starts_with("executable_") && !is_dir => &mut parsed.executable,
+                // Files are always kept when empty
+                _ if rest.starts_with("empty_") && !is_dir => &mut parsed.empty,
+                _ => break,
+            };
+            *flag = true;
+            rest = &rest[rest.find('_').map_or(0, |i| i + 1)..];
+        }
+
+        if !is_dir {
+            if let Some(stripped) = rest.strip_suffix(".literal") {
+                rest = stripped;
+            } else {
+                if parsed.encrypted {
+                    rest = rest.strip_suffix(".age").unwrap_or(rest);
+                }
+                if let Some(stripped) = rest.strip_suffix(".tmpl") {
+                    parsed.template = true;
+                    rest = stripped;
+                }
+            }
+        }
+        parsed.name.push_str(rest);
+        parsed
+    }
+
+    /// Permission bits implied by `private_`, `readonly_` and `executable_`.
+    fn permissions(&self, is_dir: bool) -> Option<Permissions> {
+        if !self.private && !self.readonly && !self.executable {
+            return None;
+        }
+        let mut bits = if is_dir || self.executable {
+            0o755
+        } else {
+            0o644
+        };
+        if self.private {
+            bits &= 0o700;
+        }
+        if self.readonly {
+            bits &= !0o222;
+        }
+        Permissions::new(bits).ok()
+    }
+}
+
+/// Plain `.chezmoiignore` patterns, matched against targets relative to
+/// the home directory.
+struct ChezmoiIgnore {
+    patterns: Vec<glob::Pattern>,
+}
+
+impl ChezmoiIgnore {
+    fn read(source_dir: &Path, root: &Path, import: &mut Import) -> Self {
+        let mut patterns = Vec::new();
+        let Ok(content) = fs::read_to_string(source_dir.join(".chezmoiignore")) else {
+            return Self { patterns };
+        };
+        let path = source_dir
+            .join(".chezmoiignore")
+            .strip_prefix(root)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+
+        if content.contains("{{") {
+            import.note(
+                &path,
+                "Uses template conditions, so none of its patterns were applied; add `when` conditions to the entries it covers",
+            );
+            return Self { patterns };
+        }
+        for line in content.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if line.starts_with('!') {
+                import.note(&path, format!("Exclusion '{}' was not applied", line));
+                continue;
+            }
+            match glob::Pattern::new(line) {
+                Ok(pattern) => patterns.push(pattern),
+                Err(_) => import.note(&path, format!("Pattern '{}' is not a valid glob", line)),
+            }
+        }
+        Self { patterns }
+    }
+
+    fn ignores(&self, target: &str) -> bool {
+        let relative = target.strip_prefix("~/").unwrap_or(target);
+        let options = glob::MatchOptions {
+            require_literal_separator: true,
+            ..Default::default()
+        };
+        self.patterns
+            .iter()
+            .any(|pattern| pattern.matches_with(relative, options))
+    }
+}
+
+fn chezmoi(root: &Path, import: &mut Import) -> anyhow::Result<()> {
+    let source_dir = match fs::read_to_string(root.join(".chezmoiroot")) {
+        Ok(subdir) => root.join(subdir.trim()),
+        Err(_) => root.to_path_buf(),
+    };
+    let ignore = ChezmoiIgnore::read(&source_dir, root, import);
+    let relative = source_dir
+        .strip_prefix(root)
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+    chezmoi_dir(root, &relative, "~", &ignore, import)
+}
+
+/// Why a `.chezmoi*` file isn't imported, or `None` if it needs no mention.
+fn chezmoi_special(name: &str) -> Option<&'static str> {
+    match name {
+        ".chezmoiignore" | ".chezmoiroot" | ".chezmoiversion" => None,
+        ".chezmoiscripts" => Some("chezmoi scripts; run them from command hooks instead"),
+        ".chezmoitemplates" => Some("Shared chezmoi templates; inline them where they are used"),
+        ".chezmoiremove" => Some("chezmoi remove list; mimic only removes files it deployed"),
+        _ if name.starts_with(".chezmoiexternal") => Some(
+            "chezmoi externals (archives and git repos) are not imported; fetch them with a hook",
+        ),
+        _ if name.starts_with(".chezmoidata") => {
+            Some("chezmoi template data; move the values into [variables]")
+        }
+        _ if name.starts_with(".chezmoi.") => Some(
+            "chezmoi config template; ask for the values with variable sources such as name.from = { prompt = \"...\" }",
+        ),
+        _ => Some("chezmoi setting; not imported"),
+    }
+}
+
+fn chezmoi_dir(
+    root: &Path,
+    dir: &Path,
+    target_dir: &str,
+    ignore: &ChezmoiIgnore,
+    import: &mut Import,
+) -> anyhow::Result<()> {
+    for (raw, is_dir) in entries(&root.join(dir))? {
+        let source = dir.join(&raw);
+        if raw.starts_with(".chezmoi") {
+            if let Some(reason) = chezmoi_special(&raw) {
+                import.note(&source, reason);
+            }
+            continue;
+        }
+        // chezmoi ignores every other name starting with a dot
+        if raw.starts_with('.') {
+            continue;
+        }
+
+        let parsed = ChezmoiName::parse(&raw, is_dir);
+        if let Some(reason) = parsed.unsupported {
+            import.note(&source, reason);
+            continue;
+        }
+        let target = join_target(target_dir, &parsed.name);
+        if ignore.ignores(&target) {
+            continue;
+        }
+
+        if is_dir {
+            if parsed.exact {
+                import.note(
+                    &source,
+                    "exact_ directory; mimic won't remove files in it that the repo doesn't have",
+                );
+            }
+            let plain =
+                |name: &str| !name.starts_with('.') && ChezmoiName::parse(name, false).name == name;
+            // Split the directory up if anything in it needs renaming or is ignored
+            let all_plain = subtree_plain(&root.join(&source), &plain)?
+                && !ignore.patterns.iter().any(|p| {
+                    p.as_str()
+                        .starts_with(target.strip_prefix("~/").unwrap_or(&target))
+                });
+            if all_plain {
+                import.dotfiles.push(Dotfile {
+                    dir_permissions: parsed.permissions(true),
+                    ..tree(&source, target)
+                });
+            } else {
+                chezmoi_dir(root, &source, &target, ignore, import)?;
+            }
+            continue;
+        }
+
+        if parsed.encrypted && raw.ends_with(".asc") {
+            import.note(
+                &source,
+                "GPG-encrypted; mimic decrypts age files only, so re-encrypt it with 'mimic encrypt'",
+            );
+            continue;
+        }
+        if parsed.template {
+            import.note(
+                &source,
+                "chezmoi template; rewrite its Go template syntax ({{ .chezmoi.os }}, ...) as Handlebars",
+            );
+        }
+        import.dotfiles.push(Dotfile {
+            template: parsed.template,
+            encrypted: parsed.encrypted,
+            permissions: parsed.permissions(false),
+            ..dotfile(&source, target)
+        });
+    }
+    Ok(())
+}
+
+// --- yadm ---
+
+/// A yadm alternate: the source and the conditions after `##`.
+struct Alternate {
+    source: PathBuf,
+    is_dir: bool,
+    default: bool,
+    template: bool,
+    /// `when` conditions, joined with `&&`
+    when: Vec<String>,
+    class: Option<String>,
+    hostname: Option<String>,
+    os: Option<&'static str>,
+    unsupported: Vec<String>,
+}
+
+impl Alternate {
+    fn parse(source: PathBuf, is_dir: bool, conditions: &str) -> Self {
+        let mut alternate = Alternate {
+            source,
+            is_dir,
+            default: false,
+            template: false,
+            when: Vec::new(),
+            class: None,
+            hostname: None,
+            os: None,
+            unsupported: Vec::new(),
+        };
+        for condition in conditions.split(',') {
+            let (attribute, value) = condition.split_once('.').unwrap_or((condition, ""));
+            match attribute {
+                "default" => alternate.default = true,
+                "t" | "template" => alternate.template = true,
+                "e" | "extension" => {}
+                "c" | "class" => alternate.class = Some(value.to_string()),
+                "h" | "hostname" => alternate.hostname = Some(value.to_string()),
+                "o" | "os" => match value {
+                    "Darwin" => alternate.os = Some("macos"),
+                    "Linux" => alternate.os = Some("linux"),
+                    _ => alternate.unsupported.push(condition.to_string()),
+                },
+                "a" | "arch" => {
+                    let arch = match value {
+                        "arm64" => "aarch64",
+                        "amd64" => "x86_64",
+                        other => other,
+                    };
+                    alternate.when.push(format!("arch == '{}'", arch));
+                }
+                "u" | "user" => alternate.when.push(format!("env.USER == '{}'", value)),
+                _ => alternate.unsupported.push(condition.to_string()),
+            }
+        }
+        alternate
+    }
+
+    fn when(&self) -> Option<String> {
+        let os = self.os.map(|os| format!("os == '{}'", os));
+        let conditions: Vec<String> = os.into_iter().chain(self.when.clone()).collect();
+        (!conditions.is_empty()).then(|| conditions.join(" && "))
+    }
+}
+
+fn yadm(root: &Path, import: &mut Import) -> anyhow::Result<()> {
+    let mut alternates: BTreeMap<String, Vec<Alternate>> = BTreeMap::new();
+    yadm_dir(root, Path::new(""), "~", &mut alternates, import)?;
+    for (target, group) in alternates {
+        yadm_alternates(target, group, import);
+    }
+    Ok(())
+}
+
+fn yadm_dir(
+    root: &Path,
+    dir: &Path,
+    target_dir: &str,
+    alternates: &mut BTreeMap<String, Vec<Alternate>>,
+    import: &mut Import,
+) -> anyhow::Result<()> {
+    for (name, is_dir) in entries(&root.join(dir))? {
+        let source = dir.join(&name);
+        if dir.as_os_str().is_empty() && (name == ".git" || is_readme(&name)) {
+            continue;
+        }
+        if source == Path::new(".config/yadm") {
+            yadm_settings(root, &source, alternates, import)?;
+            continue;
+        }
+        if source == Path::new(".local/share/yadm") {
+            import.note(
+                &source,
+                "yadm encrypted archive; add the files as encrypted = true dotfiles instead",
+            );
+            continue;
+        }
+
+        if let Some((base, conditions)) = name.split_once("##") {
+            alternates
+                .entry(join_target(target_dir, base))
+                .or_default()
+                .push(Alternate::parse(source, is_dir, conditions));
+            continue;
+        }
+
+        let target = join_target(target_dir, &name);
+        if !is_dir {
+            import.dotfiles.push(dotfile(&source, target));
+        } else if subtree_plain(&root.join(&source), &|name| !name.contains("##"))?
+            && source != Path::new(".config")
+            && source != Path::new(".local")
+            && source != Path::new(".local/share")
+        {
+            import.dotfiles.push(tree(&source, target));
+        } else {
+            yadm_dir(root, &source, &target, alternates, import)?;
+        }
+    }
+    Ok(())
+}
+
+/// `~/.config/yadm`: alternates in `alt/`, and yadm's own files.
+fn yadm_settings(
+    root: &Path,
+    dir: &Path,
+    alternates: &mut BTreeMap<String, Vec<Alternate>>,
+    import: &mut Import,
+) -> anyhow::Result<()> {
+    for (name, _) in entries(&root.join(dir))? {
+        let source = dir.join(&name);
+        match name.as_str() {
+            "alt" => yadm_dir(root, &source, "~", alternates, import)?,
+            "bootstrap" => import.note(&source, "yadm bootstrap; run it from a command hook"),
+            "encrypt" => import.note(
+                &source,
+                "yadm encrypt patterns; add those files as encrypted = true dotfiles",
+            ),
+            "hooks" => import.note(&source, "yadm hooks; run them from command hooks"),
+            _ => import.note(&source, "yadm setting; not imported"),
+        }
+    }
+    Ok(())
+}
+
+/// Turn the alternates for one target into dotfiles: conditions become
+/// `when`, classes become roles, hostnames become `[hosts.<name>]`
+/// sections, and the `default` applies when none of the others do.
+fn yadm_alternates(target: String, group: Vec<Alternate>, import: &mut Import) {
+    let mut base: Vec<Alternate> = Vec::new();
+    let mut default: Option<Alternate> = None;
+
+    for alternate in group {
+        if !alternate.unsupported.is_empty() {
+            import.note(
+                &alternate.source,
+                format!(
+                    "yadm condition '{}' has no mimic equivalent; skipped",
+                    alternate.unsupported.join(",")
+                ),
+            );
+            continue;
+        }
+        if alternate.template {
+            import.note(&alternate.source, "yadm template; rewrite it in Handlebars");
+        }
+        if alternate.default
+            && alternate.when().is_none()
+            && alternate.class.is_none()
+            && alternate.hostname.is_none()
+        {
+            default = Some(alternate);
+        } else if alternate.hostname.is_some() {
+            let entry = alternate_dotfile(&alternate, target.clone());
+            let host = import
+                .hosts
+                .entry(alternate.hostname.clone().unwrap_or_default())
+                .or_default();
+            host.dotfiles.push(entry);
+        } else {
+            base.push(alternate);
+        }
+    }
+
+    let mut skip_roles = Vec::new();
+    let mut negated = Vec::new();
+    for alternate in &base {
+        match (alternate.class.as_ref(), alternate.when()) {
+            (Some(class), None) => skip_roles.push(class.clone()),
+            (None, Some(when)) => negated.push(format!("!({})", when)),
+            _ => import.note(
+                &alternate.source,
+                "Combines a class with other conditions, so the default alternate may also apply; review both",
+            ),
+        }
+    }
+    // yadm picks the most specific alternate; mimic deploys every match, which
+    // only agrees with yadm when the alternates are for different systems
+    let exclusive = base
+        .iter()
+        .map(|alternate| alternate.os)
+        .collect::<Option<BTreeSet<_>>>()
+        .is_some_and(|systems| systems.len() == base.len());
+    if base.len() > 1 && !exclusive {
+        import.note(
+            Path::new(&target),
+            "Several alternates may match on one machine; mimic deploys each one that does",
+        );
+    }
+
+    let mut deployed_in_base = !base.is_empty();
+    for alternate in &base {
+        import
+            .dotfiles
+            .push(alternate_dotfile(alternate, target.clone()));
+    }
+    if let Some(alternate) = default {
+        let mut entry = alternate_dotfile(&alternate, target.clone());
+        entry.skip_roles = (!skip_roles.is_empty()).then_some(skip_roles);
+        entry.when = (!negated.is_empty()).then(|| negated.join(" && "));
+        import.dotfiles.push(entry);
+        deployed_in_base = true;
+    }
+
+    if deployed_in_base {
+        for host in import.hosts.values_mut() {
+            if host.dotfiles.iter().any(|d| d.target == target) {
+                host.exclude_dotfiles.push(target.clone());
+            }
+        }
+    }
+}
+
+fn alternate_dotfile(alternate: &Alternate, target: String) -> Dotfile {
+    let entry = if alternate.is_dir {
+        dotfile(&alternate.source, target)
+    } else {
+        Dotfile {
+            template: alternate.template,
+            ..dotfile(&alternate.source, target)
+        }
+    };
+    Dotfile {
+        only_roles: alternate.class.clone().map(|class| vec![class]),
+        when: alternate.when(),
+        ..entry
+    }
+}
+
+// --- plain directory ---
+
+fn dir(root: &Path, import: &mut Import) -> anyhow::Result<()> {
+    for (name, is_dir) in entries(root)? {
+        let source = Path::new(&name);
+        if matches!(
+            name.as_str(),
+            ".git" | ".gitignore" | "mimic.toml" | "mimic.lock"
+        ) || is_readme(&name)
+        {
+            continue;
+        }
+        let lower = name.to_lowercase();
+        if !is_dir
+            && (lower.ends_with(".sh")
+                || ["makefile", "justfile", "bootstrap", "install", "setup"]
+                    .iter()
+                    .any(|script| lower.starts_with(script)))
+        {
+            import.note(
+                source,
+                "Looks like a setup script; run it from a command hook",
+            );
+            continue;
+        }
+        if name == "Brewfile" {
+            import.note(
+                source,
+                "Homebrew bundle; add its packages with 'mimic brewfile import'",
+            );
+            continue;
+        }
+
+        let (plain, template) = match name
+            .strip_suffix(".tmpl")
+            .or_else(|| name.strip_suffix(".hbs"))
+        {
+            Some(stripped) if !is_dir => (stripped, true),
+            _ => (name.as_str(), false),
+        };
+        let target = if plain.starts_with('.') {
+            format!("~/{}", plain)
+        } else {
+            format!("~/.{}", plain)
+        };
+        if is_dir {
+            import.dotfiles.push(tree(source, target));
+        } else {
+            import.dotfiles.push(Dotfile {
+                template,
+                ..dotfile(source, target)
+            });
+        }
+    }
+    Ok(())
+}
+
+// --- output ---
+
+/// The generated `mimic.toml`.
+pub fn render(import: &Import, from: ImportFrom, root: &Path) -> String {
+    let mut doc = DocumentMut::new();
+
+    let mut dotfiles = ArrayOfTables::new();
+    for entry in &import.dotfiles {
+        dotfiles.push(dotfile_table(entry));
+    }
+    if !dotfiles.is_empty() {
+        doc.insert("dotfiles", Item::ArrayOfTables(dotfiles));
+    }
+
+    // Host sections match their machine by hostname, and every other
+    // machine gets the empty default host
+    if !import.hosts.is_empty() {
+        let mut hosts = Table::new();
+        hosts.set_implicit(true);
+        for (name, host) in &import.hosts {
+            let mut table = Table::new();
+            let mut matcher = InlineTable::new();
+            matcher.insert("hostname", name.into());
+            table["match"] = value(matcher);
+            if !host.exclude_dotfiles.is_empty() {
+                table["exclude_dotfiles"] = value(host.exclude_dotfiles.iter().collect::<Array>());
+            }
+            let mut dotfiles = ArrayOfTables::new();
+            for entry in &host.dotfiles {
+                dotfiles.push(dotfile_table(entry));
+            }
+            table.insert("dotfiles", Item::ArrayOfTables(dotfiles));
+            hosts.insert(name, Item::Table(table));
+        }
+        if !hosts.contains_key(DEFAULT_HOST) {
+            hosts.insert(DEFAULT_HOST, Item::Table(Table::new()));
+        }
+        doc.insert("hosts", Item::Table(hosts));
+    }
+
+    format!(
+        "# Generated by `mimic import --from {}` from {}\n# Run `mimic check` and `mimic diff` before the first `mimic apply`.\n\n{}",
+        from.name(),
+        root.display(),
+        doc
+    )
+}
+
+fn dotfile_table(entry: &Dotfile) -> Table {
+    let mut table = Table::new();
+    table["source"] = value(&entry.source);
+    table["target"] = value(&entry.target);
+    if entry.template {
+        table["template"] = value(true);
+    }
+    if entry.encrypted {
+        table["encrypted"] = value(true);
+    }
+    if entry.is_tree() {
+        table["link"] = value("tree");
+    }
+    if let Some(permissions) = entry.permissions {
+        table["permissions"] = value(permissions.to_string());
+    }
+    if let Some(permissions) = entry.dir_permissions {
+        table["dir_permissions"] = value(permissions.to_string());
+    }
+    if let Some(roles) = &entry.only_roles {
+        table["only_roles"] = value(roles.iter().collect::<Array>());
+    }
+    if let Some(roles) = &entry.skip_roles {
+        table["skip_roles"] = value(roles.iter().collect::<Array>());
+    }
+    if let Some(when) = &entry.when {
+        table["when"] = value(when);
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chezmoi_names() {
+        let parsed = ChezmoiName::parse("private_executable_dot_local", false);
+        assert_eq!(parsed.name, ".local");
+        assert_eq!(parsed.permissions(false).unwrap().to_string(), "0700");
+
+        let parsed = ChezmoiName::parse("encrypted_private_dot_netrc.age", false);
+        assert_eq!(parsed.name, ".netrc");
+        assert!(parsed.encrypted);
+        assert_eq!(parsed.permissions(false).unwrap().to_string(), "0600");
+
+        let parsed = ChezmoiName::parse("dot_gitconfig.tmpl", false);
+        assert_eq!(parsed.name, ".gitconfig");
+        assert!(parsed.template);
+
+        assert_eq!(ChezmoiName::parse("literal_dot_x", false).name, "dot_x");
+        assert!(
+            ChezmoiName::parse("run_once_install.sh", false)
+                .unsupported
+                .is_some()
+        );
+        assert!(
+            ChezmoiName::parse("executable_run_me", false)
+                .unsupported
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_yadm_conditions() {
+        let alternate = Alternate::parse(PathBuf::from("x"), false, "os.Darwin,arch.arm64,e.sh");
+        assert_eq!(
+            alternate.when().unwrap(),
+            "os == 'macos' && arch == 'aarch64'"
+        );
+        let alternate = Alternate::parse(PathBuf::from("x"), false, "distro.Ubuntu");
+        assert_eq!(alternate.unsupported, vec!["distro.Ubuntu"]);
+    }
+}
@@ -66,6 +66,30 @@ impl HomebrewManager {
         }
     }
 
+    pub fn list_taps(&self) -> Result<Vec<String>, anyhow::Error> {
+        let output = Command::new("brew").arg("tap").output();
+
+        match output {
+            Ok(output) if output.status.success() => {
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                let taps: Vec<String> = stdout
+                    .lines()
+                    .map(|line| line.trim().to_string())
+                    .filter(|line| !line.is_empty())
+                    .collect();
+                Ok(taps)
+            }
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                Err(anyhow::anyhow!("brew tap failed: {}", stderr))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(anyhow::anyhow!(
+                "Homebrew not found. Please install Homebrew from https://brew.sh"
+            )),
+            Err(e) => Err(anyhow::anyhow!("Failed to execute brew: {}", e)),
+        }
+    }
+
     /// Check if a formula is installed.
     pub fn is_installed(&self, name: &str) -> Result<bool, anyhow::Error> {
         let installed = self.list_installed()?;
@@ -78,12 +102,18 @@ impl HomebrewManager {
         Ok(installed.iter().any(|pkg| pkg == name))
     }
 
-    /// Check if a package is installed, routing to formula or cask based on type.
+    /// Check if a tap is added. Tap names are case-insensitive.
+    pub fn is_tapped(&self, name: &str) -> Result<bool, anyhow::Error> {
+        let taps = self.list_taps()?;
+        Ok(taps.iter().any(|tap| tap.eq_ignore_ascii_case(name)))
+    }
+
+    /// Check if a package is installed, routing to formula, cask or tap based on type.
     pub fn is_installed_any(&self, name: &str, package_type: &str) -> Result<bool, anyhow::Error> {
-        if package_type == "cask" {
-            self.is_installed_cask(name)
-        } else {
-            self.is_installed(name)
+        match package_type {
+            "cask" => self.is_installed_cask(name),
+            "tap" => self.is_tapped(name),
+            _ => self.is_installed(name),
         }
     }
 
@@ -125,6 +155,46 @@ impl HomebrewManager {
         }
     }
 
+    /// Add a third-party tap. Taps aren't recorded in state, so `clean`,
+    /// `undo` and `prune` never remove them.
+    pub fn tap(&self, name: &str) -> Result<(), anyhow::Error> {
+        if self.is_tapped(name)? {
+            return Ok(());
+        }
+
+        let spinner = Spinner::new(format!("Tapping {}...", name));
+
+        let output = Command::new("brew").arg("tap").arg(name).output();
+
+        match output {
+            Ok(output) if output.status.success() => {
+                spinner.finish_with_message(format!("✓ Tapped {}", name));
+                Ok(())
+            }
+            Ok(output) => {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                let exit_code = output.status.code().unwrap_or(-1);
+                spinner.finish_with_error(format!("Failed to tap {}", name));
+                Err(InstallError::CommandFailed {
+                    command: format!("brew tap {}", name),
+                    exit_code,
+                    stderr: stderr.to_string(),
+                }
+                .into())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                spinner.finish_with_error("Homebrew not found");
+                Err(anyhow::anyhow!(
+                    "Homebrew not found. Please install Homebrew from https://brew.sh"
+                ))
+            }
+            Err(e) => {
+                spinner.finish_with_error(format!("Failed to execute brew: {}", e));
+                Err(anyhow::anyhow!("Failed to execute brew: {}", e))
+            }
+        }
+    }
+
     /// Install a single cask package. Casks must be installed one at a time
     /// because they may require interactive prompts (e.g. password for system extensions).
     pub fn install_cask(&self, name: &str, state: &mut State) -> Result<(), anyhow::Error> {
@@ -300,6 +370,6 @@ mod tests {
 
     #[test]
     fn test_homebrew_manager_default() {
-        let _manager = HomebrewManager::default();
+        let _manager: HomebrewManager = Default::default();
     }
 }
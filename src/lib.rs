@@ -1,16 +1,30 @@
+pub mod adopt;
+pub mod blocks;
+pub mod brewfile;
+pub mod check;
 pub mod cli;
+pub mod condition;
 pub mod config;
 pub mod diff;
+pub mod encryption;
 pub mod error;
 pub mod expand;
+pub mod generations;
+pub mod git;
 pub mod git_auth;
 pub mod hooks;
+pub mod import;
 pub mod installer;
 pub mod linker;
+pub mod lock;
+pub mod merge;
 pub mod mise;
+pub mod output;
+pub mod schema;
 pub mod secrets;
 pub mod secrets_scan;
 pub mod spinner;
 pub mod state;
 pub mod template;
+pub mod vars;
 pub mod zerobrew;
@@ -1,15 +1,18 @@
-use crate::config::{Config, Dotfile};
+use crate::config::{Config, Dotfile, LinkStrategy, Permissions};
+use crate::encryption::decrypt_file;
 use crate::error::LinkError;
-use crate::expand::expand_path;
+use crate::expand::{expand_path, expand_path_str};
 use crate::state::{DotfileState, State};
-use crate::template::{HostContext, render_file};
+use crate::template::{HostContext, render_file, render_template_with_host};
 use anyhow::Context;
 use chrono::Local;
 use colored::Colorize;
 use dialoguer::Select;
+use sha2::{Digest, Sha256};
 use std::fs;
-use std::os::unix::fs::symlink;
+use std::os::unix::fs::{PermissionsExt, symlink};
 use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
 
 /// Strategy for resolving symlink conflicts
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -28,7 +31,7 @@ pub enum ApplyToAllChoice {
 }
 
 /// Recursively copy a directory and its contents
-fn copy_dir_all(src: &Path, dst: &Path) -> anyhow::Result<()> {
+pub fn copy_dir_all(src: &Path, dst: &Path) -> anyhow::Result<()> {
     fs::create_dir_all(dst)
         .with_context(|| format!("Failed to create directory: {}", dst.display()))?;
     for entry in
@@ -47,6 +50,161 @@ fn copy_dir_all(src: &Path, dst: &Path) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Rendered templates can contain secrets, so they are private unless the
+/// dotfile asks for something else
+const RENDERED_DIR_MODE: u32 = 0o700;
+const RENDERED_FILE_MODE: u32 = 0o600;
+
+/// Permission settings carried from a dotfile entry to what it deploys
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PermissionSpec {
+    /// Applied to the deployed file, or to every file in a deployed directory
+    pub file: Option<Permissions>,
+    /// Applied to parent directories the linker creates and to directories
+    /// inside a deployed directory
+    pub dir: Option<Permissions>,
+}
+
+impl PermissionSpec {
+    pub fn of(dotfile: &Dotfile) -> Self {
+        Self {
+            file: dotfile.permissions,
+            dir: dotfile.dir_permissions,
+        }
+    }
+
+    pub fn of_state(dotfile: &DotfileState) -> Self {
+        Self {
+            file: dotfile.permissions,
+            dir: dotfile.dir_permissions,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.file.is_none() && self.dir.is_none()
+    }
+
+    /// The mode this spec wants for a filesystem entry, if any
+    fn mode_for(&self, file_type: fs::FileType) -> Option<Permissions> {
+        if file_type.is_dir() {
+            self.dir
+        } else if file_type.is_file() {
+            self.file
+        } else {
+            None
+        }
+    }
+}
+
+fn set_mode(path: &Path, mode: u32) -> anyhow::Result<()> {
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set permissions on {}", path.display()))
+}
+
+/// Write rendered output without other users ever being able to read it:
+/// the content goes into a fresh 0600 file, which gets its final mode and is
+/// then renamed over `path`.
+fn write_private(path: &Path, content: &[u8], mode: u32) -> anyhow::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    // A leftover from an interrupted write may have a wider mode
+    match fs::remove_file(&partial) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            return Err(e).with_context(|| format!("Failed to remove: {}", partial.display()));
+        }
+        _ => {}
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(RENDERED_FILE_MODE)
+        .open(&partial)
+        .with_context(|| format!("Failed to create: {}", partial.display()))?;
+    file.write_all(content)
+        .with_context(|| format!("Failed to write: {}", partial.display()))?;
+    drop(file);
+
+    set_mode(&partial, mode)?;
+    fs::rename(&partial, path)
+        .with_context(|| format!("Failed to move {} into place", partial.display()))
+}
+
+/// Set permissions on a deployed file, or on everything inside a deployed
+/// directory. Symlinks are followed at the top level only.
+pub fn apply_permissions(path: &Path, spec: PermissionSpec) -> anyhow::Result<()> {
+    if spec.is_empty() {
+        return Ok(());
+    }
+
+    for entry in WalkDir::new(path) {
+        let entry = entry.with_context(|| format!("Failed to walk: {}", path.display()))?;
+        if let Some(mode) = spec.mode_for(entry.file_type()) {
+            set_mode(entry.path(), mode.bits())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Describe the first permission mismatch under `path`, or `None` if every
+/// file and directory has the mode the spec asks for.
+pub fn permission_drift(path: &Path, spec: PermissionSpec) -> Option<String> {
+    if spec.is_empty() {
+        return None;
+    }
+
+    for entry in WalkDir::new(path).sort_by_file_name() {
+        let Ok(entry) = entry else { continue };
+        let Some(expected) = spec.mode_for(entry.file_type()) else {
+            continue;
+        };
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+
+        let actual = metadata.permissions().mode() & 0o7777;
+        if actual != expected.bits() {
+            let location = match entry.path().strip_prefix(path) {
+                Ok(relative) if !relative.as_os_str().is_empty() => {
+                    format!(" on {}", relative.display())
+                }
+                _ => String::new(),
+            };
+            return Some(format!(
+                "permissions {:04o}{}, expected {}",
+                actual, location, expected
+            ));
+        }
+    }
+
+    None
+}
+
+/// Create `dir` and any missing ancestors, giving the newly created ones `mode`.
+fn create_parent_dirs(dir: &Path, mode: Option<Permissions>) -> anyhow::Result<()> {
+    let missing: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|d| !d.as_os_str().is_empty() && !d.exists())
+        .map(Path::to_path_buf)
+        .collect();
+
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create parent directory: {}", dir.display()))?;
+
+    if let Some(mode) = mode {
+        for created in missing {
+            set_mode(&created, mode.bits())?;
+        }
+    }
+
+    Ok(())
+}
+
 /// Create a backup of the target file or directory with timestamp suffix
 fn backup_file(target: &Path) -> anyhow::Result<PathBuf> {
     let timestamp = Local::now().format("%Y%m%d_%H%M%S");
@@ -163,7 +321,7 @@ fn resolve_conflict(
     }
 }
 
-/// Compute the rendered output path for a template source file.
+/// Compute the rendered output path for a template or encrypted source file.
 ///
 /// Uses a stable hash prefix of the source's absolute path to avoid collisions
 /// when two templates have the same filename in different directories
@@ -179,6 +337,7 @@ pub fn rendered_path_for(source: &Path) -> anyhow::Result<PathBuf> {
         .ok_or_else(|| anyhow::anyhow!("Source path has no filename: {}", source.display()))?
         .to_str()
         .ok_or_else(|| anyhow::anyhow!("Filename contains invalid UTF-8"))?
+        .trim_end_matches(".age")
         .trim_end_matches(".tmpl")
         .trim_end_matches(".hbs");
 
@@ -201,6 +360,63 @@ fn simple_hash(data: &[u8]) -> String {
     format!("{:08x}", hash as u32)
 }
 
+/// Compute a SHA-256 content hash for a file, or for every file under a directory.
+///
+/// Directory hashes cover relative paths as well as contents, so renames are
+/// detected the same way as edits.
+pub fn content_hash(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+
+    if path.is_dir() {
+        for entry in WalkDir::new(path).sort_by_file_name() {
+            let entry =
+                entry.with_context(|| format!("Failed to walk directory: {}", path.display()))?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(path).unwrap_or(entry.path());
+            hasher.update(relative.to_string_lossy().as_bytes());
+            hasher.update([0]);
+            hasher.update(
+                fs::read(entry.path())
+                    .with_context(|| format!("Failed to read: {}", entry.path().display()))?,
+            );
+        }
+    } else {
+        hasher
+            .update(fs::read(path).with_context(|| format!("Failed to read: {}", path.display()))?);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Check whether `target` is a symlink that resolves to `expected`.
+fn symlink_points_to(target: &Path, expected: &Path) -> bool {
+    if !target.is_symlink() {
+        return false;
+    }
+    let Ok(current_dest) = fs::read_link(target) else {
+        return false;
+    };
+
+    let canonical_current = fs::canonicalize(&current_dest)
+        .or_else(|_| {
+            // Handle relative symlink targets
+            if let Some(parent) = target.parent() {
+                fs::canonicalize(parent.join(&current_dest))
+            } else {
+                Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "cannot resolve",
+                ))
+            }
+        })
+        .ok();
+    let canonical_expected = fs::canonicalize(expected).ok();
+
+    canonical_current.is_some() && canonical_current == canonical_expected
+}
+
 /// Result of preparing a target for symlink creation.
 enum PrepareResult {
     /// Target is ready; contains optional backup path string.
@@ -216,44 +432,21 @@ enum PrepareResult {
 fn prepare_target(
     target: &Path,
     link_source: &Path,
+    dir_mode: Option<Permissions>,
     apply_to_all: &mut Option<ApplyToAllChoice>,
 ) -> anyhow::Result<PrepareResult> {
     let mut backup_path_str = None;
 
     if target.exists() || target.is_symlink() {
         // Idempotency: if target is already a symlink pointing to the correct source, skip
-        if target.is_symlink() {
-            if let Ok(current_dest) = fs::read_link(target) {
-                let canonical_current = fs::canonicalize(&current_dest)
-                    .or_else(|_| {
-                        // Handle relative symlink targets
-                        if let Some(parent) = target.parent() {
-                            fs::canonicalize(parent.join(&current_dest))
-                        } else {
-                            Err(std::io::Error::new(
-                                std::io::ErrorKind::NotFound,
-                                "cannot resolve",
-                            ))
-                        }
-                    })
-                    .ok();
-                let canonical_expected = fs::canonicalize(link_source).ok();
-
-                if canonical_current.is_some()
-                    && canonical_expected.is_some()
-                    && canonical_current == canonical_expected
-                {
-                    // Already correct — ensure parent dirs exist and return Ready
-                    if let Some(parent) = target.parent() {
-                        if !parent.exists() {
-                            fs::create_dir_all(parent).with_context(|| {
-                                format!("Failed to create parent directory: {}", parent.display())
-                            })?;
-                        }
-                    }
-                    return Ok(PrepareResult::AlreadyCorrect);
-                }
+        if symlink_points_to(target, link_source) {
+            // Already correct — ensure parent dirs exist and return Ready
+            if let Some(parent) = target.parent()
+                && !parent.exists()
+            {
+                create_parent_dirs(parent, dir_mode)?;
             }
+            return Ok(PrepareResult::AlreadyCorrect);
         }
 
         let resolution = resolve_conflict(target, link_source, apply_to_all)?;
@@ -278,12 +471,10 @@ fn prepare_target(
     }
 
     // Ensure parent directories exist
-    if let Some(parent) = target.parent() {
-        if !parent.exists() {
-            fs::create_dir_all(parent).with_context(|| {
-                format!("Failed to create parent directory: {}", parent.display())
-            })?;
-        }
+    if let Some(parent) = target.parent()
+        && !parent.exists()
+    {
+        create_parent_dirs(parent, dir_mode)?;
     }
 
     Ok(PrepareResult::Ready(backup_path_str))
@@ -317,6 +508,24 @@ pub fn create_symlink_with_resolution(
     target: &Path,
     state: &mut State,
     apply_to_all: &mut Option<ApplyToAllChoice>,
+) -> anyhow::Result<()> {
+    link_source(
+        source,
+        target,
+        PermissionSpec::default(),
+        state,
+        apply_to_all,
+    )
+}
+
+/// Symlink `target` to `source`. Permissions are set on the source, since that
+/// is the file programs reading the target actually open.
+fn link_source(
+    source: &Path,
+    target: &Path,
+    spec: PermissionSpec,
+    state: &mut State,
+    apply_to_all: &mut Option<ApplyToAllChoice>,
 ) -> anyhow::Result<()> {
     let expanded_source = expand_path(source)?;
     let expanded_target = expand_path(target)?;
@@ -328,20 +537,26 @@ pub fn create_symlink_with_resolution(
         ));
     }
 
-    let backup_path_str = match prepare_target(&expanded_target, &expanded_source, apply_to_all)? {
-        PrepareResult::Skipped => return Ok(()),
-        PrepareResult::AlreadyCorrect => {
-            // Still record in state so status tracking works
-            state.add_dotfile(DotfileState {
-                source: expanded_source.to_string_lossy().to_string(),
-                target: expanded_target.to_string_lossy().to_string(),
-                backup_path: None,
-                rendered_path: None,
-            });
-            return Ok(());
-        }
-        PrepareResult::Ready(bp) => bp,
-    };
+    apply_permissions(&expanded_source, spec)?;
+
+    let backup_path_str =
+        match prepare_target(&expanded_target, &expanded_source, spec.dir, apply_to_all)? {
+            PrepareResult::Skipped => return Ok(()),
+            PrepareResult::AlreadyCorrect => {
+                // Still record in state so status tracking works
+                state.add_dotfile(DotfileState {
+                    source: expanded_source.to_string_lossy().to_string(),
+                    target: expanded_target.to_string_lossy().to_string(),
+                    backup_path: None,
+                    rendered_path: None,
+                    content_hash: None,
+                    permissions: spec.file,
+                    dir_permissions: spec.dir,
+                });
+                return Ok(());
+            }
+            PrepareResult::Ready(bp) => bp,
+        };
 
     symlink(&expanded_source, &expanded_target).with_context(|| LinkError::SymlinkFailed {
         from: expanded_source.display().to_string(),
@@ -354,6 +569,135 @@ pub fn create_symlink_with_resolution(
         target: expanded_target.to_string_lossy().to_string(),
         backup_path: backup_path_str,
         rendered_path: None,
+        content_hash: None,
+        permissions: spec.file,
+        dir_permissions: spec.dir,
+    });
+
+    Ok(())
+}
+
+pub fn copy_file(source: &Path, target: &Path, state: &mut State) -> anyhow::Result<()> {
+    copy_file_with_resolution(source, target, state, &mut None)
+}
+
+pub fn copy_file_with_resolution(
+    source: &Path,
+    target: &Path,
+    state: &mut State,
+    apply_to_all: &mut Option<ApplyToAllChoice>,
+) -> anyhow::Result<()> {
+    copy_source(
+        source,
+        target,
+        PermissionSpec::default(),
+        state,
+        apply_to_all,
+    )
+}
+
+fn copy_source(
+    source: &Path,
+    target: &Path,
+    spec: PermissionSpec,
+    state: &mut State,
+    apply_to_all: &mut Option<ApplyToAllChoice>,
+) -> anyhow::Result<()> {
+    let expanded_source = expand_path(source)?;
+    let expanded_target = expand_path(target)?;
+
+    if !expanded_source.exists() {
+        return Err(anyhow::anyhow!(
+            "Source file does not exist: {}",
+            expanded_source.display()
+        ));
+    }
+
+    copy_into_place(
+        &expanded_source,
+        &expanded_source,
+        &expanded_target,
+        None,
+        spec,
+        state,
+        apply_to_all,
+    )
+}
+
+/// Write a real copy of `content` to `target` and record its hash in state.
+///
+/// `source` is what gets recorded as the dotfile source; for templates it is the
+/// template while `content` is the rendered output.
+fn copy_into_place(
+    source: &Path,
+    content: &Path,
+    target: &Path,
+    rendered_path: Option<String>,
+    spec: PermissionSpec,
+    state: &mut State,
+    apply_to_all: &mut Option<ApplyToAllChoice>,
+) -> anyhow::Result<()> {
+    let expected_hash = content_hash(content)?;
+    let previous = state
+        .dotfiles
+        .iter()
+        .find(|d| d.target == target.to_string_lossy())
+        .cloned();
+    let mut backup_path_str = previous.as_ref().and_then(|p| p.backup_path.clone());
+
+    if target.exists() && !target.is_symlink() {
+        let current_hash = content_hash(target)?;
+        if current_hash == expected_hash {
+            apply_permissions(target, spec)?;
+            state.add_dotfile(DotfileState {
+                source: source.to_string_lossy().to_string(),
+                target: target.to_string_lossy().to_string(),
+                backup_path: backup_path_str,
+                rendered_path,
+                content_hash: Some(expected_hash),
+                permissions: spec.file,
+                dir_permissions: spec.dir,
+            });
+            return Ok(());
+        }
+
+        // Our own earlier copy, untouched since: replace it without prompting
+        if previous.as_ref().and_then(|p| p.content_hash.as_deref()) == Some(&current_hash) {
+            remove_target(target)?;
+        }
+    } else if symlink_points_to(target, content) || symlink_points_to(target, source) {
+        // Switching an existing symlink deployment over to copy mode
+        remove_target(target)?;
+    }
+
+    match prepare_target(target, content, spec.dir, apply_to_all)? {
+        PrepareResult::Skipped => return Ok(()),
+        PrepareResult::AlreadyCorrect => unreachable!("copy targets are never symlinks here"),
+        PrepareResult::Ready(Some(bp)) => backup_path_str = Some(bp),
+        PrepareResult::Ready(None) => {}
+    }
+
+    if content.is_dir() {
+        copy_dir_all(content, target)?;
+    } else {
+        fs::copy(content, target).with_context(|| {
+            format!(
+                "Failed to copy {} to {}",
+                content.display(),
+                target.display()
+            )
+        })?;
+    }
+    apply_permissions(target, spec)?;
+
+    state.add_dotfile(DotfileState {
+        source: source.to_string_lossy().to_string(),
+        target: target.to_string_lossy().to_string(),
+        backup_path: backup_path_str,
+        rendered_path,
+        content_hash: Some(expected_hash),
+        permissions: spec.file,
+        dir_permissions: spec.dir,
     });
 
     Ok(())
@@ -366,13 +710,159 @@ pub fn apply_dotfile(
     state: &mut State,
     apply_to_all: &mut Option<ApplyToAllChoice>,
 ) -> anyhow::Result<()> {
-    if dotfile.is_template() {
+    if dotfile.is_tree() {
+        apply_tree_dotfile(dotfile, config, host_context, state, apply_to_all)
+    } else if dotfile.is_encrypted() {
+        apply_encrypted_dotfile(dotfile, config, host_context, state, apply_to_all)
+    } else if dotfile.is_template() {
         apply_template_dotfile(dotfile, config, host_context, state, apply_to_all)
     } else {
         apply_regular_dotfile(dotfile, state, apply_to_all)
     }
 }
 
+/// What happened to an orphaned dotfile during pruning
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PruneOutcome {
+    /// The target was removed (or already gone); `restored_backup` is set if
+    /// the file it replaced was put back
+    Removed { restored_backup: bool },
+    /// The target no longer holds what mimic deployed, so it was left alone
+    LeftInPlace(String),
+}
+
+/// Remove a dotfile that is no longer in the config, restoring the backup taken
+/// when it was first applied. The entry is dropped from state either way.
+pub fn prune_dotfile(dotfile: &DotfileState, state: &mut State) -> anyhow::Result<PruneOutcome> {
+    let target = Path::new(&dotfile.target);
+    let deployed = Path::new(dotfile.rendered_path.as_deref().unwrap_or(&dotfile.source));
+
+    if target.exists() || target.is_symlink() {
+        let ours = if dotfile.is_copy() {
+            content_hash(target).ok().as_deref() == dotfile.content_hash.as_deref()
+        } else {
+            // The source may already be deleted, leaving a dangling link
+            fs::read_link(target).is_ok_and(|dest| dest == deployed)
+                || symlink_points_to(target, deployed)
+        };
+
+        if !ours {
+            state.remove_target(&dotfile.target);
+            return Ok(PruneOutcome::LeftInPlace(if dotfile.is_copy() {
+                "modified since it was applied".to_string()
+            } else {
+                "no longer a mimic symlink".to_string()
+            }));
+        }
+
+        remove_target(target)?;
+    }
+
+    let mut restored_backup = false;
+    if let Some(backup) = &dotfile.backup_path {
+        let backup = Path::new(backup);
+        if backup.exists() {
+            fs::rename(backup, target).with_context(|| {
+                format!(
+                    "Failed to restore backup {} to {}",
+                    backup.display(),
+                    target.display()
+                )
+            })?;
+            restored_backup = true;
+        }
+    }
+
+    if let Some(rendered) = &dotfile.rendered_path {
+        let _ = fs::remove_file(rendered);
+    }
+
+    state.remove_target(&dotfile.target);
+    Ok(PruneOutcome::Removed { restored_backup })
+}
+
+/// Expand a `link = "tree"` dotfile into one entry per file under its source.
+///
+/// Each entry keeps the parent's settings (mode, permissions, template, roles)
+/// and targets the matching path under the parent's target. Template extensions
+/// are dropped from the target name, so `tree/config.tmpl` lands at
+/// `target/config`.
+pub fn tree_entries(dotfile: &Dotfile) -> anyhow::Result<Vec<Dotfile>> {
+    let source = expand_path_str(&dotfile.source)?;
+    let target = expand_path_str(&dotfile.target)?;
+
+    let mut entries = Vec::new();
+    for entry in WalkDir::new(&source).sort_by_file_name() {
+        let entry =
+            entry.with_context(|| format!("Failed to walk directory: {}", source.display()))?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        let relative = entry.path().strip_prefix(&source)?;
+        let mut child = Dotfile {
+            source: entry.path().to_string_lossy().to_string(),
+            target: target.join(relative).to_string_lossy().to_string(),
+            link: LinkStrategy::Whole,
+            ..dotfile.clone()
+        };
+        if child.is_encrypted() {
+            child.target = child.target.trim_end_matches(".age").to_string();
+        }
+        if child.is_template() {
+            child.target = child
+                .target
+                .trim_end_matches(".tmpl")
+                .trim_end_matches(".hbs")
+                .to_string();
+        }
+        entries.push(child);
+    }
+
+    Ok(entries)
+}
+
+fn apply_tree_dotfile(
+    dotfile: &Dotfile,
+    config: &Config,
+    host_context: &HostContext,
+    state: &mut State,
+    apply_to_all: &mut Option<ApplyToAllChoice>,
+) -> anyhow::Result<()> {
+    let source = expand_path_str(&dotfile.source)?;
+    let target = expand_path_str(&dotfile.target)?;
+
+    if !source.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Tree linking requires a directory source: {}",
+            source.display()
+        ));
+    }
+
+    if target.is_symlink() {
+        // A previous whole-directory link; replace it with a real directory
+        if !symlink_points_to(&target, &source) {
+            return Err(anyhow::anyhow!(
+                "Target {} is a symlink to another location\n\nTo fix:\n  - Remove the symlink, then re-run 'mimic apply'",
+                target.display()
+            ));
+        }
+        remove_target(&target)?;
+        state.dotfiles.retain(|d| Path::new(&d.target) != target);
+    } else if target.exists() && !target.is_dir() {
+        return Err(anyhow::anyhow!(
+            "Target {} exists and is not a directory\n\nTo fix:\n  - Move the file aside, then re-run 'mimic apply'",
+            target.display()
+        ));
+    }
+
+    for entry in tree_entries(dotfile)? {
+        apply_dotfile(&entry, config, host_context, state, apply_to_all)?;
+    }
+
+    Ok(())
+}
+
 fn apply_regular_dotfile(
     dotfile: &Dotfile,
     state: &mut State,
@@ -380,7 +870,12 @@ fn apply_regular_dotfile(
 ) -> anyhow::Result<()> {
     let source = PathBuf::from(&dotfile.source);
     let target = PathBuf::from(&dotfile.target);
-    create_symlink_with_resolution(&source, &target, state, apply_to_all)
+    let spec = PermissionSpec::of(dotfile);
+    if dotfile.is_copy() {
+        copy_source(&source, &target, spec, state, apply_to_all)
+    } else {
+        link_source(&source, &target, spec, state, apply_to_all)
+    }
 }
 
 fn apply_template_dotfile(
@@ -394,6 +889,48 @@ fn apply_template_dotfile(
     let target = expand_path(&PathBuf::from(&dotfile.target))?;
 
     let rendered = render_file(&source, &config.variables, host_context)?;
+    deploy_rendered(
+        dotfile,
+        &source,
+        &target,
+        rendered.as_bytes(),
+        state,
+        apply_to_all,
+    )
+}
+
+/// Decrypt an `encrypted = true` source (rendering it too if it is also a
+/// template) and deploy the plaintext like a rendered template.
+fn apply_encrypted_dotfile(
+    dotfile: &Dotfile,
+    config: &Config,
+    host_context: &HostContext,
+    state: &mut State,
+    apply_to_all: &mut Option<ApplyToAllChoice>,
+) -> anyhow::Result<()> {
+    let source = expand_path(&PathBuf::from(&dotfile.source))?;
+    let target = expand_path(&PathBuf::from(&dotfile.target))?;
+
+    let mut plaintext = decrypt_file(&source, &config.encryption)?;
+    if dotfile.is_template() {
+        let text = String::from_utf8(plaintext)
+            .with_context(|| format!("Decrypted template is not UTF-8: {}", source.display()))?;
+        plaintext = render_template_with_host(&text, &config.variables, host_context)?.into_bytes();
+    }
+    deploy_rendered(dotfile, &source, &target, &plaintext, state, apply_to_all)
+}
+
+/// Write generated content to the private rendered directory and link (or
+/// copy) the target to it. State records `source` as the dotfile source.
+fn deploy_rendered(
+    dotfile: &Dotfile,
+    source: &Path,
+    target: &Path,
+    rendered: &[u8],
+    state: &mut State,
+    apply_to_all: &mut Option<ApplyToAllChoice>,
+) -> anyhow::Result<()> {
+    let spec = PermissionSpec::of(dotfile);
 
     let rendered_dir = directories::BaseDirs::new()
         .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?
@@ -401,14 +938,31 @@ fn apply_template_dotfile(
         .join(".mimic/rendered");
 
     std::fs::create_dir_all(&rendered_dir)?;
+    set_mode(&rendered_dir, RENDERED_DIR_MODE)?;
+
+    let temp_path = rendered_path_for(source)?;
+
+    write_private(
+        &temp_path,
+        rendered,
+        spec.file.map_or(RENDERED_FILE_MODE, Permissions::bits),
+    )?;
+
+    crate::progress!("  {} Rendered: {}", "→".bright_black(), temp_path.display());
+
+    if dotfile.is_copy() {
+        return copy_into_place(
+            source,
+            &temp_path,
+            target,
+            Some(temp_path.to_string_lossy().to_string()),
+            spec,
+            state,
+            apply_to_all,
+        );
+    }
 
-    let temp_path = rendered_path_for(&source)?;
-
-    std::fs::write(&temp_path, rendered)?;
-
-    println!("  {} Rendered: {}", "→".bright_black(), temp_path.display());
-
-    let backup_path_str = match prepare_target(&target, &temp_path, apply_to_all)? {
+    let backup_path_str = match prepare_target(target, &temp_path, spec.dir, apply_to_all)? {
         PrepareResult::Skipped => return Ok(()),
         PrepareResult::AlreadyCorrect => {
             state.add_dotfile(DotfileState {
@@ -416,13 +970,16 @@ fn apply_template_dotfile(
                 target: target.to_string_lossy().to_string(),
                 backup_path: None,
                 rendered_path: Some(temp_path.to_string_lossy().to_string()),
+                content_hash: None,
+                permissions: spec.file,
+                dir_permissions: spec.dir,
             });
             return Ok(());
         }
         PrepareResult::Ready(bp) => bp,
     };
 
-    symlink(&temp_path, &target).with_context(|| LinkError::SymlinkFailed {
+    symlink(&temp_path, target).with_context(|| LinkError::SymlinkFailed {
         from: temp_path.display().to_string(),
         to: target.display().to_string(),
         reason: "symlink system call failed".to_string(),
@@ -433,6 +990,9 @@ fn apply_template_dotfile(
         target: target.to_string_lossy().to_string(),
         backup_path: backup_path_str,
         rendered_path: Some(temp_path.to_string_lossy().to_string()),
+        content_hash: None,
+        permissions: spec.file,
+        dir_permissions: spec.dir,
     });
 
     Ok(())
@@ -0,0 +1,218 @@
+//! `mimic.lock`: the resolved commit of every `[[extends]]` repo.
+//!
+//! Loads check out the locked commit instead of pulling, so a push to a shared
+//! config repo doesn't change anyone's machine until they run `mimic update`
+//! and commit the new lock file.
+
+use crate::config::ExtendsRepo;
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const LOCK_FILE: &str = "mimic.lock";
+
+const HEADER: &str = "# Generated by mimic. Run 'mimic update' to move extends repos forward.\n\n";
+
+/// The lock file that belongs to a root config file.
+pub fn lock_path_for(config_path: &Path) -> PathBuf {
+    config_path
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .join(LOCK_FILE)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct LockedRepo {
+    pub repo: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rev: Option<String>,
+    pub commit: String,
+}
+
+impl LockedRepo {
+    /// Whether this entry was resolved from the same `[[extends]]` spec.
+    /// Changing the branch, tag or rev in the config invalidates it.
+    pub fn matches(&self, extend: &ExtendsRepo) -> bool {
+        self.repo == extend.repo
+            && self.branch == extend.branch
+            && self.tag == extend.tag
+            && self.rev == extend.rev
+    }
+
+    /// The ref this entry was resolved from, e.g. `tag v2`.
+    pub fn spec(&self) -> String {
+        match (&self.rev, &self.tag, &self.branch) {
+            (Some(rev), _, _) => format!("rev {}", rev),
+            (None, Some(tag), _) => format!("tag {}", tag),
+            (None, None, Some(branch)) => format!("branch {}", branch),
+            (None, None, None) => "default branch".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub extends: Vec<LockedRepo>,
+}
+
+impl Lockfile {
+    /// Load a lock file, or an empty one if it doesn't exist.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read lock file: {}", path.display()))?;
+        toml::from_str(&content).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to parse lock file {}: {}\n\nTo fix:\n  - Restore it from git, or delete it and run 'mimic update'",
+                path.display(),
+                e
+            )
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let content = toml::to_string_pretty(self)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize lock file: {}", e))?;
+        fs::write(path, format!("{}{}", HEADER, content))
+            .with_context(|| format!("Failed to write lock file: {}", path.display()))
+    }
+
+    pub fn find(&self, extend: &ExtendsRepo) -> Option<&LockedRepo> {
+        self.extends.iter().find(|locked| locked.matches(extend))
+    }
+
+    /// Record the commit an extends repo resolved to.
+    pub fn set(&mut self, extend: &ExtendsRepo, commit: &str) {
+        let entry = LockedRepo {
+            repo: extend.repo.clone(),
+            branch: extend.branch.clone(),
+            tag: extend.tag.clone(),
+            rev: extend.rev.clone(),
+            commit: commit.to_string(),
+        };
+        match self.extends.iter_mut().find(|l| l.matches(extend)) {
+            Some(existing) => *existing = entry,
+            None => self.extends.push(entry),
+        }
+    }
+}
+
+/// A change `mimic update` made to the lock file
+#[derive(Debug, Clone, Serialize)]
+pub struct LockChange {
+    pub repo: String,
+    /// `None` if the repo wasn't locked before
+    pub from: Option<String>,
+    pub to: String,
+    /// The old and new ref (see [`LockedRepo::spec`]), when the
+    /// `[[extends]]` entry changed its branch, tag or rev
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub spec: Option<(String, String)>,
+}
+
+/// Lock file state threaded through a config load.
+pub struct ExtendsLock {
+    path: PathBuf,
+    original: Lockfile,
+    resolved: Lockfile,
+    /// Repos to re-resolve instead of using their locked commit; an empty
+    /// list means all of them. `None` for a normal load.
+    update: Option<Vec<String>>,
+}
+
+impl ExtendsLock {
+    /// Honor the lock file next to `config_path`.
+    pub fn load(config_path: &Path) -> anyhow::Result<Self> {
+        let path = lock_path_for(config_path);
+        let original = Lockfile::load(&path)?;
+        Ok(Self {
+            path,
+            original,
+            resolved: Lockfile::default(),
+            update: None,
+        })
+    }
+
+    /// Ignore locked commits for `repos` (all repos if empty).
+    pub fn for_update(config_path: &Path, repos: &[String]) -> anyhow::Result<Self> {
+        Ok(Self {
+            update: Some(repos.to_vec()),
+            ..Self::load(config_path)?
+        })
+    }
+
+    /// Whether `mimic update` asked for `extend` to move to its latest commit.
+    pub fn updates(&self, extend: &ExtendsRepo) -> bool {
+        self.update
+            .as_ref()
+            .is_some_and(|repos| repos.is_empty() || repos.iter().any(|r| r == &extend.repo))
+    }
+
+    /// The commit to check out for `extend`, or `None` to resolve it fresh.
+    pub fn locked_commit(&self, extend: &ExtendsRepo) -> Option<&str> {
+        if self.updates(extend) {
+            return None;
+        }
+        self.original.find(extend).map(|l| l.commit.as_str())
+    }
+
+    pub fn record(&mut self, extend: &ExtendsRepo, commit: &str) {
+        self.resolved.set(extend, commit);
+    }
+
+    /// Write the lock file if the resolved commits differ from it. Entries
+    /// for repos no longer extended are dropped; no file is created for a
+    /// config without extends.
+    pub fn finish(self) -> anyhow::Result<Vec<LockChange>> {
+        if self.resolved == self.original
+            || (self.resolved.extends.is_empty() && !self.path.exists())
+        {
+            return Ok(Vec::new());
+        }
+
+        let changes = self
+            .resolved
+            .extends
+            .iter()
+            .filter_map(|locked| {
+                let same_spec = |old: &LockedRepo| {
+                    (&old.branch, &old.tag, &old.rev) == (&locked.branch, &locked.tag, &locked.rev)
+                };
+                // The entry for the same spec, or else the one it replaced
+                let same_repo = || {
+                    self.original
+                        .extends
+                        .iter()
+                        .filter(|old| old.repo == locked.repo)
+                };
+                let old = same_repo()
+                    .find(|old| same_spec(old))
+                    .or_else(|| same_repo().next());
+                let from = old.map(|old| old.commit.clone());
+                let spec = old
+                    .filter(|old| !same_spec(old))
+                    .map(|old| (old.spec(), locked.spec()));
+                (from.as_deref() != Some(locked.commit.as_str()) || spec.is_some()).then(|| {
+                    LockChange {
+                        repo: locked.repo.clone(),
+                        from,
+                        to: locked.commit.clone(),
+                        spec,
+                    }
+                })
+            })
+            .collect();
+
+        self.resolved.save(&self.path)?;
+        Ok(changes)
+    }
+}
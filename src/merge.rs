@@ -0,0 +1,1099 @@
+//! Key-level merges into application config files.
+//!
+//! Apps like VS Code rewrite their own `settings.json`, so a symlink into the
+//! repo gets replaced on the next save. A merge writes only the keys from a
+//! fragment in the repo and leaves every other key to the app:
+//!
+//! ```toml
+//! [[merges]]
+//! source = "vscode/settings.json"
+//! target = "~/Library/Application Support/Code/User/settings.json"
+//! ```
+//!
+//! Tables in the fragment are merged recursively; any other value (including
+//! arrays) replaces the target's value as a whole. Each managed key's value
+//! from before the first merge is kept in state so `undo` can put it back.
+//!
+//! Values are compared as `serde_json::Value`s, but targets are edited in
+//! place: JSON(C) text is spliced and TOML goes through `toml_edit`, so
+//! comments, formatting and TOML datetimes survive. YAML is re-serialized,
+//! so a YAML target with comments is refused rather than stripped.
+
+use crate::config::{MergeEntry, MergeFormat};
+use crate::expand::expand_path_str;
+use crate::state::{MergeState, MergedKey, State};
+use anyhow::Context;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::Path;
+use toml_edit::{DocumentMut, Item, Table, TableLike};
+
+/// A key path from the document root, e.g. `["editor", "fontSize"]`.
+pub type KeyPath = Vec<String>;
+
+/// Dotted form of a key path for messages.
+pub fn display_key(path: &[String]) -> String {
+    path.join(".")
+}
+
+pub fn parse(text: &str, format: MergeFormat) -> anyhow::Result<Value> {
+    if text.trim().is_empty() {
+        return Ok(Value::Object(Map::new()));
+    }
+    let value = match format {
+        MergeFormat::Json => serde_json::from_str(&strip_json_comments(text))?,
+        MergeFormat::Toml => {
+            let table: toml::Table = toml::from_str(text)?;
+            serde_json::to_value(table)?
+        }
+        MergeFormat::Yaml => serde_yaml::from_str(text)?,
+    };
+    match value {
+        Value::Object(_) => Ok(value),
+        Value::Null => Ok(Value::Object(Map::new())),
+        _ => Err(anyhow::anyhow!("top level is not a table")),
+    }
+}
+
+/// Indentation of the first indented line, defaulting to two spaces.
+fn json_indent(text: &str) -> String {
+    text.lines()
+        .skip(1)
+        .map(|line| {
+            let trimmed = line.trim_start_matches([' ', '\t']);
+            &line[..line.len() - trimmed.len()]
+        })
+        .find(|indent| !indent.is_empty())
+        .unwrap_or("  ")
+        .to_string()
+}
+
+/// Drop `//` and `/* */` comments and trailing commas, so JSONC files such as
+/// VS Code settings parse as JSON. Edits are made to the original text, so
+/// comments are kept.
+fn strip_json_comments(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            out.push(c);
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        out.push(escaped);
+                    }
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                out.push(c);
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push('\n');
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' {
+                        break;
+                    }
+                    previous = c;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    strip_trailing_commas(&out)
+}
+
+fn strip_trailing_commas(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+        } else if c == '"' {
+            in_string = true;
+        } else if c == ','
+            && matches!(
+                text[i + 1..].trim_start().chars().next(),
+                Some('}') | Some(']')
+            )
+        {
+            continue;
+        }
+        out.push(c);
+    }
+
+    out
+}
+
+/// The keys a fragment manages: every value that isn't a non-empty table.
+pub fn managed_keys(fragment: &Value) -> Vec<(KeyPath, Value)> {
+    fn walk(value: &Value, path: &mut KeyPath, keys: &mut Vec<(KeyPath, Value)>) {
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                for (key, value) in map {
+                    path.push(key.clone());
+                    walk(value, path, keys);
+                    path.pop();
+                }
+            }
+            _ => keys.push((path.clone(), value.clone())),
+        }
+    }
+
+    let mut keys = Vec::new();
+    if fragment.as_object().is_some_and(|map| !map.is_empty()) {
+        walk(fragment, &mut Vec::new(), &mut keys);
+    }
+    keys
+}
+
+pub fn get<'a>(document: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter()
+        .try_fold(document, |value, key| value.as_object()?.get(key))
+}
+
+/// Set a key, creating parent tables as needed. Returns the parents created.
+fn set(document: &mut Value, path: &[String], value: Value) -> anyhow::Result<Vec<KeyPath>> {
+    let mut created = Vec::new();
+    let mut current = document;
+    for (depth, key) in path[..path.len() - 1].iter().enumerate() {
+        let map = current
+            .as_object_mut()
+            .ok_or_else(|| not_a_table(&path[..depth]))?;
+        if !map.contains_key(key) {
+            created.push(path[..=depth].to_vec());
+        }
+        current = map
+            .entry(key.clone())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+
+    let map = current
+        .as_object_mut()
+        .ok_or_else(|| not_a_table(&path[..path.len() - 1]))?;
+    map.insert(path[path.len() - 1].clone(), value);
+    Ok(created)
+}
+
+fn remove(document: &mut Value, path: &[String]) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let parent = parents
+        .iter()
+        .try_fold(document, |value, key| value.as_object_mut()?.get_mut(key));
+    if let Some(Value::Object(map)) = parent {
+        map.shift_remove(last);
+    }
+}
+
+fn not_a_table(path: &[String]) -> anyhow::Error {
+    anyhow::anyhow!("'{}' is not a table in the target", display_key(path))
+}
+
+/// The parents a key at `path` needs that aren't in the document yet,
+/// assuming the first `existing` of them are.
+fn missing_parents(path: &[String], existing: usize) -> Vec<KeyPath> {
+    (existing..path.len() - 1)
+        .map(|depth| path[..=depth].to_vec())
+        .collect()
+}
+
+/// A target file being edited in place. Only the managed keys change;
+/// comments, formatting and key order stay as the app wrote them.
+enum Editor {
+    /// JSON or JSONC text, edited by splicing
+    Json(String),
+    Toml(DocumentMut),
+    /// YAML is re-serialized as a whole, so files with comments are refused
+    /// when written
+    Yaml(Value),
+}
+
+impl Editor {
+    fn open(text: &str, format: MergeFormat) -> anyhow::Result<Self> {
+        let value = parse(text, format)?;
+        Ok(match format {
+            MergeFormat::Json if text.trim().is_empty() => Editor::Json("{}".to_string()),
+            MergeFormat::Json => Editor::Json(text.to_string()),
+            MergeFormat::Toml => Editor::Toml(text.parse()?),
+            MergeFormat::Yaml => Editor::Yaml(value),
+        })
+    }
+
+    /// The document as it stands, for comparing values.
+    fn value(&self) -> anyhow::Result<Value> {
+        match self {
+            Editor::Json(text) => parse(text, MergeFormat::Json),
+            Editor::Toml(doc) => parse(&doc.to_string(), MergeFormat::Toml),
+            Editor::Yaml(value) => Ok(value.clone()),
+        }
+    }
+
+    fn is_empty(&self) -> anyhow::Result<bool> {
+        Ok(self.value()?.as_object().is_some_and(Map::is_empty))
+    }
+
+    /// Set a key unless it already has this value. Returns the parents
+    /// created.
+    fn set(&mut self, path: &[String], value: &Value) -> anyhow::Result<Vec<KeyPath>> {
+        if get(&self.value()?, path) == Some(value) {
+            return Ok(Vec::new());
+        }
+        match self {
+            Editor::Json(text) => json_set(text, path, value),
+            Editor::Toml(doc) => toml_set(doc, path, value),
+            Editor::Yaml(document) => set(document, path, value.clone()),
+        }
+    }
+
+    fn remove(&mut self, path: &[String]) -> anyhow::Result<()> {
+        match self {
+            Editor::Json(text) => json_remove(text, path),
+            Editor::Toml(doc) => {
+                toml_remove(doc, path);
+                Ok(())
+            }
+            Editor::Yaml(document) => {
+                remove(document, path);
+                Ok(())
+            }
+        }
+    }
+
+    /// The edited file. `original` is the text the editor was opened with.
+    fn finish(self, original: &str) -> anyhow::Result<String> {
+        match self {
+            Editor::Json(mut text) => {
+                if original.trim().is_empty() {
+                    text.push('\n');
+                }
+                Ok(text)
+            }
+            Editor::Toml(doc) => Ok(doc.to_string()),
+            Editor::Yaml(document) => {
+                if original.trim().is_empty() && document.as_object().is_some_and(Map::is_empty) {
+                    return Ok(original.to_string());
+                }
+                Ok(serde_yaml::to_string(&document)?)
+            }
+        }
+    }
+}
+
+/// A member of a JSON object, as byte offsets into the text.
+struct JsonMember {
+    key: String,
+    key_start: usize,
+    value_start: usize,
+    value_end: usize,
+}
+
+/// A JSON object: the offsets of its braces and its members in order.
+struct JsonObject {
+    open: usize,
+    close: usize,
+    members: Vec<JsonMember>,
+}
+
+impl JsonObject {
+    /// The member that wins for `key`; like serde_json, the last duplicate.
+    fn member(&self, key: &str) -> Option<&JsonMember> {
+        self.members.iter().rev().find(|member| member.key == key)
+    }
+}
+
+/// Finds the offsets of values in JSONC text, skipping whitespace and
+/// comments. The text has already been parsed, so errors are only reported
+/// rather than explained.
+struct JsonScanner<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonScanner<'_> {
+    fn skip_space(&mut self) {
+        loop {
+            let rest = &self.text[self.pos..];
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if let Some(comment) = trimmed.strip_prefix("/*") {
+                self.pos += comment.find("*/").map_or(trimmed.len(), |end| end + 4);
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.pos).copied()
+    }
+
+    fn string(&mut self) -> anyhow::Result<()> {
+        let bytes = self.text.as_bytes();
+        let mut i = self.pos + 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => i += 2,
+                b'"' => {
+                    self.pos = i + 1;
+                    return Ok(());
+                }
+                _ => i += 1,
+            }
+        }
+        Err(anyhow::anyhow!("unterminated string"))
+    }
+
+    fn value(&mut self) -> anyhow::Result<()> {
+        match self.peek() {
+            Some(b'"') => self.string(),
+            Some(b'{') => self.object().map(|_| ()),
+            Some(b'[') => {
+                self.pos += 1;
+                loop {
+                    self.skip_space();
+                    match self.peek() {
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(());
+                        }
+                        Some(b',') => self.pos += 1,
+                        Some(_) => self.value()?,
+                        None => return Err(anyhow::anyhow!("unterminated array")),
+                    }
+                }
+            }
+            Some(_) => {
+                let rest = &self.text[self.pos..];
+                let len = rest
+                    .find(|c: char| c.is_whitespace() || matches!(c, ',' | '}' | ']' | '/'))
+                    .unwrap_or(rest.len());
+                if len == 0 {
+                    return Err(anyhow::anyhow!("unexpected character at byte {}", self.pos));
+                }
+                self.pos += len;
+                Ok(())
+            }
+            None => Err(anyhow::anyhow!("unexpected end of file")),
+        }
+    }
+
+    fn object(&mut self) -> anyhow::Result<JsonObject> {
+        let open = self.pos;
+        self.pos += 1;
+        let mut members = Vec::new();
+        loop {
+            self.skip_space();
+            match self.peek() {
+                Some(b'}') => {
+                    self.pos += 1;
+                    return Ok(JsonObject {
+                        open,
+                        close: self.pos - 1,
+                        members,
+                    });
+                }
+                Some(b',') => self.pos += 1,
+                Some(b'"') => {
+                    let key_start = self.pos;
+                    self.string()?;
+                    let key = serde_json::from_str(&self.text[key_start..self.pos])?;
+                    self.skip_space();
+                    if self.peek() != Some(b':') {
+                        return Err(anyhow::anyhow!("expected ':' at byte {}", self.pos));
+                    }
+                    self.pos += 1;
+                    self.skip_space();
+                    let value_start = self.pos;
+                    self.value()?;
+                    members.push(JsonMember {
+                        key,
+                        key_start,
+                        value_start,
+                        value_end: self.pos,
+                    });
+                }
+                _ => return Err(anyhow::anyhow!("expected a key at byte {}", self.pos)),
+            }
+        }
+    }
+}
+
+/// The object at `path`, `Ok(None)` if a key on the way is missing.
+fn json_object(text: &str, path: &[String]) -> anyhow::Result<Option<JsonObject>> {
+    let mut scanner = JsonScanner { text, pos: 0 };
+    scanner.skip_space();
+    let mut object = scanner.object()?;
+    for (depth, key) in path.iter().enumerate() {
+        let Some(member) = object.member(key) else {
+            return Ok(None);
+        };
+        if text.as_bytes()[member.value_start] != b'{' {
+            return Err(not_a_table(&path[..=depth]));
+        }
+        scanner.pos = member.value_start;
+        object = scanner.object()?;
+    }
+    Ok(Some(object))
+}
+
+/// The whitespace at the start of the line containing `at`.
+fn line_indent(text: &str, at: usize) -> &str {
+    let line = &text[text[..at].rfind('\n').map_or(0, |i| i + 1)..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// A value as pretty JSON whose continuation lines start at `indent`.
+fn render_json(value: &Value, unit: &str, indent: &str) -> anyhow::Result<String> {
+    use serde::Serialize;
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(unit.as_bytes());
+    let mut out = Vec::new();
+    value.serialize(&mut serde_json::Serializer::with_formatter(
+        &mut out, formatter,
+    ))?;
+    Ok(String::from_utf8(out)?.replace('\n', &format!("\n{}", indent)))
+}
+
+fn json_set(text: &mut String, path: &[String], value: &Value) -> anyhow::Result<Vec<KeyPath>> {
+    let unit = json_indent(text);
+    // The deepest existing parent, and how many of the parents exist
+    let mut existing = path.len() - 1;
+    let object = loop {
+        match json_object(text, &path[..existing])? {
+            Some(object) => break object,
+            None => existing -= 1,
+        }
+    };
+
+    let key = &path[existing];
+    if existing == path.len() - 1
+        && let Some(member) = object.member(key)
+    {
+        let indent = line_indent(text, member.key_start);
+        let rendered = render_json(value, &unit, indent)?;
+        text.replace_range(member.value_start..member.value_end, &rendered);
+        return Ok(Vec::new());
+    }
+
+    let mut nested = value.clone();
+    for parent in path[existing + 1..].iter().rev() {
+        nested = Value::Object(Map::from_iter([(parent.clone(), nested)]));
+    }
+    let key = serde_json::to_string(key)?;
+    match (object.members.first(), object.members.last()) {
+        (Some(first), Some(last)) if text[object.open..first.key_start].contains('\n') => {
+            let indent = line_indent(text, first.key_start);
+            let member = format!(
+                ",\n{}{}: {}",
+                indent,
+                key,
+                render_json(&nested, &unit, indent)?
+            );
+            text.insert_str(last.value_end, &member);
+        }
+        (_, Some(last)) => {
+            let member = format!(", {}: {}", key, serde_json::to_string(&nested)?);
+            text.insert_str(last.value_end, &member);
+        }
+        _ => {
+            let outer = line_indent(text, object.open).to_string();
+            let indent = format!("{}{}", outer, unit);
+            let member = format!(
+                "\n{}{}: {}",
+                indent,
+                key,
+                render_json(&nested, &unit, &indent)?
+            );
+            if text[object.open + 1..object.close].trim().is_empty() {
+                text.replace_range(
+                    object.open + 1..object.close,
+                    &format!("{}\n{}", member, outer),
+                );
+            } else {
+                text.insert_str(object.open + 1, &member);
+            }
+        }
+    }
+    Ok(missing_parents(path, existing))
+}
+
+/// Remove a member along with the comma that separates it from its
+/// neighbour, so removing a key mimic added restores the original text.
+fn json_remove(text: &mut String, path: &[String]) -> anyhow::Result<()> {
+    let Some((last, parents)) = path.split_last() else {
+        return Ok(());
+    };
+    let object = match json_object(text, parents) {
+        Ok(Some(object)) => object,
+        // Nothing to remove if the parent is gone or no longer a table
+        Ok(None) | Err(_) => return Ok(()),
+    };
+    let Some(index) = object
+        .members
+        .iter()
+        .rposition(|member| &member.key == last)
+    else {
+        return Ok(());
+    };
+
+    let members = &object.members;
+    if index > 0 {
+        text.replace_range(members[index - 1].value_end..members[index].value_end, "");
+    } else if let Some(next) = members.get(1) {
+        text.replace_range(members[0].key_start..next.key_start, "");
+    } else {
+        text.replace_range(object.open + 1..members[0].value_end, "");
+        let close = object.close - (members[0].value_end - object.open - 1);
+        if text[object.open + 1..close].trim().is_empty() {
+            text.replace_range(object.open + 1..close, "");
+        }
+    }
+    Ok(())
+}
+
+/// A JSON value as TOML. Datetimes read from TOML come back as datetimes.
+fn toml_value(value: &Value) -> anyhow::Result<toml_edit::Value> {
+    Ok(match value {
+        Value::Null => return Err(anyhow::anyhow!("null has no TOML equivalent")),
+        Value::Bool(b) => (*b).into(),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => i.into(),
+            None => n
+                .as_f64()
+                .ok_or_else(|| anyhow::anyhow!("{} does not fit in a TOML number", n))?
+                .into(),
+        },
+        Value::String(s) => s.as_str().into(),
+        Value::Array(items) => items
+            .iter()
+            .map(toml_value)
+            .collect::<anyhow::Result<toml_edit::Array>>()?
+            .into(),
+        Value::Object(map) => {
+            if let Some(Value::String(datetime)) = map.get(TOML_DATETIME)
+                && map.len() == 1
+            {
+                return Ok(datetime.parse::<toml_edit::Datetime>()?.into());
+            }
+            let mut table = toml_edit::InlineTable::new();
+            for (key, value) in map {
+                table.insert(key, toml_value(value)?);
+            }
+            table.into()
+        }
+    })
+}
+
+/// The key `toml` uses for datetimes when they pass through serde.
+const TOML_DATETIME: &str = "$__toml_private_datetime";
+
+fn toml_set(doc: &mut DocumentMut, path: &[String], value: &Value) -> anyhow::Result<Vec<KeyPath>> {
+    let (last, parents) = path.split_last().expect("key paths are never empty");
+    let mut created = Vec::new();
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    let mut inline = false;
+    for (depth, key) in parents.iter().enumerate() {
+        if !table.contains_key(key) {
+            created.push(path[..=depth].to_vec());
+        }
+        let item = table.entry(key).or_insert_with(|| {
+            if inline {
+                Item::Value(toml_edit::InlineTable::new().into())
+            } else {
+                let mut parent = Table::new();
+                parent.set_implicit(true);
+                Item::Table(parent)
+            }
+        });
+        inline = item.is_value();
+        table = item
+            .as_table_like_mut()
+            .ok_or_else(|| not_a_table(&path[..=depth]))?;
+    }
+
+    let mut new = toml_value(value)?;
+    match table.get_mut(last) {
+        // Keep the comment after the old value
+        Some(Item::Value(old)) => {
+            *new.decor_mut() = old.decor().clone();
+            *old = new;
+        }
+        Some(item) => *item = Item::Value(new),
+        None => {
+            table.insert(last, Item::Value(new));
+        }
+    }
+    Ok(created)
+}
+
+fn toml_remove(doc: &mut DocumentMut, path: &[String]) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut table: &mut dyn TableLike = doc.as_table_mut();
+    for key in parents {
+        match table.get_mut(key).and_then(Item::as_table_like_mut) {
+            Some(parent) => table = parent,
+            None => return,
+        }
+    }
+    table.remove(last);
+}
+
+/// A comment in YAML text: a `#` at the start of a line or after whitespace,
+/// outside quotes.
+fn has_yaml_comments(text: &str) -> bool {
+    text.lines().any(|line| {
+        let mut quote = None;
+        let mut previous = ' ';
+        for c in line.chars() {
+            match (quote, c) {
+                (None, '"' | '\'') => quote = Some(c),
+                (Some(q), _) if c == q => quote = None,
+                (None, '#') if previous.is_whitespace() => return true,
+                _ => {}
+            }
+            previous = c;
+        }
+        false
+    })
+}
+
+/// Put a managed key back to its pre-merge value, or remove it.
+fn restore(editor: &mut Editor, key: &MergedKey) -> anyhow::Result<()> {
+    match &key.previous {
+        Some(previous) => {
+            editor.set(&key.path, &decode(previous)?)?;
+        }
+        None => editor.remove(&key.path)?,
+    }
+    Ok(())
+}
+
+/// Remove created parent tables that are now empty, deepest first. Returns
+/// the ones still in use.
+fn prune_created(editor: &mut Editor, created: &[KeyPath]) -> anyhow::Result<Vec<KeyPath>> {
+    let mut sorted = created.to_vec();
+    sorted.sort_by_key(|path| std::cmp::Reverse(path.len()));
+
+    let mut kept = Vec::new();
+    for path in sorted {
+        match get(&editor.value()?, &path) {
+            Some(Value::Object(map)) if map.is_empty() => editor.remove(&path)?,
+            Some(_) => kept.push(path),
+            None => {}
+        }
+    }
+    kept.reverse();
+    Ok(kept)
+}
+
+fn encode(value: &Value) -> String {
+    value.to_string()
+}
+
+fn decode(value: &str) -> anyhow::Result<Value> {
+    serde_json::from_str(value).context("Corrupt value recorded in state")
+}
+
+fn read_text(path: &Path) -> anyhow::Result<String> {
+    if path.exists() {
+        fs::read_to_string(path).with_context(|| format!("Failed to read: {}", path.display()))
+    } else {
+        Ok(String::new())
+    }
+}
+
+fn parse_error(path: &Path, format: MergeFormat) -> String {
+    format!(
+        "Failed to parse {}\n\nTo fix:\n  - Check the file is valid {}",
+        path.display(),
+        format_name(format)
+    )
+}
+
+fn read_document(path: &Path, format: MergeFormat) -> anyhow::Result<Value> {
+    let text = read_text(path)?;
+    parse(&text, format).with_context(|| parse_error(path, format))
+}
+
+fn open_document(path: &Path, format: MergeFormat) -> anyhow::Result<(Editor, String)> {
+    let text = read_text(path)?;
+    let editor = Editor::open(&text, format).with_context(|| parse_error(path, format))?;
+    Ok((editor, text))
+}
+
+fn write_document(path: &Path, editor: Editor, original: &str) -> anyhow::Result<()> {
+    let yaml = matches!(editor, Editor::Yaml(_));
+    let updated = editor
+        .finish(original)
+        .with_context(|| format!("Failed to serialize {}", path.display()))?;
+    if updated == original {
+        return Ok(());
+    }
+    if yaml && has_yaml_comments(original) {
+        return Err(anyhow::anyhow!(
+            "Cannot merge into {}: it has comments, which rewriting the YAML would drop\n\nTo fix:\n  - Remove the comments from the file\n  - Or deploy the whole file as a [[dotfiles]] entry",
+            path.display()
+        ));
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    fs::write(path, updated).with_context(|| format!("Failed to write: {}", path.display()))
+}
+
+fn format_name(format: MergeFormat) -> &'static str {
+    match format {
+        MergeFormat::Json => "JSON",
+        MergeFormat::Toml => "TOML",
+        MergeFormat::Yaml => "YAML",
+    }
+}
+
+/// Read and parse a merge's fragment.
+pub fn load_fragment(merge: &MergeEntry) -> anyhow::Result<Value> {
+    let format = merge.resolved_format()?;
+    let source = expand_path_str(&merge.source)?;
+    let text = fs::read_to_string(&source)
+        .with_context(|| format!("Failed to read merge fragment: {}", source.display()))?;
+    parse(&text, format).with_context(|| {
+        format!(
+            "Failed to parse merge fragment {}\n\nTo fix:\n  - Check the file is valid {}",
+            source.display(),
+            format_name(format)
+        )
+    })
+}
+
+/// Managed keys whose value in the target differs from the fragment.
+pub fn differing_keys(merge: &MergeEntry) -> anyhow::Result<Vec<String>> {
+    let format = merge.resolved_format()?;
+    let fragment = load_fragment(merge)?;
+    let document = read_document(&expand_path_str(&merge.target)?, format)?;
+
+    Ok(managed_keys(&fragment)
+        .into_iter()
+        .filter(|(path, value)| get(&document, path) != Some(value))
+        .map(|(path, _)| display_key(&path))
+        .collect())
+}
+
+/// Merge a fragment into its target and record the managed keys in state.
+pub fn apply_merge(merge: &MergeEntry, state: &mut State) -> anyhow::Result<()> {
+    let format = merge.resolved_format()?;
+    let fragment = load_fragment(merge)?;
+    let target = expand_path_str(&merge.target)?;
+    let existed = target.exists();
+    let (mut editor, original) = open_document(&target, format)?;
+    let document = editor.value()?;
+
+    let target_str = target.to_string_lossy().to_string();
+    let previous = state
+        .merges
+        .iter()
+        .find(|m| m.target == target_str && m.source == merge.source)
+        .cloned();
+
+    let mut keys = Vec::new();
+    let mut created = previous
+        .as_ref()
+        .map(|p| p.created.clone())
+        .unwrap_or_default();
+
+    for (path, value) in managed_keys(&fragment) {
+        // Keep the value from before the first merge, not our own
+        let recorded = previous
+            .as_ref()
+            .and_then(|p| p.keys.iter().find(|k| k.path == path));
+        let before = match recorded {
+            Some(key) => key.previous.clone(),
+            None => get(&document, &path).map(encode),
+        };
+
+        for parent in editor
+            .set(&path, &value)
+            .with_context(|| format!("Failed to merge into {}", target.display()))?
+        {
+            if !created.contains(&parent) {
+                created.push(parent);
+            }
+        }
+        keys.push(MergedKey {
+            path,
+            value: encode(&value),
+            previous: before,
+        });
+    }
+
+    // Keys dropped from the fragment go back to their pre-merge values
+    if let Some(previous) = &previous {
+        for key in &previous.keys {
+            if !keys.iter().any(|k| k.path == key.path) {
+                restore(&mut editor, key)?;
+            }
+        }
+    }
+    let created = prune_created(&mut editor, &created)?;
+
+    write_document(&target, editor, &original)?;
+
+    state.add_merge(MergeState {
+        source: merge.source.clone(),
+        target: target_str,
+        format,
+        keys,
+        created,
+        created_file: previous.map_or(!existed, |p| p.created_file),
+    });
+
+    Ok(())
+}
+
+/// Write the recorded values of a merge back into its target.
+pub fn write_merge(merge: &MergeState) -> anyhow::Result<()> {
+    let target = Path::new(&merge.target);
+    let (mut editor, original) = open_document(target, merge.format)?;
+    for key in &merge.keys {
+        editor.set(&key.path, &decode(&key.value)?)?;
+    }
+    write_document(target, editor, &original)
+}
+
+/// Put every managed key back to its pre-merge value. Returns whether the
+/// target existed.
+pub fn undo_merge(merge: &MergeState) -> anyhow::Result<bool> {
+    let target = Path::new(&merge.target);
+    if !target.exists() {
+        return Ok(false);
+    }
+
+    let (mut editor, original) = open_document(target, merge.format)?;
+    for key in merge.keys.iter().rev() {
+        restore(&mut editor, key)?;
+    }
+    prune_created(&mut editor, &merge.created)?;
+
+    if merge.created_file && editor.is_empty()? {
+        fs::remove_file(target)
+            .with_context(|| format!("Failed to remove: {}", target.display()))?;
+    } else {
+        write_document(target, editor, &original)?;
+    }
+    Ok(true)
+}
+
+/// Why the managed keys no longer match what was merged, or `None`. Keys the
+/// app owns are never compared.
+pub fn merge_drift(merge: &MergeState) -> Option<String> {
+    let target = Path::new(&merge.target);
+    if !target.exists() {
+        return Some("file missing".to_string());
+    }
+    let document = match read_document(target, merge.format) {
+        Ok(document) => document,
+        Err(_) => return Some("unparseable".to_string()),
+    };
+
+    let changed: Vec<String> = merge
+        .keys
+        .iter()
+        .filter(|key| {
+            decode(&key.value)
+                .map(|value| get(&document, &key.path) != Some(&value))
+                .unwrap_or(true)
+        })
+        .map(|key| display_key(&key.path))
+        .collect();
+
+    (!changed.is_empty()).then(|| format!("changed: {}", changed.join(", ")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_managed_keys_are_leaves() {
+        let fragment = json!({
+            "editor": { "fontSize": 14, "rulers": [80, 100] },
+            "telemetry": false,
+            "empty": {}
+        });
+        let keys: Vec<String> = managed_keys(&fragment)
+            .iter()
+            .map(|(path, _)| display_key(path))
+            .collect();
+        assert_eq!(
+            keys,
+            vec!["editor.fontSize", "editor.rulers", "telemetry", "empty"]
+        );
+    }
+
+    #[test]
+    fn test_jsonc_comments_and_trailing_commas() {
+        let text = r#"{
+    // font
+    "editor.fontSize": 14, /* inline */
+    "url": "http://example.com//path",
+    "list": [1, 2,],
+}"#;
+        let value = parse(text, MergeFormat::Json).unwrap();
+        assert_eq!(value["url"], "http://example.com//path");
+        assert_eq!(value["list"], json!([1, 2]));
+        assert_eq!(json_indent(text), "    ");
+    }
+
+    /// Set each key, then restore them all and prune what was created.
+    fn round_trip(text: &str, format: MergeFormat, keys: &[(&[&str], Value)]) -> (String, String) {
+        let mut editor = Editor::open(text, format).unwrap();
+        let document = editor.value().unwrap();
+        let mut created = Vec::new();
+        let mut recorded = Vec::new();
+        for (path, value) in keys {
+            let path: KeyPath = path.iter().map(|k| k.to_string()).collect();
+            created.extend(editor.set(&path, value).unwrap());
+            recorded.push(MergedKey {
+                previous: get(&document, &path).map(encode),
+                value: encode(value),
+                path,
+            });
+        }
+        let applied = editor.finish(text).unwrap();
+
+        let mut editor = Editor::open(&applied, format).unwrap();
+        for key in recorded.iter().rev() {
+            restore(&mut editor, key).unwrap();
+        }
+        prune_created(&mut editor, &created).unwrap();
+        let restored = editor.finish(&applied).unwrap();
+        (applied, restored)
+    }
+
+    #[test]
+    fn test_json_edits_keep_comments_and_trailing_commas() {
+        let text = r#"{
+    // written by the app
+    "window.zoomLevel": 1, /* inline */
+    "editor": { "fontSize": 12 },
+    "list": [1, 2,],
+}
+"#;
+        let (applied, restored) = round_trip(
+            text,
+            MergeFormat::Json,
+            &[
+                (&["editor", "fontSize"], json!(14)),
+                (&["ui", "theme"], json!("dark")),
+                (&["window.zoomLevel"], json!(1)),
+            ],
+        );
+        assert_eq!(
+            applied,
+            r#"{
+    // written by the app
+    "window.zoomLevel": 1, /* inline */
+    "editor": { "fontSize": 14 },
+    "list": [1, 2,],
+    "ui": {
+        "theme": "dark"
+    },
+}
+"#
+        );
+        assert_eq!(restored, text);
+
+        let (applied, restored) = round_trip("{}", MergeFormat::Json, &[(&["a"], json!([1, 2]))]);
+        assert_eq!(applied, "{\n  \"a\": [\n    1,\n    2\n  ]\n}");
+        assert_eq!(restored, "{}");
+    }
+
+    #[test]
+    fn test_json_remove_first_and_only_members() {
+        let mut text = r#"{ "a": 1, "b": 2 }"#.to_string();
+        json_remove(&mut text, &["a".to_string()]).unwrap();
+        assert_eq!(text, r#"{ "b": 2 }"#);
+        json_remove(&mut text, &["b".to_string()]).unwrap();
+        assert_eq!(text, "{}");
+
+        let mut text = "{\n  \"a\": 1 // keep\n}".to_string();
+        json_remove(&mut text, &["a".to_string()]).unwrap();
+        assert_eq!(text, "{ // keep\n}");
+    }
+
+    #[test]
+    fn test_toml_edits_keep_datetimes_and_comments() {
+        let text = r#"# written by the app
+created = 1979-05-27T07:32:00Z
+
+[ui]
+scale = 1.5 # hidpi
+"#;
+        let (applied, restored) = round_trip(
+            text,
+            MergeFormat::Toml,
+            &[
+                (&["ui", "scale"], json!(2.0)),
+                (&["ui", "theme"], json!("dark")),
+                (&["editor", "font", "size"], json!(14)),
+            ],
+        );
+        assert_eq!(
+            applied,
+            r#"# written by the app
+created = 1979-05-27T07:32:00Z
+
+[ui]
+scale = 2.0 # hidpi
+theme = "dark"
+
+[editor.font]
+size = 14
+"#
+        );
+        assert_eq!(restored, text);
+
+        // A datetime in a fragment is written as a datetime
+        let fragment = parse("at = 2024-01-01T00:00:00Z\n", MergeFormat::Toml).unwrap();
+        let mut editor = Editor::open("", MergeFormat::Toml).unwrap();
+        editor.set(&["at".to_string()], &fragment["at"]).unwrap();
+        assert_eq!(editor.finish("").unwrap(), "at = 2024-01-01T00:00:00Z\n");
+    }
+
+    #[test]
+    fn test_yaml_comments_are_detected() {
+        assert!(has_yaml_comments("# app settings\ngit: {}\n"));
+        assert!(has_yaml_comments("pager: delta # colours\n"));
+        assert!(!has_yaml_comments("color: '#ff0000'\nurl: a#b\n"));
+    }
+}
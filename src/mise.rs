@@ -71,7 +71,7 @@ pub fn generate_mise_config(config: &crate::config::Config) -> Result<()> {
     let path = MiseConfig::default_path()?;
     mise_config.write_to_file(&path)?;
 
-    println!(
+    crate::progress!(
         "  {} Generated mise config: {}",
         "→".bright_black(),
         path.display()
@@ -0,0 +1,397 @@
+//! Machine-readable output for `--output json`.
+//!
+//! The report types in this module are the documented JSON contract for fleet
+//! scripts; fields may be added but existing ones are not renamed or removed.
+//! While a JSON report is being produced, human-readable progress is routed
+//! to stderr so stdout carries exactly one JSON document.
+
+use crate::check::Finding;
+use crate::config::{
+    Config, Dotfile, HostCandidate, HostMatch, HostSelection, MachineFacts, Packages, Removal,
+    SecretMetadata,
+};
+use crate::diff::{Change, ResourceType};
+use crate::hooks::Hook;
+use crate::vars::ResolvedVariable;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::os::fd::AsFd;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Process exit codes. Errors have always exited with 1, so drift and
+/// failures share it; `--output json` tells them apart.
+pub mod exit_code {
+    /// Command succeeded (and `status` found no drift)
+    pub const SUCCESS: i32 = 0;
+    /// `status` found drift, or `check` found errors
+    pub const DRIFT: i32 = 1;
+    /// Command failed
+    pub const ERROR: i32 = 1;
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Colored, human-readable text
+    #[default]
+    Text,
+    /// A single JSON document on stdout
+    Json,
+}
+
+static PROGRESS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Send human-readable progress to stderr instead of stdout.
+pub fn set_progress_to_stderr(enabled: bool) {
+    PROGRESS_TO_STDERR.store(enabled, Ordering::Relaxed);
+}
+
+pub fn progress_to_stderr() -> bool {
+    PROGRESS_TO_STDERR.load(Ordering::Relaxed)
+}
+
+/// Stdout for child processes whose output is shown to the user.
+pub fn child_stdout() -> Stdio {
+    if progress_to_stderr() {
+        std::io::stderr()
+            .as_fd()
+            .try_clone_to_owned()
+            .map(Stdio::from)
+            .unwrap_or_else(|_| Stdio::null())
+    } else {
+        Stdio::inherit()
+    }
+}
+
+/// Print a line of human-readable progress (stdout, or stderr under JSON output).
+#[macro_export]
+macro_rules! progress {
+    () => {
+        if $crate::output::progress_to_stderr() {
+            eprintln!()
+        } else {
+            println!()
+        }
+    };
+    ($($arg:tt)*) => {
+        if $crate::output::progress_to_stderr() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+pub fn print_json<T: Serialize>(value: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct DiffSummary {
+    pub add: usize,
+    pub modify: usize,
+    pub remove: usize,
+}
+
+/// `mimic diff --output json`
+#[derive(Debug, Serialize)]
+pub struct DiffReport {
+    pub host: Option<String>,
+    pub changes: Vec<Change>,
+    pub summary: DiffSummary,
+}
+
+impl DiffReport {
+    pub fn new(host: Option<String>, changes: Vec<Change>) -> Self {
+        let mut summary = DiffSummary::default();
+        for change in &changes {
+            match change {
+                Change::Add { .. } => summary.add += 1,
+                Change::Modify { .. } => summary.modify += 1,
+                Change::Remove { .. } => summary.remove += 1,
+                Change::AlreadyCorrect { .. } => {}
+            }
+        }
+        Self {
+            host,
+            changes,
+            summary,
+        }
+    }
+}
+
+/// Drift record for one managed resource.
+#[derive(Debug, Serialize)]
+pub struct ResourceStatus {
+    pub resource_type: ResourceType,
+    /// Target path for dotfiles, package name for packages
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manager: Option<String>,
+    pub in_sync: bool,
+    /// Why the resource drifted, e.g. "missing" or "modified in place"
+    pub reason: Option<String>,
+    /// Drift that can't be repaired by re-linking (shown in red)
+    #[serde(skip)]
+    pub severe: bool,
+}
+
+/// Where the config repo is relative to the commit recorded at apply time.
+#[derive(Debug, Serialize)]
+pub struct RepoStatus {
+    pub path: String,
+    pub applied_commit: String,
+    pub applied_dirty: bool,
+    /// Current HEAD, or `None` if the repo can no longer be read
+    pub head: Option<String>,
+    pub dirty: bool,
+    /// One of "same", "ahead", "behind", "diverged", "unknown", "missing"
+    pub relation: &'static str,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+/// `mimic status --output json`
+#[derive(Debug, Serialize)]
+pub struct StatusReport {
+    pub in_sync: bool,
+    pub active_host: Option<String>,
+    pub resources: Vec<ResourceStatus>,
+    pub config_repo: Option<RepoStatus>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApplyStatus {
+    Applied,
+    /// Orphaned resource removed from the system
+    Removed,
+    Skipped,
+    Failed,
+}
+
+/// Outcome of applying one resource.
+#[derive(Debug, Serialize)]
+pub struct ApplyResult {
+    pub resource_type: ResourceType,
+    pub name: String,
+    pub status: ApplyStatus,
+    pub error: Option<String>,
+}
+
+impl ApplyResult {
+    pub fn applied(resource_type: ResourceType, name: impl Into<String>) -> Self {
+        Self {
+            resource_type,
+            name: name.into(),
+            status: ApplyStatus::Applied,
+            error: None,
+        }
+    }
+
+    pub fn removed(resource_type: ResourceType, name: impl Into<String>) -> Self {
+        Self {
+            resource_type,
+            name: name.into(),
+            status: ApplyStatus::Removed,
+            error: None,
+        }
+    }
+
+    pub fn skipped(resource_type: ResourceType, name: impl Into<String>, reason: &str) -> Self {
+        Self {
+            resource_type,
+            name: name.into(),
+            status: ApplyStatus::Skipped,
+            error: Some(reason.to_string()),
+        }
+    }
+
+    pub fn failed(resource_type: ResourceType, name: impl Into<String>, error: String) -> Self {
+        Self {
+            resource_type,
+            name: name.into(),
+            status: ApplyStatus::Failed,
+            error: Some(error),
+        }
+    }
+}
+
+/// `mimic apply --output json`
+#[derive(Debug, Serialize)]
+pub struct ApplyReport {
+    pub host: Option<String>,
+    pub dry_run: bool,
+    pub changes: Vec<Change>,
+    pub results: Vec<ApplyResult>,
+    pub generation: Option<u32>,
+}
+
+/// A host's fully merged configuration (`mimic hosts list/show --output json`).
+#[derive(Debug, Serialize)]
+pub struct HostReport {
+    pub name: String,
+    /// Ancestors from the root of the `inherits` chain down to the direct parent
+    pub inherits: Vec<String>,
+    /// Rules that select this host on matching machines
+    #[serde(rename = "match", skip_serializing_if = "Option::is_none")]
+    pub matcher: Option<HostMatch>,
+    pub roles: Vec<String>,
+    pub variables: BTreeMap<String, serde_json::Value>,
+    pub dotfiles: Vec<Dotfile>,
+    pub packages: Packages,
+    pub hooks: Vec<Hook>,
+    pub secrets: BTreeMap<String, SecretMetadata>,
+    pub mise_tools: BTreeMap<String, String>,
+    /// Entries taken out by `exclude_*` lists and `enabled = false`
+    pub removed: Vec<RemovedEntry>,
+}
+
+/// An entry removed on a host, and the layer that removed it
+#[derive(Debug, Serialize)]
+pub struct RemovedEntry {
+    #[serde(flatten)]
+    pub removal: Removal,
+    /// `base`, `role <name>` or a host in the `inherits` chain
+    pub from: String,
+}
+
+impl HostReport {
+    pub fn new(config: &Config, name: &str) -> anyhow::Result<Self> {
+        let mut inherits = config.host_chain(name)?;
+        inherits.pop();
+        let merged = config.with_host(name)?;
+        let removed = config
+            .host_removals(name)?
+            .into_iter()
+            .map(|(from, removal)| RemovedEntry { removal, from })
+            .collect();
+        let roles = merged
+            .hosts
+            .get(name)
+            .map(|h| h.roles.clone())
+            .unwrap_or_default();
+
+        Ok(Self {
+            name: name.to_string(),
+            inherits,
+            matcher: config.hosts.get(name).and_then(|h| h.matcher.clone()),
+            roles,
+            variables: merged.variables.into_iter().collect(),
+            dotfiles: merged.dotfiles,
+            packages: merged.packages.normalized(),
+            hooks: merged.hooks,
+            secrets: merged.secrets.into_iter().collect(),
+            mise_tools: merged.mise.tools.into_iter().collect(),
+            removed,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HostsReport {
+    pub hosts: Vec<HostReport>,
+}
+
+/// What a role contributes to the hosts that have it (`mimic roles list/show
+/// --output json`).
+#[derive(Debug, Serialize)]
+pub struct RoleReport {
+    pub name: String,
+    /// Whether the role has a `[roles.<name>]` section or is only a tag
+    pub bundle: bool,
+    pub hosts: Vec<String>,
+    pub variables: BTreeMap<String, serde_json::Value>,
+    pub dotfiles: Vec<Dotfile>,
+    pub packages: Packages,
+    pub hooks: Vec<Hook>,
+    pub mise_tools: BTreeMap<String, String>,
+    /// What the role takes out of the base config
+    pub removes: Vec<Removal>,
+}
+
+impl RoleReport {
+    pub fn new(config: &Config, name: &str) -> anyhow::Result<Self> {
+        let bundle = config.roles.get(name);
+        let hosts = config.hosts_with_role(name);
+        if bundle.is_none() && hosts.is_empty() {
+            return Err(anyhow::anyhow!(
+                "Role '{}' not found in config\n\nTo fix:\n  - Define a [roles.{}] section or add '{}' to a host's roles\n  - Run 'mimic roles list' to see the configured roles",
+                name,
+                name,
+                name
+            ));
+        }
+        let bundle = bundle.cloned().unwrap_or_default();
+        let removes = bundle.as_host().removals();
+        let mut packages = bundle.packages.normalized();
+        packages.homebrew.retain(|p| p.enabled);
+        packages.zerobrew.retain(|p| p.enabled);
+
+        Ok(Self {
+            name: name.to_string(),
+            bundle: config.roles.contains_key(name),
+            hosts,
+            variables: bundle.variables.into_iter().collect(),
+            dotfiles: bundle.dotfiles.into_iter().filter(|d| d.enabled).collect(),
+            packages,
+            hooks: bundle.hooks,
+            mise_tools: bundle.mise.tools.into_iter().collect(),
+            removes,
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct RolesReport {
+    pub roles: Vec<RoleReport>,
+}
+
+/// Problems `mimic check` found (`mimic check --output json`).
+#[derive(Debug, Serialize)]
+pub struct CheckReport {
+    pub findings: Vec<Finding>,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+/// A config generated by `mimic import` (`mimic import --output json`).
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub config: PathBuf,
+    /// False under --dry-run
+    pub written: bool,
+    pub dotfiles: usize,
+    /// What couldn't be translated
+    pub notes: Vec<crate::import::Note>,
+}
+
+/// Template variables after sources are resolved (`mimic vars --output json`).
+#[derive(Debug, Serialize)]
+pub struct VarsReport {
+    pub host: Option<String>,
+    /// Where prompt answers are saved on this machine
+    pub data_file: PathBuf,
+    pub variables: Vec<ResolvedVariable>,
+}
+
+/// Which host this machine resolves to (`mimic hosts detect --output json`).
+#[derive(Debug, Serialize)]
+pub struct HostDetectReport {
+    pub machine: MachineFacts,
+    /// `None` when no host, or more than one, matches
+    pub selected: Option<HostSelection>,
+    /// Why no host could be selected
+    pub error: Option<String>,
+    pub candidates: Vec<HostCandidate>,
+}
@@ -0,0 +1,149 @@
+//! JSON Schema for `mimic.toml`, generated from the config types so it can't
+//! drift from what mimic actually reads.
+//!
+//! The generated schema is tidied for TOML editors: TOML has no `null`, so
+//! optional values are written as their plain type, and unit enums become a
+//! string `enum` that editors can complete.
+
+use crate::config::Config;
+use schemars::generate::SchemaSettings;
+use serde_json::{Map, Value, json};
+
+/// The schema for a `mimic.toml` file, as draft-07 JSON Schema (the version
+/// taplo and most editors support).
+pub fn config_schema() -> Value {
+    let mut schema = SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<Config>()
+        .to_value();
+    tidy(&mut schema);
+    schema["title"] = json!("mimic.toml");
+    if let Some(hook) = schema.pointer_mut("/definitions/Hook") {
+        add_hook_types(hook);
+    }
+    schema
+}
+
+fn tidy(value: &mut Value) {
+    match value {
+        Value::Object(schema) => {
+            for child in schema.values_mut() {
+                tidy(child);
+            }
+            drop_null(schema);
+            string_enum(schema);
+            // Unknown keys are almost always typos; `mimic check` reports them too
+            if schema.contains_key("properties") && !schema.contains_key("additionalProperties") {
+                schema.insert("additionalProperties".to_string(), json!(false));
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(tidy),
+        _ => {}
+    }
+}
+
+/// `Option<T>` as plain `T`.
+fn drop_null(schema: &mut Map<String, Value>) {
+    if schema.get("default") == Some(&Value::Null) {
+        schema.remove("default");
+    }
+    if let Some(Value::Array(types)) = schema.get_mut("type") {
+        types.retain(|t| t != "null");
+        if types.len() == 1 {
+            let single = types.remove(0);
+            schema.insert("type".to_string(), single);
+        }
+    }
+    let is_null = |option: &Value| option.get("type") == Some(&json!("null"));
+    let other = match schema.get("anyOf") {
+        Some(Value::Array(options)) => match options.as_slice() {
+            [other, null] | [null, other] if is_null(null) && !is_null(other) => {
+                Some(other.clone())
+            }
+            _ => None,
+        },
+        _ => None,
+    };
+    if let Some(other) = other {
+        schema.remove("anyOf");
+        if let Value::Object(other) = other {
+            for (key, value) in other {
+                schema.entry(key).or_insert(value);
+            }
+        }
+    }
+}
+
+/// A `oneOf` of documented string constants as `enum`, with the variant
+/// docs folded into the description.
+fn string_enum(schema: &mut Map<String, Value>) {
+    let Some(Value::Array(variants)) = schema.get("oneOf") else {
+        return;
+    };
+    let values: Option<Vec<(Value, Option<String>)>> = variants
+        .iter()
+        .map(|variant| {
+            let value = variant.get("const").filter(|c| c.is_string())?.clone();
+            let description = variant
+                .get("description")
+                .and_then(Value::as_str)
+                .map(String::from);
+            Some((value, description))
+        })
+        .collect();
+    let Some(values) = values else {
+        return;
+    };
+
+    let described: Vec<String> = values
+        .iter()
+        .filter_map(|(value, description)| {
+            Some(format!("`{}`: {}", value.as_str()?, description.as_ref()?))
+        })
+        .collect();
+    schema.remove("oneOf");
+    schema.insert("type".to_string(), json!("string"));
+    schema.insert(
+        "enum".to_string(),
+        values.into_iter().map(|(value, _)| value).collect(),
+    );
+    if !described.is_empty() && !schema.contains_key("description") {
+        schema.insert("description".to_string(), json!(described.join("; ")));
+    }
+}
+
+/// List every hook `type` on the hook itself, so editors can complete it
+/// before the variant is known.
+fn add_hook_types(hook: &mut Value) {
+    let types: Vec<Value> = hook["oneOf"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|variant| variant.pointer("/properties/type/const").cloned())
+        .collect();
+    hook["type"] = json!("object");
+    hook["properties"] = json!({
+        "type": {
+            "description": "What the hook does",
+            "type": "string",
+            "enum": types,
+        }
+    });
+    hook["required"] = json!(["type"]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optional_values_have_no_null() {
+        let schema = config_schema();
+        let dotfile = &schema["definitions"]["Dotfile"];
+        assert_eq!(dotfile["properties"]["when"]["type"], "string");
+        assert_eq!(dotfile["properties"]["only_roles"]["type"], "array");
+        assert!(dotfile["properties"]["when"].get("default").is_none());
+        assert_eq!(dotfile["additionalProperties"], false);
+        assert!(!schema.to_string().contains("\"null\""));
+    }
+}
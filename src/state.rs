@@ -1,3 +1,4 @@
+use crate::config::{MergeFormat, Permissions};
 use crate::hooks::Hook;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
@@ -12,6 +13,63 @@ pub struct DotfileState {
     pub backup_path: Option<String>,
     #[serde(default)]
     pub rendered_path: Option<String>,
+    /// SHA-256 of the content written to the target. Only set for copy-mode
+    /// dotfiles, where there is no symlink to compare against.
+    #[serde(default)]
+    pub content_hash: Option<String>,
+    /// Permissions enforced on the deployed file, checked by `status`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub permissions: Option<Permissions>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dir_permissions: Option<Permissions>,
+}
+
+impl DotfileState {
+    /// Check if this dotfile was deployed as a copy rather than a symlink
+    pub fn is_copy(&self) -> bool {
+        self.content_hash.is_some()
+    }
+}
+
+/// A managed block as it was last written into its target file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockState {
+    pub name: String,
+    pub target: String,
+    pub comment: String,
+    /// Rendered content between the markers
+    pub content: String,
+    /// mimic created the target file, so undo may delete it once empty
+    #[serde(default)]
+    pub created_file: bool,
+    /// mimic added a newline to the end of the file before the block
+    #[serde(default)]
+    pub added_newline: bool,
+}
+
+/// Keys merged from a fragment into an application's config file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergeState {
+    pub source: String,
+    pub target: String,
+    pub format: MergeFormat,
+    pub keys: Vec<MergedKey>,
+    /// Parent tables mimic created, removed again on undo once empty
+    #[serde(default)]
+    pub created: Vec<Vec<String>>,
+    /// mimic created the target file, so undo may delete it once empty
+    #[serde(default)]
+    pub created_file: bool,
+}
+
+/// A managed key, with values stored as JSON text so any format round-trips
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergedKey {
+    pub path: Vec<String>,
+    pub value: String,
+    /// Value before the first merge; `None` if the key didn't exist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub previous: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,8 +82,18 @@ pub struct PackageState {
 pub struct State {
     pub active_host: Option<String>,
     pub applied_commit: Option<String>,
+    /// Root of the git repository `applied_commit` refers to
+    #[serde(default)]
+    pub applied_repo: Option<String>,
+    /// Whether the repo had uncommitted changes when it was applied
+    #[serde(default)]
+    pub applied_dirty: bool,
     pub applied_at: DateTime<Utc>,
     pub dotfiles: Vec<DotfileState>,
+    #[serde(default)]
+    pub blocks: Vec<BlockState>,
+    #[serde(default)]
+    pub merges: Vec<MergeState>,
     pub packages: Vec<PackageState>,
     #[serde(default)]
     pub hooks: Vec<Hook>,
@@ -37,8 +105,12 @@ impl State {
         Self {
             active_host: None,
             applied_commit: None,
+            applied_repo: None,
+            applied_dirty: false,
             applied_at: Utc::now(),
             dotfiles: Vec::new(),
+            blocks: Vec::new(),
+            merges: Vec::new(),
             packages: Vec::new(),
             hooks: Vec::new(),
         }
@@ -97,6 +169,34 @@ impl State {
         self.applied_at = Utc::now();
     }
 
+    /// Add or update a managed block in the state, keyed by target and name.
+    pub fn add_block(&mut self, block: BlockState) {
+        if let Some(existing) = self
+            .blocks
+            .iter_mut()
+            .find(|b| b.target == block.target && b.name == block.name)
+        {
+            *existing = block;
+        } else {
+            self.blocks.push(block);
+        }
+        self.applied_at = Utc::now();
+    }
+
+    /// Add or update a merge in the state, keyed by target and source.
+    pub fn add_merge(&mut self, merge: MergeState) {
+        if let Some(existing) = self
+            .merges
+            .iter_mut()
+            .find(|m| m.target == merge.target && m.source == merge.source)
+        {
+            *existing = merge;
+        } else {
+            self.merges.push(merge);
+        }
+        self.applied_at = Utc::now();
+    }
+
     /// Add or update a package in the state.
     /// If a package with the same name already exists, it is replaced.
     pub fn add_package(&mut self, package: PackageState) {
@@ -114,10 +214,20 @@ impl State {
         self.applied_at = Utc::now();
     }
 
+    /// Remove a dotfile by target path
+    pub fn remove_target(&mut self, target: &str) {
+        self.dotfiles.retain(|d| d.target != target);
+        self.applied_at = Utc::now();
+    }
+
     /// Clear all state
     pub fn clear(&mut self) {
         self.applied_commit = None;
+        self.applied_repo = None;
+        self.applied_dirty = false;
         self.dotfiles.clear();
+        self.blocks.clear();
+        self.merges.clear();
         self.packages.clear();
         self.hooks.clear();
         self.applied_at = Utc::now();
@@ -151,6 +261,9 @@ mod tests {
             target: "test_target".to_string(),
             backup_path: None,
             rendered_path: None,
+            content_hash: None,
+            permissions: None,
+            dir_permissions: None,
         });
 
         state.add_package(PackageState {
@@ -1,7 +1,7 @@
+use crate::config::Variables;
 use anyhow::{Context, Result};
 use handlebars::{Handlebars, handlebars_helper};
 use serde_json::{Value, json};
-use std::collections::HashMap;
 use std::path::Path;
 
 handlebars_helper!(includes: |array: Vec<Value>, value: Value| {
@@ -14,17 +14,22 @@ pub struct HostContext {
     pub roles: Vec<String>,
 }
 
-pub fn render_template(template: &str, variables: &HashMap<String, String>) -> Result<String> {
-    let host_context = HostContext {
-        name: "default".to_string(),
-        roles: vec![],
-    };
-    render_template_with_host(template, variables, &host_context)
+impl Default for HostContext {
+    fn default() -> Self {
+        Self {
+            name: "default".to_string(),
+            roles: vec![],
+        }
+    }
+}
+
+pub fn render_template(template: &str, variables: &Variables) -> Result<String> {
+    render_template_with_host(template, variables, &HostContext::default())
 }
 
 pub fn render_template_with_host(
     template: &str,
-    variables: &HashMap<String, String>,
+    variables: &Variables,
     host_context: &HostContext,
 ) -> Result<String> {
     let mut handlebars = Handlebars::new();
@@ -56,7 +61,7 @@ pub fn render_template_with_host(
 
 pub fn render_file(
     source_path: &Path,
-    variables: &HashMap<String, String>,
+    variables: &Variables,
     host_context: &HostContext,
 ) -> Result<String> {
     let content = std::fs::read_to_string(source_path)
@@ -0,0 +1,351 @@
+//! Variables whose values come from the machine instead of the repo.
+//!
+//! A variable can name a source under a `from` key instead of holding a
+//! value:
+//!
+//! ```toml
+//! [variables]
+//! work_email.from = { env = "WORK_EMAIL" }
+//! computer.from = { command = "scutil --get ComputerName" }
+//! signing_key.from = { file = "~/.local/signing-key-id" }
+//! git_email.from = { prompt = "Work email?" }
+//! gpu.from = { command = "nvidia-smi --query-gpu=name --format=csv,noheader", default = "none" }
+//! ```
+//!
+//! Sources are resolved on the merged variables of the selected host, right
+//! before anything is rendered. Answers to prompts are saved in a data file
+//! next to the state file, so each machine is asked once and the answers
+//! never end up in the dotfiles repo.
+
+use crate::config::Variables;
+use crate::expand::expand_path_str;
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the prompt answer file, kept next to the state file.
+pub const DATA_FILE: &str = "data.toml";
+
+const HEADER: &str =
+    "# Answers to mimic variable prompts on this machine. Don't commit this file.\n\n";
+
+/// Where a variable's value is read from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Env(String),
+    Command(String),
+    File(String),
+    Prompt(String),
+}
+
+impl Source {
+    /// Recognize `{ from = { env = "..." } }` (or `command`, `file`,
+    /// `prompt`), optionally with a `default` inside `from`. `from` has to be
+    /// the only key, so an ordinary table such as `{ env = "production" }` is
+    /// never taken for a source. Any other table is an ordinary variable.
+    fn parse(value: &Value) -> Option<(Self, Option<&Value>)> {
+        let outer = value.as_object()?;
+        if outer.len() != 1 {
+            return None;
+        }
+        let table = outer.get("from")?.as_object()?;
+        let mut source = None;
+        let mut default = None;
+        for (key, value) in table {
+            let text = value.as_str();
+            match (key.as_str(), text) {
+                ("default", _) => default = Some(value),
+                (_, None) => return None,
+                (_, Some(_)) if source.is_some() => return None,
+                ("env", Some(text)) => source = Some(Source::Env(text.to_string())),
+                ("command", Some(text)) => source = Some(Source::Command(text.to_string())),
+                ("file", Some(text)) => source = Some(Source::File(text.to_string())),
+                ("prompt", Some(text)) => source = Some(Source::Prompt(text.to_string())),
+                _ => return None,
+            }
+        }
+        source.map(|source| (source, default))
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Source::Env(name) => format!("env {}", name),
+            Source::Command(command) => format!("command `{}`", command),
+            Source::File(path) => format!("file {}", path),
+            Source::Prompt(question) => format!("prompt \"{}\"", question),
+        }
+    }
+}
+
+/// Whether a variable names a source rather than holding a value.
+pub fn is_source(value: &Value) -> bool {
+    Source::parse(value).is_some()
+}
+
+/// A variable after resolution and where its value came from.
+#[derive(Debug, Clone, Serialize)]
+pub struct ResolvedVariable {
+    /// Dotted path, e.g. `git.email`
+    pub name: String,
+    pub value: Value,
+    /// `config` for values written in the config, otherwise the source
+    pub source: String,
+}
+
+/// Prompt answers saved on this machine, keyed by variable path.
+#[derive(Debug, Default)]
+pub struct DataFile {
+    path: PathBuf,
+    answers: BTreeMap<String, String>,
+    changed: bool,
+}
+
+impl DataFile {
+    /// Load the data file, or an empty one if it doesn't exist yet.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let answers = if path.exists() {
+            let content = fs::read_to_string(path)
+                .with_context(|| format!("Failed to read data file: {}", path.display()))?;
+            toml::from_str(&content).map_err(|e| {
+                anyhow::anyhow!(
+                    "Failed to parse data file {}: {}\n\nTo fix:\n  - Correct the file, or delete it to be asked again",
+                    path.display(),
+                    e
+                )
+            })?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            answers,
+            changed: false,
+        })
+    }
+
+    /// Write the file if a new answer was recorded.
+    pub fn save(&self) -> anyhow::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        let content = toml::to_string(&self.answers)
+            .map_err(|e| anyhow::anyhow!("Failed to serialize data file: {}", e))?;
+        fs::write(&self.path, format!("{}{}", HEADER, content))
+            .with_context(|| format!("Failed to write data file: {}", self.path.display()))
+    }
+}
+
+/// Replace every source in `variables` with its value. Returns the resolved
+/// variables and, for each value, where it came from (sorted by name).
+pub fn resolve(
+    variables: &Variables,
+    data: &mut DataFile,
+) -> anyhow::Result<(Variables, Vec<ResolvedVariable>)> {
+    let mut report = Vec::new();
+    let mut resolved = Variables::new();
+    let mut names: Vec<&String> = variables.keys().collect();
+    names.sort();
+    for name in names {
+        let value = resolve_value(name, &variables[name], data, true, &mut report)?;
+        resolved.insert(name.clone(), value);
+    }
+    Ok((resolved, report))
+}
+
+/// Resolve what can be read without side effects, for commands that only
+/// report: environment variables, files and saved prompt answers. Commands
+/// aren't run, nothing is asked and `data` is left unchanged. Variables that
+/// can't be read this way are left out and returned by name with the reason.
+pub fn resolve_cached(
+    variables: &Variables,
+    data: &mut DataFile,
+) -> (Variables, Vec<(String, anyhow::Error)>) {
+    let mut resolved = Variables::new();
+    let mut unresolved = Vec::new();
+    let mut names: Vec<&String> = variables.keys().collect();
+    names.sort();
+    for name in names {
+        match resolve_value(name, &variables[name], data, false, &mut Vec::new()) {
+            Ok(value) => {
+                resolved.insert(name.clone(), value);
+            }
+            Err(e) => unresolved.push((name.clone(), e)),
+        }
+    }
+    (resolved, unresolved)
+}
+
+fn resolve_value(
+    path: &str,
+    value: &Value,
+    data: &mut DataFile,
+    interactive: bool,
+    report: &mut Vec<ResolvedVariable>,
+) -> anyhow::Result<Value> {
+    if let Some((source, default)) = Source::parse(value) {
+        let (value, description) = match read_source(path, &source, data, interactive)? {
+            Some(value) => (Value::String(value), source.describe()),
+            None => match default {
+                Some(default) => (
+                    default.clone(),
+                    format!("default, {} unavailable", source.describe()),
+                ),
+                None => return Err(missing_source(path, &source)),
+            },
+        };
+        report.push(ResolvedVariable {
+            name: path.to_string(),
+            value: value.clone(),
+            source: description,
+        });
+        return Ok(value);
+    }
+
+    match value {
+        Value::Object(table) if !table.is_empty() => {
+            let mut resolved = serde_json::Map::new();
+            for (key, value) in table {
+                let child = format!("{}.{}", path, key);
+                let value = resolve_value(&child, value, data, interactive, report)?;
+                resolved.insert(key.clone(), value);
+            }
+            Ok(Value::Object(resolved))
+        }
+        _ => {
+            report.push(ResolvedVariable {
+                name: path.to_string(),
+                value: value.clone(),
+                source: "config".to_string(),
+            });
+            Ok(value.clone())
+        }
+    }
+}
+
+/// Read a source. `None` means it has no value here (unset variable, missing
+/// file, failed command) and the `default` applies. Unless `interactive`,
+/// commands and unanswered prompts are errors rather than run or asked.
+fn read_source(
+    path: &str,
+    source: &Source,
+    data: &mut DataFile,
+    interactive: bool,
+) -> anyhow::Result<Option<String>> {
+    let unanswered = matches!(source, Source::Prompt(_)) && !data.answers.contains_key(path);
+    if !interactive && (matches!(source, Source::Command(_)) || unanswered) {
+        return Err(anyhow::anyhow!(
+            "Variable '{}' comes from {}, which only diff, apply and vars read\n\nTo fix:\n  - Run 'mimic vars' to see its value on this machine",
+            path,
+            source.describe()
+        ));
+    }
+    match source {
+        Source::Env(name) => Ok(std::env::var(name).ok()),
+        Source::Command(command) => {
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .output()
+                .with_context(|| format!("Failed to run command for variable '{}'", path))?;
+            Ok(output.status.success().then(|| {
+                String::from_utf8_lossy(&output.stdout)
+                    .trim_end()
+                    .to_string()
+            }))
+        }
+        Source::File(file) => {
+            let file = expand_path_str(file)?;
+            Ok(fs::read_to_string(file)
+                .ok()
+                .map(|content| content.trim_end().to_string()))
+        }
+        Source::Prompt(question) => {
+            if let Some(answer) = data.answers.get(path) {
+                return Ok(Some(answer.clone()));
+            }
+            if !std::io::stdin().is_terminal() {
+                return Ok(None);
+            }
+            let answer: String = dialoguer::Input::new()
+                .with_prompt(question)
+                .interact_text()
+                .with_context(|| format!("Failed to read an answer for variable '{}'", path))?;
+            data.answers.insert(path.to_string(), answer.clone());
+            data.changed = true;
+            Ok(Some(answer))
+        }
+    }
+}
+
+fn missing_source(path: &str, source: &Source) -> anyhow::Error {
+    let key = path.rsplit('.').next().unwrap_or(path);
+    match source {
+        Source::Env(name) => anyhow::anyhow!(
+            "Variable '{}' reads ${}, which is not set\n\nTo fix:\n  - Export {} before running mimic\n  - Or add a default: {}.from = {{ env = \"{}\", default = \"...\" }}",
+            path,
+            name,
+            name,
+            key,
+            name
+        ),
+        Source::Command(command) => anyhow::anyhow!(
+            "Variable '{}' runs `{}`, which failed\n\nTo fix:\n  - Run the command yourself to see the error\n  - Or add a default: {}.from = {{ command = \"...\", default = \"...\" }}",
+            path,
+            command,
+            key
+        ),
+        Source::File(file) => anyhow::anyhow!(
+            "Variable '{}' reads {}, which could not be read\n\nTo fix:\n  - Create the file on this machine\n  - Or add a default: {}.from = {{ file = \"...\", default = \"...\" }}",
+            path,
+            file,
+            key
+        ),
+        Source::Prompt(question) => anyhow::anyhow!(
+            "Variable '{}' needs an answer to \"{}\", but mimic is not running in a terminal\n\nTo fix:\n  - Run 'mimic vars' in a terminal once to answer it\n  - Or add a default: {}.from = {{ prompt = \"...\", default = \"...\" }}",
+            path,
+            question,
+            key
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_only_source_tables_are_sources() {
+        assert_eq!(
+            Source::parse(&json!({ "from": { "env": "HOME" } })),
+            Some((Source::Env("HOME".to_string()), None))
+        );
+        let default = json!("none");
+        assert_eq!(
+            Source::parse(&json!({ "from": { "command": "true", "default": "none" } })),
+            Some((Source::Command("true".to_string()), Some(&default)))
+        );
+        assert_eq!(
+            Source::parse(&json!({ "from": { "env": "A", "file": "b" } })),
+            None
+        );
+        assert_eq!(
+            Source::parse(&json!({ "from": { "env": "A" }, "region": "eu" })),
+            None
+        );
+        assert_eq!(Source::parse(&json!({ "from": { "env": 3 } })), None);
+        assert_eq!(Source::parse(&json!({ "from": "env" })), None);
+        // Tables without `from` are ordinary variables
+        assert_eq!(Source::parse(&json!({ "env": "production" })), None);
+        assert_eq!(Source::parse(&json!({ "command": "deploy" })), None);
+    }
+}
@@ -126,10 +126,7 @@ impl ZerobrewManager {
             if to_install.len() == 1 { "" } else { "s" }
         ));
 
-        let output = Command::new("zb")
-            .arg("install")
-            .args(&to_install)
-            .output();
+        let output = Command::new("zb").arg("install").args(&to_install).output();
 
         match output {
             Ok(output) if output.status.success() => {
@@ -199,6 +196,6 @@ mod tests {
 
     #[test]
     fn test_zerobrew_manager_default() {
-        let _manager = ZerobrewManager::default();
+        let _manager: ZerobrewManager = Default::default();
     }
 }
@@ -0,0 +1,144 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use mimic::state::State;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+#[test]
+fn test_add_adopts_file_into_repo() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    let repo = temp_dir.path().join("repo");
+    fs::create_dir_all(home.join(".config")).unwrap();
+    fs::create_dir_all(&repo).unwrap();
+
+    let original = home.join(".config/starship.toml");
+    fs::write(&original, "add_newline = false\n").unwrap();
+
+    let config_path = repo.join("mimic.toml");
+    fs::write(
+        &config_path,
+        "# Personal dotfiles\n\n[variables]\nname = \"me\" # used in gitconfig\n",
+    )
+    .unwrap();
+    let state_path = temp_dir.path().join("state.toml");
+
+    cargo_bin_cmd!("mimic")
+        .env("HOME", &home)
+        .arg("add")
+        .arg(&original)
+        .arg("--config")
+        .arg(&config_path)
+        .arg("--state")
+        .arg(&state_path)
+        .arg("--yes")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Linked"));
+
+    let moved = repo.join("dotfiles/starship.toml");
+    assert_eq!(fs::read_to_string(&moved).unwrap(), "add_newline = false\n");
+    assert!(original.is_symlink());
+    assert_eq!(
+        fs::read_link(&original).unwrap().canonicalize().unwrap(),
+        moved.canonicalize().unwrap()
+    );
+
+    let config = fs::read_to_string(&config_path).unwrap();
+    assert!(config.starts_with("# Personal dotfiles\n"));
+    assert!(config.contains("name = \"me\" # used in gitconfig"));
+    assert!(config.contains("[[dotfiles]]"));
+    assert!(config.contains("source = \"dotfiles/starship.toml\""));
+    assert!(config.contains("target = \"~/.config/starship.toml\""));
+
+    let state = State::load(&state_path).unwrap();
+    assert_eq!(state.dotfiles.len(), 1);
+    assert_eq!(state.dotfiles[0].target, original.to_string_lossy());
+
+    // Adding it again is refused: it's a symlink now
+    cargo_bin_cmd!("mimic")
+        .env("HOME", &home)
+        .arg("add")
+        .arg(&original)
+        .arg("--config")
+        .arg(&config_path)
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("is a symlink"));
+}
+
+#[test]
+fn test_add_with_host_and_template() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    let repo = temp_dir.path().join("repo");
+    fs::create_dir_all(&home).unwrap();
+    fs::create_dir_all(&repo).unwrap();
+
+    let original = home.join(".gitconfig");
+    fs::write(&original, "[user]\n  name = me\n").unwrap();
+
+    let config_path = repo.join("mimic.toml");
+    fs::write(&config_path, "[hosts.work]\nroles = [\"work\"]\n").unwrap();
+    let state_path = temp_dir.path().join("state.toml");
+
+    cargo_bin_cmd!("mimic")
+        .env("HOME", &home)
+        .arg("add")
+        .arg(&original)
+        .arg("--template")
+        .arg("--host")
+        .arg("work")
+        .arg("--config")
+        .arg(&config_path)
+        .arg("--state")
+        .arg(&state_path)
+        .arg("--yes")
+        .assert()
+        .success();
+
+    let config: toml::Value = toml::from_str(&fs::read_to_string(&config_path).unwrap()).unwrap();
+    let entry = &config["hosts"]["work"]["dotfiles"][0];
+    assert_eq!(entry["source"].as_str(), Some("dotfiles/gitconfig"));
+    assert_eq!(entry["template"].as_bool(), Some(true));
+    assert!(config.get("dotfiles").is_none());
+
+    assert!(original.is_symlink());
+    assert_eq!(
+        fs::read_to_string(&original).unwrap(),
+        "[user]\n  name = me\n"
+    );
+}
+
+#[test]
+fn test_add_dry_run_changes_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    let repo = temp_dir.path().join("repo");
+    fs::create_dir_all(&home).unwrap();
+    fs::create_dir_all(&repo).unwrap();
+
+    let original = home.join(".zshrc");
+    fs::write(&original, "export A=1").unwrap();
+    let config_path = repo.join("mimic.toml");
+    fs::write(&config_path, "").unwrap();
+
+    cargo_bin_cmd!("mimic")
+        .env("HOME", &home)
+        .arg("add")
+        .arg(&original)
+        .arg("--config")
+        .arg(&config_path)
+        .arg("--state")
+        .arg(temp_dir.path().join("state.toml"))
+        .arg("--dry-run")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("dotfiles/zshrc"));
+
+    assert!(!original.is_symlink());
+    assert!(!repo.join("dotfiles").exists());
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), "");
+}
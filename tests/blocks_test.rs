@@ -0,0 +1,100 @@
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+mod common;
+use common::mimic;
+
+fn write_config(path: &Path, target: &Path) {
+    fs::write(
+        path,
+        format!(
+            r#"
+[variables]
+bin_dir = "$HOME/.local/bin"
+
+[[blocks]]
+name = "path"
+target = "{}"
+content = """
+export PATH="{{{{variables.bin_dir}}}}:$PATH"
+"""
+"#,
+            target.display()
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_block_inserted_and_undone_without_touching_surroundings() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let target = home.join(".bashrc");
+    let original = "# distro defaults\nalias ll='ls -l'";
+    fs::write(&target, original).unwrap();
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    write_config(&config_path, &target);
+
+    mimic(&home, &["diff"], &config_path, Some(&state_path))
+        .success()
+        .stdout(predicate::str::contains("block"))
+        .stdout(predicate::str::contains("path in"));
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+    let applied = fs::read_to_string(&target).unwrap();
+    assert_eq!(
+        applied,
+        "# distro defaults\nalias ll='ls -l'\n# BEGIN mimic: path\nexport PATH=\"$HOME/.local/bin:$PATH\"\n# END mimic: path\n"
+    );
+    mimic(&home, &["status"], &config_path, Some(&state_path)).success();
+
+    // Edits outside the block are not drift
+    fs::write(&target, format!("export EDITOR=vim\n{}", applied)).unwrap();
+    mimic(&home, &["status"], &config_path, Some(&state_path)).success();
+
+    // Edits inside the block are
+    let edited = fs::read_to_string(&target)
+        .unwrap()
+        .replace(".local/bin", "bin");
+    fs::write(&target, edited).unwrap();
+    mimic(&home, &["status"], &config_path, Some(&state_path))
+        .code(1)
+        .stdout(predicate::str::contains("modified in place"));
+    mimic(&home, &["diff"], &config_path, Some(&state_path))
+        .success()
+        .stdout(predicate::str::contains("content differs"));
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+    mimic(&home, &["status"], &config_path, Some(&state_path)).success();
+
+    mimic(&home, &["undo", "--yes"], &config_path, Some(&state_path))
+        .success()
+        .stdout(predicate::str::contains("1 blocks removed"));
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        format!("export EDITOR=vim\n{}", original)
+    );
+}
+
+#[test]
+fn test_block_created_file_removed_on_undo() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let target = home.join(".config/env.sh");
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    write_config(&config_path, &target);
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+    assert!(target.exists());
+
+    mimic(&home, &["undo", "--yes"], &config_path, Some(&state_path)).success();
+    assert!(!target.exists());
+}
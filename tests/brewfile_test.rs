@@ -0,0 +1,142 @@
+use mimic::config::Config;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+mod common;
+use common::mimic;
+
+const CONFIG: &str = r#"# Shared packages
+[packages]
+brew = ["git", "ripgrep"]
+
+[hosts.work]
+roles = ["dev"]
+exclude_packages = ["ripgrep"]
+
+[hosts.home]
+
+[roles.dev.packages]
+cask = ["docker"]
+"#;
+
+#[test]
+fn test_brewfile_import_adds_packages_to_host() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path();
+    let config_path = home.join("mimic.toml");
+    fs::write(&config_path, CONFIG).unwrap();
+    fs::write(
+        home.join("Brewfile"),
+        r#"tap "homebrew/cask-fonts"
+brew "jq"
+brew "gnu-sed" unless OS.mac?
+cask "iterm2" if OS.mac?
+vscode "rust-lang.rust-analyzer"
+"#,
+    )
+    .unwrap();
+    let brewfile = home.join("Brewfile").display().to_string();
+
+    mimic(
+        home,
+        &["--host", "work", "brewfile", "import", &brewfile],
+        &config_path,
+        None,
+    )
+    .success()
+    .stdout(predicate::str::contains("[hosts.work.packages]"))
+    .stdout(predicate::str::contains(
+        "+ cask iterm2 (when os == 'macos')",
+    ))
+    .stdout(predicate::str::contains(format!(
+        "{}:5: 'vscode' entries have no mimic equivalent",
+        brewfile
+    )))
+    .stdout(predicate::str::contains("Added 4 packages"));
+
+    let text = fs::read_to_string(&config_path).unwrap();
+    assert!(text.starts_with("# Shared packages\n"));
+    let config = Config::from_file(&config_path).unwrap();
+    let shared = config.packages.normalized();
+    let shared: Vec<&str> = shared.homebrew.iter().map(|p| p.name.as_str()).collect();
+    assert_eq!(shared, vec!["git", "ripgrep"]);
+    let work = config.hosts["work"].packages.normalized();
+    let names: Vec<(&str, &str)> = work
+        .homebrew
+        .iter()
+        .map(|p| (p.name.as_str(), p.pkg_type.as_str()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            ("gnu-sed", "formula"),
+            ("iterm2", "cask"),
+            ("jq", "formula"),
+            ("homebrew/cask-fonts", "tap"),
+        ]
+    );
+
+    // Importing again adds nothing
+    mimic(
+        home,
+        &["--host", "work", "brewfile", "import", &brewfile],
+        &config_path,
+        None,
+    )
+    .success()
+    .stdout(predicate::str::contains("4 already listed"));
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), text);
+}
+
+#[test]
+fn test_brewfile_export_uses_the_hosts_packages() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path();
+    let config_path = home.join("mimic.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "{}\n[hosts.work.packages]\ntap = [\"hashicorp/tap\"]\n",
+            CONFIG
+        ),
+    )
+    .unwrap();
+
+    mimic(home, &["--host", "work", "brewfile", "export"], &config_path, None)
+        .success()
+        .stdout(predicate::str::diff(
+            "# Generated by `mimic brewfile export` for host work\n\ntap \"hashicorp/tap\"\n\nbrew \"git\"\n\ncask \"docker\"\n",
+        ));
+
+    let exported = home.join("Brewfile");
+    mimic(
+        home,
+        &[
+            "--host",
+            "home",
+            "brewfile",
+            "export",
+            &exported.display().to_string(),
+        ],
+        &config_path,
+        None,
+    )
+    .success()
+    .stdout(predicate::str::contains("Wrote 2 packages"));
+    assert_eq!(
+        fs::read_to_string(&exported).unwrap(),
+        "# Generated by `mimic brewfile export` for host home\n\nbrew \"git\"\nbrew \"ripgrep\"\n"
+    );
+
+    // An exported Brewfile imports back as a no-op
+    mimic(
+        home,
+        &["brewfile", "import", &exported.display().to_string()],
+        &config_path,
+        None,
+    )
+    .success()
+    .stdout(predicate::str::contains("2 already listed"))
+    .stdout(predicate::str::contains("Added 0 packages"));
+}
@@ -0,0 +1,216 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use predicates::prelude::*;
+use std::fs;
+use tempfile::TempDir;
+
+mod common;
+use common::mimic;
+
+#[test]
+fn test_check_reports_problems_with_file_and_line() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let repo = temp_dir.path().join("repo");
+    fs::create_dir_all(repo.join("dots/nvim")).unwrap();
+    fs::write(repo.join("dots/zshrc"), "export EDITOR=nvim\n").unwrap();
+    fs::write(
+        repo.join("dots/gitconfig.tmpl"),
+        "[user]\n  name = {{variables.name}}\n  email = {{variables.git.email}}\n",
+    )
+    .unwrap();
+
+    let config_path = repo.join("mimic.toml");
+    fs::write(
+        &config_path,
+        r#"include = ["hosts.toml"]
+
+[variables]
+name = "Jo"
+
+[[dotfiles]]
+source = "dots/zshrc"
+target = "~/.zshrc"
+only_role = ["dev"]
+
+[[dotfiles]]
+source = "dots/gitconfig.tmpl"
+target = "~/.gitconfig"
+
+[[dotfiles]]
+source = "dots/missing"
+target = "~/.zshrc"
+
+[[dotfiles]]
+source = "dots/nvim"
+target = "~/.config/nvim"
+
+[[merges]]
+source = "dots/zshrc"
+target = "~/.config/nvim/settings.json"
+
+[[hooks]]
+type = "command"
+name = "setup"
+command = ""
+on_failure = "continue"
+retries = 3
+
+[roles.gaming]
+variables = { steam = true }
+"#,
+    )
+    .unwrap();
+    fs::write(
+        repo.join("hosts.toml"),
+        r#"[hosts.work]
+roles = ["dev"]
+variables = { git = { email = "jo@work.example" } }
+
+[hosts.home]
+roles = ["dev"]
+
+[hosts.laptop]
+inherits = "wrok"
+"#,
+    )
+    .unwrap();
+
+    let config = config_path.display().to_string();
+    let hosts = repo.join("hosts.toml").display().to_string();
+    let template = repo.join("dots/gitconfig.tmpl").display().to_string();
+    mimic(&home, &["check"], &config_path, None)
+        .code(1)
+        .stdout(predicate::str::contains(format!(
+            "{}:9: error: Unknown key 'only_role' in [[dotfiles]]; did you mean 'only_roles'?",
+            config
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{}:15: error: Dotfile source not found: dots/missing",
+            config
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{}:15: error: Target ~/.zshrc is also deployed by the dotfile at {}:6",
+            config, config
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{}:23: warning: Target ~/.config/nvim/settings.json is inside ~/.config/nvim",
+            config
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{}:30: error: Hook 'setup' has an empty command",
+            config
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{}:32: error: Unknown key 'retries' in [[hooks]]",
+            config
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{}:34: warning: Role 'gaming' is not given to any host",
+            config
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{}:9: error: Host 'laptop' inherits from unknown host 'wrok'; did you mean 'work'?",
+            hosts
+        )))
+        .stdout(predicate::str::contains(format!(
+            "{}:3: error: Template variable 'variables.git.email' is not defined for hosts 'home', 'laptop'",
+            template
+        )))
+        .stdout(predicate::str::contains("7 errors, 2 warnings"));
+
+    let output = cargo_bin_cmd!("mimic")
+        .env("HOME", &home)
+        .args(["check", "--output", "json", "--config"])
+        .arg(&config_path)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["errors"], 7);
+    assert_eq!(report["warnings"], 2);
+    assert_eq!(report["findings"][0]["file"], template);
+    assert_eq!(report["findings"][0]["line"], 3);
+    assert_eq!(report["findings"][0]["severity"], "error");
+}
+
+#[test]
+fn test_check_stops_at_errors_that_prevent_loading() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+
+    fs::write(
+        &config_path,
+        "[[hooks]]\ntype = \"comand\"\ncommand = \"true\"\n\n[[dotfiles]]\nsource = \"missing\"\ntarget = \"~/.x\"\nwhen = \"os ==\"\n",
+    )
+    .unwrap();
+    mimic(&home, &["check"], &config_path, None)
+        .code(1)
+        .stdout(predicate::str::contains(
+            ":2: error: Unknown hook type 'comand'; did you mean 'command'?",
+        ))
+        .stdout(predicate::str::contains(
+            ":8: error: Invalid `when` condition",
+        ))
+        .stdout(predicate::str::contains("source not found").not());
+
+    fs::write(&config_path, "[variables\nname = 1\n").unwrap();
+    mimic(&home, &["check"], &config_path, None)
+        .code(1)
+        .stdout(predicate::str::contains(":1: error: Invalid TOML"));
+
+    fs::write(
+        &config_path,
+        "[variables]\nname = \"Jo\"\n\n[[dotfiles]]\nsource = \"mimic.toml\"\ntarget = \"~/.x\"\n",
+    )
+    .unwrap();
+    mimic(&home, &["check"], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains("No problems found"));
+}
+
+#[test]
+fn test_check_reports_every_independent_problem() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    fs::write(temp_dir.path().join("zshrc"), "export EDITOR=nvim\n").unwrap();
+    fs::write(
+        temp_dir.path().join("gitconfig.tmpl"),
+        "{{#if variables.signing_key}}signingkey = {{variables.signing_key}}{{/if}}\nname = {{variables.name}}\n",
+    )
+    .unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+    fs::write(
+        &config_path,
+        r#"[[dotfiles]]
+source = "zshrc"
+target = "~/.zshrc"
+
+[[dotfiles]]
+source = "gitconfig.tmpl"
+target = "~/.zshrc"
+
+[hosts.work]
+inherits = "laptop"
+"#,
+    )
+    .unwrap();
+
+    // The unknown `inherits` doesn't hide the target and template checks,
+    // and testing an optional variable with #if is fine
+    mimic(&home, &["check"], &config_path, None)
+        .code(1)
+        .stdout(predicate::str::contains(
+            ":10: error: Host 'work' inherits from unknown host 'laptop'",
+        ))
+        .stdout(predicate::str::contains(
+            ":5: error: Target ~/.zshrc is also deployed",
+        ))
+        .stdout(predicate::str::contains(
+            "gitconfig.tmpl:2: error: Template variable 'variables.name' is not defined",
+        ))
+        .stdout(predicate::str::contains("signing_key").not());
+}
@@ -1,11 +1,11 @@
-use assert_cmd::Command;
+use assert_cmd::cargo::cargo_bin_cmd;
 use predicates::prelude::*;
 use std::fs;
 use tempfile::TempDir;
 
 #[test]
 fn test_cli_help() {
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("--help");
 
     cmd.assert()
@@ -19,7 +19,7 @@ fn test_cli_help() {
 
 #[test]
 fn test_cli_version() {
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("--version");
 
     cmd.assert()
@@ -29,7 +29,7 @@ fn test_cli_version() {
 
 #[test]
 fn test_cli_diff_missing_config() {
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("diff").arg("--config").arg("/nonexistent.toml");
 
     cmd.assert()
@@ -61,7 +61,7 @@ target = "{}/.vimrc"
     );
     fs::write(&config_path, config_content).unwrap();
 
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("diff").arg("--config").arg(config_path);
 
     cmd.assert()
@@ -96,7 +96,7 @@ target = "{}"
     );
     fs::write(&config_path, config_content).unwrap();
 
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("diff").arg("--config").arg(config_path);
 
     cmd.assert().success().stdout(predicate::str::contains("✓"));
@@ -123,7 +123,7 @@ target = "{}/.zshrc"
     );
     fs::write(&config_path, config_content).unwrap();
 
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -161,7 +161,7 @@ target = "{}"
     );
     fs::write(&config_path, config_content).unwrap();
 
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -192,7 +192,7 @@ fn test_cli_status_no_state() {
     let temp_dir = TempDir::new().unwrap();
     let state_path = temp_dir.path().join("nonexistent_state.toml");
 
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("--state").arg(&state_path).arg("status");
 
     cmd.assert()
@@ -205,7 +205,7 @@ fn test_cli_undo_nothing_to_undo() {
     let temp_dir = TempDir::new().unwrap();
     let state_path = temp_dir.path().join("nonexistent_state.toml");
 
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("--state").arg(&state_path).arg("undo");
 
     cmd.assert()
@@ -215,7 +215,7 @@ fn test_cli_undo_nothing_to_undo() {
 
 #[test]
 fn test_cli_apply_missing_config() {
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("apply").arg("--config").arg("/nonexistent.toml");
 
     cmd.assert()
@@ -225,7 +225,7 @@ fn test_cli_apply_missing_config() {
 
 #[test]
 fn test_cli_global_flags() {
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.arg("--help");
 
     cmd.assert()
@@ -259,9 +259,140 @@ target = "{}/.gitconfig"
     );
     fs::write(&cwd_config, config_content).unwrap();
 
-    let mut cmd = Command::cargo_bin("mimic").unwrap();
+    let mut cmd = cargo_bin_cmd!("mimic");
     cmd.current_dir(temp_dir.path()).arg("diff");
 
     // Should succeed by finding mimic.toml in CWD
     cmd.assert().success();
 }
+
+#[test]
+fn test_cli_roles_list_and_show() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+    fs::write(
+        &config_path,
+        r#"
+[[roles.work.dotfiles]]
+source = "work/gitconfig"
+target = "~/.gitconfig"
+
+[[roles.work.packages.homebrew]]
+name = "slack"
+type = "cask"
+
+[roles.work.variables]
+email = "me@work.example"
+
+[hosts.laptop]
+roles = ["work", "mac"]
+"#,
+    )
+    .unwrap();
+
+    let roles = |args: &[&str]| {
+        cargo_bin_cmd!("mimic")
+            .env("HOME", temp_dir.path())
+            .args(args)
+            .arg("--config")
+            .arg(&config_path)
+            .assert()
+    };
+
+    roles(&["roles", "list"])
+        .success()
+        .stdout(predicate::str::contains(
+            "work (1 dotfile, 1 package, 1 variable) hosts: laptop",
+        ))
+        .stdout(predicate::str::contains("mac (tag only) hosts: laptop"));
+
+    roles(&["roles", "show", "work"])
+        .success()
+        .stdout(predicate::str::contains("email = me@work.example"))
+        .stdout(predicate::str::contains("work/gitconfig → ~/.gitconfig"))
+        .stdout(predicate::str::contains("slack (cask)"));
+
+    roles(&["hosts", "show", "laptop"])
+        .success()
+        .stdout(predicate::str::contains("slack (cask) (from role work)"));
+
+    roles(&["roles", "show", "gaming"])
+        .failure()
+        .stderr(predicate::str::contains("Role 'gaming' not found"));
+}
+
+#[test]
+fn test_cli_render_typed_variables() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+    fs::write(
+        &config_path,
+        r#"
+[variables]
+signing = true
+
+[[variables.identities]]
+name = "work"
+email = "me@corp.example"
+
+[[variables.identities]]
+name = "home"
+email = "me@home.example"
+"#,
+    )
+    .unwrap();
+    let template = temp_dir.path().join("gitconfig.tmpl");
+    fs::write(
+        &template,
+        "{{#each variables.identities}}{{name}}={{email}};{{/each}}{{#if variables.signing}}sign{{/if}}",
+    )
+    .unwrap();
+
+    let render = |args: &[&str]| {
+        cargo_bin_cmd!("mimic")
+            .env("HOME", temp_dir.path())
+            .arg("render")
+            .args(args)
+            .arg("--config")
+            .arg(&config_path)
+            .assert()
+    };
+
+    render(&[template.to_str().unwrap()])
+        .success()
+        .stdout(predicate::str::contains(
+            "work=me@corp.example;home=me@home.example;sign",
+        ));
+
+    render(&[])
+        .success()
+        .stdout(predicate::str::contains("signing = true"))
+        .stdout(predicate::str::contains("[1]"))
+        .stdout(predicate::str::contains("email = \"me@home.example\""));
+}
+
+#[test]
+fn test_cli_schema() {
+    let output = cargo_bin_cmd!("mimic").arg("schema").output().unwrap();
+    assert!(output.status.success());
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    let definitions = &schema["definitions"];
+    let hook_types = definitions["Hook"]["properties"]["type"]["enum"]
+        .as_array()
+        .unwrap();
+    assert!(hook_types.contains(&serde_json::json!("command")));
+    assert!(hook_types.contains(&serde_json::json!("uv-python")));
+    assert_eq!(
+        definitions["FailureMode"]["enum"],
+        serde_json::json!(["continue", "fail"])
+    );
+    for (name, property) in definitions["Dotfile"]["properties"].as_object().unwrap() {
+        assert!(
+            property["description"].as_str().is_some(),
+            "Dotfile.{} has no description",
+            name
+        );
+    }
+    assert_eq!(schema["properties"]["hosts"]["type"], "object");
+}
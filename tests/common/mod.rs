@@ -0,0 +1,28 @@
+//! Helpers shared by the integration tests.
+
+#![allow(dead_code)]
+
+use assert_cmd::Command;
+use assert_cmd::cargo::cargo_bin_cmd;
+use std::path::Path;
+
+/// `mimic <args> --config <config> [--state <state>]` with `HOME` set, for
+/// tests that need to set more on the command before running it.
+pub fn command(home: &Path, args: &[&str], config: &Path, state: Option<&Path>) -> Command {
+    let mut cmd = cargo_bin_cmd!("mimic");
+    cmd.env("HOME", home).args(args).arg("--config").arg(config);
+    if let Some(state) = state {
+        cmd.arg("--state").arg(state);
+    }
+    cmd
+}
+
+/// Run `mimic <args> --config <config> [--state <state>]` with `HOME` set.
+pub fn mimic(
+    home: &Path,
+    args: &[&str],
+    config: &Path,
+    state: Option<&Path>,
+) -> assert_cmd::assert::Assert {
+    command(home, args, config, state).assert()
+}
@@ -0,0 +1,169 @@
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+mod common;
+
+fn mimic(
+    home: &Path,
+    args: &[&str],
+    config: &Path,
+    state: &Path,
+    flag: Option<&str>,
+) -> assert_cmd::assert::Assert {
+    let mut cmd = common::command(home, args, config, Some(state));
+    cmd.env_remove("MIMIC_TEST_FLAG");
+    if let Some(flag) = flag {
+        cmd.env("MIMIC_TEST_FLAG", flag);
+    }
+    cmd.assert()
+}
+
+#[test]
+fn test_when_is_evaluated_by_diff_apply_and_status() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let source_dir = temp_dir.path().join("dotfiles");
+    fs::create_dir_all(&source_dir).unwrap();
+    fs::write(source_dir.join("gitconfig"), "[user]\n").unwrap();
+    fs::write(source_dir.join("plan9rc"), "rc\n").unwrap();
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[dotfiles]]
+source = "{src}/gitconfig"
+target = "~/.gitconfig"
+when = "env.MIMIC_TEST_FLAG == 'on' && exists('{src}')"
+
+[[dotfiles]]
+source = "{src}/plan9rc"
+target = "~/.plan9rc"
+when = "os == 'plan9'"
+"#,
+            src = source_dir.display()
+        ),
+    )
+    .unwrap();
+
+    mimic(&home, &["diff"], &config_path, &state_path, Some("on"))
+        .success()
+        .stdout(predicate::str::contains(".gitconfig"))
+        .stdout(predicate::str::contains(".plan9rc").not());
+
+    mimic(
+        &home,
+        &["apply", "--yes", "--verbose"],
+        &config_path,
+        &state_path,
+        Some("on"),
+    )
+    .success()
+    .stdout(predicate::str::contains("condition not met"));
+    assert!(home.join(".gitconfig").is_symlink());
+    assert!(!home.join(".plan9rc").exists());
+
+    mimic(&home, &["status"], &config_path, &state_path, Some("on")).success();
+
+    // The link is intact, but the condition that put it there no longer holds
+    mimic(&home, &["status"], &config_path, &state_path, None)
+        .code(1)
+        .stdout(predicate::str::contains("condition no longer met"));
+}
+
+#[test]
+fn test_invalid_when_fails_config_load() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    fs::write(
+        &config_path,
+        r#"
+[[dotfiles]]
+source = "zshrc"
+target = "~/.zshrc"
+when = "os == 'linux' &&"
+"#,
+    )
+    .unwrap();
+
+    mimic(&home, &["diff"], &config_path, &state_path, None)
+        .code(1)
+        .stderr(predicate::str::contains("Invalid when expression"))
+        .stderr(predicate::str::contains("~/.zshrc"));
+}
+
+#[test]
+fn test_status_reads_variables_without_side_effects() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let source = temp_dir.path().join("gitconfig");
+    fs::write(&source, "[user]\n").unwrap();
+    let marker = temp_dir.path().join("marker");
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[variables]
+flag.from = {{ env = "MIMIC_TEST_FLAG" }}
+machine.from = {{ command = "touch {marker} && echo work" }}
+
+[[dotfiles]]
+source = "{source}"
+target = "~/.gitconfig"
+when = "variables.flag == 'on'"
+
+[[dotfiles]]
+source = "{source}"
+target = "~/.work-gitconfig"
+when = "variables.machine == 'work'"
+"#,
+            marker = marker.display(),
+            source = source.display()
+        ),
+    )
+    .unwrap();
+
+    mimic(
+        &home,
+        &["apply", "--yes"],
+        &config_path,
+        &state_path,
+        Some("on"),
+    )
+    .success();
+    assert!(home.join(".work-gitconfig").is_symlink());
+    fs::remove_file(&marker).unwrap();
+
+    // The command isn't run and the unset variable is reported, not ignored
+    mimic(&home, &["status"], &config_path, &state_path, None)
+        .success()
+        .stderr(predicate::str::contains(format!(
+            "Condition on {} not checked: Variable 'flag' reads $MIMIC_TEST_FLAG",
+            home.join(".gitconfig").display()
+        )))
+        .stderr(predicate::str::contains(format!(
+            "Condition on {} not checked: Variable 'machine' comes from command",
+            home.join(".work-gitconfig").display()
+        )));
+    assert!(!marker.exists());
+    assert!(!temp_dir.path().join("data.toml").exists());
+
+    mimic(&home, &["status"], &config_path, &state_path, Some("off"))
+        .code(1)
+        .stdout(predicate::str::contains(
+            "condition no longer met: variables.flag == 'on'",
+        ));
+}
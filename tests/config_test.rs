@@ -30,9 +30,9 @@ fn test_parse_valid_config() {
     assert_eq!(config.variables.len(), 2);
     assert_eq!(
         config.variables.get("email"),
-        Some(&"user@example.com".to_string())
+        Some(&"user@example.com".into())
     );
-    assert_eq!(config.variables.get("name"), Some(&"John Doe".to_string()));
+    assert_eq!(config.variables.get("name"), Some(&"John Doe".into()));
 
     assert_eq!(config.dotfiles.len(), 2);
     assert_eq!(config.dotfiles[0].source, "zsh/zshrc");
@@ -203,7 +203,7 @@ fn test_parse_config_from_file() {
 
     assert_eq!(
         config.variables.get("email"),
-        Some(&"test@example.com".to_string())
+        Some(&"test@example.com".into())
     );
     assert_eq!(config.dotfiles.len(), 1);
 
@@ -507,3 +507,283 @@ target = "~/.gitconfig"
         );
     }
 }
+
+#[test]
+fn test_include_globs_relative_to_including_file() {
+    use std::fs;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::create_dir_all(repo.join("hosts/laptop")).unwrap();
+
+    fs::write(
+        repo.join("mimic.toml"),
+        r#"
+include = ["packages.toml", "hosts/*.toml"]
+
+[variables]
+editor = "nvim"
+"#,
+    )
+    .unwrap();
+    fs::write(
+        repo.join("packages.toml"),
+        r#"
+[variables]
+editor = "vim"
+shell = "zsh"
+
+[packages]
+brew = ["git"]
+"#,
+    )
+    .unwrap();
+    fs::write(
+        repo.join("hosts/laptop.toml"),
+        r#"
+include = ["laptop/*.toml"]
+
+[hosts.laptop]
+roles = ["personal"]
+
+[[hosts.laptop.dotfiles]]
+source = "zshrc"
+target = "~/.zshrc"
+"#,
+    )
+    .unwrap();
+    fs::write(
+        repo.join("hosts/laptop/gitconfig.toml"),
+        r#"
+[[dotfiles]]
+source = "gitconfig"
+target = "~/.gitconfig"
+"#,
+    )
+    .unwrap();
+
+    let config = Config::from_file(repo.join("mimic.toml")).unwrap();
+
+    // The including file overrides what it includes
+    assert_eq!(config.variables["editor"], "nvim");
+    assert_eq!(config.variables["shell"], "zsh");
+    assert_eq!(config.packages.normalized().homebrew[0].name, "git");
+
+    // Sources resolve relative to the file that declares them
+    let laptop = &config.hosts["laptop"];
+    assert_eq!(laptop.roles, vec!["personal"]);
+    assert!(
+        laptop.dotfiles[0].source.ends_with("hosts/zshrc"),
+        "{}",
+        laptop.dotfiles[0].source
+    );
+    assert!(
+        config.dotfiles[0]
+            .source
+            .ends_with("hosts/laptop/gitconfig"),
+        "{}",
+        config.dotfiles[0].source
+    );
+}
+
+#[test]
+fn test_include_errors_name_file_and_line() {
+    use std::fs;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::write(
+        repo.join("mimic.toml"),
+        "# main config\ninclude = [\n  \"packages.toml\",\n  \"hooks.toml\",\n]\n",
+    )
+    .unwrap();
+    fs::write(repo.join("packages.toml"), "[packages]\nbrew = [\"git\"]\n").unwrap();
+    fs::write(
+        repo.join("hooks.toml"),
+        "[[dotfiles]]\nsource = \"a\"\ntarget = 42\n",
+    )
+    .unwrap();
+
+    let err = Config::from_file(repo.join("mimic.toml"))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("hooks.toml"), "{}", err);
+    assert!(err.contains("mimic.toml:4"), "{}", err);
+    assert!(err.contains("line 3"), "{}", err);
+
+    fs::write(repo.join("hooks.toml"), "include = [\"mimic.toml\"]\n").unwrap();
+    let err = Config::from_file(repo.join("mimic.toml"))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Cyclic"), "{}", err);
+
+    fs::remove_file(repo.join("hooks.toml")).unwrap();
+    let err = Config::from_file(repo.join("mimic.toml"))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Included config not found"), "{}", err);
+    assert!(err.contains("mimic.toml:4"), "{}", err);
+}
+
+#[test]
+fn test_typed_variables_deep_merge_and_append() {
+    use serde_json::json;
+    use std::fs;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::write(
+        repo.join("mimic.toml"),
+        r#"
+include = ["team.toml"]
+
+[variables]
+editors = ["nvim"]
+"paths+" = ["~/bin"]
+
+[variables.git]
+email = "me@example.com"
+
+[hosts.laptop]
+roles = ["work"]
+
+[hosts.laptop.variables]
+"paths+" = ["/opt/work/bin"]
+
+[hosts.laptop.variables.git]
+signing = true
+"#,
+    )
+    .unwrap();
+    fs::write(
+        repo.join("team.toml"),
+        r#"
+[variables]
+editors = ["vim", "emacs"]
+paths = ["/usr/local/bin"]
+retries = 3
+
+[variables.git]
+name = "Team Member"
+email = "team@example.com"
+"#,
+    )
+    .unwrap();
+
+    let config = Config::from_file(repo.join("mimic.toml")).unwrap();
+    assert_eq!(config.variables["editors"], json!(["nvim"]));
+    assert_eq!(
+        config.variables["paths"],
+        json!(["/usr/local/bin", "~/bin"])
+    );
+    assert_eq!(config.variables["retries"], json!(3));
+    assert!(!config.variables.contains_key("paths+"));
+
+    let merged = config.with_host("laptop").unwrap();
+    assert_eq!(
+        merged.variables["paths"],
+        json!(["/usr/local/bin", "~/bin", "/opt/work/bin"])
+    );
+    assert_eq!(
+        merged.variables["git"],
+        json!({ "name": "Team Member", "email": "me@example.com", "signing": true })
+    );
+}
+
+#[test]
+fn test_including_file_removes_entries_it_includes() {
+    use std::fs;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::write(
+        repo.join("team.toml"),
+        r#"
+[packages]
+brew = ["git", "docker"]
+
+[[dotfiles]]
+source = "tmux.conf"
+target = "~/.tmux.conf"
+
+[[merges]]
+source = "vscode.json"
+target = "~/.config/Code/User/settings.json"
+"#,
+    )
+    .unwrap();
+    fs::write(
+        repo.join("mimic.toml"),
+        r#"
+include = ["team.toml"]
+exclude_packages = ["docker"]
+exclude_dotfiles = ["~/.tmux.conf"]
+
+[[merges]]
+target = "~/.config/Code/User/settings.json"
+enabled = false
+
+[[dotfiles]]
+source = "zshrc"
+target = "~/.zshrc"
+"#,
+    )
+    .unwrap();
+
+    let config = Config::from_file(repo.join("mimic.toml")).unwrap();
+    let packages: Vec<String> = config
+        .packages
+        .normalized()
+        .homebrew
+        .into_iter()
+        .map(|p| p.name)
+        .collect();
+    assert_eq!(packages, vec!["git"]);
+    assert_eq!(config.dotfiles.len(), 1);
+    assert_eq!(config.dotfiles[0].target, "~/.zshrc");
+    assert!(config.merges.is_empty());
+    assert_eq!(config.removed.len(), 3);
+
+    // Only a disabled entry may leave out its source
+    let error = Config::from_str("[[dotfiles]]\ntarget = \"~/.zshrc\"\n").unwrap_err();
+    assert!(error.to_string().contains("has no source"), "{}", error);
+}
+
+#[test]
+fn test_excluding_an_entry_of_the_same_file_is_not_a_removal() {
+    use std::fs;
+    use tempfile::TempDir;
+
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path();
+    fs::write(repo.join("team.toml"), "[packages]\nbrew = [\"git\"]\n").unwrap();
+    fs::write(
+        repo.join("mimic.toml"),
+        r#"
+include = ["team.toml"]
+exclude_dotfiles = ["~/.b"]
+exclude_packages = ["git"]
+
+[[dotfiles]]
+source = "b"
+target = "~/.b"
+
+[hosts.default]
+"#,
+    )
+    .unwrap();
+
+    let config = Config::from_file(repo.join("mimic.toml")).unwrap();
+    assert_eq!(config.dotfiles.len(), 1);
+    let removals: Vec<String> = config
+        .host_removals("default")
+        .unwrap()
+        .into_iter()
+        .map(|(_, removal)| removal.to_string())
+        .collect();
+    assert_eq!(removals, vec!["package git"]);
+}
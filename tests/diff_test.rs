@@ -1,6 +1,8 @@
-use mimic::config::{Config, Dotfile, Package, Packages};
+use mimic::config::{Config, DeployMode, Dotfile, LinkStrategy, Package, Packages};
 use mimic::diff::{Change, DiffEngine};
 use mimic::installer::HomebrewManager;
+use mimic::linker::copy_file;
+use mimic::state::State;
 use std::collections::HashMap;
 use std::fs;
 use std::os::unix::fs::symlink;
@@ -19,18 +21,34 @@ fn test_diff_detects_new_dotfile() {
         source: source.to_str().unwrap().to_string(),
         target: target.to_str().unwrap().to_string(),
         template: false,
+        encrypted: false,
+        mode: Default::default(),
+        link: Default::default(),
+        permissions: None,
+        dir_permissions: None,
         only_roles: None,
         skip_roles: None,
+        when: None,
+        enabled: true,
     };
 
     let config = Config {
         extends: vec![],
+        include: Vec::new(),
         variables: Default::default(),
         dotfiles: vec![dotfile],
+        blocks: vec![],
+        merges: vec![],
+        encryption: Default::default(),
         packages: Packages::default(),
         hosts: HashMap::new(),
+        roles: HashMap::new(),
         secrets: HashMap::new(),
         mise: Default::default(),
+        exclude_dotfiles: vec![],
+        exclude_packages: vec![],
+        exclude_hooks: vec![],
+        removed: vec![],
         hooks: vec![],
     };
 
@@ -62,18 +80,34 @@ fn test_diff_symlink_already_correct() {
         source: source.to_str().unwrap().to_string(),
         target: target.to_str().unwrap().to_string(),
         template: false,
+        encrypted: false,
+        mode: Default::default(),
+        link: Default::default(),
+        permissions: None,
+        dir_permissions: None,
         only_roles: None,
         skip_roles: None,
+        when: None,
+        enabled: true,
     };
 
     let config = Config {
         extends: vec![],
+        include: Vec::new(),
         variables: Default::default(),
         dotfiles: vec![dotfile],
+        blocks: vec![],
+        merges: vec![],
+        encryption: Default::default(),
         packages: Packages::default(),
         hosts: HashMap::new(),
+        roles: HashMap::new(),
         secrets: HashMap::new(),
         mise: Default::default(),
+        exclude_dotfiles: vec![],
+        exclude_packages: vec![],
+        exclude_hooks: vec![],
+        removed: vec![],
         hooks: vec![],
     };
 
@@ -106,18 +140,34 @@ fn test_diff_wrong_symlink_target() {
         source: source.to_str().unwrap().to_string(),
         target: target.to_str().unwrap().to_string(),
         template: false,
+        encrypted: false,
+        mode: Default::default(),
+        link: Default::default(),
+        permissions: None,
+        dir_permissions: None,
         only_roles: None,
         skip_roles: None,
+        when: None,
+        enabled: true,
     };
 
     let config = Config {
         extends: vec![],
+        include: Vec::new(),
         variables: Default::default(),
         dotfiles: vec![dotfile],
+        blocks: vec![],
+        merges: vec![],
+        encryption: Default::default(),
         packages: Packages::default(),
         hosts: HashMap::new(),
+        roles: HashMap::new(),
         secrets: HashMap::new(),
         mise: Default::default(),
+        exclude_dotfiles: vec![],
+        exclude_packages: vec![],
+        exclude_hooks: vec![],
+        removed: vec![],
         hooks: vec![],
     };
 
@@ -148,18 +198,34 @@ fn test_diff_target_is_regular_file() {
         source: source.to_str().unwrap().to_string(),
         target: target.to_str().unwrap().to_string(),
         template: false,
+        encrypted: false,
+        mode: Default::default(),
+        link: Default::default(),
+        permissions: None,
+        dir_permissions: None,
         only_roles: None,
         skip_roles: None,
+        when: None,
+        enabled: true,
     };
 
     let config = Config {
         extends: vec![],
+        include: Vec::new(),
         variables: Default::default(),
         dotfiles: vec![dotfile],
+        blocks: vec![],
+        merges: vec![],
+        encryption: Default::default(),
         packages: Packages::default(),
         hosts: HashMap::new(),
+        roles: HashMap::new(),
         secrets: HashMap::new(),
         mise: Default::default(),
+        exclude_dotfiles: vec![],
+        exclude_packages: vec![],
+        exclude_hooks: vec![],
+        removed: vec![],
         hooks: vec![],
     };
 
@@ -183,22 +249,34 @@ fn test_diff_package_not_installed() {
         pkg_type: "formula".to_string(),
         only_roles: None,
         skip_roles: None,
+        when: None,
+        enabled: true,
     };
 
     let config = Config {
         extends: vec![],
+        include: Vec::new(),
         variables: Default::default(),
         dotfiles: vec![],
+        blocks: vec![],
+        merges: vec![],
+        encryption: Default::default(),
         packages: Packages {
             homebrew: vec![package],
             brew: Vec::new(),
             cask: Vec::new(),
+            tap: Vec::new(),
             zerobrew: Vec::new(),
             zb: Vec::new(),
         },
         hosts: HashMap::new(),
+        roles: HashMap::new(),
         secrets: HashMap::new(),
         mise: Default::default(),
+        exclude_dotfiles: vec![],
+        exclude_packages: vec![],
+        exclude_hooks: vec![],
+        removed: vec![],
         hooks: vec![],
     };
 
@@ -231,27 +309,50 @@ fn test_diff_multiple_changes() {
 
     let config = Config {
         extends: vec![],
+        include: Vec::new(),
         variables: Default::default(),
         dotfiles: vec![
             Dotfile {
                 source: source1.to_str().unwrap().to_string(),
                 target: target1.to_str().unwrap().to_string(),
                 template: false,
+                encrypted: false,
+                mode: Default::default(),
+                link: Default::default(),
+                permissions: None,
+                dir_permissions: None,
                 only_roles: None,
                 skip_roles: None,
+                when: None,
+                enabled: true,
             },
             Dotfile {
                 source: source2.to_str().unwrap().to_string(),
                 target: target2.to_str().unwrap().to_string(),
                 template: false,
+                encrypted: false,
+                mode: Default::default(),
+                link: Default::default(),
+                permissions: None,
+                dir_permissions: None,
                 only_roles: None,
                 skip_roles: None,
+                when: None,
+                enabled: true,
             },
         ],
+        blocks: vec![],
+        merges: vec![],
+        encryption: Default::default(),
         packages: Packages::default(),
         hosts: HashMap::new(),
+        roles: HashMap::new(),
         secrets: HashMap::new(),
         mise: Default::default(),
+        exclude_dotfiles: vec![],
+        exclude_packages: vec![],
+        exclude_hooks: vec![],
+        removed: vec![],
         hooks: vec![],
     };
 
@@ -286,18 +387,34 @@ fn test_diff_pretty_format() {
         source: source.to_str().unwrap().to_string(),
         target: target.to_str().unwrap().to_string(),
         template: false,
+        encrypted: false,
+        mode: Default::default(),
+        link: Default::default(),
+        permissions: None,
+        dir_permissions: None,
         only_roles: None,
         skip_roles: None,
+        when: None,
+        enabled: true,
     };
 
     let config = Config {
         extends: vec![],
+        include: Vec::new(),
         variables: Default::default(),
         dotfiles: vec![dotfile],
+        blocks: vec![],
+        merges: vec![],
+        encryption: Default::default(),
         packages: Packages::default(),
         hosts: HashMap::new(),
+        roles: HashMap::new(),
         secrets: HashMap::new(),
         mise: Default::default(),
+        exclude_dotfiles: vec![],
+        exclude_packages: vec![],
+        exclude_hooks: vec![],
+        removed: vec![],
         hooks: vec![],
     };
 
@@ -310,3 +427,103 @@ fn test_diff_pretty_format() {
     // Should contain colored output (checking for presence of arrows/symbols)
     assert!(formatted.contains("→") || formatted.contains("+"));
 }
+
+fn copy_dotfile(source: &std::path::Path, target: &std::path::Path) -> Dotfile {
+    Dotfile {
+        source: source.to_str().unwrap().to_string(),
+        target: target.to_str().unwrap().to_string(),
+        template: false,
+        encrypted: false,
+        mode: DeployMode::Copy,
+        link: Default::default(),
+        permissions: None,
+        dir_permissions: None,
+        only_roles: None,
+        skip_roles: None,
+        when: None,
+        enabled: true,
+    }
+}
+
+#[test]
+fn test_diff_copy_mode_reports_modified_in_place() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("ssh_config");
+    let target = temp.path().join("target_ssh_config");
+
+    fs::write(&source, "Host *").unwrap();
+    let mut state = State::new();
+    copy_file(&source, &target, &mut state).unwrap();
+
+    let engine = DiffEngine::new().with_state(&state);
+    let dotfile = copy_dotfile(&source, &target);
+    assert!(matches!(
+        engine.diff_dotfile(&dotfile).unwrap(),
+        Change::AlreadyCorrect { .. }
+    ));
+
+    fs::write(&target, "Host * edited").unwrap();
+    match engine.diff_dotfile(&dotfile).unwrap() {
+        Change::Modify { reason, .. } => assert_eq!(reason, "modified in place"),
+        other => panic!("Expected Change::Modify, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_diff_copy_mode_reports_source_changed() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("ssh_config");
+    let target = temp.path().join("target_ssh_config");
+
+    fs::write(&source, "Host *").unwrap();
+    let mut state = State::new();
+    copy_file(&source, &target, &mut state).unwrap();
+
+    fs::write(&source, "Host github.com").unwrap();
+
+    let engine = DiffEngine::new().with_state(&state);
+    match engine
+        .diff_dotfile(&copy_dotfile(&source, &target))
+        .unwrap()
+    {
+        Change::Modify { reason, .. } => assert_eq!(reason, "source changed"),
+        other => panic!("Expected Change::Modify, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_diff_tree_link_reports_each_file() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("foo");
+    let target = temp.path().join("target_foo");
+
+    fs::create_dir_all(source.join("sub")).unwrap();
+    fs::write(source.join("a.conf"), "a").unwrap();
+    fs::write(source.join("sub/b.conf"), "b").unwrap();
+    fs::create_dir_all(&target).unwrap();
+    symlink(source.join("a.conf"), target.join("a.conf")).unwrap();
+
+    let dotfile = Dotfile {
+        source: source.to_str().unwrap().to_string(),
+        target: target.to_str().unwrap().to_string(),
+        template: false,
+        encrypted: false,
+        mode: Default::default(),
+        link: LinkStrategy::Tree,
+        permissions: None,
+        dir_permissions: None,
+        only_roles: None,
+        skip_roles: None,
+        when: None,
+        enabled: true,
+    };
+
+    let changes = DiffEngine::new().diff_tree_dotfile(&dotfile).unwrap();
+
+    assert_eq!(changes.len(), 2);
+    assert!(matches!(changes[0], Change::AlreadyCorrect { .. }));
+    match &changes[1] {
+        Change::Add { description, .. } => assert!(description.contains("sub/b.conf")),
+        other => panic!("Expected Change::Add, got {:?}", other),
+    }
+}
@@ -0,0 +1,108 @@
+use predicates::prelude::*;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use tempfile::TempDir;
+
+mod common;
+use common::mimic;
+
+#[test]
+fn test_encrypt_then_apply_decrypts_privately() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let repo = temp_dir.path().join("repo");
+    fs::create_dir_all(&repo).unwrap();
+
+    let plaintext = repo.join("npmrc");
+    fs::write(&plaintext, "//registry.npmjs.org/:_authToken=s3cr3t\n").unwrap();
+
+    let target = home.join(".npmrc");
+    let config_path = repo.join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "[[dotfiles]]\nsource = \"npmrc.age\"\ntarget = \"{}\"\nencrypted = true\n",
+            target.display()
+        ),
+    )
+    .unwrap();
+
+    let plaintext_arg = plaintext.to_string_lossy().to_string();
+    mimic(
+        &home,
+        &["encrypt", &plaintext_arg],
+        &config_path,
+        Some(&state_path),
+    )
+    .success()
+    .stdout(predicate::str::contains("Generated a new age key"));
+
+    let encrypted = repo.join("npmrc.age");
+    assert!(!plaintext.exists());
+    let ciphertext = fs::read(&encrypted).unwrap();
+    assert!(!String::from_utf8_lossy(&ciphertext).contains("s3cr3t"));
+    assert_eq!(
+        fs::metadata(home.join(".mimic/age.key"))
+            .unwrap()
+            .permissions()
+            .mode()
+            & 0o777,
+        0o600
+    );
+
+    let encrypted_arg = encrypted.to_string_lossy().to_string();
+    mimic(
+        &home,
+        &["decrypt", &encrypted_arg],
+        &config_path,
+        Some(&state_path),
+    )
+    .success()
+    .stdout("//registry.npmjs.org/:_authToken=s3cr3t\n");
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+    assert!(target.is_symlink());
+    let rendered = fs::read_link(&target).unwrap();
+    assert!(rendered.starts_with(home.join(".mimic/rendered")));
+    assert!(rendered.to_string_lossy().ends_with("_npmrc"));
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "//registry.npmjs.org/:_authToken=s3cr3t\n"
+    );
+    assert_eq!(
+        fs::metadata(&rendered).unwrap().permissions().mode() & 0o777,
+        0o600
+    );
+
+    mimic(&home, &["status"], &config_path, Some(&state_path)).success();
+    mimic(&home, &["diff"], &config_path, Some(&state_path))
+        .success()
+        .stdout(predicate::str::contains("~").not());
+}
+
+#[test]
+fn test_apply_without_identity_explains_fix() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let source = temp_dir.path().join("vpn.conf.age");
+    fs::write(&source, "not really encrypted").unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "[[dotfiles]]\nsource = \"{}\"\ntarget = \"{}\"\nencrypted = true\n",
+            source.display(),
+            home.join("vpn.conf").display()
+        ),
+    )
+    .unwrap();
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path))
+        .stderr(predicate::str::contains("No age identity"));
+    assert!(!home.join("vpn.conf").exists());
+}
@@ -0,0 +1,202 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn write_config(config_path: &Path, entries: &[(&Path, &Path)]) {
+    let mut content = String::new();
+    for (source, target) in entries {
+        content.push_str(&format!(
+            "[[dotfiles]]\nsource = \"{}\"\ntarget = \"{}\"\n\n",
+            source.display(),
+            target.display()
+        ));
+    }
+    fs::write(config_path, content).unwrap();
+}
+
+fn apply(config_path: &Path, state_path: &Path) {
+    cargo_bin_cmd!("mimic")
+        .arg("apply")
+        .arg("--config")
+        .arg(config_path)
+        .arg("--state")
+        .arg(state_path)
+        .arg("--yes")
+        .assert()
+        .success();
+}
+
+#[test]
+fn test_history_lists_each_apply() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    let source = temp_dir.path().join("zshrc");
+    let target = temp_dir.path().join(".zshrc");
+    fs::write(&source, "export A=1").unwrap();
+
+    write_config(&config_path, &[(&source, &target)]);
+    apply(&config_path, &state_path);
+    fs::remove_file(&target).unwrap();
+    apply(&config_path, &state_path);
+
+    cargo_bin_cmd!("mimic")
+        .arg("history")
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("#1"))
+        .stdout(predicate::str::contains("#2"))
+        .stdout(predicate::str::contains("latest"));
+}
+
+#[test]
+fn test_history_empty() {
+    let temp_dir = TempDir::new().unwrap();
+    let state_path = temp_dir.path().join("state.toml");
+
+    cargo_bin_cmd!("mimic")
+        .arg("history")
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("No generations recorded"));
+}
+
+#[test]
+fn test_rollback_restores_earlier_generation_after_undo() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    let zshrc = temp_dir.path().join("zshrc");
+    let vimrc = temp_dir.path().join("vimrc");
+    let zshrc_target = temp_dir.path().join(".zshrc");
+    let vimrc_target = temp_dir.path().join(".vimrc");
+
+    fs::write(&zshrc, "export A=1").unwrap();
+    fs::write(&vimrc, "set number").unwrap();
+    fs::write(&zshrc_target, "original zshrc").unwrap();
+
+    // Generation 1 manages .zshrc (backing up the original file)
+    write_config(&config_path, &[(&zshrc, &zshrc_target)]);
+    apply(&config_path, &state_path);
+
+    // Generation 2 manages .vimrc only
+    cargo_bin_cmd!("mimic")
+        .arg("undo")
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&zshrc_target).unwrap(), "original zshrc");
+    fs::remove_file(&zshrc_target).unwrap();
+    write_config(&config_path, &[(&vimrc, &vimrc_target)]);
+    apply(&config_path, &state_path);
+    assert!(vimrc_target.is_symlink());
+
+    cargo_bin_cmd!("mimic")
+        .arg("rollback")
+        .arg("1")
+        .arg("--state")
+        .arg(&state_path)
+        .arg("--yes")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Rolled back to generation 1"));
+
+    assert!(!vimrc_target.exists());
+    assert!(zshrc_target.is_symlink());
+    assert_eq!(fs::read_link(&zshrc_target).unwrap(), zshrc);
+
+    // The backup consumed by undo is back, so undo works again
+    cargo_bin_cmd!("mimic")
+        .arg("undo")
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("1 backups restored"));
+    assert_eq!(fs::read_to_string(&zshrc_target).unwrap(), "original zshrc");
+
+    cargo_bin_cmd!("mimic")
+        .arg("history")
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("rollback to #1"));
+}
+
+#[test]
+fn test_rollback_unknown_generation() {
+    let temp_dir = TempDir::new().unwrap();
+    let state_path = temp_dir.path().join("state.toml");
+
+    cargo_bin_cmd!("mimic")
+        .arg("rollback")
+        .arg("7")
+        .arg("--state")
+        .arg(&state_path)
+        .arg("--yes")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Generation 7 not found"));
+}
+
+#[test]
+fn test_rollback_keeps_edited_copies() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    let zshrc = temp_dir.path().join("zshrc");
+    let vimrc = temp_dir.path().join("vimrc");
+    let zshrc_target = temp_dir.path().join(".zshrc");
+    let vimrc_target = temp_dir.path().join(".vimrc");
+    fs::write(&zshrc, "export A=1").unwrap();
+    fs::write(&vimrc, "set number").unwrap();
+
+    let copy = |source: &Path, target: &Path| {
+        format!(
+            "[[dotfiles]]\nsource = \"{}\"\ntarget = \"{}\"\nmode = \"copy\"\n\n",
+            source.display(),
+            target.display()
+        )
+    };
+    fs::write(&config_path, copy(&zshrc, &zshrc_target)).unwrap();
+    apply(&config_path, &state_path);
+    fs::write(
+        &config_path,
+        copy(&zshrc, &zshrc_target) + &copy(&vimrc, &vimrc_target),
+    )
+    .unwrap();
+    apply(&config_path, &state_path);
+
+    fs::write(&zshrc_target, "export A=2").unwrap();
+    fs::write(&vimrc_target, "set relativenumber").unwrap();
+
+    cargo_bin_cmd!("mimic")
+        .arg("rollback")
+        .arg("1")
+        .arg("--state")
+        .arg(&state_path)
+        .arg("--yes")
+        .assert()
+        .stderr(predicate::str::contains(format!(
+            "Left {} in place: modified since it was applied",
+            zshrc_target.display()
+        )))
+        .stderr(predicate::str::contains(format!(
+            "Left {} in place: modified since it was applied",
+            vimrc_target.display()
+        )));
+
+    assert_eq!(fs::read_to_string(&zshrc_target).unwrap(), "export A=2");
+    assert_eq!(
+        fs::read_to_string(&vimrc_target).unwrap(),
+        "set relativenumber"
+    );
+}
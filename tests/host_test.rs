@@ -1,4 +1,4 @@
-use mimic::config::{Config, HostConfig, Packages};
+use mimic::config::{Config, HostConfig, MachineFacts, Packages};
 use std::collections::HashMap;
 
 #[test]
@@ -38,37 +38,52 @@ fn test_host_config_parsing() {
 #[test]
 fn test_host_merge() {
     let mut base_vars = HashMap::new();
-    base_vars.insert("editor".to_string(), "nvim".to_string());
-    base_vars.insert("shell".to_string(), "fish".to_string());
+    base_vars.insert("editor".to_string(), "nvim".into());
+    base_vars.insert("shell".to_string(), "fish".into());
 
     let mut host_vars = HashMap::new();
-    host_vars.insert("email".to_string(), "personal@example.com".to_string());
-    host_vars.insert("editor".to_string(), "vim".to_string());
+    host_vars.insert("email".to_string(), "personal@example.com".into());
+    host_vars.insert("editor".to_string(), "vim".into());
 
     let mut hosts = HashMap::new();
     hosts.insert(
         "personal".to_string(),
         HostConfig {
             inherits: None,
+            matcher: None,
             roles: vec!["personal".to_string()],
             variables: host_vars,
             dotfiles: vec![],
+            blocks: vec![],
+            merges: vec![],
             packages: Packages::default(),
             hooks: vec![],
             secrets: HashMap::new(),
             mise: Default::default(),
+            exclude_dotfiles: vec![],
+            exclude_packages: vec![],
+            exclude_hooks: vec![],
         },
     );
 
     let config = Config {
         extends: vec![],
+        include: Vec::new(),
         variables: base_vars,
         dotfiles: vec![],
+        blocks: vec![],
+        merges: vec![],
+        encryption: Default::default(),
         packages: Packages::default(),
         hosts,
+        roles: HashMap::new(),
         hooks: vec![],
         secrets: HashMap::new(),
         mise: Default::default(),
+        exclude_dotfiles: vec![],
+        exclude_packages: vec![],
+        exclude_hooks: vec![],
+        removed: vec![],
     };
 
     let merged = config.with_host("personal").unwrap();
@@ -85,12 +100,21 @@ fn test_host_merge() {
 fn test_host_not_found() {
     let config = Config {
         extends: vec![],
+        include: Vec::new(),
         variables: HashMap::new(),
         dotfiles: vec![],
+        blocks: vec![],
+        merges: vec![],
+        encryption: Default::default(),
         packages: Packages::default(),
         hosts: HashMap::new(),
+        roles: HashMap::new(),
         secrets: HashMap::new(),
         mise: Default::default(),
+        exclude_dotfiles: vec![],
+        exclude_packages: vec![],
+        exclude_hooks: vec![],
+        removed: vec![],
         hooks: vec![],
     };
 
@@ -295,7 +319,7 @@ fn test_complex_multi_host_scenario() {
         work.variables.get("http_proxy").unwrap(),
         "http://localhost:3128"
     );
-    assert!(work.variables.get("openai_model").is_none());
+    assert!(!work.variables.contains_key("openai_model"));
     assert_eq!(work.dotfiles.len(), 2);
     assert_eq!(work.packages.homebrew.len(), 2);
     assert!(work.packages.homebrew.iter().any(|p| p.name == "git"));
@@ -320,3 +344,384 @@ fn test_host_variable_override() {
     assert_eq!(merged.variables.get("editor").unwrap(), "emacs");
     assert_eq!(merged.variables.get("theme").unwrap(), "dark");
 }
+
+#[test]
+fn test_host_inherits_multi_level_chain() {
+    let config = Config::from_str(
+        r#"
+        [variables]
+        editor = "nvim"
+
+        [hosts.base-mac]
+        roles = ["mac"]
+        [hosts.base-mac.variables]
+        shell = "zsh"
+        [hosts.base-mac.packages]
+        brew = ["git"]
+
+        [hosts.laptop]
+        inherits = "base-mac"
+        roles = ["laptop"]
+        [hosts.laptop.variables]
+        email = "me@example.com"
+        [hosts.laptop.mise.tools]
+        node = "20"
+
+        [hosts.work-laptop]
+        inherits = "laptop"
+        roles = ["work", "mac"]
+        [hosts.work-laptop.variables]
+        email = "me@corp.com"
+        [hosts.work-laptop.packages]
+        brew = ["awscli"]
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.host_chain("work-laptop").unwrap(),
+        vec!["base-mac", "laptop", "work-laptop"]
+    );
+
+    let merged = config.with_host("work-laptop").unwrap();
+    assert_eq!(merged.variables.get("editor").unwrap(), "nvim");
+    assert_eq!(merged.variables.get("shell").unwrap(), "zsh");
+    assert_eq!(merged.variables.get("email").unwrap(), "me@corp.com");
+    assert_eq!(merged.mise.tools.get("node").unwrap(), "20");
+
+    let names: Vec<&str> = merged
+        .packages
+        .homebrew
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect();
+    assert_eq!(names, vec!["git", "awscli"]);
+
+    // Resolved roles are visible through the merged host entry
+    assert_eq!(
+        merged.hosts.get("work-laptop").unwrap().roles,
+        vec!["mac", "laptop", "work"]
+    );
+}
+
+#[test]
+fn test_host_inherits_cycle_detected() {
+    let config = Config::from_str(
+        r#"
+        [hosts.a]
+        inherits = "b"
+
+        [hosts.b]
+        inherits = "c"
+
+        [hosts.c]
+        inherits = "a"
+        "#,
+    )
+    .unwrap();
+
+    let err = config.with_host("a").unwrap_err().to_string();
+    assert!(err.contains("Cyclic host inherits"), "{}", err);
+    assert!(err.contains("- a\n  - b\n  - c\n  - a"), "{}", err);
+}
+
+#[test]
+fn test_host_inherits_unknown_parent() {
+    let config = Config::from_str(
+        r#"
+        [hosts.work]
+        inherits = "missing"
+        "#,
+    )
+    .unwrap();
+
+    let err = config.with_host("work").unwrap_err().to_string();
+    assert!(
+        err.contains("inherits from unknown host 'missing'"),
+        "{}",
+        err
+    );
+}
+
+fn machine(hostname: &str, os: &str, user: &str) -> MachineFacts {
+    MachineFacts {
+        hostname: hostname.to_string(),
+        os: os.to_string(),
+        user: user.to_string(),
+    }
+}
+
+#[test]
+fn test_host_selected_by_match_rules() {
+    let config = Config::from_str(
+        r#"
+        [hosts.laptop]
+        match = { hostname = "MBP-*", os = "macos" }
+
+        [hosts.ci]
+        match = { user = "runner" }
+
+        [hosts.workstation]
+        roles = ["dev"]
+    "#,
+    )
+    .unwrap();
+
+    let selected = config
+        .select_host(&machine("mbp-ab12cd", "macos", "me"))
+        .unwrap();
+    assert_eq!(selected.name, "laptop");
+    assert!(selected.reason.contains("hostname 'MBP-*' matches"));
+
+    let selected = config
+        .select_host(&machine("fv-az123", "linux", "runner"))
+        .unwrap();
+    assert_eq!(selected.name, "ci");
+
+    // A host named after the hostname wins over match rules
+    let selected = config
+        .select_host(&machine("workstation", "linux", "runner"))
+        .unwrap();
+    assert_eq!(selected.name, "workstation");
+
+    let err = config
+        .select_host(&machine("MBP-AB12CD", "linux", "me"))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("No host matches"), "{}", err);
+    assert!(err.contains("os 'macos' does not match 'linux'"), "{}", err);
+    assert!(
+        err.contains("ci: user 'runner' does not match 'me'"),
+        "{}",
+        err
+    );
+
+    let err = config
+        .select_host(&machine("MBP-1", "macos", "runner"))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Several hosts match"), "{}", err);
+    assert!(err.contains("laptop") && err.contains("ci"), "{}", err);
+}
+
+#[test]
+fn test_host_default_fallback() {
+    let config = Config::from_str(
+        r#"
+        [hosts.laptop]
+        match = { hostname = "MBP-*" }
+
+        [hosts.default]
+        roles = ["minimal"]
+    "#,
+    )
+    .unwrap();
+
+    let selected = config
+        .select_host(&machine("random-vm", "linux", "me"))
+        .unwrap();
+    assert_eq!(selected.name, "default");
+
+    let selected = config
+        .select_host(&machine("MBP-9", "macos", "me"))
+        .unwrap();
+    assert_eq!(selected.name, "laptop");
+
+    assert!(Config::from_str("[hosts.x]\nmatch = { hostnme = \"a\" }\n").is_err());
+}
+
+#[test]
+fn test_role_bundles_merge_between_base_and_host() {
+    let config_str = r#"
+        [variables]
+        editor = "vi"
+        email = "me@home.example"
+
+        [roles.work.variables]
+        email = "me@work.example"
+        vpn = "corp"
+
+        [[roles.work.dotfiles]]
+        source = "work/ssh_config"
+        target = "~/.ssh/config"
+
+        [[roles.work.packages.homebrew]]
+        name = "slack"
+        type = "cask"
+
+        [roles.work.mise.tools]
+        node = "20"
+
+        [roles.unused.variables]
+        editor = "emacs"
+
+        [hosts.base-laptop]
+        roles = ["work"]
+
+        [hosts.laptop]
+        inherits = "base-laptop"
+        roles = ["mac"]
+
+        [hosts.laptop.variables]
+        vpn = "home"
+
+        [hosts.laptop.mise.tools]
+        node = "22"
+    "#;
+
+    let config: Config = toml::from_str(config_str).unwrap();
+    let merged = config.with_host("laptop").unwrap();
+
+    assert_eq!(merged.variables["editor"], "vi");
+    assert_eq!(merged.variables["email"], "me@work.example");
+    assert_eq!(merged.variables["vpn"], "home");
+    assert_eq!(merged.dotfiles[0].target, "~/.ssh/config");
+    assert_eq!(merged.packages.homebrew[0].name, "slack");
+    assert_eq!(merged.mise.tools["node"], "22");
+
+    assert_eq!(config.role_names(), vec!["mac", "unused", "work"]);
+    assert_eq!(
+        config.hosts_with_role("work"),
+        vec!["base-laptop", "laptop"]
+    );
+    assert!(config.hosts_with_role("unused").is_empty());
+}
+
+#[test]
+fn test_roles_and_hosts_remove_inherited_entries() {
+    let config_str = r#"
+        [[dotfiles]]
+        source = "tmux.conf"
+        target = "~/.tmux.conf"
+
+        [[dotfiles]]
+        source = "zshrc"
+        target = "~/.zshrc"
+
+        [[blocks]]
+        name = "path"
+        target = "~/.bashrc"
+        content = "export PATH=$HOME/bin:$PATH"
+
+        [packages]
+        brew = ["git", "htop"]
+        cask = ["slack"]
+
+        [[hooks]]
+        type = "command"
+        name = "setup"
+        command = "true"
+        on_failure = "continue"
+
+        [roles.minimal]
+        exclude_packages = ["slack"]
+        exclude_hooks = ["setup"]
+
+        [hosts.work]
+        roles = ["minimal"]
+        exclude_dotfiles = ["$HOME/.tmux.conf"]
+
+        [[hosts.work.blocks]]
+        name = "path"
+        target = "~/.bashrc"
+        enabled = false
+
+        [[hosts.work.packages.homebrew]]
+        name = "htop"
+        type = "formula"
+        enabled = false
+
+        [hosts.laptop]
+        inherits = "work"
+
+        [[hosts.laptop.dotfiles]]
+        source = "laptop/tmux.conf"
+        target = "~/.tmux.conf"
+
+        [[hosts.laptop.dotfiles]]
+        target = "~/.zshrc"
+        enabled = false
+    "#;
+
+    let config = Config::from_str(config_str).unwrap();
+
+    let work = config.with_host("work").unwrap();
+    let targets: Vec<&str> = work.dotfiles.iter().map(|d| d.target.as_str()).collect();
+    assert_eq!(targets, vec!["~/.zshrc"]);
+    assert!(work.blocks.is_empty());
+    let packages: Vec<&str> = work
+        .packages
+        .homebrew
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect();
+    assert_eq!(packages, vec!["git"]);
+    assert!(work.hooks.is_empty());
+
+    // A host further down the chain can add back what its parent removed
+    let laptop = config.with_host("laptop").unwrap();
+    let targets: Vec<&str> = laptop.dotfiles.iter().map(|d| d.target.as_str()).collect();
+    assert_eq!(targets, vec!["~/.tmux.conf"]);
+    assert_eq!(laptop.dotfiles[0].source, "laptop/tmux.conf");
+
+    let removals: Vec<(String, String)> = config
+        .host_removals("laptop")
+        .unwrap()
+        .into_iter()
+        .map(|(origin, removal)| (origin, format!("{} ({})", removal, removal.by)))
+        .collect();
+    assert_eq!(
+        removals,
+        vec![
+            (
+                "role minimal".into(),
+                "package slack (exclude_packages)".into()
+            ),
+            ("role minimal".into(), "hook setup (exclude_hooks)".into()),
+            (
+                "work".into(),
+                "dotfile $HOME/.tmux.conf (exclude_dotfiles)".into()
+            ),
+            (
+                "work".into(),
+                "block path in ~/.bashrc (enabled = false)".into()
+            ),
+            ("work".into(), "package htop (enabled = false)".into()),
+            ("laptop".into(), "dotfile ~/.zshrc (enabled = false)".into()),
+        ]
+    );
+}
+
+#[test]
+fn test_removals_that_match_nothing_beneath_are_not_listed() {
+    let config_str = r#"
+        exclude_dotfiles = ["~/.a"]
+
+        [[dotfiles]]
+        source = "a"
+        target = "~/.a"
+
+        [hosts.work]
+        exclude_dotfiles = ["~/.a", "~/.b"]
+        exclude_packages = ["missing"]
+
+        [[hosts.work.dotfiles]]
+        source = "b"
+        target = "~/.b"
+    "#;
+
+    let config = Config::from_str(config_str).unwrap();
+
+    let work = config.with_host("work").unwrap();
+    let targets: Vec<&str> = work.dotfiles.iter().map(|d| d.target.as_str()).collect();
+    assert_eq!(targets, vec!["~/.b"]);
+
+    let removals: Vec<(String, String)> = config
+        .host_removals("work")
+        .unwrap()
+        .into_iter()
+        .map(|(origin, removal)| (origin, removal.to_string()))
+        .collect();
+    assert_eq!(removals, vec![("work".into(), "dotfile ~/.a".into())]);
+    assert_eq!(work.removed.len(), 1);
+}
@@ -0,0 +1,212 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use mimic::config::Config;
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn write(path: &Path, content: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+}
+
+fn import(home: &Path, from: &str, repo: &Path) -> assert_cmd::assert::Assert {
+    cargo_bin_cmd!("mimic")
+        .env("HOME", home)
+        .args(["import", "--from", from])
+        .arg(repo)
+        .assert()
+}
+
+fn find<'a>(config: &'a Config, target: &str) -> Vec<&'a mimic::config::Dotfile> {
+    config
+        .dotfiles
+        .iter()
+        .filter(|dotfile| dotfile.target == target)
+        .collect()
+}
+
+#[test]
+fn test_import_stow_packages() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    let repo = home.join("dotfiles");
+    write(&repo.join("zsh/.zshrc"), "export EDITOR=nvim\n");
+    write(&repo.join("git/dot-config/git/config"), "[user]\n");
+    write(&repo.join("nvim/.config/nvim/init.lua"), "-- nvim\n");
+    write(&repo.join("README.md"), "# dotfiles\n");
+    write(&repo.join(".stowrc"), "--dotfiles\n--verbose\n");
+
+    import(&home, "stow", &repo)
+        .success()
+        .stdout(predicate::str::contains("Wrote 3 dotfiles"))
+        .stdout(predicate::str::contains(
+            ".stowrc: Option '--verbose' has no mimic equivalent",
+        ));
+
+    let config = Config::from_file(repo.join("mimic.toml")).unwrap();
+    let zshrc = find(&config, "~/.zshrc")[0];
+    assert_eq!(Path::new(&zshrc.source), repo.join("zsh/.zshrc"));
+    let git = find(&config, "~/.config")[0];
+    assert_eq!(Path::new(&git.source), repo.join("git/dot-config"));
+    assert!(git.is_tree());
+    assert!(find(&config, "~/.config")[1].is_tree());
+
+    // An existing config is never overwritten by accident
+    import(&home, "stow", &repo)
+        .code(1)
+        .stderr(predicate::str::contains("Pass --force to overwrite it"));
+}
+
+#[test]
+fn test_import_chezmoi_source_names() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    let repo = temp_dir.path().join("chezmoi");
+    write(&repo.join("dot_zshrc"), "export EDITOR=nvim\n");
+    write(&repo.join("dot_gitconfig.tmpl"), "email = {{ .email }}\n");
+    write(&repo.join("private_dot_ssh/config"), "Host *\n");
+    write(&repo.join("executable_dot_local/bin/tool"), "#!/bin/sh\n");
+    write(&repo.join("encrypted_private_dot_netrc.age"), "age\n");
+    write(&repo.join("run_once_install.sh"), "#!/bin/sh\n");
+    write(&repo.join("dot_cache/junk"), "x\n");
+    write(&repo.join(".chezmoiignore"), ".cache\n");
+    write(
+        &repo.join(".chezmoidata.toml"),
+        "email = \"jo@example.com\"\n",
+    );
+
+    import(&home, "chezmoi", &repo)
+        .success()
+        .stdout(predicate::str::contains(
+            "run_once_install.sh: chezmoi script",
+        ))
+        .stdout(predicate::str::contains(
+            "dot_gitconfig.tmpl: chezmoi template",
+        ))
+        .stdout(predicate::str::contains(
+            ".chezmoidata.toml: chezmoi template data",
+        ));
+
+    let config = Config::from_file(repo.join("mimic.toml")).unwrap();
+    assert!(find(&config, "~/.gitconfig")[0].is_template());
+    let ssh = find(&config, "~/.ssh")[0];
+    assert!(ssh.is_tree());
+    assert_eq!(ssh.dir_permissions.unwrap().to_string(), "0700");
+    let netrc = find(&config, "~/.netrc")[0];
+    assert!(netrc.is_encrypted());
+    assert_eq!(netrc.permissions.unwrap().to_string(), "0600");
+    // executable_ on a directory isn't an attribute, so the name is kept
+    assert_eq!(find(&config, "~/executable_dot_local").len(), 1);
+    assert!(find(&config, "~/.cache").is_empty());
+    assert_eq!(config.dotfiles.len(), 5);
+}
+
+#[test]
+fn test_import_yadm_alternates() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let repo = temp_dir.path().join("yadm");
+    write(&repo.join(".bashrc##os.Darwin"), "mac\n");
+    write(&repo.join(".bashrc##os.Linux"), "linux\n");
+    write(&repo.join(".bashrc##default"), "other\n");
+    write(&repo.join(".vimrc##hostname.work"), "work\n");
+    write(&repo.join(".vimrc##default"), "home\n");
+    write(
+        &repo.join(".config/yadm/alt/.tmux.conf##class.dev"),
+        "dev\n",
+    );
+    write(&repo.join(".config/yadm/bootstrap"), "#!/bin/sh\n");
+    write(&repo.join(".profile##distro.Ubuntu"), "ubuntu\n");
+    write(&repo.join(".config/kitty/kitty.conf"), "font_size 12\n");
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    cargo_bin_cmd!("mimic")
+        .env("HOME", &home)
+        .args(["import", "--from", "yadm", "--config"])
+        .arg(&config_path)
+        .arg(&repo)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            ".config/yadm/bootstrap: yadm bootstrap",
+        ))
+        .stdout(predicate::str::contains("yadm condition 'distro.Ubuntu'"))
+        .stdout(predicate::str::contains("Several alternates").not());
+
+    let config = Config::from_file(&config_path).unwrap();
+    let bashrc = find(&config, "~/.bashrc");
+    assert_eq!(bashrc.len(), 3);
+    assert_eq!(bashrc[0].when.as_deref(), Some("os == 'macos'"));
+    assert_eq!(
+        bashrc[2].when.as_deref(),
+        Some("!(os == 'macos') && !(os == 'linux')")
+    );
+    assert_eq!(Path::new(&bashrc[2].source), repo.join(".bashrc##default"));
+    assert_eq!(
+        find(&config, "~/.tmux.conf")[0].only_roles,
+        Some(vec!["dev".to_string()])
+    );
+    assert!(find(&config, "~/.config/kitty")[0].is_tree());
+
+    let work = &config.hosts["work"];
+    assert_eq!(work.exclude_dotfiles, vec!["~/.vimrc"]);
+    assert_eq!(work.dotfiles[0].target, "~/.vimrc");
+    assert!(work.matcher.is_some());
+    assert!(config.hosts.contains_key("default"));
+
+    // Machines other than work fall back to the default host
+    cargo_bin_cmd!("mimic")
+        .env("HOME", &home)
+        .args(["hosts", "detect", "--config"])
+        .arg(&config_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Selected: default"));
+    cargo_bin_cmd!("mimic")
+        .env("HOME", &home)
+        .args(["diff", "--config"])
+        .arg(&config_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(".vimrc##default"));
+}
+
+#[test]
+fn test_import_plain_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let repo = temp_dir.path().join("dots");
+    write(&repo.join("zshrc"), "export EDITOR=nvim\n");
+    write(&repo.join(".gitconfig.tmpl"), "name = {{variables.name}}\n");
+    write(&repo.join("config/nvim/init.lua"), "-- nvim\n");
+    write(&repo.join("install.sh"), "#!/bin/sh\n");
+    write(&repo.join("Brewfile"), "brew \"git\"\n");
+
+    let output = cargo_bin_cmd!("mimic")
+        .env("HOME", &home)
+        .args(["import", "--from", "dir", "--dry-run", "--output", "json"])
+        .arg(&repo)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["written"], false);
+    assert_eq!(report["dotfiles"], 3);
+    assert_eq!(report["notes"][0]["path"], "Brewfile");
+    assert_eq!(report["notes"][1]["path"], "install.sh");
+    assert!(!repo.join("mimic.toml").exists());
+
+    import(&home, "dir", &repo).success();
+    let config = Config::from_file(repo.join("mimic.toml")).unwrap();
+    let zshrc = find(&config, "~/.zshrc")[0];
+    assert_eq!(Path::new(&zshrc.source), repo.join("zshrc"));
+    assert!(find(&config, "~/.gitconfig")[0].is_template());
+    assert!(find(&config, "~/.config")[0].is_tree());
+
+    import(&home, "dir", &temp_dir.path().join("missing"))
+        .code(1)
+        .stderr(predicate::str::contains("Not a directory"));
+}
@@ -1,4 +1,4 @@
-use assert_cmd::Command;
+use assert_cmd::cargo::cargo_bin_cmd;
 use predicates::prelude::*;
 use std::fs;
 use tempfile::TempDir;
@@ -39,8 +39,7 @@ target = "{}"
     .unwrap();
 
     // Run apply with --yes to skip prompts
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -63,8 +62,7 @@ target = "{}"
     assert!(state_path.exists());
 
     // Run status to verify all in sync
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("status")
         .arg("--state")
         .arg(&state_path)
@@ -121,8 +119,7 @@ target = "{}"
     .unwrap();
 
     // Run apply with --yes (auto-backup conflicts)
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -185,8 +182,7 @@ target = "{}"
     .unwrap();
 
     // Initial apply
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -200,8 +196,7 @@ target = "{}"
     fs::remove_file(&target_path).unwrap();
 
     // Run status - should detect drift
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("status")
         .arg("--state")
         .arg(&state_path)
@@ -211,8 +206,7 @@ target = "{}"
         .stdout(predicate::str::contains("missing"));
 
     // Run apply to fix drift
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -223,8 +217,7 @@ target = "{}"
         .success();
 
     // Run status again - should be in sync
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("status")
         .arg("--state")
         .arg(&state_path)
@@ -273,8 +266,7 @@ target = "{}"
     .unwrap();
 
     // Apply (creates backup)
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -288,8 +280,7 @@ target = "{}"
     assert!(target_path.is_symlink());
 
     // Run undo
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("undo")
         .arg("--state")
         .arg(&state_path)
@@ -308,8 +299,7 @@ target = "{}"
     assert_eq!(restored_content, "original content");
 
     // Run undo again - should say nothing to undo
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("undo")
         .arg("--state")
         .arg(&state_path)
@@ -353,8 +343,7 @@ target = "{}"
     .unwrap();
 
     // Run apply with --dry-run
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -410,8 +399,7 @@ target = "{}"
     .unwrap();
 
     // Apply config
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -458,8 +446,7 @@ target = "{}"
     )
     .unwrap();
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -470,8 +457,7 @@ target = "{}"
         .success();
 
     // Step 2: Verify status shows in sync
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("status")
         .arg("--state")
         .arg(&state_path)
@@ -502,8 +488,7 @@ target = "{}"
     .unwrap();
 
     // Step 4: Run diff to preview changes
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("diff")
         .arg("--config")
         .arg(&config_path)
@@ -512,8 +497,7 @@ target = "{}"
         .stdout(predicate::str::contains("file2.conf"));
 
     // Step 5: Apply updated config
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -528,8 +512,7 @@ target = "{}"
     assert!(target2.is_symlink());
 
     // Step 6: Undo everything
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("undo")
         .arg("--state")
         .arg(&state_path)
@@ -579,8 +562,7 @@ target = "{}"
     )
     .unwrap();
 
-    let output = Command::cargo_bin("mimic")
-        .unwrap()
+    let output = cargo_bin_cmd!("mimic")
         .arg("diff")
         .arg("--config")
         .arg(&config_path)
@@ -615,8 +597,7 @@ target = "/tmp/target.conf"
     )
     .unwrap();
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -631,8 +612,7 @@ target = "/tmp/target.conf"
     let bad_config_path = temp_path.join("bad.toml");
     fs::write(&bad_config_path, "[[dotfiles]\nmissing = bracket").unwrap();
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&bad_config_path)
@@ -669,8 +649,7 @@ target = "{}"
     )
     .unwrap();
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -680,8 +659,7 @@ target = "{}"
         .assert()
         .success();
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("status")
         .arg("--state")
         .arg(&state_path)
@@ -719,8 +697,7 @@ target = "{}"
     )
     .unwrap();
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -756,8 +733,7 @@ target = "{}"
     )
     .unwrap();
 
-    let output = Command::cargo_bin("mimic")
-        .unwrap()
+    let output = cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -815,8 +791,7 @@ target = "{}"
     )
     .unwrap();
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -859,8 +834,7 @@ target = "{}"
     )
     .unwrap();
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -914,8 +888,7 @@ target = "{}"
     // Run apply from a completely different working directory (temp_path, NOT config_dir)
     // This would have failed before the fix because "dotfiles/test.conf" would resolve
     // against CWD instead of the config file's directory.
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -964,8 +937,7 @@ target = "{}"
     .unwrap();
 
     // Run diff from a different directory
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("diff")
         .arg("--config")
         .arg(&config_path)
@@ -1005,8 +977,7 @@ target = "{}"
     )
     .unwrap();
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -1032,3 +1003,138 @@ target = "{}"
         "Backup should be created for conflicting file"
     );
 }
+
+/// Copy-mode dotfiles are written as real files and status reports
+/// in-place edits as drift.
+#[test]
+fn test_copy_mode_apply_and_status() {
+    let temp_dir = TempDir::new().unwrap();
+    let temp_path = temp_dir.path();
+
+    let source_path = temp_path.join("ssh_config");
+    let target_path = temp_path.join("target_ssh_config");
+    let config_path = temp_path.join("mimic.toml");
+    let state_path = temp_path.join("state.toml");
+
+    fs::write(&source_path, "Host *\n").unwrap();
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[dotfiles]]
+source = "{}"
+target = "{}"
+mode = "copy"
+"#,
+            source_path.display(),
+            target_path.display()
+        ),
+    )
+    .unwrap();
+
+    cargo_bin_cmd!("mimic")
+        .arg("apply")
+        .arg("--config")
+        .arg(&config_path)
+        .arg("--state")
+        .arg(&state_path)
+        .arg("--yes")
+        .assert()
+        .success();
+
+    assert!(target_path.exists());
+    assert!(!target_path.is_symlink());
+    assert_eq!(fs::read_to_string(&target_path).unwrap(), "Host *\n");
+
+    cargo_bin_cmd!("mimic")
+        .arg("status")
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .success();
+
+    fs::write(&target_path, "Host *\n  User edited\n").unwrap();
+
+    cargo_bin_cmd!("mimic")
+        .arg("status")
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .failure()
+        .stdout(predicate::str::contains("modified in place"));
+}
+
+fn git(dir: &std::path::Path, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args([
+            "-c",
+            "user.name=mimic",
+            "-c",
+            "user.email=mimic@example.com",
+        ])
+        .args(args)
+        .output()
+        .unwrap()
+        .status;
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+#[test]
+fn test_status_reports_config_repo_ahead_of_applied_commit() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo = temp_dir.path().join("dotfiles");
+    let target_path = temp_dir.path().join("target_zshrc");
+    let state_path = temp_dir.path().join("state.toml");
+    let config_path = repo.join("mimic.toml");
+
+    fs::create_dir_all(&repo).unwrap();
+    fs::write(repo.join("zshrc"), "export A=1\n").unwrap();
+    fs::write(
+        &config_path,
+        format!(
+            "[[dotfiles]]\nsource = \"zshrc\"\ntarget = \"{}\"\n",
+            target_path.display()
+        ),
+    )
+    .unwrap();
+    git(&repo, &["init", "-q"]);
+    git(&repo, &["add", "-A"]);
+    git(&repo, &["commit", "-q", "-m", "initial"]);
+
+    cargo_bin_cmd!("mimic")
+        .arg("apply")
+        .arg("--config")
+        .arg(&config_path)
+        .arg("--state")
+        .arg(&state_path)
+        .arg("--yes")
+        .assert()
+        .success();
+
+    let state = fs::read_to_string(&state_path).unwrap();
+    assert!(state.contains("applied_commit"));
+    assert!(state.contains("applied_dirty = false"));
+
+    cargo_bin_cmd!("mimic")
+        .arg("status")
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("config repo at applied commit"));
+
+    fs::write(repo.join("zshrc"), "export A=2\n").unwrap();
+    git(&repo, &["commit", "-q", "-am", "bump"]);
+
+    cargo_bin_cmd!("mimic")
+        .arg("status")
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains(
+            "config repo is 1 commit ahead of last apply",
+        ));
+}
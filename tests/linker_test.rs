@@ -1,5 +1,7 @@
-use mimic::linker::create_symlink;
+use mimic::config::{Config, Dotfile, LinkStrategy};
+use mimic::linker::{apply_dotfile, content_hash, copy_file, create_symlink};
 use mimic::state::State;
+use mimic::template::HostContext;
 use std::fs;
 use std::os::unix::fs::symlink;
 use std::path::PathBuf;
@@ -153,3 +155,166 @@ fn test_symlink_to_existing_symlink_is_idempotent() {
     assert_eq!(state.dotfiles[0].source, source.to_string_lossy());
     assert_eq!(state.dotfiles[0].target, target.to_string_lossy());
 }
+
+#[test]
+fn test_copy_file_writes_real_file_and_records_hash() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("source.txt");
+    let target = temp.path().join("target.txt");
+
+    fs::write(&source, "copied content").unwrap();
+
+    let mut state = State::new();
+    copy_file(&source, &target, &mut state).unwrap();
+
+    assert!(target.exists());
+    assert!(!target.is_symlink());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "copied content");
+
+    assert_eq!(state.dotfiles.len(), 1);
+    assert!(state.dotfiles[0].is_copy());
+    assert_eq!(
+        state.dotfiles[0].content_hash.as_deref(),
+        Some(content_hash(&source).unwrap().as_str())
+    );
+}
+
+#[test]
+fn test_copy_file_replaces_own_unmodified_copy_when_source_changes() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("source.txt");
+    let target = temp.path().join("target.txt");
+
+    fs::write(&source, "v1").unwrap();
+
+    let mut state = State::new();
+    copy_file(&source, &target, &mut state).unwrap();
+
+    // Source moves on; the deployed copy is untouched, so no conflict prompt
+    fs::write(&source, "v2").unwrap();
+    copy_file(&source, &target, &mut state).unwrap();
+
+    assert_eq!(fs::read_to_string(&target).unwrap(), "v2");
+    assert_eq!(state.dotfiles.len(), 1);
+    assert_eq!(
+        state.dotfiles[0].content_hash.as_deref(),
+        Some(content_hash(&source).unwrap().as_str())
+    );
+}
+
+#[test]
+fn test_copy_file_replaces_existing_symlink_to_source() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("source.txt");
+    let target = temp.path().join("target.txt");
+
+    fs::write(&source, "content").unwrap();
+    symlink(&source, &target).unwrap();
+
+    let mut state = State::new();
+    copy_file(&source, &target, &mut state).unwrap();
+
+    assert!(!target.is_symlink());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "content");
+}
+
+#[test]
+fn test_content_hash_covers_directory_contents() {
+    let temp = TempDir::new().unwrap();
+    let dir = temp.path().join("config");
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("a.toml"), "a = 1").unwrap();
+
+    let before = content_hash(&dir).unwrap();
+    fs::write(dir.join("a.toml"), "a = 2").unwrap();
+    let after = content_hash(&dir).unwrap();
+
+    assert_ne!(before, after);
+}
+
+fn tree_dotfile(source: &std::path::Path, target: &std::path::Path) -> Dotfile {
+    Dotfile {
+        source: source.to_string_lossy().to_string(),
+        target: target.to_string_lossy().to_string(),
+        template: false,
+        encrypted: false,
+        mode: Default::default(),
+        link: LinkStrategy::Tree,
+        permissions: None,
+        dir_permissions: None,
+        only_roles: None,
+        skip_roles: None,
+        when: None,
+        enabled: true,
+    }
+}
+
+fn default_host() -> HostContext {
+    HostContext {
+        name: "default".to_string(),
+        roles: vec![],
+    }
+}
+
+#[test]
+fn test_tree_link_creates_real_dirs_and_per_file_links() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("foo");
+    let target = temp.path().join("config/foo");
+
+    fs::create_dir_all(source.join("themes")).unwrap();
+    fs::write(source.join("config.toml"), "a = 1").unwrap();
+    fs::write(source.join("themes/dark.toml"), "bg = 0").unwrap();
+
+    let mut state = State::new();
+    apply_dotfile(
+        &tree_dotfile(&source, &target),
+        &Config::default(),
+        &default_host(),
+        &mut state,
+        &mut None,
+    )
+    .unwrap();
+
+    assert!(target.is_dir() && !target.is_symlink());
+    assert!(target.join("themes").is_dir() && !target.join("themes").is_symlink());
+    assert!(target.join("config.toml").is_symlink());
+    assert!(target.join("themes/dark.toml").is_symlink());
+
+    // Files written by apps land in the real directory, not the repo
+    fs::write(target.join("cache.db"), "runtime").unwrap();
+    assert!(!source.join("cache.db").exists());
+
+    assert_eq!(state.dotfiles.len(), 2);
+    assert!(
+        state
+            .dotfiles
+            .iter()
+            .any(|d| d.target == target.join("themes/dark.toml").to_string_lossy())
+    );
+}
+
+#[test]
+fn test_tree_link_replaces_whole_directory_link() {
+    let temp = TempDir::new().unwrap();
+    let source = temp.path().join("foo");
+    let target = temp.path().join("target_foo");
+
+    fs::create_dir_all(&source).unwrap();
+    fs::write(source.join("config.toml"), "a = 1").unwrap();
+    symlink(&source, &target).unwrap();
+
+    let mut state = State::new();
+    apply_dotfile(
+        &tree_dotfile(&source, &target),
+        &Config::default(),
+        &default_host(),
+        &mut state,
+        &mut None,
+    )
+    .unwrap();
+
+    assert!(!target.is_symlink());
+    assert!(target.join("config.toml").is_symlink());
+    assert!(source.join("config.toml").exists());
+}
@@ -0,0 +1,300 @@
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+
+mod common;
+use common::mimic;
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "git {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+fn commit_team_config(team: &Path, value: &str) -> String {
+    fs::write(
+        team.join("mimic.toml"),
+        format!("[variables]\nteam = \"{}\"\n", value),
+    )
+    .unwrap();
+    git(team, &["add", "-A"]);
+    git(team, &["commit", "--quiet", "-m", value]);
+    git(team, &["rev-parse", "HEAD"])
+}
+
+#[test]
+fn test_lock_pins_extends_until_update() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let team = temp_dir.path().join("team");
+    fs::create_dir_all(&team).unwrap();
+    git(&team, &["init", "--quiet"]);
+    let first = commit_team_config(&team, "v1");
+
+    let repo = temp_dir.path().join("dotfiles");
+    fs::create_dir_all(&repo).unwrap();
+    let config_path = repo.join("mimic.toml");
+    fs::write(
+        &config_path,
+        format!("[[extends]]\nrepo = \"{}\"\n", team.display()),
+    )
+    .unwrap();
+    let template = repo.join("team.tmpl");
+    fs::write(&template, "team={{variables.team}}").unwrap();
+    let template = template.to_str().unwrap();
+    let lock_path = repo.join("mimic.lock");
+
+    mimic(&home, &["render", template], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains("team=v1"));
+    let lock = fs::read_to_string(&lock_path).unwrap();
+    assert!(lock.contains(&first), "{}", lock);
+
+    // A push to the team repo doesn't change anything until `mimic update`
+    let second = commit_team_config(&team, "v2");
+    mimic(&home, &["render", template], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains("team=v1"));
+
+    mimic(&home, &["update"], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains(&first[..12]))
+        .stdout(predicate::str::contains(&second[..12]));
+    assert!(fs::read_to_string(&lock_path).unwrap().contains(&second));
+    mimic(&home, &["render", template], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains("team=v2"));
+
+    mimic(&home, &["update"], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains("up to date"));
+
+    // An unknown name is rejected before any repo is fetched
+    let third = commit_team_config(&team, "v3");
+    mimic(
+        &home,
+        &[
+            "update",
+            team.to_str().unwrap(),
+            "https://example.com/other.git",
+        ],
+        &config_path,
+        None,
+    )
+    .failure()
+    .stderr(predicate::str::contains("not an extends repo"));
+    let lock = fs::read_to_string(&lock_path).unwrap();
+    assert!(lock.contains(&second) && !lock.contains(&third), "{}", lock);
+}
+
+#[test]
+fn test_extends_pinned_to_tag_and_rev() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let team = temp_dir.path().join("team");
+    fs::create_dir_all(&team).unwrap();
+    git(&team, &["init", "--quiet"]);
+    let first = commit_team_config(&team, "v1");
+    git(&team, &["tag", "release-1"]);
+    let second = commit_team_config(&team, "v2");
+
+    let repo = temp_dir.path().join("dotfiles");
+    fs::create_dir_all(&repo).unwrap();
+    let config_path = repo.join("mimic.toml");
+    let template = repo.join("team.tmpl");
+    fs::write(&template, "team={{variables.team}}").unwrap();
+    let template = template.to_str().unwrap();
+
+    fs::write(
+        &config_path,
+        format!(
+            "[[extends]]\nrepo = \"{}\"\ntag = \"release-1\"\n",
+            team.display()
+        ),
+    )
+    .unwrap();
+    mimic(&home, &["render", template], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains("team=v1"));
+    let lock = fs::read_to_string(repo.join("mimic.lock")).unwrap();
+    assert!(lock.contains("tag = \"release-1\""), "{}", lock);
+    assert!(lock.contains(&first), "{}", lock);
+
+    // Moving off the tag is reported against the commit the tag was locked at
+    fs::write(
+        &config_path,
+        format!("[[extends]]\nrepo = \"{}\"\n", team.display()),
+    )
+    .unwrap();
+    mimic(&home, &["update"], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains(format!(
+            "{} → {} (tag release-1 → default branch)",
+            &first[..12],
+            &second[..12]
+        )));
+
+    fs::write(
+        &config_path,
+        format!(
+            "[[extends]]\nrepo = \"{}\"\nrev = \"{}\"\n",
+            team.display(),
+            &first[..10]
+        ),
+    )
+    .unwrap();
+    mimic(&home, &["render", template], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains("team=v1"));
+
+    fs::write(
+        &config_path,
+        format!(
+            "[[extends]]\nrepo = \"{}\"\nrev = \"{}\"\ntag = \"release-1\"\n",
+            team.display(),
+            first
+        ),
+    )
+    .unwrap();
+    mimic(&home, &["render", template], &config_path, None)
+        .failure()
+        .stderr(predicate::str::contains(
+            "more than one of branch, tag and rev",
+        ));
+}
+
+#[test]
+fn test_extends_cache_ttl_refresh_and_offline() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let team = temp_dir.path().join("team");
+    fs::create_dir_all(&team).unwrap();
+    git(&team, &["init", "--quiet"]);
+    commit_team_config(&team, "v1");
+
+    let repo = temp_dir.path().join("dotfiles");
+    fs::create_dir_all(&repo).unwrap();
+    let config_path = repo.join("mimic.toml");
+    fs::write(
+        &config_path,
+        format!("[[extends]]\nrepo = \"{}\"\nttl = \"6h\"\n", team.display()),
+    )
+    .unwrap();
+    let template = repo.join("team.tmpl");
+    fs::write(&template, "team={{variables.team}}").unwrap();
+    let template = template.to_str().unwrap();
+    let lock_path = repo.join("mimic.lock");
+
+    mimic(&home, &["render", template], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains("team=v1"));
+
+    // Without a lock entry, a fresh cache is used as is
+    commit_team_config(&team, "v2");
+    fs::remove_file(&lock_path).unwrap();
+    mimic(&home, &["render", template], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains("team=v1"));
+
+    fs::remove_file(&lock_path).unwrap();
+    mimic(
+        &home,
+        &["render", template, "--refresh"],
+        &config_path,
+        None,
+    )
+    .success()
+    .stdout(predicate::str::contains("team=v2"));
+
+    // An unreachable remote falls back to the cached checkout
+    fs::rename(&team, temp_dir.path().join("moved")).unwrap();
+    fs::remove_file(&lock_path).unwrap();
+    mimic(
+        &home,
+        &["render", template, "--refresh"],
+        &config_path,
+        None,
+    )
+    .success()
+    .stdout(predicate::str::contains("team=v2"))
+    .stderr(predicate::str::contains("using the cached copy"));
+
+    mimic(&home, &["update"], &config_path, None).failure();
+
+    let other_home = temp_dir.path().join("other-home");
+    fs::create_dir_all(&other_home).unwrap();
+    mimic(
+        &other_home,
+        &["render", template, "--offline"],
+        &config_path,
+        None,
+    )
+    .failure()
+    .stderr(predicate::str::contains(
+        "not cached and --offline was given",
+    ));
+}
+
+#[test]
+fn test_unreachable_locked_commit_is_an_error() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let team = temp_dir.path().join("team");
+    fs::create_dir_all(&team).unwrap();
+    git(&team, &["init", "--quiet"]);
+    let first = commit_team_config(&team, "v1");
+
+    let repo = temp_dir.path().join("dotfiles");
+    fs::create_dir_all(&repo).unwrap();
+    let config_path = repo.join("mimic.toml");
+    fs::write(
+        &config_path,
+        format!("[[extends]]\nrepo = \"{}\"\n", team.display()),
+    )
+    .unwrap();
+    let template = repo.join("team.tmpl");
+    fs::write(&template, "team={{variables.team}}").unwrap();
+    let template = template.to_str().unwrap();
+    let lock_path = repo.join("mimic.lock");
+
+    mimic(&home, &["render", template], &config_path, None)
+        .success()
+        .stdout(predicate::str::contains("team=v1"));
+
+    // A teammate locked a commit this machine never fetched, and the remote
+    // is unreachable: the cached v1 must not stand in for it
+    let second = commit_team_config(&team, "v2");
+    let lock = fs::read_to_string(&lock_path)
+        .unwrap()
+        .replace(&first, &second);
+    fs::write(&lock_path, &lock).unwrap();
+    fs::rename(&team, temp_dir.path().join("moved")).unwrap();
+
+    mimic(&home, &["render", template], &config_path, None)
+        .failure()
+        .stderr(predicate::str::contains(
+            "is not cached and could not be fetched",
+        ));
+    assert_eq!(fs::read_to_string(&lock_path).unwrap(), lock);
+}
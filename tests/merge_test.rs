@@ -0,0 +1,197 @@
+use mimic::config::MergeFormat;
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+mod common;
+use common::mimic;
+
+fn read_json(path: &Path) -> serde_json::Value {
+    mimic::merge::parse(&fs::read_to_string(path).unwrap(), MergeFormat::Json).unwrap()
+}
+
+fn write_config(path: &Path, source: &Path, target: &Path) {
+    fs::write(
+        path,
+        format!(
+            "[[merges]]\nsource = \"{}\"\ntarget = \"{}\"\n",
+            source.display(),
+            target.display()
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_json_merge_preserves_app_keys_and_undo_restores() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let source = temp_dir.path().join("settings.json");
+    fs::write(
+        &source,
+        r#"{ "editor": { "fontSize": 14 }, "telemetry.enabled": false, "files": { "exclude": ["*.o"] } }"#,
+    )
+    .unwrap();
+
+    let target = home.join("settings.json");
+    let original = r#"{
+    // written by the app
+    "window.zoomLevel": 1,
+    "editor": { "fontSize": 12, "tabSize": 4 },
+}"#;
+    fs::write(&target, original).unwrap();
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    write_config(&config_path, &source, &target);
+
+    mimic(&home, &["diff"], &config_path, Some(&state_path))
+        .success()
+        .stdout(predicate::str::contains("merge"))
+        .stdout(predicate::str::contains("editor.fontSize"));
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+    // Comments and the trailing comma are kept
+    let text = fs::read_to_string(&target).unwrap();
+    assert!(text.contains("// written by the app"));
+    assert!(text.trim_end().ends_with(",\n}"));
+    assert_eq!(
+        read_json(&target),
+        serde_json::json!({
+            "window.zoomLevel": 1,
+            "editor": { "fontSize": 14, "tabSize": 4 },
+            "telemetry.enabled": false,
+            "files": { "exclude": ["*.o"] }
+        })
+    );
+    mimic(&home, &["status"], &config_path, Some(&state_path)).success();
+
+    // The app rewriting its own keys is not drift
+    let app_edit = fs::read_to_string(&target)
+        .unwrap()
+        .replace("\"window.zoomLevel\": 1", "\"window.zoomLevel\": 3");
+    fs::write(&target, app_edit).unwrap();
+    mimic(&home, &["status"], &config_path, Some(&state_path)).success();
+
+    let managed_edit = fs::read_to_string(&target)
+        .unwrap()
+        .replace("\"fontSize\": 14", "\"fontSize\": 16");
+    fs::write(&target, managed_edit).unwrap();
+    mimic(&home, &["status"], &config_path, Some(&state_path))
+        .code(1)
+        .stdout(predicate::str::contains("changed: editor.fontSize"));
+
+    // Re-applying keeps the value from before the first merge
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+    mimic(&home, &["undo", "--yes"], &config_path, Some(&state_path))
+        .success()
+        .stdout(predicate::str::contains("1 merged files restored"));
+
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        original.replace("\"window.zoomLevel\": 1", "\"window.zoomLevel\": 3")
+    );
+}
+
+#[test]
+fn test_toml_and_yaml_merges() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let toml_source = temp_dir.path().join("fragment.toml");
+    fs::write(&toml_source, "[ui]\ntheme = \"dark\"\n").unwrap();
+    let toml_target = home.join("app.toml");
+    fs::write(&toml_target, "version = 2\n\n[ui]\nscale = 1.5\n").unwrap();
+
+    let yaml_source = temp_dir.path().join("fragment.yaml");
+    fs::write(&yaml_source, "git:\n  paging:\n    pager: delta\n").unwrap();
+    let yaml_target = home.join(".config/lazygit/config.yml");
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    fs::write(
+        &config_path,
+        format!(
+            "[[merges]]\nsource = \"{}\"\ntarget = \"{}\"\n\n[[merges]]\nsource = \"{}\"\ntarget = \"{}\"\n",
+            toml_source.display(),
+            toml_target.display(),
+            yaml_source.display(),
+            yaml_target.display()
+        ),
+    )
+    .unwrap();
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+
+    let merged: toml::Table = toml::from_str(&fs::read_to_string(&toml_target).unwrap()).unwrap();
+    assert_eq!(merged["version"].as_integer(), Some(2));
+    assert_eq!(merged["ui"]["scale"].as_float(), Some(1.5));
+    assert_eq!(merged["ui"]["theme"].as_str(), Some("dark"));
+    assert!(
+        fs::read_to_string(&yaml_target)
+            .unwrap()
+            .contains("pager: delta")
+    );
+    mimic(&home, &["status"], &config_path, Some(&state_path)).success();
+
+    mimic(&home, &["undo", "--yes"], &config_path, Some(&state_path)).success();
+    let restored: toml::Table = toml::from_str(&fs::read_to_string(&toml_target).unwrap()).unwrap();
+    assert!(restored["ui"].get("theme").is_none());
+    assert_eq!(restored["ui"]["scale"].as_float(), Some(1.5));
+    // mimic created the YAML file, so it goes away again
+    assert!(!yaml_target.exists());
+}
+
+#[test]
+fn test_toml_merge_keeps_datetimes_and_comments() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let source = temp_dir.path().join("fragment.toml");
+    fs::write(&source, "[ui]\ntheme = \"dark\"\nscale = 2.0\n").unwrap();
+    let target = home.join("app.toml");
+    let original =
+        "# written by the app\ncreated = 1979-05-27T07:32:00Z\n\n[ui]\nscale = 1.5 # hidpi\n";
+    fs::write(&target, original).unwrap();
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    write_config(&config_path, &source, &target);
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+    assert_eq!(
+        fs::read_to_string(&target).unwrap(),
+        "# written by the app\ncreated = 1979-05-27T07:32:00Z\n\n[ui]\nscale = 2.0 # hidpi\ntheme = \"dark\"\n"
+    );
+    mimic(&home, &["status"], &config_path, Some(&state_path)).success();
+
+    mimic(&home, &["undo", "--yes"], &config_path, Some(&state_path)).success();
+    assert_eq!(fs::read_to_string(&target).unwrap(), original);
+}
+
+#[test]
+fn test_yaml_merge_refuses_files_with_comments() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let source = temp_dir.path().join("fragment.yaml");
+    fs::write(&source, "git:\n  paging:\n    pager: delta\n").unwrap();
+    let target = home.join("config.yml");
+    let original = "# lazygit settings\ngui:\n  theme: dark\n";
+    fs::write(&target, original).unwrap();
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    write_config(&config_path, &source, &target);
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).stderr(
+        predicate::str::contains("it has comments, which rewriting the YAML would drop"),
+    );
+    assert_eq!(fs::read_to_string(&target).unwrap(), original);
+}
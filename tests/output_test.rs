@@ -0,0 +1,148 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+
+fn setup(temp_dir: &TempDir) -> (PathBuf, PathBuf, PathBuf) {
+    let source = temp_dir.path().join("zshrc");
+    let target = temp_dir.path().join("target_zshrc");
+    let config_path = temp_dir.path().join("mimic.toml");
+
+    fs::write(&source, "export A=1").unwrap();
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[dotfiles]]
+source = "{}"
+target = "{}"
+
+[hosts.base]
+roles = ["dev"]
+
+[hosts.base.variables]
+editor = "nvim"
+
+[hosts.work]
+inherits = "base"
+roles = ["work"]
+"#,
+            source.display(),
+            target.display()
+        ),
+    )
+    .unwrap();
+
+    (config_path, target, temp_dir.path().join("state.toml"))
+}
+
+fn json_output(args: &[&str], config: &Path, state: &Path, expected_code: i32) -> Value {
+    let output = cargo_bin_cmd!("mimic")
+        .args(args)
+        .arg("--config")
+        .arg(config)
+        .arg("--state")
+        .arg(state)
+        .args(["--output", "json"])
+        .output()
+        .unwrap();
+
+    assert_eq!(
+        output.status.code(),
+        Some(expected_code),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    serde_json::from_slice(&output.stdout).expect("stdout should be a single JSON document")
+}
+
+#[test]
+fn test_diff_json_lists_changes() {
+    let temp_dir = TempDir::new().unwrap();
+    let (config, _, state) = setup(&temp_dir);
+
+    let report = json_output(&["diff", "--host", "work"], &config, &state, 0);
+
+    assert_eq!(report["host"], "work");
+    assert_eq!(report["changes"][0]["action"], "add");
+    assert_eq!(report["changes"][0]["resource_type"], "dotfile");
+    assert_eq!(report["summary"]["add"], 1);
+    assert_eq!(report["summary"]["modify"], 0);
+}
+
+#[test]
+fn test_apply_json_reports_results_and_status_json_reports_drift() {
+    let temp_dir = TempDir::new().unwrap();
+    let (config, target, state) = setup(&temp_dir);
+
+    let report = json_output(&["apply", "--yes", "--host", "work"], &config, &state, 0);
+    assert_eq!(report["dry_run"], false);
+    assert_eq!(report["results"][0]["status"], "applied");
+    assert_eq!(
+        report["results"][0]["name"],
+        target.to_string_lossy().as_ref()
+    );
+    assert_eq!(report["generation"], 1);
+
+    let status = json_output(&["status"], &config, &state, 0);
+    assert_eq!(status["in_sync"], true);
+    assert_eq!(status["active_host"], "work");
+
+    fs::remove_file(&target).unwrap();
+
+    let status = json_output(&["status"], &config, &state, 1);
+    assert_eq!(status["in_sync"], false);
+    assert_eq!(status["resources"][0]["in_sync"], false);
+    assert_eq!(status["resources"][0]["reason"], "missing");
+}
+
+#[test]
+fn test_hosts_show_json_is_merged_config() {
+    let temp_dir = TempDir::new().unwrap();
+    let (config, _, state) = setup(&temp_dir);
+
+    let host = json_output(&["hosts", "show", "work"], &config, &state, 0);
+    assert_eq!(host["name"], "work");
+    assert_eq!(host["inherits"], serde_json::json!(["base"]));
+    assert_eq!(host["roles"], serde_json::json!(["dev", "work"]));
+    assert_eq!(host["variables"]["editor"], "nvim");
+    assert_eq!(host["dotfiles"][0]["mode"], "symlink");
+
+    let hosts = json_output(&["hosts", "list"], &config, &state, 0);
+    assert_eq!(hosts["hosts"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_json_errors_exit_with_code_1() {
+    let temp_dir = TempDir::new().unwrap();
+    let (config, _, state) = setup(&temp_dir);
+
+    let report = json_output(&["hosts", "show", "missing"], &config, &state, 1);
+    assert!(report["error"].as_str().unwrap().contains("not found"));
+
+    let report = json_output(&["undo"], &config, &state, 1);
+    assert!(report["error"].as_str().unwrap().contains("not supported"));
+}
+
+#[test]
+fn test_hosts_detect_json_explains_selection() {
+    let temp_dir = TempDir::new().unwrap();
+    let (config, _, state) = setup(&temp_dir);
+    let mut content = fs::read_to_string(&config).unwrap();
+    content.push_str(&format!(
+        "match = {{ os = \"{}\" }}\n",
+        std::env::consts::OS
+    ));
+    fs::write(&config, content).unwrap();
+
+    let report = json_output(&["hosts", "detect"], &config, &state, 0);
+    assert_eq!(report["selected"]["name"], "work");
+    assert_eq!(report["machine"]["os"], std::env::consts::OS);
+    assert_eq!(report["candidates"][1]["name"], "work");
+    assert_eq!(report["candidates"][1]["matched"], true);
+    assert_eq!(report["candidates"][0]["matched"], false);
+
+    let host = json_output(&["hosts", "show", "work"], &config, &state, 0);
+    assert_eq!(host["match"]["os"], std::env::consts::OS);
+}
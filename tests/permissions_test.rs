@@ -0,0 +1,126 @@
+use mimic::config::Config;
+use predicates::prelude::*;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::TempDir;
+
+mod common;
+use common::mimic;
+
+fn mode(path: &Path) -> u32 {
+    fs::metadata(path).unwrap().permissions().mode() & 0o7777
+}
+
+#[test]
+fn test_permissions_parse_as_octal() {
+    let config = Config::from_str(
+        r#"
+[[dotfiles]]
+source = "netrc"
+target = "~/.netrc"
+permissions = "0600"
+dir_permissions = 0o700
+"#,
+    )
+    .unwrap();
+    assert_eq!(config.dotfiles[0].permissions.unwrap().bits(), 0o600);
+    assert_eq!(config.dotfiles[0].dir_permissions.unwrap().bits(), 0o700);
+
+    let err = Config::from_str(
+        r#"
+[[dotfiles]]
+source = "netrc"
+target = "~/.netrc"
+permissions = "rw-------"
+"#,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("expected an octal mode"));
+}
+
+#[test]
+fn test_copy_permissions_enforced_and_drift_reported() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let source = temp_dir.path().join("ssh_config");
+    fs::write(&source, "Host *\n").unwrap();
+    fs::set_permissions(&source, fs::Permissions::from_mode(0o644)).unwrap();
+
+    let target = home.join(".ssh/config");
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[[dotfiles]]
+source = "{}"
+target = "{}"
+mode = "copy"
+permissions = "0600"
+dir_permissions = "0700"
+"#,
+            source.display(),
+            target.display()
+        ),
+    )
+    .unwrap();
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+    assert_eq!(mode(&target), 0o600);
+    assert_eq!(mode(&home.join(".ssh")), 0o700);
+    // Existing parents are left alone
+    assert_ne!(mode(&home), 0o700);
+
+    mimic(&home, &["status"], &config_path, Some(&state_path)).success();
+
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o644)).unwrap();
+
+    mimic(&home, &["status"], &config_path, Some(&state_path))
+        .code(1)
+        .stdout(predicate::str::contains("permissions 0644, expected 0600"));
+    mimic(&home, &["diff"], &config_path, Some(&state_path))
+        .success()
+        .stdout(predicate::str::contains("~"))
+        .stdout(predicate::str::contains("permissions 0644, expected 0600"));
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+    assert_eq!(mode(&target), 0o600);
+    mimic(&home, &["status"], &config_path, Some(&state_path)).success();
+}
+
+#[test]
+fn test_rendered_templates_are_private() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+    let source = temp_dir.path().join("netrc.tmpl");
+    fs::write(&source, "machine example.com login {{variables.user}}\n").unwrap();
+
+    let target = home.join(".netrc");
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[variables]
+user = "me"
+
+[[dotfiles]]
+source = "{}"
+target = "{}"
+"#,
+            source.display(),
+            target.display()
+        ),
+    )
+    .unwrap();
+
+    mimic(&home, &["apply", "--yes"], &config_path, Some(&state_path)).success();
+    assert!(target.is_symlink());
+    assert_eq!(mode(&home.join(".mimic/rendered")), 0o700);
+    assert_eq!(mode(&target), 0o600);
+}
@@ -0,0 +1,127 @@
+use assert_cmd::cargo::cargo_bin_cmd;
+use mimic::state::State;
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+fn write_config(config_path: &Path, entries: &[(&Path, &Path)]) {
+    let mut content = String::new();
+    for (source, target) in entries {
+        content.push_str(&format!(
+            "[[dotfiles]]\nsource = \"{}\"\ntarget = \"{}\"\n\n",
+            source.display(),
+            target.display()
+        ));
+    }
+    fs::write(config_path, content).unwrap();
+}
+
+fn mimic(args: &[&str], config_path: &Path, state_path: &Path) -> assert_cmd::assert::Assert {
+    cargo_bin_cmd!("mimic")
+        .args(args)
+        .arg("--config")
+        .arg(config_path)
+        .arg("--state")
+        .arg(state_path)
+        .assert()
+}
+
+#[test]
+fn test_removed_entry_is_pruned_and_backup_restored() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    let zshrc = temp_dir.path().join("zshrc");
+    let vimrc = temp_dir.path().join("vimrc");
+    let zshrc_target = temp_dir.path().join(".zshrc");
+    let vimrc_target = temp_dir.path().join(".vimrc");
+
+    fs::write(&zshrc, "export A=1").unwrap();
+    fs::write(&vimrc, "set number").unwrap();
+    fs::write(&vimrc_target, "original vimrc").unwrap();
+
+    write_config(
+        &config_path,
+        &[(&zshrc, &zshrc_target), (&vimrc, &vimrc_target)],
+    );
+    mimic(&["apply", "--yes"], &config_path, &state_path).success();
+    assert!(vimrc_target.is_symlink());
+
+    // Drop the vimrc entry from the config
+    write_config(&config_path, &[(&zshrc, &zshrc_target)]);
+
+    mimic(&["diff"], &config_path, &state_path)
+        .success()
+        .stdout(predicate::str::contains("no longer in config"))
+        .stdout(predicate::str::contains("1 to remove"));
+
+    // Non-interactive runs only prune when asked to
+    mimic(&["apply", "--yes"], &config_path, &state_path)
+        .success()
+        .stdout(predicate::str::contains("use --prune to remove"));
+    assert!(vimrc_target.is_symlink());
+
+    mimic(&["apply", "--yes", "--prune"], &config_path, &state_path).success();
+    assert!(!vimrc_target.is_symlink());
+    assert_eq!(fs::read_to_string(&vimrc_target).unwrap(), "original vimrc");
+    assert!(zshrc_target.is_symlink());
+
+    let state = State::load(&state_path).unwrap();
+    assert_eq!(state.dotfiles.len(), 1);
+    assert_eq!(state.dotfiles[0].target, zshrc_target.to_string_lossy());
+
+    mimic(&["diff"], &config_path, &state_path)
+        .success()
+        .stdout(predicate::str::contains("no longer in config").not());
+}
+
+#[test]
+fn test_prune_leaves_replaced_target_alone() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    let zshrc = temp_dir.path().join("zshrc");
+    let zshrc_target = temp_dir.path().join(".zshrc");
+
+    fs::write(&zshrc, "export A=1").unwrap();
+    write_config(&config_path, &[(&zshrc, &zshrc_target)]);
+    mimic(&["apply", "--yes"], &config_path, &state_path).success();
+
+    // User replaces the link with their own file, then drops the entry
+    fs::remove_file(&zshrc_target).unwrap();
+    fs::write(&zshrc_target, "hand written").unwrap();
+    write_config(&config_path, &[]);
+
+    mimic(&["apply", "--yes", "--prune"], &config_path, &state_path)
+        .success()
+        .stdout(predicate::str::contains("left in place"));
+
+    assert_eq!(fs::read_to_string(&zshrc_target).unwrap(), "hand written");
+    assert!(State::load(&state_path).unwrap().dotfiles.is_empty());
+}
+
+#[test]
+fn test_prune_removes_dangling_link_when_source_deleted() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    let zshrc = temp_dir.path().join("zshrc");
+    let vimrc = temp_dir.path().join("vimrc");
+    let zshrc_target = temp_dir.path().join(".zshrc");
+    let vimrc_target = temp_dir.path().join(".vimrc");
+
+    fs::write(&zshrc, "export A=1").unwrap();
+    fs::write(&vimrc, "set number").unwrap();
+    write_config(
+        &config_path,
+        &[(&zshrc, &zshrc_target), (&vimrc, &vimrc_target)],
+    );
+    mimic(&["apply", "--yes"], &config_path, &state_path).success();
+
+    fs::remove_file(&vimrc).unwrap();
+    write_config(&config_path, &[(&zshrc, &zshrc_target)]);
+
+    mimic(&["apply", "--yes", "--prune"], &config_path, &state_path).success();
+    assert!(!vimrc_target.exists() && !vimrc_target.is_symlink());
+}
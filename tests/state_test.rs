@@ -17,6 +17,9 @@ fn test_state_persistence() {
         target: "/home/user/.vimrc".to_string(),
         backup_path: Some("/home/user/.vimrc.backup".to_string()),
         rendered_path: None,
+        content_hash: None,
+        permissions: None,
+        dir_permissions: None,
     });
     state.add_package(PackageState {
         name: "git".to_string(),
@@ -63,6 +66,9 @@ fn test_state_add_remove_dotfile() {
         target: "/home/user/.bashrc".to_string(),
         backup_path: None,
         rendered_path: None,
+        content_hash: None,
+        permissions: None,
+        dir_permissions: None,
     });
 
     assert_eq!(state.dotfiles.len(), 1);
@@ -82,6 +88,9 @@ fn test_state_clear() {
         target: "/home/user/.zshrc".to_string(),
         backup_path: None,
         rendered_path: None,
+        content_hash: None,
+        permissions: None,
+        dir_permissions: None,
     });
 
     // Clear should reset everything
@@ -107,7 +116,7 @@ fn test_state_atomic_write() {
     let tmp_files: Vec<_> = fs::read_dir(temp_dir.path())
         .unwrap()
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().extension().map_or(false, |ext| ext == "tmp"))
+        .filter(|e| e.path().extension().is_some_and(|ext| ext == "tmp"))
         .collect();
 
     assert_eq!(tmp_files.len(), 0, "Temporary files should be cleaned up");
@@ -128,6 +137,9 @@ fn test_state_multiple_dotfiles() {
             target: format!("/home/user/.config/file{}", i),
             backup_path: None,
             rendered_path: None,
+            content_hash: None,
+            permissions: None,
+            dir_permissions: None,
         });
     }
 
@@ -5,6 +5,7 @@ use std::path::PathBuf;
 use tempfile::TempDir;
 
 /// Helper to create a test state file with dotfiles and packages
+#[allow(dead_code)]
 fn create_test_state(state_path: &PathBuf) -> anyhow::Result<State> {
     let mut state = State::new();
 
@@ -13,6 +14,9 @@ fn create_test_state(state_path: &PathBuf) -> anyhow::Result<State> {
         target: "/tmp/test_target1".to_string(),
         backup_path: None,
         rendered_path: None,
+        content_hash: None,
+        permissions: None,
+        dir_permissions: None,
     });
 
     state.add_dotfile(DotfileState {
@@ -20,6 +24,9 @@ fn create_test_state(state_path: &PathBuf) -> anyhow::Result<State> {
         target: "/tmp/test_target2".to_string(),
         backup_path: None,
         rendered_path: None,
+        content_hash: None,
+        permissions: None,
+        dir_permissions: None,
     });
 
     state.add_package(PackageState {
@@ -52,6 +59,9 @@ fn test_status_detects_broken_symlink() -> anyhow::Result<()> {
         target: target.to_string_lossy().to_string(),
         backup_path: None,
         rendered_path: None,
+        content_hash: None,
+        permissions: None,
+        dir_permissions: None,
     });
     state.save(&state_path)?;
 
@@ -93,6 +103,9 @@ fn test_status_detects_wrong_symlink_target() -> anyhow::Result<()> {
         target: target.to_string_lossy().to_string(),
         backup_path: None,
         rendered_path: None,
+        content_hash: None,
+        permissions: None,
+        dir_permissions: None,
     });
     state.save(&state_path)?;
 
@@ -134,6 +147,9 @@ fn test_status_detects_all_in_sync() -> anyhow::Result<()> {
         target: target.to_string_lossy().to_string(),
         backup_path: None,
         rendered_path: None,
+        content_hash: None,
+        permissions: None,
+        dir_permissions: None,
     });
     state.save(&state_path)?;
 
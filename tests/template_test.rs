@@ -5,8 +5,8 @@ use std::collections::HashMap;
 #[test]
 fn test_simple_variable_substitution() {
     let mut vars = HashMap::new();
-    vars.insert("name".to_string(), "Alice".to_string());
-    vars.insert("email".to_string(), "alice@example.com".to_string());
+    vars.insert("name".to_string(), "Alice".into());
+    vars.insert("email".to_string(), "alice@example.com".into());
 
     let template = "Hello {{ variables.name }}, your email is {{ variables.email }}";
     let result = render_template(template, &vars).unwrap();
@@ -54,7 +54,7 @@ fn test_system_variables() {
 #[test]
 fn test_mixed_system_and_user_variables() {
     let mut vars = HashMap::new();
-    vars.insert("app_name".to_string(), "MyApp".to_string());
+    vars.insert("app_name".to_string(), "MyApp".into());
 
     let template = "{{ variables.app_name }} running on {{ system.hostname }} ({{ system.os }})";
     let result = render_template(template, &vars).unwrap();
@@ -90,8 +90,15 @@ fn test_template_detection_tmpl_suffix() {
         source: "config.fish.tmpl".to_string(),
         target: "~/.config/fish/config.fish".to_string(),
         template: false,
+        encrypted: false,
+        mode: Default::default(),
+        link: Default::default(),
+        permissions: None,
+        dir_permissions: None,
         only_roles: None,
         skip_roles: None,
+        when: None,
+        enabled: true,
     };
     assert!(dotfile.is_template());
 }
@@ -102,8 +109,15 @@ fn test_template_detection_hbs_suffix() {
         source: "config.fish.hbs".to_string(),
         target: "~/.config/fish/config.fish".to_string(),
         template: false,
+        encrypted: false,
+        mode: Default::default(),
+        link: Default::default(),
+        permissions: None,
+        dir_permissions: None,
         only_roles: None,
         skip_roles: None,
+        when: None,
+        enabled: true,
     };
     assert!(dotfile.is_template());
 }
@@ -114,8 +128,15 @@ fn test_template_detection_explicit_flag() {
         source: "config.fish".to_string(),
         target: "~/.config/fish/config.fish".to_string(),
         template: true,
+        encrypted: false,
+        mode: Default::default(),
+        link: Default::default(),
+        permissions: None,
+        dir_permissions: None,
         only_roles: None,
         skip_roles: None,
+        when: None,
+        enabled: true,
     };
     assert!(dotfile.is_template());
 }
@@ -126,8 +147,15 @@ fn test_template_detection_regular_file() {
         source: "config.fish".to_string(),
         target: "~/.config/fish/config.fish".to_string(),
         template: false,
+        encrypted: false,
+        mode: Default::default(),
+        link: Default::default(),
+        permissions: None,
+        dir_permissions: None,
         only_roles: None,
         skip_roles: None,
+        when: None,
+        enabled: true,
     };
     assert!(!dotfile.is_template());
 }
@@ -191,11 +219,8 @@ fn test_includes_helper_empty_array() {
 #[test]
 fn test_complex_template_with_all_features() {
     let mut vars = HashMap::new();
-    vars.insert("editor".to_string(), "nvim".to_string());
-    vars.insert(
-        "http_proxy".to_string(),
-        "http://localhost:3128".to_string(),
-    );
+    vars.insert("editor".to_string(), "nvim".into());
+    vars.insert("http_proxy".to_string(), "http://localhost:3128".into());
 
     let host_ctx = HostContext {
         name: "work-laptop".to_string(),
@@ -222,7 +247,7 @@ set -gx HTTP_PROXY "{{ variables.http_proxy }}"
 #[test]
 fn test_variables_namespace() {
     let mut vars = HashMap::new();
-    vars.insert("editor".to_string(), "vim".to_string());
+    vars.insert("editor".to_string(), "vim".into());
 
     let template = "Editor: {{ variables.editor }}";
     let result = render_template(template, &vars).unwrap();
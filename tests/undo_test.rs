@@ -1,7 +1,6 @@
-use assert_cmd::Command;
+use assert_cmd::cargo::cargo_bin_cmd;
 use predicates::prelude::*;
 use std::fs;
-use std::os::unix::fs::symlink;
 use std::path::PathBuf;
 use tempfile::TempDir;
 
@@ -35,8 +34,7 @@ target = "{}"
 
     let state_path = temp_dir.path().join("state.toml");
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -59,8 +57,7 @@ target = "{}"
         .collect();
     assert_eq!(backup_files.len(), 1);
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("undo")
         .arg("--state")
         .arg(&state_path)
@@ -82,8 +79,7 @@ fn test_undo_nothing_to_undo() {
     let temp_dir = TempDir::new().unwrap();
     let state_path = temp_dir.path().join("state.toml");
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("undo")
         .arg("--state")
         .arg(&state_path)
@@ -109,8 +105,7 @@ target = "{}"
 
     let state_path = temp_dir.path().join("state.toml");
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -124,8 +119,7 @@ target = "{}"
 
     fs::remove_file(&target_path).unwrap();
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("undo")
         .arg("--state")
         .arg(&state_path)
@@ -170,8 +164,7 @@ target = "{}"
 
     let state_path = temp_dir.path().join("state.toml");
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -184,8 +177,7 @@ target = "{}"
     assert!(target1.is_symlink());
     assert!(target2.is_symlink());
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("undo")
         .arg("--state")
         .arg(&state_path)
@@ -217,8 +209,7 @@ target = "{}"
 
     let state_path = temp_dir.path().join("state.toml");
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("apply")
         .arg("--config")
         .arg(&config_path)
@@ -230,8 +221,7 @@ target = "{}"
 
     assert!(target_path.is_symlink());
 
-    Command::cargo_bin("mimic")
-        .unwrap()
+    cargo_bin_cmd!("mimic")
         .arg("undo")
         .arg("--state")
         .arg(&state_path)
@@ -242,3 +232,52 @@ target = "{}"
 
     assert!(!target_path.exists());
 }
+
+#[test]
+fn test_undo_keeps_edited_copy() {
+    let (temp_dir, config_path, source_path, target_path) = setup_test_env();
+
+    fs::write(&target_path, "original content").unwrap();
+
+    let config_content = format!(
+        r#"
+[[dotfiles]]
+source = "{}"
+target = "{}"
+mode = "copy"
+"#,
+        source_path.display(),
+        target_path.display()
+    );
+    fs::write(&config_path, config_content).unwrap();
+
+    let state_path = temp_dir.path().join("state.toml");
+
+    cargo_bin_cmd!("mimic")
+        .arg("apply")
+        .arg("--config")
+        .arg(&config_path)
+        .arg("--state")
+        .arg(&state_path)
+        .arg("--yes")
+        .assert()
+        .success();
+
+    assert!(!target_path.is_symlink());
+    fs::write(&target_path, "edited after apply").unwrap();
+
+    cargo_bin_cmd!("mimic")
+        .arg("undo")
+        .arg("--state")
+        .arg(&state_path)
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("modified since it was applied"))
+        .stdout(predicate::str::contains("Original kept at:"))
+        .stdout(predicate::str::contains("0 symlinks removed"));
+
+    assert_eq!(
+        fs::read_to_string(&target_path).unwrap(),
+        "edited after apply"
+    );
+}
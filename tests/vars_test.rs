@@ -0,0 +1,162 @@
+use predicates::prelude::*;
+use std::fs;
+use std::path::Path;
+use tempfile::TempDir;
+
+mod common;
+
+fn mimic(
+    home: &Path,
+    args: &[&str],
+    config: &Path,
+    state: Option<&Path>,
+) -> assert_cmd::assert::Assert {
+    common::command(home, args, config, state)
+        .env("MIMIC_TEST_EMAIL", "me@corp.example")
+        .env_remove("MIMIC_TEST_UNSET")
+        .assert()
+}
+
+#[test]
+fn test_variable_sources_resolve_before_rendering() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(home.join(".local")).unwrap();
+    fs::write(home.join(".local/signing-key-id"), "ABCD1234\n").unwrap();
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state/state.toml");
+    fs::write(
+        &config_path,
+        r#"
+[variables]
+email.from = { env = "MIMIC_TEST_EMAIL" }
+region.from = { env = "MIMIC_TEST_UNSET", default = "eu" }
+computer.from = { command = "echo build-box" }
+signing_key.from = { file = "~/.local/signing-key-id" }
+
+[variables.git]
+name.from = { prompt = "Your name for git?" }
+"#,
+    )
+    .unwrap();
+    let template = temp_dir.path().join("gitconfig.tmpl");
+    fs::write(
+        &template,
+        "{{variables.git.name}} <{{variables.email}}> {{variables.signing_key}} {{variables.computer}} {{variables.region}}",
+    )
+    .unwrap();
+    let template = template.to_str().unwrap();
+
+    // Not a terminal and no saved answer
+    mimic(
+        &home,
+        &["render", template],
+        &config_path,
+        Some(&state_path),
+    )
+    .code(1)
+    .stderr(predicate::str::contains("Your name for git?"))
+    .stderr(predicate::str::contains("mimic vars"));
+
+    fs::create_dir_all(temp_dir.path().join("state")).unwrap();
+    fs::write(
+        temp_dir.path().join("state/data.toml"),
+        "\"git.name\" = \"Jo Doe\"\n",
+    )
+    .unwrap();
+
+    mimic(
+        &home,
+        &["render", template],
+        &config_path,
+        Some(&state_path),
+    )
+    .success()
+    .stdout(predicate::str::contains(
+        "Jo Doe <me@corp.example> ABCD1234 build-box eu",
+    ));
+
+    mimic(&home, &["vars"], &config_path, Some(&state_path))
+        .success()
+        .stdout(predicate::str::contains(
+            "email = me@corp.example (from env MIMIC_TEST_EMAIL)",
+        ))
+        .stdout(predicate::str::contains(
+            "region = eu (from default, env MIMIC_TEST_UNSET unavailable)",
+        ))
+        .stdout(predicate::str::contains(
+            "git.name = Jo Doe (from prompt \"Your name for git?\")",
+        ))
+        .stdout(predicate::str::contains(
+            "computer = build-box (from command `echo build-box`)",
+        ));
+}
+
+#[test]
+fn test_failed_variable_source_names_the_variable() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    fs::write(
+        &config_path,
+        r#"
+[variables]
+token.from = { env = "MIMIC_TEST_UNSET" }
+"#,
+    )
+    .unwrap();
+
+    mimic(&home, &["vars"], &config_path, Some(&state_path))
+        .code(1)
+        .stderr(predicate::str::contains(
+            "Variable 'token' reads $MIMIC_TEST_UNSET, which is not set",
+        ));
+}
+
+#[test]
+fn test_tables_without_from_are_ordinary_variables() {
+    let temp_dir = TempDir::new().unwrap();
+    let home = temp_dir.path().join("home");
+    fs::create_dir_all(&home).unwrap();
+
+    let config_path = temp_dir.path().join("mimic.toml");
+    let state_path = temp_dir.path().join("state.toml");
+    let marker = temp_dir.path().join("ran");
+    fs::write(
+        &config_path,
+        format!(
+            r#"
+[variables.deploy]
+env = "production"
+
+[variables.build]
+command = "touch {}"
+"#,
+            marker.display()
+        ),
+    )
+    .unwrap();
+    let template = temp_dir.path().join("deploy.tmpl");
+    fs::write(
+        &template,
+        "{{variables.deploy.env}} / {{variables.build.command}}",
+    )
+    .unwrap();
+
+    mimic(
+        &home,
+        &["render", template.to_str().unwrap()],
+        &config_path,
+        Some(&state_path),
+    )
+    .success()
+    .stdout(predicate::str::contains(format!(
+        "production / touch {}",
+        marker.display()
+    )));
+    assert!(!marker.exists());
+}